@@ -1,9 +1,18 @@
-use crate::utils::{list, ChangeData, ChangeOpt, Error, ListOpt, Result};
+use crate::utils::{
+    create_http_client, get_pkgs, is_offline, is_published, list, package_registry, ChangeData,
+    ChangeOpt, Error, ListOpt, Pkg, RegistryOpt, Result, WorkspaceContext, INTERNAL_ERR,
+};
 
-use cargo_metadata::Metadata;
-use clap::Parser;
+use clap::{ArgEnum, Parser};
 use oclif::term::TERM_OUT;
 
+/// Machine readable outputs for specific consumers, next to the generic `--json`
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Format {
+    /// A GitHub Actions matrix keyed by crate, like `{"crate":["a","b"]}`
+    GhaMatrix,
+}
+
 /// List crates that have changed since the last tagged release
 #[derive(Debug, Parser)]
 pub struct Changed {
@@ -16,33 +25,130 @@ pub struct Changed {
     /// Return non-zero exit code if no changes detected
     #[clap(long)]
     error_on_empty: bool,
+
+    /// List the crates whose current version is not on the registry yet, instead of
+    /// comparing with git
+    #[clap(long, conflicts_with_all = &["since", "force", "force-all-on", "fetch-tags", "precise"])]
+    against_registry: bool,
+
+    /// Leave out the package of the workspace manifest
+    #[clap(long)]
+    exclude_root: bool,
+
+    /// Only consider the package of the workspace manifest
+    #[clap(long, conflicts_with = "exclude-root")]
+    root_only: bool,
+
+    /// Print the crates for a specific consumer: gha-matrix (a single line JSON object for
+    /// the `matrix` of a GitHub Actions job)
+    #[clap(long, arg_enum, value_name = "FORMAT", conflicts_with_all = &["json", "long"])]
+    format: Option<Format>,
+
+    #[clap(flatten)]
+    registry: RegistryOpt,
 }
 
 impl Changed {
-    pub fn run(self, metadata: Metadata) -> Result {
+    pub fn run(self, ctx: &WorkspaceContext) -> Result {
+        if self.against_registry {
+            let pkgs = self.filter_root(self.unpublished(ctx)?);
+
+            if pkgs.is_empty() && self.error_on_empty {
+                return self.finish();
+            }
+
+            return self.print(&pkgs);
+        }
+
         let mut since = self.change.since.clone();
 
         if self.change.since.is_none() {
-            let change_data = ChangeData::new(&metadata, &self.change)?;
+            let change_data = ChangeData::new(ctx, &self.change)?;
 
             if change_data.count == "0" {
-                TERM_OUT
-                    .write_line("Current HEAD is already released, skipping change detection")?;
+                if self.format.is_none() {
+                    TERM_OUT.write_line(
+                        "Current HEAD is already released, skipping change detection",
+                    )?;
+                } else if !self.error_on_empty {
+                    // Consumers still get something they can parse. A job given an empty matrix
+                    // fails in GitHub Actions though, `--error-on-empty` stops before it instead
+                    return self.print(&[]);
+                }
+
                 return self.finish();
             }
 
             since = change_data.since;
         }
 
-        let pkgs = self
-            .change
-            .get_changed_pkgs(&metadata, &since, self.list.all)?;
+        let (pkgs, _) = self.change.get_changed_pkgs(ctx, &since, self.list.all)?;
+        let pkgs = self.filter_root(pkgs);
 
-        if pkgs.0.is_empty() && self.error_on_empty {
+        if pkgs.is_empty() && self.error_on_empty {
             return self.finish();
         }
 
-        list(&pkgs.0, self.list)
+        self.print(&pkgs)
+    }
+
+    fn print(self, pkgs: &[Pkg]) -> Result {
+        match self.format {
+            Some(Format::GhaMatrix) => {
+                let crates = pkgs
+                    .iter()
+                    .filter(|x| self.list.all || !x.private)
+                    .map(|x| x.name.as_str())
+                    .collect::<Vec<_>>();
+
+                TERM_OUT.write_line(&serde_json::json!({ "crate": crates }).to_string())?;
+                Ok(())
+            }
+            None => list(pkgs, self.list),
+        }
+    }
+
+    fn filter_root(&self, mut pkgs: Vec<Pkg>) -> Vec<Pkg> {
+        if self.exclude_root {
+            pkgs.retain(|p| !p.is_root());
+        }
+
+        if self.root_only {
+            pkgs.retain(|p| p.is_root());
+        }
+
+        pkgs
+    }
+
+    // private crates and the ones skipped by publish never reach the registry
+    fn unpublished(&self, ctx: &WorkspaceContext) -> Result<Vec<Pkg>> {
+        let metadata = &ctx.metadata;
+
+        if is_offline() {
+            return Err(Error::Offline("check the registry".to_string()));
+        }
+
+        let http_client = create_http_client(&metadata.workspace_root, &self.registry.token)?;
+        let mut pkgs = vec![];
+
+        for p in get_pkgs(metadata, false)? {
+            if p.config.skip_publish.unwrap_or(false) {
+                continue;
+            }
+
+            let pkg = metadata
+                .packages
+                .iter()
+                .find(|x| x.id == p.id)
+                .expect(INTERNAL_ERR);
+            let index_url = package_registry(metadata, self.registry.registry.as_ref(), pkg)?;
+
+            if !is_published(&http_client, index_url, &p.name, &p.version.to_string())? {
+                pkgs.push(p);
+            }
+        }
+
+        Ok(pkgs)
     }
 
     fn finish(self) -> Result {
@@ -0,0 +1,315 @@
+use crate::utils::{
+    cargo_output, check_resolver, get_dry_run, info, is_offline, log_command, log_output,
+    parse_rust_version, rust_version, warn, Error, MemberToolchain, Resolver, Result, RustVersion,
+    INTERNAL_ERR,
+};
+
+use camino::Utf8PathBuf;
+use cargo_metadata::Metadata;
+use clap::{ArgEnum, Parser};
+use oclif::console::strip_ansi_codes;
+use semver::Version;
+use toml_edit::{value, Document};
+
+use std::{
+    collections::BTreeSet as Set,
+    fs,
+    process::{Command, Output},
+};
+
+/// Check that the crates compile with their declared `rust-version`
+#[derive(Debug, Parser)]
+pub struct CheckMsrv {
+    /// Raise the `rust-version` of the crates which do not compile to the installed stable
+    #[clap(long)]
+    fix: bool,
+
+    /// Fail instead of warning when the resolver needs a newer cargo than the crates support
+    #[clap(long)]
+    strict: bool,
+}
+
+impl CheckMsrv {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let root_manifest = metadata.workspace_root.join("Cargo.toml");
+        let workspace = fs::read_to_string(&root_manifest)?.parse::<Document>()?;
+
+        let mut members = metadata
+            .packages
+            .iter()
+            .filter(|x| metadata.workspace_members.contains(&x.id))
+            .collect::<Vec<_>>();
+
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let manifests = members
+            .iter()
+            .map(|x| Ok(fs::read_to_string(&x.manifest_path)?.parse::<Document>()?))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.check_resolver(&workspace, &manifests)?;
+
+        let mut installed = Set::new();
+        let mut stable = None;
+        let mut problems = vec![];
+
+        for (pkg, manifest) in members.into_iter().zip(&manifests) {
+            let Some(rust_version) = rust_version(manifest, &workspace) else {
+                warn!("no rust-version", pkg.name);
+                continue;
+            };
+
+            let toolchain = rust_version.toolchain().to_string();
+
+            if installed.insert(toolchain.clone()) {
+                install_toolchain(&metadata.workspace_root, &toolchain)?;
+            }
+
+            info!("checking", format!("{} with {}", pkg.name, toolchain));
+
+            let dir = pkg
+                .manifest_path
+                .parent()
+                .ok_or_else(|| Error::ManifestHasNoParent(pkg.name.clone()))?;
+
+            let toolchain_arg = format!("+{}", toolchain);
+            let (status, _, stderr) = cargo_output(dir, &[toolchain_arg.as_str(), "check"], &[])?;
+
+            if status.success() {
+                info!("compiles", format!("{} with {}", pkg.name, toolchain));
+                continue;
+            }
+
+            let errors = compiler_errors(&strip_ansi_codes(&stderr));
+
+            if self.fix {
+                if stable.is_none() {
+                    stable = Some(stable_version(&metadata.workspace_root)?);
+                }
+
+                let stable = stable.as_ref().expect(INTERNAL_ERR);
+
+                if parse_rust_version(&toolchain).is_some_and(|x| x < *stable) {
+                    let path = match rust_version {
+                        RustVersion::Package(_) => pkg.manifest_path.clone(),
+                        RustVersion::Workspace(_) => root_manifest.clone(),
+                    };
+
+                    self.fix_rust_version(&path, &rust_version, stable)?;
+                    continue;
+                }
+            }
+
+            problems.push(format!(
+                " - {} (rust-version {}):\n{}",
+                pkg.name,
+                toolchain,
+                errors.join("\n")
+            ));
+        }
+
+        if !problems.is_empty() {
+            return Err(Error::MsrvCheck(problems.join("\n")));
+        }
+
+        info!("success", "ok");
+        Ok(())
+    }
+
+    fn check_resolver(&self, workspace: &Document, manifests: &[Document]) -> Result {
+        let resolver = workspace
+            .get("workspace")
+            .and_then(|x| x.get("resolver"))
+            .and_then(|x| x.as_str())
+            .and_then(|x| Resolver::from_str(x, false).ok());
+
+        let Some(resolver) = resolver else {
+            return Ok(());
+        };
+
+        let members = manifests
+            .iter()
+            .filter_map(|x| MemberToolchain::read(x, workspace))
+            .collect::<Vec<_>>();
+
+        check_resolver(resolver, &members, self.strict)
+    }
+
+    fn fix_rust_version(
+        &self,
+        path: &Utf8PathBuf,
+        rust_version: &RustVersion,
+        stable: &Version,
+    ) -> Result {
+        let new_version = format!("{}.{}", stable.major, stable.minor);
+
+        if get_dry_run() {
+            info!(
+                "would set rust-version",
+                format!("{} in {}", new_version, path)
+            );
+            return Ok(());
+        }
+
+        let mut manifest = fs::read_to_string(path)?.parse::<Document>()?;
+
+        match rust_version {
+            RustVersion::Package(_) => {
+                manifest["package"]["rust-version"] = value(new_version.clone());
+            }
+            RustVersion::Workspace(_) => {
+                manifest["workspace"]["package"]["rust-version"] = value(new_version.clone());
+            }
+        }
+
+        fs::write(path, manifest.to_string())?;
+
+        info!(
+            "fixed rust-version",
+            format!(
+                "{} => {} in {}",
+                rust_version.toolchain(),
+                new_version,
+                path
+            )
+        );
+        Ok(())
+    }
+}
+
+/// The lines starting the diagnostics of the failed compilation, or the last line when there are none
+fn compiler_errors(stderr: &str) -> Vec<String> {
+    let errors = stderr
+        .lines()
+        .filter(|x| x.starts_with("error"))
+        .map(|x| format!("   {}", x))
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        return stderr
+            .lines()
+            .last()
+            .map(|x| format!("   {}", x))
+            .into_iter()
+            .collect();
+    }
+
+    errors
+}
+
+fn install_toolchain(root: &Utf8PathBuf, toolchain: &str) -> Result {
+    if is_offline() {
+        let output = run(Command::new("rustup")
+            .current_dir(root)
+            .args(["toolchain", "list"]))?;
+
+        if is_installed(&String::from_utf8(output.stdout)?, toolchain) {
+            return Ok(());
+        }
+
+        return Err(Error::Toolchain(
+            toolchain.to_string(),
+            "it is not installed and cannot be downloaded in offline mode".to_string(),
+        ));
+    }
+
+    info!("installing toolchain", toolchain);
+
+    let output = run(Command::new("rustup").current_dir(root).args([
+        "toolchain",
+        "install",
+        toolchain,
+        "--profile",
+        "minimal",
+    ]))?;
+
+    if !output.status.success() {
+        return Err(Error::Toolchain(
+            toolchain.to_string(),
+            String::from_utf8(output.stderr)?.trim().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn stable_version(root: &Utf8PathBuf) -> Result<Version> {
+    let output = run(Command::new("rustc")
+        .current_dir(root)
+        .args(["+stable", "--version"]))?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    parse_rustc_version(&stdout).ok_or_else(|| {
+        Error::Toolchain(
+            "stable".to_string(),
+            format!("unexpected `rustc --version` output: {}", stdout.trim()),
+        )
+    })
+}
+
+fn run(cmd: &mut Command) -> Result<Output> {
+    log_command(cmd);
+
+    let output = cmd.output()?;
+    log_output(&output.stdout, &output.stderr);
+
+    Ok(output)
+}
+
+/// Whether `rustup toolchain list` shows the toolchain, which is listed with its target
+fn is_installed(list: &str, toolchain: &str) -> bool {
+    let prefix = format!("{}-", toolchain);
+
+    list.lines()
+        .filter_map(|x| x.split_whitespace().next())
+        .any(|x| x == toolchain || x.starts_with(&prefix))
+}
+
+fn parse_rustc_version(out: &str) -> Option<Version> {
+    out.split_whitespace()
+        .nth(1)
+        .and_then(|x| Version::parse(x).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_rustc_version() {
+        assert_eq!(
+            parse_rustc_version("rustc 1.82.0 (f6e511eec 2024-10-15)\n"),
+            Version::parse("1.82.0").ok()
+        );
+        assert_eq!(parse_rustc_version("error: toolchain not installed"), None);
+    }
+
+    #[test]
+    fn test_compiler_errors() {
+        let stderr = "    Checking a v0.1.0\nerror[E0658]: use of unstable library feature\n  --> src/lib.rs:1:1\nerror: could not compile `a`";
+
+        assert_eq!(
+            compiler_errors(stderr),
+            vec![
+                "   error[E0658]: use of unstable library feature",
+                "   error: could not compile `a`"
+            ]
+        );
+        assert_eq!(
+            compiler_errors("    Checking a v0.1.0\nwarning: build failed, waiting for other jobs"),
+            vec!["   warning: build failed, waiting for other jobs"]
+        );
+        assert!(compiler_errors("").is_empty());
+    }
+
+    #[test]
+    fn test_is_installed() {
+        let list =
+            "stable-x86_64-unknown-linux-gnu (active, default)\n1.70-x86_64-unknown-linux-gnu\n";
+
+        assert!(is_installed(list, "1.70"));
+        assert!(!is_installed(list, "1.7"));
+        assert!(!is_installed(list, "1.70.0"));
+        assert!(!is_installed("no installed toolchains\n", "1.70"));
+    }
+}
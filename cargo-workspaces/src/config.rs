@@ -0,0 +1,38 @@
+use crate::utils::{info, validate_config, Error, Result};
+
+use cargo_metadata::Metadata;
+use clap::{ArgGroup, Parser};
+
+/// Inspect the `workspaces` metadata tables of the workspace and its crates
+#[derive(Debug, Parser)]
+#[clap(group(ArgGroup::new("mode").required(true).args(&["validate"])))]
+pub struct Config {
+    /// Check the tables for unknown keys and values of the wrong type
+    #[clap(long)]
+    validate: bool,
+}
+
+impl Config {
+    pub fn run(self, metadata: Metadata) -> Result {
+        // Invalid values failed loading the workspace already, and the unknown keys were
+        // warned about. Unlike the other commands, they fail the validation.
+        let unknown = validate_config(&metadata).unknown.len();
+
+        if unknown > 0 {
+            return Err(Error::UnknownConfigKeys(unknown));
+        }
+
+        let crates = metadata.workspace_members.len();
+
+        info!(
+            "valid",
+            format!(
+                "config of the workspace and its {} crate{}",
+                crates,
+                if crates == 1 { "" } else { "s" }
+            )
+        );
+
+        Ok(())
+    }
+}
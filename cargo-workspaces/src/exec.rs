@@ -1,10 +1,104 @@
-use crate::utils::{dag, filter_private, info, Error, Result, INTERNAL_ERR};
+use crate::utils::{
+    cargo_flags, dag, filter_private, get_dry_run, info, is_offline, isolate, log_command,
+    output_timeout, record_completed, relative_path, stop_if_interrupted, tee_timeout,
+    wait_timeout, wait_timeout_stderr, warn, ChildGuard, Error, Junit, Result, Timeout,
+    WorkspaceContext, INTERNAL_ERR, STDERR_TAIL, TIMED_OUT_EXIT_CODE,
+};
 
-use cargo_metadata::Metadata;
 use clap::Parser;
 use globset::{Error as GlobsetError, Glob};
+use oclif::term::TERM_OUT;
+use serde::Serialize;
+use serde_json::to_string_pretty;
 
-use std::{process::Command, result::Result as StdResult};
+use std::{
+    collections::BTreeSet as Set,
+    fs::{self, OpenOptions},
+    io::{stderr, stdout, Write},
+    path::PathBuf,
+    process::{Command, ExitStatus, Stdio},
+    result::Result as StdResult,
+    slice,
+    str::FromStr,
+    time::Instant,
+};
+
+/// How the exit code is chosen when commands fail
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExitCode {
+    /// The exit code of the first failing crate
+    First,
+    /// The highest exit code seen
+    Max,
+    /// Always the given exit code
+    Custom(u8),
+    /// The number of failed crates, capped at 125
+    Summary,
+}
+
+impl FromStr for ExitCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        match s {
+            "first" => Ok(ExitCode::First),
+            "max" => Ok(ExitCode::Max),
+            "summary" => Ok(ExitCode::Summary),
+            "custom" => Err("custom needs the exit code, like custom 3".to_string()),
+            _ => Err(format!(
+                "expected one of first, max, summary or custom <N>, got {}",
+                s
+            )),
+        }
+    }
+}
+
+/// The report printed by `--json-summary`
+#[derive(Serialize, Debug)]
+struct Summary<'a> {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    results: &'a [CrateResult],
+}
+
+#[derive(Serialize, Debug)]
+struct CrateResult {
+    name: String,
+    path: String,
+    exit_code: i32,
+    duration_ms: u64,
+    stdout_bytes: u64,
+    stderr_bytes: u64,
+}
+
+impl ExitCode {
+    /// Splits the strategy off the words starting with the value of `--exit-code`, where
+    /// `custom` is followed by the exit code, and returns it with the command
+    fn split(words: &[String]) -> StdResult<(Self, &[String]), String> {
+        match words {
+            [custom, code, rest @ ..] if custom == "custom" => match code.parse() {
+                Ok(code) => Ok((ExitCode::Custom(code), rest)),
+                Err(_) => Err(format!(
+                    "expected an exit code from 0 to 255 after custom, got {}",
+                    code
+                )),
+            },
+            [strategy, rest @ ..] => Ok((strategy.parse()?, rest)),
+            [] => Ok((ExitCode::First, words)),
+        }
+    }
+
+    /// Reduces the exit codes of the failed crates, in order, to the one to exit with
+    fn code(&self, failures: &[i32]) -> i32 {
+        match self {
+            ExitCode::First => failures.first().copied().unwrap_or(1),
+            ExitCode::Max => failures.iter().copied().max().unwrap_or(1),
+            ExitCode::Custom(code) => i32::from(*code),
+            ExitCode::Summary => failures.len().min(125) as i32,
+        }
+    }
+}
 
 /// Execute an arbitrary command in each crate
 #[derive(Debug, Parser)]
@@ -22,66 +116,595 @@ pub struct Exec {
     #[clap(long)]
     ignore_private: bool,
 
+    /// Skip the package of the workspace manifest
+    #[clap(long)]
+    exclude_root: bool,
+
+    /// Only run in the package of the workspace manifest
+    #[clap(long, conflicts_with = "exclude-root")]
+    root_only: bool,
+
+    /// Only run in the crates which no other member depends on
+    #[clap(long)]
+    no_deps: bool,
+
+    /// Only run in the first N of the selected crates, in the order they would run in
+    #[clap(long, value_name = "N")]
+    max_crates: Option<usize>,
+
+    /// Run the command at the workspace root instead of the directory of each crate, use
+    /// `{name}` or `CARGO_WS_PKG_NAME` to point it to the crate
+    #[clap(long)]
+    at_root: bool,
+
+    /// Append `-p {name}` to commands starting with `cargo` (implies --at-root)
+    #[clap(long)]
+    cargo: bool,
+
+    /// Buffer the output of each crate and print it at once after a header
+    #[clap(long)]
+    group_output: bool,
+
+    /// Report but tolerate non-zero exits in the crates matched by glob
+    #[clap(long, value_name = "PATTERN")]
+    ignore_errors_for: Option<String>,
+
+    /// How to choose the exit code when commands fail: first, max, summary (number of failed
+    /// crates) or custom <N>
+    #[clap(
+        long,
+        value_name = "STRATEGY",
+        default_value = "first",
+        possible_values = &["first", "max", "summary", "custom"]
+    )]
+    exit_code: String,
+
+    /// Kill the command of a crate if it runs longer than the duration (like 90s or 10m)
+    #[clap(long, value_name = "DURATION")]
+    timeout: Option<Timeout>,
+
+    /// Record the crates which succeeded in the file and skip them when running again, the
+    /// file is removed once every crate succeeded
+    #[clap(long, value_name = "PATH", parse(from_os_str))]
+    continue_file: Option<PathBuf>,
+
+    /// Write the outcome of each crate to the file as a JUnit XML report
+    #[clap(long, value_name = "PATH", parse(from_os_str))]
+    junit: Option<PathBuf>,
+
+    /// Name of the test suite in the JUnit report
+    #[clap(long, value_name = "NAME", default_value = "cargo-workspaces")]
+    junit_suite: String,
+
+    /// Build cargo commands with the profile, which is also in `CARGO_PROFILE` for all commands
+    #[clap(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Print the exit code, duration and output size of each crate as JSON once done
+    #[clap(long)]
+    json_summary: bool,
+
     #[clap(required = true)]
     args: Vec<String>,
 }
 
 impl Exec {
-    pub fn run(&self, metadata: Metadata) -> Result {
+    pub fn run(&self, ctx: &WorkspaceContext) -> Result {
+        let metadata = &ctx.metadata;
+        // The exit code of `--exit-code custom <N>` comes first in the arguments
+        let words = [slice::from_ref(&self.exit_code), &self.args].concat();
+        let (strategy, command) = ExitCode::split(&words).map_err(Error::ExecArgs)?;
+
+        if command.is_empty() {
+            return Err(Error::ExecArgs("missing the command to run".to_string()));
+        }
         let pkgs = metadata
             .packages
             .iter()
             .map(|x| (x.clone(), x.version.to_string()))
             .collect::<Vec<_>>();
 
-        let (names, mut visited) = dag(&pkgs);
+        let (names, mut visited) = dag(&pkgs)?;
 
         if self.ignore_private {
             visited = filter_private(visited, &pkgs);
         }
 
+        let root_manifest = metadata.workspace_root.join("Cargo.toml");
+
+        if self.exclude_root {
+            visited.retain(|x| *x != root_manifest);
+        }
+
+        if self.root_only {
+            visited.retain(|x| *x == root_manifest);
+
+            if visited.is_empty() {
+                warn!("no root package", "the workspace manifest is virtual");
+            }
+        }
+
+        if self.no_deps {
+            visited.retain(|x| {
+                let (pkg, _) = names.get(x).expect(INTERNAL_ERR);
+                ctx.dependency_graph()
+                    .direct_dependents(&pkg.name)
+                    .next()
+                    .is_none()
+            });
+        }
+
         let ignore = self
             .ignore
             .clone()
             .map(|x| Glob::new(&x))
             .map_or::<StdResult<_, GlobsetError>, _>(Ok(None), |x| Ok(x.ok()))?;
 
-        let mut errored = false;
-        for p in &visited {
-            let (pkg, _) = names.get(p).expect(INTERNAL_ERR);
+        if let Some(pattern) = &ignore {
+            let matcher = pattern.compile_matcher();
+
+            visited.retain(|x| {
+                let (pkg, _) = names.get(x).expect(INTERNAL_ERR);
+                !matcher.is_match(&pkg.name)
+            });
+        }
+
+        if let Some(max) = self.max_crates
+            && visited.len() > max
+        {
+            info!(
+                "limiting",
+                format!("to the first {} of {} crates", max, visited.len())
+            );
+            visited.truncate(max);
+        }
+
+        // An invalid pattern is an error instead of tolerating no failures
+        let ignore_errors_for = self
+            .ignore_errors_for
+            .as_deref()
+            .map(Glob::new)
+            .transpose()?;
+
+        let completed = match &self.continue_file {
+            Some(path) if path.exists() => fs::read_to_string(path)?
+                .lines()
+                .map(|x| x.to_string())
+                .collect(),
+            _ => Set::new(),
+        };
+
+        let junit = match &self.junit {
+            Some(path) if get_dry_run() => {
+                info!("would write junit report", path.display());
+                None
+            }
+            Some(path) => Some(Junit::new(path.clone(), self.junit_suite.clone())),
+            None => None,
+        };
+
+        let mut failures = vec![];
+        let mut failed = vec![];
+        let mut results = vec![];
+        // Whether the output of the last crate did not end with a newline
+        let mut open_line = false;
+
+        // Run in a closure so that the report is written whichever way the run ends
+        let ran = (|| -> Result {
+            for p in &visited {
+                stop_if_interrupted();
+
+                let (pkg, _) = names.get(p).expect(INTERNAL_ERR);
+
+                if completed.contains(&pkg.name) {
+                    info!("skipping completed", pkg.name);
+
+                    if let Some(junit) = &junit {
+                        junit.skip(&pkg.name, "completed in a previous run");
+                    }
 
-            if let Some(pattern) = &ignore
-                && pattern.compile_matcher().is_match(&pkg.name) {
                     continue;
                 }
 
-            let dir = pkg
-                .manifest_path
-                .parent()
-                .ok_or_else(|| Error::ManifestHasNoParent(pkg.name.clone()))?;
+                let dir = pkg
+                    .manifest_path
+                    .parent()
+                    .ok_or_else(|| Error::ManifestHasNoParent(pkg.name.clone()))?;
 
-            let status = Command::new(self.args.first().expect(INTERNAL_ERR))
-                .args(&self.args[1..])
-                .current_dir(dir)
-                .status()?;
+                let version = pkg.version.to_string();
+                let substituted = command
+                    .iter()
+                    .map(|x| substitute(x, &pkg.name, &version, dir.as_str()))
+                    .collect::<Vec<_>>();
+                let (command, post_args) = split_post_args(&substituted);
+                let mut args = command.to_vec();
 
-            if !status.success() {
-                match self.no_bail {
-                    true => errored = true,
-                    false => return Err(Error::Bail),
+                if self.cargo && args[0] == "cargo" {
+                    args.extend(["-p".to_string(), pkg.name.clone()]);
+                }
+
+                // The global flags go before the subcommand, where every cargo command takes them
+                if args[0] == "cargo" {
+                    let at = 1 + args[1..].iter().take_while(|x| x.starts_with('+')).count();
+                    let flags = cargo_flags()
+                        .into_iter()
+                        .filter(|x| !args.iter().any(|y| y == x))
+                        .map(|x| x.to_string())
+                        .collect::<Vec<_>>();
+
+                    args.splice(at..at, flags);
+                }
+
+                if let Some(profile) = &self.profile
+                    && takes_profile(&args)
+                    && !args
+                        .iter()
+                        .any(|x| x == "--profile" || x.starts_with("--profile="))
+                {
+                    args.extend(["--profile".to_string(), profile.clone()]);
+                }
+
+                args.extend(post_args.iter().cloned());
+
+                let cwd = if self.at_root || self.cargo {
+                    &metadata.workspace_root
+                } else {
+                    dir
+                };
+
+                if get_dry_run() {
+                    info!("would run", format!("{} in {}", args.join(" "), cwd));
+                    continue;
+                }
+
+                let mut cmd = Command::new(args.first().expect(INTERNAL_ERR));
+
+                cmd.args(&args[1..])
+                    .current_dir(cwd)
+                    .env("CARGO_WS_PKG_NAME", &pkg.name);
+
+                // Any cargo invoked by the command should not touch the network either
+                if is_offline() {
+                    cmd.env("CARGO_NET_OFFLINE", "true");
+                }
+
+                if let Some(profile) = &self.profile {
+                    cmd.env("CARGO_PROFILE", profile);
+                }
+
+                if self.timeout.is_some() {
+                    isolate(&mut cmd);
+                }
+
+                log_command(&cmd);
+
+                if let Some(junit) = &junit {
+                    junit.start(&pkg.name);
+                }
+
+                let start = Instant::now();
+
+                // The sizes of the output are only known when it goes through us
+                let (status, stderr_tail, (stdout_bytes, stderr_bytes)) = if self.group_output {
+                    let child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+                    let guard = ChildGuard::new(child.id());
+                    let (status, output_stdout, output_stderr) =
+                        output_timeout(child, self.timeout)?;
+                    drop(guard);
+
+                    let mut out = stdout().lock();
+                    writeln!(out, "=== {} ===", pkg.name)?;
+                    out.write_all(&output_stdout)?;
+                    out.flush()?;
+                    open_line = output_stdout.last().is_some_and(|x| *x != b'\n');
+
+                    let mut err = stderr().lock();
+                    err.write_all(&output_stderr)?;
+                    err.flush()?;
+
+                    let bytes = (output_stdout.len() as u64, output_stderr.len() as u64);
+                    (status, output_stderr, bytes)
+                } else if self.json_summary {
+                    // Counted while still showing it as it comes
+                    let child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+                    let _guard = ChildGuard::new(child.id());
+
+                    let tee = tee_timeout(child, self.timeout, STDERR_TAIL)?;
+
+                    if let Some(last) = tee.stdout_last {
+                        open_line = last != b'\n';
+                    }
+
+                    (
+                        tee.status,
+                        tee.stderr_tail,
+                        (tee.stdout_bytes, tee.stderr_bytes),
+                    )
+                } else if junit.is_some() {
+                    // Keep the end of stderr for the report while still showing it as it comes
+                    let child = cmd.stderr(Stdio::piped()).spawn()?;
+                    let _guard = ChildGuard::new(child.id());
+
+                    let (status, stderr_tail) =
+                        wait_timeout_stderr(child, self.timeout, STDERR_TAIL)?;
+                    (status, stderr_tail, (0, 0))
+                } else {
+                    let mut child = cmd.spawn()?;
+                    let _guard = ChildGuard::new(child.id());
+
+                    (wait_timeout(&mut child, self.timeout)?, vec![], (0, 0))
+                };
+
+                results.push(CrateResult {
+                    name: pkg.name.clone(),
+                    path: relative_path(dir, &metadata.workspace_root)
+                        .map(|x| x.to_string())
+                        .filter(|x| !x.is_empty())
+                        .unwrap_or_else(|| ".".to_string()),
+                    exit_code: status.map_or(TIMED_OUT_EXIT_CODE, exit_code),
+                    duration_ms: start.elapsed().as_millis() as u64,
+                    stdout_bytes,
+                    stderr_bytes,
+                });
+
+                let (code, reason) = match status {
+                    Some(status) if status.success() => {
+                        if let Some(junit) = &junit {
+                            junit.pass();
+                        }
+
+                        self.record_continue(&pkg.name)?;
+                        record_completed(pkg.name.clone());
+                        continue;
+                    }
+                    Some(status) => {
+                        let code = exit_code(status);
+                        (code, format!("exit code {}", code))
+                    }
+                    None => {
+                        let reason =
+                            format!("timed out after {}", self.timeout.expect(INTERNAL_ERR));
+
+                        warn!("killed", format!("{} {}", pkg.name, reason));
+                        (TIMED_OUT_EXIT_CODE, reason)
+                    }
+                };
+
+                // Tolerated failures are still reported as such
+                if let Some(junit) = &junit {
+                    junit.fail(&reason, &stderr_tail);
+                }
+
+                if let Some(pattern) = &ignore_errors_for
+                    && pattern.compile_matcher().is_match(&pkg.name)
+                {
+                    warn!("ignoring failure", pkg.name);
+                    continue;
+                }
+
+                failures.push(code);
+                failed.push(format!("{} ({})", pkg.name, reason));
+
+                if !self.no_bail {
+                    return Err(Error::Bail(strategy.code(&failures)));
                 }
             }
-        }
 
-        match errored {
-            true => {
-                info!("failed", "error(s) occurred");
-                Err(Error::Bail)
+            Ok(())
+        })();
+
+        // Printed even when the run failed, with what ran until then
+        let summary = self.print_summary(&results, open_line);
+
+        if let Some(junit) = &junit {
+            if let Err(err) = &ran {
+                junit.error(&err.to_string());
+            }
+
+            // The error of the run matters more than the one of the report
+            if let Err(err) = junit.write()
+                && ran.is_ok()
+            {
+                return Err(err.into());
             }
-            false => {
-                info!("success", "ok");
-                Ok(())
+        }
+
+        ran?;
+        summary?;
+
+        if failures.is_empty() {
+            if let Some(path) = &self.continue_file
+                && path.exists()
+                && !get_dry_run()
+            {
+                fs::remove_file(path)?;
             }
+
+            info!("success", "ok");
+            Ok(())
+        } else {
+            info!(
+                "failed",
+                format!("{} crate(s) failed: {}", failures.len(), failed.join(", "))
+            );
+            Err(Error::Bail(strategy.code(&failures)))
+        }
+    }
+
+    fn print_summary(&self, results: &[CrateResult], open_line: bool) -> Result {
+        if !self.json_summary {
+            return Ok(());
+        }
+
+        if open_line {
+            TERM_OUT.write_line("")?;
+        }
+
+        let succeeded = results.iter().filter(|x| x.exit_code == 0).count();
+        let summary = Summary {
+            total: results.len(),
+            succeeded,
+            failed: results.len() - succeeded,
+            results,
+        };
+
+        TERM_OUT.write_line(&to_string_pretty(&summary)?)?;
+        Ok(())
+    }
+
+    // Appends as soon as a crate finished, so that the progress survives an interruption
+    fn record_continue(&self, name: &str) -> Result {
+        if let Some(path) = &self.continue_file {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn exit_code(status: ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+
+    // Mimic the shells for commands killed by a signal
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    1
+}
+
+/// Splits the arguments at a nested `--`, the flags added to cargo commands go before it so
+/// that they don't end up with the harness arguments in `cargo test -- --nocapture`
+fn split_post_args(args: &[String]) -> (&[String], &[String]) {
+    match args.iter().skip(1).position(|x| x == "--") {
+        Some(i) => args.split_at(i + 1),
+        None => (args, &[]),
+    }
+}
+
+/// The cargo subcommands accepting `--profile`
+const PROFILE_COMMANDS: &[&str] = &[
+    "build", "check", "test", "bench", "run", "clippy", "doc", "install", "rustc",
+];
+
+/// Whether the command is a cargo subcommand accepting `--profile`, past any `+toolchain`
+fn takes_profile(args: &[String]) -> bool {
+    if args[0] != "cargo" {
+        return false;
+    }
+
+    args[1..]
+        .iter()
+        .find(|x| !x.starts_with('+'))
+        .is_some_and(|x| PROFILE_COMMANDS.contains(&x.as_str()))
+}
+
+/// Replaces `{name}`, `{version}` and `{path}` in the argument with the attributes of the
+/// crate, while `{{` and `}}` stand for literal braces
+fn substitute(arg: &str, name: &str, version: &str, path: &str) -> String {
+    let mut ret = String::new();
+    let mut rest = arg;
+
+    while let Some(i) = rest.find(['{', '}']) {
+        ret.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            ret.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+
+        let value = [("{name}", name), ("{version}", version), ("{path}", path)]
+            .into_iter()
+            .find(|(token, _)| rest.starts_with(token));
+
+        if let Some((token, value)) = value {
+            ret.push_str(value);
+            rest = &rest[token.len()..];
+        } else {
+            // Unknown tokens are kept as they are
+            ret.push_str(&rest[..1]);
+            rest = &rest[1..];
         }
     }
+
+    ret.push_str(rest);
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let failures = [101, 2, 130];
+
+        assert_eq!(ExitCode::First.code(&failures), 101);
+        assert_eq!(ExitCode::Max.code(&failures), 130);
+        assert_eq!(ExitCode::Custom(3).code(&failures), 3);
+        assert_eq!(ExitCode::Summary.code(&failures), 3);
+        assert_eq!(ExitCode::Summary.code(&[1; 200]), 125);
+    }
+
+    #[test]
+    fn test_exit_code_split() {
+        let words = |x: &str| x.split(' ').map(|x| x.to_string()).collect::<Vec<_>>();
+
+        let all = words("max cargo build");
+        assert_eq!(ExitCode::split(&all), Ok((ExitCode::Max, &all[1..])));
+
+        let all = words("custom 42 cargo build");
+        assert_eq!(ExitCode::split(&all), Ok((ExitCode::Custom(42), &all[2..])));
+
+        assert!(ExitCode::split(&words("custom 256 false")).is_err());
+        assert!(ExitCode::split(&words("custom")).is_err());
+        assert!(ExitCode::split(&words("custom=42 false")).is_err());
+    }
+
+    #[test]
+    fn test_split_post_args() {
+        let args = |x: &str| x.split(' ').map(|x| x.to_string()).collect::<Vec<_>>();
+
+        let all = args("cargo test {name} -- --nocapture {name}");
+        let (command, post) = split_post_args(&all);
+        assert_eq!(command, args("cargo test {name}"));
+        assert_eq!(post, args("-- --nocapture {name}"));
+
+        let all = args("cargo test");
+        assert_eq!(split_post_args(&all), (&all[..], &[][..]));
+    }
+
+    #[test]
+    fn test_takes_profile() {
+        let args = |x: &str| x.split(' ').map(|x| x.to_string()).collect::<Vec<_>>();
+
+        assert!(takes_profile(&args("cargo build")));
+        assert!(takes_profile(&args("cargo +nightly clippy")));
+        assert!(!takes_profile(&args("cargo metadata")));
+        assert!(!takes_profile(&args("cargo fmt")));
+        assert!(!takes_profile(&args("cargo")));
+        assert!(!takes_profile(&args("make build")));
+    }
+
+    #[test]
+    fn test_substitute() {
+        let sub = |arg| substitute(arg, "dep1", "0.1.0", "/ws/dep1");
+
+        assert_eq!(sub("myorg/{name}:{version}"), "myorg/dep1:0.1.0");
+        assert_eq!(sub("{path}/target"), "/ws/dep1/target");
+        assert_eq!(sub("{{name}}"), "{name}");
+        assert_eq!(sub("{{{name}}}"), "{dep1}");
+        assert_eq!(sub("{other} {"), "{other} {");
+        assert_eq!(sub("plain"), "plain");
+    }
 }
@@ -0,0 +1,107 @@
+use crate::utils::{get_pkgs, info, Error, Result};
+
+use cargo_metadata::Metadata;
+use clap::{ArgEnum, Parser};
+
+use std::fs;
+
+const GITHUB: &str = include_str!("templates/ci-github.yml");
+const GITLAB: &str = include_str!("templates/ci-gitlab.yml");
+
+#[derive(Debug, Clone, ArgEnum)]
+pub enum Provider {
+    Github,
+    Gitlab,
+}
+
+impl Provider {
+    fn template(&self) -> &'static str {
+        match self {
+            Provider::Github => GITHUB,
+            Provider::Gitlab => GITLAB,
+        }
+    }
+
+    fn path(&self) -> &'static str {
+        match self {
+            Provider::Github => ".github/workflows/ci.yml",
+            Provider::Gitlab => ".gitlab-ci.yml",
+        }
+    }
+}
+
+/// Generate CI configuration for the workspace
+#[derive(Debug, Parser)]
+pub struct GenerateCi {
+    /// The CI provider
+    #[clap(arg_enum)]
+    provider: Provider,
+
+    /// Overwrite the CI configuration if it already exists
+    #[clap(long)]
+    force: bool,
+}
+
+impl GenerateCi {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let path = metadata.workspace_root.join(self.provider.path());
+
+        if path.exists() && !self.force {
+            return Err(Error::PathAlreadyExists);
+        }
+
+        let members = get_pkgs(&metadata, true)?
+            .into_iter()
+            .map(|p| p.name)
+            .collect::<Vec<_>>();
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&path, render(self.provider.template(), &members))?;
+
+        info!("created", path);
+        Ok(())
+    }
+}
+
+/// Replaces the `{{members}}` line with a YAML list item for each member,
+/// using the indentation of the placeholder
+fn render(template: &str, members: &[String]) -> String {
+    let mut lines = vec![];
+
+    for line in template.lines() {
+        if line.trim() == "{{members}}" {
+            let indent = &line[..line.len() - line.trim_start().len()];
+
+            lines.extend(members.iter().map(|m| format!("{}- {}", indent, m)));
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+
+    format!("{}\n", lines.join("\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = "matrix:\n  crate:\n    {{members}}\nsteps:\n";
+        let members = vec!["dep1".to_string(), "top".to_string()];
+
+        assert_eq!(
+            render(template, &members),
+            "matrix:\n  crate:\n    - dep1\n    - top\nsteps:\n"
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        assert!(GITHUB.contains("{{members}}"));
+        assert!(GITLAB.contains("{{members}}"));
+    }
+}
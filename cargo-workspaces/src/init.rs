@@ -1,35 +1,48 @@
-use crate::utils::{Error, Result, git, info, warn};
+use crate::utils::{
+    CONFIG_TABLES, Error, INTERNAL_ERR, MemberToolchain, Resolver, Result, Transaction,
+    check_resolver, extend_members, get_dry_run, git, info, is_excluded, manifest_dirs,
+    metadata_command, relative_path, update_readme, warn,
+};
 
-use camino::Utf8PathBuf;
-use cargo_metadata::MetadataCommand;
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::{ArgEnum, Parser};
+use dialoguer::{MultiSelect, theme::ColorfulTheme};
 use dunce::canonicalize;
-use glob::glob;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use oclif::term::{TERM_ERR, TERM_OUT};
+use serde::Serialize;
 use toml_edit::{Array, Document, Formatted, Item, Table, Value};
 
 use std::{
-    collections::HashSet, env, fs::{self, read_to_string, write}, io::ErrorKind, path::PathBuf
+    collections::HashSet, env, fs::{self, read_to_string}, io::ErrorKind, path::{Path, PathBuf}
 };
 
-#[derive(Debug, Clone, Copy, ArgEnum)]
-pub enum Resolver {
-    #[clap(name = "1")]
-    V1,
-    #[clap(name = "2")]
-    V2,
-    #[clap(name = "3")]
-    V3,
-}
+// https://doc.rust-lang.org/cargo/reference/config.html
+const CARGO_CONFIG: &str = r#"# Cargo configuration for this workspace
+# See https://doc.rust-lang.org/cargo/reference/config.html for all the keys
 
-impl Resolver {
-    fn name(&self) -> &str {
-        match self {
-            Resolver::V1 => "1",
-            Resolver::V2 => "2",
-            Resolver::V3 => "3",
-        }
-    }
-}
+[build]
+# Directory for all generated artifacts, shared by all the workspace members
+target-dir = "target"
+
+[net]
+# Number of times to retry possibly spurious network errors
+retry = 3
+
+[alias]
+# Custom subcommands, e.g. `cargo ws-check` runs `cargo check --workspace`
+ws-check = "check --workspace --all-targets"
+"#;
+
+// https://editorconfig.org
+const EDITORCONFIG: &str = r#"root = true
+
+[*.rs]
+indent_style = space
+indent_size = 4
+trim_trailing_whitespace = true
+end_of_line = lf
+"#;
 
 /// Initializes a new cargo workspace
 #[derive(Debug, Parser)]
@@ -42,32 +55,100 @@ pub struct Init {
     /// [default: 3]
     #[clap(short, long, arg_enum)]
     pub resolver: Option<Resolver>,
+
+    /// Write a `.cargo/config.toml` with common workspace settings
+    #[clap(long)]
+    pub generate_cargo_config: bool,
+
+    /// Overwrite an existing `.cargo/config.toml` when generating it
+    #[clap(long, requires = "generate-cargo-config")]
+    pub force_cargo_config: bool,
+
+    /// Write an index of the members into the workspace README.md
+    #[clap(long)]
+    pub readme_index: bool,
+
+    /// Write an `.editorconfig` with the formatting conventions of Rust
+    #[clap(long)]
+    pub editorconfig: bool,
+
+    /// Do not write an `.editorconfig`, even if the init config asks for it
+    #[clap(long, conflicts_with = "editorconfig")]
+    pub no_editorconfig: bool,
+
+    /// Choose which of the discovered crates become members from a checklist
+    #[clap(long)]
+    pub interactive: bool,
+
+    /// Fail instead of warning when the resolver needs a newer cargo than the crates support
+    #[clap(long)]
+    pub strict: bool,
+
+    /// Require a virtual manifest, failing if the root `Cargo.toml` has a `[package]`
+    #[clap(long = "virtual")]
+    pub virtual_manifest: bool,
+
+    /// Print the initialized workspace and its members as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// The workspace as printed by `--json`
+#[derive(Serialize, Debug)]
+struct Initialized {
+    root: String,
+    resolver: Option<String>,
+    root_package: bool,
+    members: Vec<InitializedMember>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    dry_run: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct InitializedMember {
+    name: String,
+    path: String,
 }
 
 impl Init {
     pub fn run(&self) -> Result {
+        if self.interactive && !TERM_ERR.is_term() {
+            return Err(Error::NotInteractive("--interactive".to_string()));
+        }
+
+        let dry_run = get_dry_run();
+
         // Create directory if it doesn't exist
-        if !self.path.is_dir() {
+        let created = !self.path.is_dir();
+
+        if created && !dry_run {
             self.new_ws_repo()?
         }
 
         let cargo_toml = self.path.join("Cargo.toml");
 
-        // NOTE: Globset is not used here because it does not support file iterator
-        let pkgs = glob(&format!("{}/**/Cargo.toml", self.path.display()))?.filter_map(|e| e.ok());
+        let path = Utf8Path::from_path(&self.path)
+            .ok_or_else(|| Error::Init(format!("{} is not valid UTF-8", self.path.display())))?;
 
         let mut workspace_roots = HashSet::new();
 
-        for path in pkgs {
-            let metadata = MetadataCommand::default()
-                .manifest_path(path)
-                .exec()
-                .map_err(|e| Error::Init(e.to_string()))?;
+        // Nothing to discover in a directory which would be created
+        if !created {
+            for dir in manifest_dirs(path)? {
+                let metadata = metadata_command()
+                    .manifest_path(dir.join("Cargo.toml"))
+                    .exec()
+                    .map_err(|e| Error::Init(e.to_string()))?;
 
-            workspace_roots.insert(metadata.workspace_root);
+                workspace_roots.insert(metadata.workspace_root);
+            }
         }
 
-        let ws = canonicalize(&self.path)?;
+        let ws = if created {
+            env::current_dir()?.join(&self.path)
+        } else {
+            canonicalize(&self.path)?
+        };
 
         let mut document = match read_to_string(cargo_toml.as_path()) {
             Ok(manifest) => manifest.parse()?,
@@ -77,18 +158,41 @@ impl Init {
 
         let is_root_package = document.get("package").is_some();
 
-        let workspace = document
-            .entry("workspace")
-            .or_insert_with(|| Item::Table(Table::default()))
-            .as_table_mut()
-            .ok_or_else(|| {
-                Error::WorkspaceBadFormat(
-                    "no workspace table found in workspace Cargo.toml".to_string(),
-                )
-            })?;
+        // The root package is kept otherwise
+        if is_root_package && self.virtual_manifest {
+            return Err(Error::Init(format!(
+                "{} has a [package], which a virtual manifest cannot have",
+                cargo_toml.display()
+            )));
+        }
+
+        let package_resolver = take_package_resolver(&mut document);
+
+        let workspace = workspace_table(&mut document)?;
+        let config = InitConfig::read(workspace)?;
+
+        let generate_cargo_config = self.generate_cargo_config || config.generate_cargo_config;
+        let readme_index = self.readme_index || config.readme_index;
+        let editorconfig = !self.no_editorconfig && (self.editorconfig || config.editorconfig);
+
+        // workspace exclusions, honored when discovering members
+        let mut exclude = string_array(workspace.get("exclude"), "exclude")?;
+
+        for path in config.exclude {
+            if !exclude.contains(&path) {
+                workspace
+                    .entry("exclude")
+                    .or_insert_with(|| Item::Value(Value::Array(Array::new())))
+                    .as_array_mut()
+                    .expect(INTERNAL_ERR)
+                    .push(path.as_str());
+
+                exclude.push(path);
+            }
+        }
 
         // workspace members
-        {
+        let members = {
             let workspace_members = workspace
                 .entry("members")
                 .or_insert_with(|| Item::Value(Value::Array(Array::new())))
@@ -101,14 +205,43 @@ impl Init {
 
             if !workspace_members.is_empty() {
                 info!("already initialized", self.path.display());
+
+                // Globs have no manifest and are left out of the JSON
+                let members = workspace_members
+                    .iter()
+                    .filter_map(|x| x.as_str().map(|x| x.to_string()))
+                    .collect::<Vec<_>>();
+
+                if !dry_run {
+                    self.write_extras(
+                        &cargo_toml,
+                        generate_cargo_config,
+                        readme_index,
+                        editorconfig,
+                    )?;
+                }
+
+                if self.json {
+                    self.print_json(&ws, &document, is_root_package, &members)?;
+                }
+
                 return Ok(());
             }
 
-            let mut members: Vec<_> = workspace_roots
+            let ws = Utf8PathBuf::from_path_buf(ws.clone())
+                .map_err(|ws| Error::Init(format!("{} is not valid UTF-8", ws.display())))?;
+
+            let mut members = workspace_roots
                 .iter()
-                .filter_map(|m| m.strip_prefix(&ws).ok())
-                .map(|path| path.to_string())
-                .collect();
+                .map(|m| {
+                    relative_path(m, &ws)
+                        .map(|path| path.to_string())
+                        .ok_or_else(|| Error::PackageNotInWorkspace {
+                            id: m.to_string(),
+                            ws: ws.to_string(),
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?;
 
             // Remove the root Cargo.toml if not package
             if !is_root_package
@@ -118,27 +251,244 @@ impl Init {
 
             members.sort();
 
+            let found = members.clone();
+
+            if self.interactive {
+                let deselected;
+                (members, deselected) =
+                    choose_members(members, &exclude, config.members.as_ref(), &ws)?;
+
+                for path in deselected {
+                    workspace
+                        .entry("exclude")
+                        .or_insert_with(|| Item::Value(Value::Array(Array::new())))
+                        .as_array_mut()
+                        .expect(INTERNAL_ERR)
+                        .push(path);
+                }
+            } else {
+                members.retain(|m| deselect_reason(m, &exclude, config.members.as_ref()).is_none());
+            }
+
+            // A new directory is expected to be empty, but an existing one without any crate
+            // is most likely not the intended workspace root
+            if members.is_empty() && !created {
+                return Err(no_members_error(&self.path, &found));
+            }
+
             info!("crates", members.join(", "));
 
-            let max_member = members.len().saturating_sub(1);
+            let workspace_members = workspace
+                .get_mut("members")
+                .and_then(Item::as_array_mut)
+                .expect(INTERNAL_ERR);
+
+            extend_members(workspace_members, members.clone());
+            members
+        };
+
+        // workspace resolver, which is kept from the root package unless one is chosen
+        let inherited = package_resolver
+            .as_ref()
+            .and_then(|x| x.as_str())
+            .and_then(|x| Resolver::from_str(x, false).ok())
+            .filter(|_| !workspace.contains_key("resolver"));
+        let resolver = self.resolver.or(config.resolver).or(inherited);
+
+        if let Some(package_resolver) = &package_resolver {
+            let package_resolver = package_resolver.to_string();
+
+            if resolver.is_some() && resolver == inherited {
+                info!(
+                    "moved resolver",
+                    format!("{} from [package] to [workspace]", package_resolver.trim())
+                );
+            } else {
+                warn!(
+                    "removed resolver",
+                    format!(
+                        "{} from [package], the resolver belongs to [workspace]",
+                        package_resolver.trim()
+                    )
+                );
+            }
+        }
+
+        if !workspace.contains_key("resolver") {
+            let resolver = resolver.unwrap_or(Resolver::V3);
+
+            workspace.insert(
+                "resolver",
+                Item::Value(Value::String(Formatted::new(resolver.name().to_owned()))),
+            );
+
+            check_resolver(
+                resolver,
+                &self.member_toolchains(&members, &document)?,
+                self.strict,
+            )?;
+        }
+
+        if dry_run {
+            info!("would initialize", self.path.display());
+        } else {
+            let mut transaction = Transaction::new();
+            transaction.write(&cargo_toml, document.to_string());
+            transaction.commit()?;
+
+            self.write_extras(
+                &cargo_toml,
+                generate_cargo_config,
+                readme_index,
+                editorconfig,
+            )?;
+
+            info!("initialized", self.path.display());
+        }
+
+        if self.json {
+            self.print_json(&ws, &document, is_root_package, &members)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_extras(
+        &self,
+        cargo_toml: &Path,
+        generate_cargo_config: bool,
+        readme_index: bool,
+        editorconfig: bool,
+    ) -> Result {
+        if generate_cargo_config {
+            self.write_cargo_config()?;
+        }
+
+        if readme_index {
+            self.write_readme_index(cargo_toml)?;
+        }
+
+        if editorconfig {
+            self.write_editorconfig()?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the workspace for `--json`, with the names read from the member manifests
+    fn print_json(
+        &self,
+        ws: &Path,
+        document: &Document,
+        is_root_package: bool,
+        members: &[String],
+    ) -> Result {
+        let mut initialized = Initialized {
+            root: ws.display().to_string(),
+            resolver: document
+                .get("workspace")
+                .and_then(|x| x.get("resolver"))
+                .and_then(|x| x.as_str())
+                .map(|x| x.to_string()),
+            root_package: is_root_package,
+            members: vec![],
+            dry_run: get_dry_run(),
+        };
+
+        for member in members {
+            let manifest = if member.is_empty() {
+                document.clone()
+            } else {
+                match read_to_string(self.path.join(member).join("Cargo.toml")) {
+                    Ok(manifest) => manifest.parse()?,
+                    Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err.into()),
+                }
+            };
+
+            if let Some(name) = manifest
+                .get("package")
+                .and_then(|x| x.get("name"))
+                .and_then(|x| x.as_str())
+            {
+                initialized.members.push(InitializedMember {
+                    name: name.to_string(),
+                    path: if member.is_empty() {
+                        ".".to_string()
+                    } else {
+                        member.clone()
+                    },
+                });
+            }
+        }
+
+        TERM_OUT.write_line(&serde_json::to_string_pretty(&initialized)?)?;
+        Ok(())
+    }
+
+    /// Reads the manifests of the members, the root package being in the workspace manifest
+    fn member_toolchains(
+        &self,
+        members: &[String],
+        document: &Document,
+    ) -> Result<Vec<MemberToolchain>> {
+        let mut ret = vec![];
+
+        for member in members {
+            let manifest = if member.is_empty() {
+                document.clone()
+            } else {
+                read_to_string(self.path.join(member).join("Cargo.toml"))?.parse()?
+            };
+
+            ret.extend(MemberToolchain::read(&manifest, document));
+        }
+
+        Ok(ret)
+    }
+
+    fn write_cargo_config(&self) -> Result {
+        let config_path = self.path.join(".cargo").join("config.toml");
 
-            workspace_members.extend(members.into_iter().enumerate().map(|(i, val)| {
-                let prefix = "\n    ";
-                let suffix = if i == max_member { ",\n" } else { "" };
-                Value::String(Formatted::new(val)).decorated(prefix, suffix)
-            }));
+        if config_path.exists() && !self.force_cargo_config {
+            warn!(
+                "not overwriting existing cargo config (use --force-cargo-config)",
+                config_path.display()
+            );
+            return Ok(());
         }
 
-        // workspace resolver
-        if let Some(resolver) = self.resolver.or(Some(Resolver::V3)) {
-            workspace.entry("resolver").or_insert_with(|| {
-                Item::Value(Value::String(Formatted::new(resolver.name().to_owned())))
-            });
+        fs::create_dir_all(self.path.join(".cargo"))?;
+        fs::write(&config_path, CARGO_CONFIG)?;
+
+        info!("created", config_path.display());
+        Ok(())
+    }
+
+    fn write_editorconfig(&self) -> Result {
+        let path = self.path.join(".editorconfig");
+
+        if path.exists() {
+            warn!("not overwriting existing .editorconfig", path.display());
+            return Ok(());
         }
 
-        write(cargo_toml, document.to_string())?;
+        fs::write(&path, EDITORCONFIG)?;
+
+        info!("created", path.display());
+        Ok(())
+    }
+
+    fn write_readme_index(&self, cargo_toml: &Path) -> Result {
+        let metadata = metadata_command()
+            .manifest_path(cargo_toml)
+            .exec()
+            .map_err(|e| Error::Init(e.to_string()))?;
+
+        if update_readme(&metadata)? {
+            info!("updated", self.path.join("README.md").display());
+        }
 
-        info!("initialized", self.path.display());
         Ok(())
     }
 
@@ -168,11 +518,436 @@ impl Init {
         // Create .gitignore file with content "/target"
         let gitignore_path = new_dir.join(".gitignore");
 
-        Ok(if fs::write(&gitignore_path, "**/target").is_err() {
+        if fs::write(&gitignore_path, "**/target").is_err() {
             warn!(
                 "create or write .gitignore failed ",
                 &gitignore_path.display()
             );
+        }
+
+        Ok(())
+    }
+}
+
+/// Defaults for the flags, read from `[workspace.metadata.workspaces.init]` (or
+/// `cargo-workspaces.init`) so that an organization can ship them in its template `Cargo.toml`.
+#[derive(Debug, Default)]
+struct InitConfig {
+    resolver: Option<Resolver>,
+    /// Only the discovered crates matching these globs become members
+    members: Option<GlobSet>,
+    exclude: Vec<String>,
+    generate_cargo_config: bool,
+    readme_index: bool,
+    editorconfig: bool,
+}
+
+impl InitConfig {
+    fn read(workspace: &Table) -> Result<Self> {
+        // The first of the config tables present wins, like for the other options
+        let Some((table, config)) = CONFIG_TABLES
+            .iter()
+            .find_map(|table| Some((table, workspace.get("metadata")?.get(table)?)))
+        else {
+            return Ok(Self::default());
+        };
+
+        let Some(init) = config.get("init") else {
+            return Ok(Self::default());
+        };
+
+        let prefix = format!("workspace.metadata.{}.init", table);
+
+        let bad_format = |key: &str, what: &str| {
+            Error::WorkspaceBadFormat(format!("{}.{} {}", prefix, key, what))
+        };
+
+        let resolver = match init.get("resolver") {
+            Some(item) => {
+                let value = match (item.as_str(), item.as_integer()) {
+                    (Some(x), _) => x.to_string(),
+                    (_, Some(x)) => x.to_string(),
+                    _ => return Err(bad_format("resolver", "was not a string")),
+                };
+
+                Some(
+                    Resolver::from_str(&value, false)
+                        .map_err(|_| bad_format("resolver", "was not 1, 2 or 3"))?,
+                )
+            }
+            None => None,
+        };
+
+        let members = match init.get("members") {
+            Some(item) => {
+                let mut builder = GlobSetBuilder::new();
+
+                for pattern in string_array(Some(item), &format!("{}.members", prefix))? {
+                    builder.add(Glob::new(pattern.trim_start_matches("./"))?);
+                }
+
+                Some(builder.build()?)
+            }
+            None => None,
+        };
+
+        let flag = |key: &str| match init.get(key) {
+            Some(item) => item
+                .as_bool()
+                .ok_or_else(|| bad_format(key, "was not a boolean")),
+            None => Ok(false),
+        };
+
+        Ok(Self {
+            resolver,
+            members,
+            exclude: string_array(init.get("exclude"), &format!("{}.exclude", prefix))?,
+            generate_cargo_config: flag("generate_cargo_config")?,
+            readme_index: flag("readme_index")?,
+            editorconfig: flag("editorconfig")?,
+        })
+    }
+}
+
+fn string_array(item: Option<&Item>, name: &str) -> Result<Vec<String>> {
+    let item = match item {
+        Some(item) => item,
+        None => return Ok(vec![]),
+    };
+
+    item.as_array()
+        .ok_or_else(|| {
+            Error::WorkspaceBadFormat(format!("{} was not an array in workspace Cargo.toml", name))
+        })?
+        .iter()
+        .map(|x| {
+            x.as_str().map(|x| x.to_string()).ok_or_else(|| {
+                Error::WorkspaceBadFormat(format!(
+                    "{} items were not strings in workspace Cargo.toml",
+                    name
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Removes `package.resolver`, which would conflict with the one of the workspace
+fn take_package_resolver(document: &mut Document) -> Option<Item> {
+    let mut resolver = document
+        .get_mut("package")
+        .and_then(Item::as_table_like_mut)?
+        .remove("resolver")?;
+
+    if let Some(value) = resolver.as_value_mut() {
+        value.decor_mut().clear();
+    }
+
+    Some(resolver)
+}
+
+/// Returns the `workspace` table of the manifest, creating it if needed.
+///
+/// Other tools sometimes write it as an inline table (`workspace = { members = [] }`) or
+/// with dotted keys (`workspace.members = []`), both of which are turned into a standard
+/// `[workspace]` table so that the members can be listed one per line.
+fn workspace_table(document: &mut Document) -> Result<&mut Table> {
+    let root = document.as_table_mut();
+    let item = root
+        .entry("workspace")
+        .or_insert_with(|| Item::Table(Table::default()));
+
+    let converted = if item.is_inline_table() {
+        // Inline tables can't hold comments, so there is nothing to preserve but the keys
+        if let Ok(mut table) = std::mem::take(item).into_table() {
+            table.fmt();
+            *item = Item::Table(table);
+        }
+
+        true
+    } else if let Some(table) = item.as_table_mut()
+        && table.is_dotted()
+    {
+        table.set_dotted(false);
+        true
+    } else {
+        false
+    };
+
+    // The key is now printed as the table header, `[workspace]`
+    if converted && let Some(decor) = root.key_decor_mut("workspace") {
+        decor.clear();
+    }
+
+    root.get_mut("workspace")
+        .and_then(Item::as_table_mut)
+        .ok_or_else(|| {
+            Error::WorkspaceBadFormat(
+                "no workspace table found in workspace Cargo.toml".to_string(),
+            )
         })
+}
+
+/// Why the discovered crate is not a member by default
+fn deselect_reason(
+    member: &str,
+    exclude: &[String],
+    patterns: Option<&GlobSet>,
+) -> Option<&'static str> {
+    if is_excluded(member, exclude) {
+        Some("excluded")
+    } else if !member.is_empty() && patterns.is_some_and(|x| !x.is_match(member)) {
+        Some("not matching init.members")
+    } else {
+        None
+    }
+}
+
+/// Tells apart a directory without crates from one whose crates were all left out
+fn no_members_error(path: &Path, found: &[String]) -> Error {
+    if found.is_empty() {
+        return Error::NoCratesFound(path.display().to_string());
+    }
+
+    Error::AllCratesLeftOut {
+        path: path.display().to_string(),
+        crates: found.join(", "),
+    }
+}
+
+/// Whether the discovered crate is the root of a workspace of its own
+fn is_nested_workspace(member: &str, ws: &Utf8Path) -> bool {
+    !member.is_empty()
+        && read_to_string(ws.join(member).join("Cargo.toml"))
+            .ok()
+            .and_then(|x| x.parse::<Document>().ok())
+            .is_some_and(|x| x.contains_key("workspace"))
+}
+
+/// Lets the user choose the members among the discovered crates. Returns them along with
+/// the deselected crates which need to be added to `workspace.exclude`.
+fn choose_members(
+    candidates: Vec<String>,
+    exclude: &[String],
+    patterns: Option<&GlobSet>,
+    ws: &Utf8Path,
+) -> Result<(Vec<String>, Vec<String>)> {
+    let reasons = candidates
+        .iter()
+        .map(|m| {
+            deselect_reason(m, exclude, patterns)
+                .or_else(|| is_nested_workspace(m, ws).then_some("nested workspace"))
+        })
+        .collect::<Vec<_>>();
+
+    let items = candidates
+        .iter()
+        .zip(&reasons)
+        .map(|(m, reason)| {
+            let name = if m.is_empty() { "." } else { m.as_str() };
+
+            match reason {
+                Some(reason) => format!("{} ({})", name, reason),
+                None => name.to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let defaults = reasons.iter().map(|x| x.is_none()).collect::<Vec<_>>();
+
+    let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Workspace members")
+        .items(&items)
+        .defaults(&defaults)
+        .interact_on(&TERM_ERR)?;
+
+    let mut members = vec![];
+    let mut deselected = vec![];
+
+    for (i, member) in candidates.into_iter().enumerate() {
+        if chosen.contains(&i) {
+            members.push(member);
+        } else if !member.is_empty() && !is_excluded(&member, exclude) {
+            // The root package can't be excluded from its own workspace
+            deselected.push(member);
+        }
+    }
+
+    Ok((members, deselected))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn init_members(manifest: &str) -> String {
+        let mut document = manifest.parse::<Document>().unwrap();
+        let workspace = workspace_table(&mut document).unwrap();
+        let members = workspace
+            .entry("members")
+            .or_insert_with(|| Item::Value(Value::Array(Array::new())))
+            .as_array_mut()
+            .unwrap();
+
+        extend_members(members, vec!["a".to_string(), "b".to_string()]);
+        document.to_string()
+    }
+
+    const MEMBERS: &str = "members = [\n    \"a\",\n    \"b\",\n]\n";
+
+    #[test]
+    fn test_take_package_resolver() {
+        let mut document = "[package]\nname = \"root\"\nresolver = \"2\" # old\n"
+            .parse::<Document>()
+            .unwrap();
+
+        let resolver = take_package_resolver(&mut document).unwrap();
+        assert_eq!(resolver.as_str(), Some("2"));
+        assert_eq!(document.to_string(), "[package]\nname = \"root\"\n");
+
+        assert!(take_package_resolver(&mut document).is_none());
+        assert!(take_package_resolver(&mut Document::default()).is_none());
+    }
+
+    #[test]
+    fn test_workspace_table() {
+        let out = init_members("# The workspace\n[workspace]\nmembers = []\n");
+
+        assert_eq!(out, format!("# The workspace\n[workspace]\n{}", MEMBERS));
+    }
+
+    #[test]
+    fn test_workspace_table_dotted() {
+        let out = init_members("workspace.members = [ ]\n\n[package]\nname = \"root\"\n");
+
+        assert!(out.contains(&format!("[workspace]\n{}", MEMBERS)));
+        assert!(out.contains("[package]\nname = \"root\"\n"));
+        assert!(!out.contains("workspace.members"));
+    }
+
+    #[test]
+    fn test_workspace_table_inline() {
+        let out = init_members("workspace = { members = [], resolver = \"2\" }\n");
+
+        assert!(out.starts_with(&format!("[workspace]\n{}", MEMBERS)));
+        assert!(out.contains("resolver = \"2\"\n"));
+        assert!(!out.contains('{'));
+    }
+
+    #[test]
+    fn test_workspace_table_not_table() {
+        let mut document = "workspace = 1\n".parse::<Document>().unwrap();
+
+        assert!(workspace_table(&mut document).is_err());
+    }
+
+    fn init_config(manifest: &str) -> Result<InitConfig> {
+        let mut document = manifest.parse::<Document>().unwrap();
+        InitConfig::read(workspace_table(&mut document).unwrap())
+    }
+
+    #[test]
+    fn test_init_config() {
+        let config = init_config(
+            r#"
+[workspace]
+members = []
+
+[workspace.metadata.workspaces.init]
+resolver = "2"
+members = ["crates/*"]
+exclude = ["examples"]
+readme_index = true
+editorconfig = true
+"#,
+        )
+        .unwrap();
+
+        assert!(matches!(config.resolver, Some(Resolver::V2)));
+        assert!(config.members.unwrap().is_match("crates/a"));
+        assert_eq!(config.exclude, vec!["examples"]);
+        assert!(config.readme_index);
+        assert!(config.editorconfig);
+        assert!(!config.generate_cargo_config);
+    }
+
+    #[test]
+    fn test_init_config_missing() {
+        let config = init_config("[workspace]\nmembers = []\n").unwrap();
+
+        assert!(config.resolver.is_none());
+        assert!(config.members.is_none());
+        assert!(config.exclude.is_empty());
+    }
+
+    #[test]
+    fn test_init_config_bad_format() {
+        let err = init_config("[workspace.metadata.workspaces.init]\nresolver = \"4\"\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("init.resolver was not 1, 2 or 3"));
+
+        let err = init_config("[workspace.metadata.workspaces.init]\nreadme_index = 1\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("init.readme_index was not a boolean"));
+    }
+
+    #[test]
+    fn test_init_config_cargo_workspaces() {
+        let config =
+            init_config("[workspace.metadata.cargo-workspaces.init]\nexclude = [\"examples\"]\n")
+                .unwrap();
+        assert_eq!(config.exclude, vec!["examples"]);
+
+        let err = init_config("[workspace.metadata.cargo-workspaces.init]\nreadme_index = 1\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("workspace.metadata.cargo-workspaces.init.readme_index"));
+
+        // Like the other options, the `workspaces` table wins
+        let config = init_config(
+            r#"
+[workspace.metadata.workspaces]
+allow_branch = "main"
+
+[workspace.metadata.cargo-workspaces.init]
+exclude = ["examples"]
+"#,
+        )
+        .unwrap();
+        assert!(config.exclude.is_empty());
+    }
+
+    #[test]
+    fn test_no_members_error() {
+        assert_eq!(
+            no_members_error(Path::new("."), &[]).to_string(),
+            "did not find any crates in ."
+        );
+        assert_eq!(
+            no_members_error(Path::new("."), &["dep1".to_string(), "dep2".to_string()]).to_string(),
+            "all the crates found in . were left out (dep1, dep2)"
+        );
+    }
+
+    #[test]
+    fn test_deselect_reason() {
+        let exclude = vec!["examples".to_string()];
+        let patterns = GlobSetBuilder::new()
+            .add(Glob::new("crates/*").unwrap())
+            .build()
+            .unwrap();
+
+        assert_eq!(deselect_reason("crates/a", &exclude, Some(&patterns)), None);
+        assert_eq!(deselect_reason("", &exclude, Some(&patterns)), None);
+        assert_eq!(
+            deselect_reason("examples/a", &exclude, Some(&patterns)),
+            Some("excluded")
+        );
+        assert_eq!(
+            deselect_reason("tools/gen", &exclude, Some(&patterns)),
+            Some("not matching init.members")
+        );
+        assert_eq!(deselect_reason("tools/gen", &exclude, None), None);
     }
 }
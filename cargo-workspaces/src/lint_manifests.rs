@@ -0,0 +1,27 @@
+use crate::utils::{check_packaged_files, get_dry_run, info, Error, Result};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+
+/// Check the manifests of the publishable crates for readme and license files which
+/// would not be packaged
+#[derive(Debug, Parser)]
+pub struct LintManifests {
+    /// Copy the workspace license files into the crates or inherit the workspace readme
+    /// where possible
+    #[clap(long)]
+    fix: bool,
+}
+
+impl LintManifests {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let found = check_packaged_files(&metadata, self.fix && !get_dry_run())?;
+
+        if found.remaining > 0 {
+            return Err(Error::LintManifests(found.remaining));
+        }
+
+        info!("success", "ok");
+        Ok(())
+    }
+}
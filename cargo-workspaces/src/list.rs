@@ -1,6 +1,24 @@
-use crate::utils::{dag, get_pkgs, list, ListOpt, Result, INTERNAL_ERR};
-use cargo_metadata::Metadata;
-use clap::Parser;
+use crate::utils::{
+    create_http_client, dag, extend_members, get_dry_run, get_pkgs, git, info, is_excluded,
+    is_offline, list, list_with, manifest_dirs, relative_path, unknown_member, CratesIo,
+    DependencyGraph, Dependent, Error, ListOpt, NameStatus, Pkg, Result, WorkspaceContext,
+    INTERNAL_ERR,
+};
+use camino::Utf8PathBuf;
+use cargo_metadata::{Metadata, Package};
+use clap::{ArgEnum, Parser};
+use oclif::{console::style, term::TERM_OUT};
+use serde::Serialize;
+use serde_json::{to_string_pretty, Value};
+use toml_edit::{Array, Document, Item, Value as TomlValue};
+
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
 
 /// List crates in the project
 #[derive(Debug, Parser)]
@@ -8,23 +26,240 @@ use clap::Parser;
 pub struct List {
     #[clap(flatten)]
     list: ListOpt,
+
+    /// Show the value at the given dotted key path of each crate's `package.metadata`
+    #[clap(long, value_name = "KEY", conflicts_with = "long")]
+    metadata: Option<String>,
+
+    /// Show information as CSV with a header row
+    #[clap(long, conflicts_with_all = &["long", "json", "metadata"])]
+    csv: bool,
+
+    /// Show the crates which depend on the given crate, directly or transitively
+    #[clap(
+        long,
+        value_name = "CRATE",
+        conflicts_with_all = &["long", "metadata", "csv"]
+    )]
+    dependents_of: Option<String>,
+
+    /// Only show the crates which depend on the given crate directly
+    #[clap(long, requires = "dependents-of")]
+    direct_only: bool,
+
+    /// Show the features declared by each crate
+    #[clap(long, conflicts_with_all = &["long", "metadata", "csv", "dependents-of"])]
+    features: bool,
+
+    /// Columns to show in the CSV output
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "COLUMNS",
+        use_value_delimiter = true,
+        requires = "csv"
+    )]
+    csv_columns: Vec<Column>,
+
+    /// Show the git commit which last modified each crate
+    #[clap(long, conflicts_with_all = &["metadata", "csv", "dependents-of", "features"])]
+    last_commit: bool,
+
+    /// Show the crates which no other member depends on and which are not default members
+    #[clap(
+        long,
+        conflicts_with_all = &["metadata", "csv", "dependents-of", "features", "last-commit"]
+    )]
+    orphans: bool,
+
+    /// Show the crates on disk which are neither members, excluded nor in a nested workspace
+    #[clap(
+        long,
+        conflicts_with_all = &["long", "metadata", "csv", "dependents-of", "features", "last-commit", "orphans"]
+    )]
+    unreferenced: bool,
+
+    /// Add the unreferenced crates to `workspace.members`, or to `workspace.exclude` with `--fix=exclude`
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "ARRAY",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "members",
+        requires = "unreferenced"
+    )]
+    fix: Option<Fix>,
+
+    /// Check whether the name of each crate is available on crates.io, yours or taken by someone else
+    #[clap(
+        long,
+        requires = "owner",
+        conflicts_with_all = &["long", "metadata", "csv", "dependents-of", "features", "last-commit", "orphans", "unreferenced", "count"]
+    )]
+    check_names: bool,
+
+    /// A crates.io user or team (`github:org:team`) owning your crates, can be repeated
+    #[clap(
+        long,
+        value_name = "LOGIN",
+        multiple_occurrences = true,
+        requires = "check-names"
+    )]
+    owner: Vec<String>,
+
+    /// Only print the number of the crates which would be shown
+    #[clap(
+        long,
+        conflicts_with_all = &["long", "json", "metadata", "csv", "features", "last-commit"]
+    )]
+    count: bool,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Fix {
+    Members,
+    Exclude,
+}
+
+impl Fix {
+    fn key(&self) -> &'static str {
+        match self {
+            Fix::Members => "members",
+            Fix::Exclude => "exclude",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Column {
+    Name,
+    Version,
+    Path,
+    Edition,
+    Publish,
+    Description,
+    License,
+    Authors,
+}
+
+impl Column {
+    fn name(&self) -> &'static str {
+        match self {
+            Column::Name => "name",
+            Column::Version => "version",
+            Column::Path => "path",
+            Column::Edition => "edition",
+            Column::Publish => "publish",
+            Column::Description => "description",
+            Column::License => "license",
+            Column::Authors => "authors",
+        }
+    }
+
+    fn value(&self, pkg: &Pkg, package: &Package) -> String {
+        match self {
+            Column::Name => pkg.name.clone(),
+            Column::Version => pkg.version.to_string(),
+            Column::Path => pkg.path.display().to_string(),
+            Column::Edition => package.edition.clone(),
+            Column::Publish => match &package.publish {
+                None => "true".to_string(),
+                Some(registries) if registries.is_empty() => "false".to_string(),
+                Some(registries) => registries.join(";"),
+            },
+            Column::Description => package.description.clone().unwrap_or_default(),
+            Column::License => package.license.clone().unwrap_or_default(),
+            Column::Authors => package.authors.join(";"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Dependents<'a> {
+    name: &'a str,
+    dependents: &'a [Dependent],
+    /// Direct dependents of each crate, including the given one
+    adjacency: Map<&'a str, Vec<&'a str>>,
+}
+
+#[derive(Serialize)]
+struct Features<'a> {
+    name: &'a str,
+    /// Names of the features with what they enable
+    features: Map<&'a str, &'a [String]>,
+}
+
+#[derive(Serialize)]
+struct WithLastCommit<'a> {
+    #[serde(flatten)]
+    pkg: &'a Pkg,
+    last_commit_sha: Option<&'a str>,
+    last_commit_date: Option<&'a str>,
+    last_commit_author: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Orphan<'a> {
+    #[serde(flatten)]
+    pkg: &'a Pkg,
+    binary: bool,
+    publishable: bool,
+    last_commit_date: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct NameCheck<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    status: NameStatus,
+}
+
+#[derive(Serialize)]
+struct Unreferenced {
+    name: String,
+    /// Relative to the workspace root
+    path: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct LastCommit {
+    sha: String,
+    date: String,
+    author: String,
+}
+
+#[derive(Serialize)]
+struct MetadataValue<'a> {
+    name: &'a str,
+    value: Option<&'a Value>,
 }
 
 impl List {
-    pub fn run(self, metadata: Metadata) -> Result {
-        let pkgs = metadata
+    pub fn run(self, ctx: &WorkspaceContext) -> Result {
+        let metadata = &ctx.metadata;
+        let packages = metadata
             .packages
             .iter()
             .map(|x| (x.clone(), x.version.to_string()))
             .collect::<Vec<_>>();
 
-        let (names, visited) = dag(&pkgs);
+        let (names, visited) = dag(&packages)?;
+
+        if self.unreferenced {
+            return self.list_unreferenced(metadata);
+        }
+
+        if let Some(name) = &self.dependents_of {
+            return self.list_dependents(metadata, ctx.dependency_graph(), name);
+        }
 
         let pkg_ids = visited
             .into_iter()
             .map(|p| names.get(&p).expect(INTERNAL_ERR).0.id.clone());
 
-        let pkgs = get_pkgs(&metadata, self.list.all)?;
+        let pkgs = get_pkgs(metadata, self.list.all)?;
 
         let ordered_pkgs = pkg_ids
             .into_iter()
@@ -32,6 +267,667 @@ impl List {
             .cloned()
             .collect::<Vec<_>>();
 
+        if let Some(key) = &self.metadata {
+            return self.list_metadata(metadata, &ordered_pkgs, key);
+        }
+
+        if self.csv {
+            return self.list_csv(metadata, &ordered_pkgs);
+        }
+
+        if self.features {
+            return self.list_features(metadata, &ordered_pkgs);
+        }
+
+        if self.check_names {
+            return self.list_check_names(metadata, &ordered_pkgs);
+        }
+
+        if self.last_commit {
+            return self.list_last_commit(metadata, ordered_pkgs);
+        }
+
+        if self.orphans {
+            return self.list_orphans(metadata, ctx.dependency_graph(), ordered_pkgs);
+        }
+
+        if self.count {
+            return print_count(ordered_pkgs.len());
+        }
+
         list(&ordered_pkgs, self.list)
     }
+
+    fn list_metadata(&self, metadata: &Metadata, pkgs: &[Pkg], key: &str) -> Result {
+        let values = pkgs
+            .iter()
+            .map(|p| {
+                let pkg = metadata
+                    .packages
+                    .iter()
+                    .find(|x| x.id == p.id)
+                    .expect(INTERNAL_ERR);
+
+                MetadataValue {
+                    name: &p.name,
+                    value: metadata_value(&pkg.metadata, key),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if self.list.list.json {
+            return Ok(TERM_OUT.write_line(&to_string_pretty(&values)?)?);
+        }
+
+        let width = values.iter().map(|x| x.name.len()).max().unwrap_or(0);
+
+        for v in values {
+            let value = match v.value {
+                Some(Value::String(s)) => s.clone(),
+                Some(value) => value.to_string(),
+                None => "".to_string(),
+            };
+
+            TERM_OUT.write_line(format!("{:w$} {}", v.name, value, w = width).trim_end())?;
+        }
+
+        Ok(())
+    }
+
+    fn list_check_names(&self, metadata: &Metadata, pkgs: &[Pkg]) -> Result {
+        if is_offline() {
+            return Err(Error::Offline("check the names on crates.io".to_string()));
+        }
+
+        let mut crates_io = CratesIo::new(create_http_client(&metadata.workspace_root, &None)?);
+        let width = pkgs.iter().map(|x| x.name.len()).max().unwrap_or(0);
+        let mut values = vec![];
+
+        // Printed as they come since the requests are rate limited
+        for p in pkgs {
+            let status = crates_io.name_status(&p.name, &self.owner)?;
+
+            if self.list.list.json {
+                values.push(NameCheck {
+                    name: &p.name,
+                    status,
+                });
+                continue;
+            }
+
+            let status = match status {
+                NameStatus::Available => style("available").green().to_string(),
+                NameStatus::Yours => "yours".to_string(),
+                NameStatus::Conflict(owners) => {
+                    style(format!("conflicts (owned by {})", owners.join(", ")))
+                        .red()
+                        .to_string()
+                }
+            };
+
+            TERM_OUT.write_line(&format!("{:w$} {}", p.name, status, w = width))?;
+        }
+
+        if self.list.list.json {
+            TERM_OUT.write_line(&to_string_pretty(&values)?)?;
+        }
+
+        Ok(())
+    }
+
+    fn list_unreferenced(&self, metadata: &Metadata) -> Result {
+        let crates = unreferenced(metadata)?;
+
+        if self.count {
+            print_count(crates.len())?;
+        } else if self.list.list.json {
+            TERM_OUT.write_line(&to_string_pretty(&crates)?)?;
+        } else {
+            let width = crates.iter().map(|x| x.name.len()).max().unwrap_or(0);
+
+            for c in &crates {
+                TERM_OUT.write_line(&format!("{:w$} {}", c.name, c.path, w = width))?;
+            }
+        }
+
+        if let Some(fix) = self.fix
+            && !crates.is_empty()
+        {
+            let paths = crates.into_iter().map(|x| x.path).collect::<Vec<_>>();
+            let manifest = metadata.workspace_root.join("Cargo.toml");
+
+            if get_dry_run() {
+                info!(
+                    "would add",
+                    format!("{} to workspace.{}", paths.join(", "), fix.key())
+                );
+                return Ok(());
+            }
+
+            let mut document = fs::read_to_string(&manifest)?.parse::<Document>()?;
+            let array = document
+                .get_mut("workspace")
+                .and_then(|x| x.as_table_like_mut())
+                .and_then(|x| {
+                    x.entry(fix.key())
+                        .or_insert(Item::Value(TomlValue::Array(Array::new())))
+                        .as_array_mut()
+                })
+                .ok_or_else(|| {
+                    Error::WorkspaceBadFormat(format!(
+                        "{} was not an array in workspace Cargo.toml",
+                        fix.key()
+                    ))
+                })?;
+
+            extend_members(array, paths.clone());
+            fs::write(&manifest, document.to_string())?;
+
+            info!(
+                "added",
+                format!("{} to workspace.{}", paths.join(", "), fix.key())
+            );
+        }
+
+        Ok(())
+    }
+
+    fn list_last_commit(self, metadata: &Metadata, pkgs: Vec<Pkg>) -> Result {
+        let commits = last_commits(&metadata.workspace_root, &pkgs)?;
+
+        if self.list.list.json {
+            let values = pkgs
+                .iter()
+                .map(|p| {
+                    let commit = commits.get(&p.name);
+
+                    WithLastCommit {
+                        pkg: p,
+                        last_commit_sha: commit.map(|x| x.sha.as_str()),
+                        last_commit_date: commit.map(|x| x.date.as_str()),
+                        last_commit_author: commit.map(|x| x.author.as_str()),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            return Ok(TERM_OUT.write_line(&to_string_pretty(&values)?)?);
+        }
+
+        let extra = commits
+            .into_iter()
+            .map(|(name, c)| {
+                let sha = c.sha.get(..7).unwrap_or(&c.sha);
+                let day = c.date.split(' ').next().unwrap_or_default();
+
+                let row = format!("{} {} {}", style(sha).yellow(), day, c.author);
+
+                (name, row)
+            })
+            .collect();
+
+        list_with(&pkgs, self.list, &extra)
+    }
+
+    fn list_orphans(self, metadata: &Metadata, graph: &DependencyGraph, pkgs: Vec<Pkg>) -> Result {
+        let members = metadata
+            .packages
+            .iter()
+            .filter(|x| metadata.workspace_members.contains(&x.id))
+            .map(|x| x.name.as_str())
+            .collect::<Set<_>>();
+        let default_members = default_members(metadata)?;
+
+        let orphans = pkgs
+            .into_iter()
+            .filter(|p| !p.config.entry_point.unwrap_or(false))
+            .filter(|p| !default_members.contains(&p.path))
+            .filter(|p| {
+                graph
+                    .direct_dependents(&p.name)
+                    .all(|(name, _)| !members.contains(name))
+            })
+            .collect::<Vec<_>>();
+
+        if self.count {
+            return print_count(orphans.len());
+        }
+
+        let commits = last_commits(&metadata.workspace_root, &orphans)?;
+
+        let values = orphans
+            .iter()
+            .map(|p| {
+                let package = metadata
+                    .packages
+                    .iter()
+                    .find(|x| x.id == p.id)
+                    .expect(INTERNAL_ERR);
+
+                Orphan {
+                    pkg: p,
+                    binary: package
+                        .targets
+                        .iter()
+                        .any(|x| x.kind.iter().any(|k| k == "bin")),
+                    publishable: !p.private && !p.config.skip_publish.unwrap_or(false),
+                    last_commit_date: commits.get(&p.name).map(|x| x.date.as_str()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if self.list.list.json {
+            return Ok(TERM_OUT.write_line(&to_string_pretty(&values)?)?);
+        }
+
+        let extra = values
+            .iter()
+            .map(|o| {
+                let mut notes = vec![];
+
+                if o.binary {
+                    notes.push("binary".to_string());
+                }
+
+                if o.publishable {
+                    notes.push("publishable".to_string());
+                }
+
+                notes.push(match o.last_commit_date {
+                    Some(date) => {
+                        format!(
+                            "last touched {}",
+                            date.split(' ').next().unwrap_or_default()
+                        )
+                    }
+                    None => "never committed".to_string(),
+                });
+
+                (o.pkg.name.clone(), notes.join(", "))
+            })
+            .collect();
+
+        list_with(&orphans, self.list, &extra)
+    }
+
+    fn list_features(&self, metadata: &Metadata, pkgs: &[Pkg]) -> Result {
+        let values = pkgs
+            .iter()
+            .map(|p| {
+                let pkg = metadata
+                    .packages
+                    .iter()
+                    .find(|x| x.id == p.id)
+                    .expect(INTERNAL_ERR);
+
+                Features {
+                    name: &p.name,
+                    features: pkg
+                        .features
+                        .iter()
+                        .map(|(k, v)| (k.as_str(), v.as_slice()))
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if self.list.list.json {
+            return Ok(TERM_OUT.write_line(&to_string_pretty(&values)?)?);
+        }
+
+        let width = values.iter().map(|x| x.name.len()).max().unwrap_or(0);
+
+        for v in values {
+            let features = v.features.keys().copied().collect::<Vec<_>>().join(", ");
+
+            TERM_OUT.write_line(format!("{:w$} {}", v.name, features, w = width).trim_end())?;
+        }
+
+        Ok(())
+    }
+
+    fn list_dependents(&self, metadata: &Metadata, graph: &DependencyGraph, name: &str) -> Result {
+        let members = get_pkgs(metadata, true)?;
+
+        if !members.iter().any(|p| p.name == name) {
+            return Err(unknown_member(name, &members));
+        }
+
+        let shown = get_pkgs(metadata, self.list.all)?;
+        let dependents = graph
+            .dependents(name, self.direct_only)
+            .into_iter()
+            .filter(|d| shown.iter().any(|p| p.name == d.name))
+            .collect::<Vec<_>>();
+
+        if self.count {
+            return print_count(dependents.len());
+        }
+
+        if self.list.list.json {
+            let listed = dependents
+                .iter()
+                .map(|d| d.name.as_str())
+                .chain([name])
+                .collect::<Vec<_>>();
+
+            // Every edge between the listed crates, not only the shortest paths
+            let adjacency = listed
+                .iter()
+                .map(|&x| {
+                    let direct = graph
+                        .direct_dependents(x)
+                        .map(|(pkg, _)| pkg)
+                        .filter(|pkg| listed.contains(pkg))
+                        .collect();
+
+                    (x, direct)
+                })
+                .collect();
+
+            let value = Dependents {
+                name,
+                dependents: &dependents,
+                adjacency,
+            };
+
+            return Ok(TERM_OUT.write_line(&to_string_pretty(&value)?)?);
+        }
+
+        let width = dependents.iter().map(|x| x.name.len()).max().unwrap_or(0);
+
+        for d in dependents {
+            TERM_OUT.write_line(&format!(
+                "{:w$} {} (depth {}, via {})",
+                d.name,
+                d.kinds.join(", "),
+                d.depth,
+                d.via,
+                w = width
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    fn list_csv(&self, metadata: &Metadata, pkgs: &[Pkg]) -> Result {
+        let columns = if self.csv_columns.is_empty() {
+            Column::value_variants().to_vec()
+        } else {
+            self.csv_columns.clone()
+        };
+
+        let header = columns.iter().map(|c| c.name().to_string()).collect();
+        TERM_OUT.write_str(&csv_record(header))?;
+
+        for pkg in pkgs {
+            let package = metadata
+                .packages
+                .iter()
+                .find(|x| x.id == pkg.id)
+                .expect(INTERNAL_ERR);
+
+            let record = columns.iter().map(|c| c.value(pkg, package)).collect();
+            TERM_OUT.write_str(&csv_record(record))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Paths of the `workspace.default-members`, relative to the workspace root like [`Pkg::path`]
+fn default_members(metadata: &Metadata) -> Result<Vec<PathBuf>> {
+    let manifest =
+        fs::read_to_string(metadata.workspace_root.join("Cargo.toml"))?.parse::<Document>()?;
+
+    let paths = manifest
+        .get("workspace")
+        .and_then(|x| x.get("default-members"))
+        .and_then(|x| x.as_array())
+        .map(|x| x.iter().filter_map(|x| x.as_str()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    Ok(paths.into_iter().map(member_path).collect())
+}
+
+/// The crates found on disk which cargo does not consider as part of the workspace
+fn unreferenced(metadata: &Metadata) -> Result<Vec<Unreferenced>> {
+    let root = &metadata.workspace_root;
+    let manifest = fs::read_to_string(root.join("Cargo.toml"))?.parse::<Document>()?;
+
+    let exclude = manifest
+        .get("workspace")
+        .and_then(|x| x.get("exclude"))
+        .and_then(|x| x.as_array())
+        .map(|x| {
+            x.iter()
+                .filter_map(|x| x.as_str())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let members = metadata
+        .packages
+        .iter()
+        .filter(|x| metadata.workspace_members.contains(&x.id))
+        .filter_map(|x| x.manifest_path.parent())
+        .collect::<Set<_>>();
+
+    let mut nested: Vec<Utf8PathBuf> = vec![];
+    let mut ret = vec![];
+
+    // Sorted, so a nested workspace is seen before its members
+    for dir in manifest_dirs(root)? {
+        if members.contains(dir.as_path()) || nested.iter().any(|x| dir.starts_with(x)) {
+            continue;
+        }
+
+        let path = relative_path(&dir, root).expect(INTERNAL_ERR).to_string();
+
+        if path.is_empty() || is_excluded(&path, &exclude) {
+            continue;
+        }
+
+        let Ok(document) = fs::read_to_string(dir.join("Cargo.toml"))?.parse::<Document>() else {
+            continue;
+        };
+
+        if document.contains_key("workspace") {
+            nested.push(dir);
+            continue;
+        }
+
+        let Some(package) = document.get("package") else {
+            continue;
+        };
+
+        // Belongs to the workspace it points to
+        if package.get("workspace").is_some() {
+            continue;
+        }
+
+        if let Some(name) = package.get("name").and_then(|x| x.as_str()) {
+            ret.push(Unreferenced {
+                name: name.to_string(),
+                path,
+            });
+        }
+    }
+
+    Ok(ret)
+}
+
+fn print_count(count: usize) -> Result {
+    Ok(TERM_OUT.write_line(&count.to_string())?)
+}
+
+fn member_path(path: &str) -> PathBuf {
+    let path = path.trim_end_matches('/');
+    let path = path.strip_prefix("./").unwrap_or(path);
+
+    if path.is_empty() {
+        PathBuf::from(".")
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// Runs `git log` for each crate, a few at a time
+fn last_commits(root: &Utf8PathBuf, pkgs: &[Pkg]) -> Result<Map<String, LastCommit>> {
+    let next = AtomicUsize::new(0);
+    let workers = thread::available_parallelism()
+        .map_or(1, |x| x.get())
+        .min(pkgs.len());
+
+    let results = thread::scope(|s| {
+        let handles = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut ret = vec![];
+
+                    while let Some(pkg) = pkgs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        ret.push(last_commit(root, pkg).map(|x| (pkg.name.clone(), x)));
+                    }
+
+                    ret
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .flat_map(|x| x.join().expect(INTERNAL_ERR))
+            .collect::<Vec<_>>()
+    });
+
+    let mut commits = Map::new();
+
+    for result in results {
+        if let (name, Some(commit)) = result? {
+            commits.insert(name, commit);
+        }
+    }
+
+    Ok(commits)
+}
+
+fn last_commit(root: &Utf8PathBuf, pkg: &Pkg) -> Result<Option<LastCommit>> {
+    let path = pkg.path.to_string_lossy().to_string();
+
+    let (status, out, _) = git(root, &["log", "-1", "--format=%H %ai %an", "--", &path])?;
+
+    if !status.success() {
+        return Err(Error::NotGit);
+    }
+
+    Ok(parse_last_commit(&out))
+}
+
+/// Parses `%H %ai %an`, the date has spaces in it
+fn parse_last_commit(out: &str) -> Option<LastCommit> {
+    let mut parts = out.trim().splitn(5, ' ');
+    let sha = parts.next().filter(|x| !x.is_empty())?;
+    let date = [parts.next()?, parts.next()?, parts.next()?].join(" ");
+
+    Some(LastCommit {
+        sha: sha.to_string(),
+        date,
+        author: parts.next().unwrap_or_default().to_string(),
+    })
+}
+
+// https://www.rfc-editor.org/rfc/rfc4180
+fn csv_record(fields: Vec<String>) -> String {
+    let fields = fields
+        .into_iter()
+        .map(|f| {
+            if f.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", f.replace('"', "\"\""))
+            } else {
+                f
+            }
+        })
+        .collect::<Vec<_>>();
+
+    format!("{}\r\n", fields.join(","))
+}
+
+fn metadata_value<'a>(metadata: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(metadata, |value, k| value.get(k))
+        .filter(|x| !x.is_null())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_metadata_value() {
+        let metadata = json!({ "ourcompany": { "team": "infra", "tier": 2 } });
+
+        assert_eq!(
+            metadata_value(&metadata, "ourcompany.team"),
+            Some(&json!("infra"))
+        );
+        assert_eq!(
+            metadata_value(&metadata, "ourcompany.tier"),
+            Some(&json!(2))
+        );
+        assert_eq!(metadata_value(&metadata, "ourcompany.owner"), None);
+        assert_eq!(metadata_value(&Value::Null, "ourcompany"), None);
+    }
+
+    #[test]
+    fn test_parse_last_commit() {
+        assert_eq!(
+            parse_last_commit(
+                "0123456789abcdef0123456789abcdef01234567 2024-10-15 12:34:56 +0200 Jane Doe"
+            ),
+            Some(LastCommit {
+                sha: "0123456789abcdef0123456789abcdef01234567".to_string(),
+                date: "2024-10-15 12:34:56 +0200".to_string(),
+                author: "Jane Doe".to_string(),
+            })
+        );
+        assert_eq!(parse_last_commit(""), None);
+    }
+
+    #[test]
+    fn test_name_check_json() {
+        let check = NameCheck {
+            name: "serde",
+            status: NameStatus::Conflict(vec!["dtolnay".to_string()]),
+        };
+
+        assert_eq!(
+            serde_json::to_value(&check).unwrap(),
+            json!({ "name": "serde", "status": "conflict", "owners": ["dtolnay"] })
+        );
+    }
+
+    #[test]
+    fn test_member_path() {
+        assert_eq!(member_path("crates/a"), PathBuf::from("crates/a"));
+        assert_eq!(member_path("./crates/a/"), PathBuf::from("crates/a"));
+        assert_eq!(member_path("."), PathBuf::from("."));
+        assert_eq!(member_path("./"), PathBuf::from("."));
+    }
+
+    #[test]
+    fn test_csv_record() {
+        assert_eq!(
+            csv_record(vec!["dep1".to_string(), "0.1.0".to_string()]),
+            "dep1,0.1.0\r\n"
+        );
+        assert_eq!(
+            csv_record(vec![
+                "a, b".to_string(),
+                "say \"hi\"".to_string(),
+                "".to_string()
+            ]),
+            "\"a, b\",\"say \"\"hi\"\"\",\r\n"
+        );
+    }
 }
@@ -1,18 +1,29 @@
 mod changed;
+mod check_msrv;
+mod config;
 mod new;
 mod exec;
+mod generate_ci;
 mod init;
+mod lint_manifests;
 mod list;
+mod path;
 mod plan;
 mod publish;
+mod readme_index;
 mod rename;
+mod root;
+mod tag;
+mod verify_release;
 mod version;
 
 mod utils;
 
-use cargo_metadata::{CargoOpt, MetadataCommand};
+use cargo_metadata::CargoOpt;
 use clap::Parser;
-use oclif::finish;
+use oclif::{finish, CliError};
+
+use std::{path::Path, process::exit};
 
 #[derive(Debug, Parser)]
 enum Subcommand {
@@ -26,6 +37,15 @@ enum Subcommand {
     Rename(rename::Rename),
     Init(init::Init),
     Plan(plan::Plan),
+    Root(root::Root),
+    Path(path::Path),
+    GenerateCi(generate_ci::GenerateCi),
+    ReadmeIndex(readme_index::ReadmeIndex),
+    CheckMsrv(check_msrv::CheckMsrv),
+    VerifyRelease(verify_release::VerifyRelease),
+    Tag(tag::Tag),
+    Config(config::Config),
+    LintManifests(lint_manifests::LintManifests),
 }
 
 #[derive(Debug, Parser)]
@@ -39,9 +59,29 @@ struct Opt {
     #[clap(long, value_name = "path")]
     manifest_path: Option<String>,
 
-    /// Verbose mode
-    #[clap(short)]
-    verbose: bool,
+    /// Path to the workspace root directory
+    #[clap(long, value_name = "path", conflicts_with = "manifest-path")]
+    workspace_root: Option<String>,
+
+    /// Verbose mode, repeat to also show the commands being run (-vv) and their output (-vvv)
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Run without accessing the network (forwarded to cargo)
+    #[clap(long, env = "CARGO_NET_OFFLINE")]
+    offline: bool,
+
+    /// Require Cargo.lock and cache to be up to date (forwarded to cargo)
+    #[clap(long)]
+    frozen: bool,
+
+    /// Require Cargo.lock to be up to date (forwarded to cargo)
+    #[clap(long)]
+    locked: bool,
+
+    /// Don't perform any writes in init, version, publish, exec and tag
+    #[clap(long, env = "WORKSPACES_DRY_RUN")]
+    dry_run: bool,
 
     #[clap(subcommand)]
     subcommand: Subcommand,
@@ -55,51 +95,59 @@ enum Cargo {
 }
 
 fn main() {
-    set_handlers();
+    utils::set_interrupt_handler();
 
     let Cargo::Workspaces(opt) = Cargo::parse();
 
-    if opt.verbose {
-        utils::set_debug();
+    utils::set_verbosity(opt.verbose);
+
+    if opt.dry_run {
+        utils::set_dry_run();
     }
 
+    utils::set_cargo_flags(opt.offline, opt.frozen, opt.locked);
+
     let result = if let Subcommand::Init(ref init) = opt.subcommand {
         init.run()
     } else {
-        let mut cmd = MetadataCommand::new();
+        let mut cmd = utils::metadata_command();
 
         cmd.features(CargoOpt::AllFeatures);
         cmd.no_deps();
 
         if let Some(path) = opt.manifest_path {
             cmd.manifest_path(path);
+        } else if let Some(root) = opt.workspace_root {
+            cmd.manifest_path(Path::new(&root).join("Cargo.toml"));
         }
 
-        let metadata = cmd.exec().unwrap();
-
-        match opt.subcommand {
-            Subcommand::List(x) => x.run(metadata),
-            Subcommand::Changed(x) => x.run(metadata),
-            Subcommand::Version(x) => x.run(metadata),
-            Subcommand::Publish(x) => x.run(metadata),
-            Subcommand::Exec(x) => x.run(metadata),
-            Subcommand::New(x) => x.run(metadata),
-            Subcommand::Rename(x) => x.run(metadata),
-            Subcommand::Plan(x) => x.run(metadata),
+        utils::WorkspaceContext::load(&cmd).and_then(|ctx| match opt.subcommand {
+            Subcommand::List(x) => x.run(&ctx),
+            Subcommand::Changed(x) => x.run(&ctx),
+            Subcommand::Version(x) => x.run(&ctx),
+            Subcommand::Publish(x) => x.run(&ctx),
+            Subcommand::Exec(x) => x.run(&ctx),
+            Subcommand::New(x) => x.run(&ctx),
+            Subcommand::Rename(x) => x.run(ctx.metadata),
+            Subcommand::Plan(x) => x.run(ctx.metadata),
+            Subcommand::Root(x) => x.run(ctx.metadata),
+            Subcommand::Path(x) => x.run(ctx.metadata),
+            Subcommand::GenerateCi(x) => x.run(ctx.metadata),
+            Subcommand::ReadmeIndex(x) => x.run(ctx.metadata),
+            Subcommand::VerifyRelease(x) => x.run(ctx.metadata),
+            Subcommand::CheckMsrv(x) => x.run(ctx.metadata),
+            Subcommand::Tag(x) => x.run(&ctx),
+            Subcommand::Config(x) => x.run(ctx.metadata),
+            Subcommand::LintManifests(x) => x.run(ctx.metadata),
             _ => unreachable!(),
-        }
+        })
     };
 
-    finish(result)
-}
+    // Forward the exit code of the failed child commands instead of the generic one
+    if let Err(utils::Error::Bail(code)) = result {
+        let _ = utils::Error::Bail(code).print();
+        exit(code);
+    }
 
-fn set_handlers() {
-    // https://github.com/console-rs/dialoguer/issues/77
-    ctrlc::set_handler(move || {
-        let term = dialoguer::console::Term::stdout();
-        let _ = term.show_cursor();
-        // Mimic normal `Ctrl-C` exit code.
-        std::process::exit(130);
-    })
-    .expect("Error setting Ctrl-C handler");
+    finish(result)
 }
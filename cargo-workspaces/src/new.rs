@@ -1,4 +1,7 @@
-use crate::utils::{Error, INTERNAL_ERR, Result, cargo, change_versions, info};
+use crate::utils::{
+    Error, INTERNAL_ERR, Result, WorkspaceContext, cargo, change_versions, check_crate_name, info,
+    update_readme,
+};
 
 use camino::Utf8PathBuf;
 use cargo_metadata::Metadata;
@@ -8,7 +11,7 @@ use dunce::canonicalize;
 use glob::Pattern;
 use oclif::term::TERM_ERR;
 use semver::Version;
-use toml_edit::{Array, Document, Formatted, Item, Table, Value};
+use toml_edit::{Array, Document, Formatted, InlineTable, Item, Table, Value};
 
 use std::{
     collections::BTreeMap as Map,
@@ -47,17 +50,31 @@ pub struct New {
     #[clap(short, long)]
     lib: bool,
 
-    /// The name of the crate
+    /// The name of the crate [default: the last component of the path]
     #[clap(short, long)]
     name: Option<String>,
 
     /// Create members with interactive interface
     #[clap(short = 'y', long = "yes")]
     enable_interaction: bool,
+
+    /// Update the index of members in the workspace README.md
+    #[clap(long)]
+    readme_index: bool,
+
+    /// Add the crate to `[workspace.dependencies]` of the workspace manifest
+    #[clap(long)]
+    workspace_dep: bool,
+
+    /// The initial version of the crate [default: 0.0.0, or 0.1.0 with --workspace-dep]
+    #[clap(long, value_name = "VERSION")]
+    version: Option<Version>,
 }
 
 impl New {
-    pub fn run(&self, metadata: Metadata) -> Result {
+    pub fn run(&self, ctx: &WorkspaceContext) -> Result {
+        let metadata = &ctx.metadata;
+
         if canonicalize(&metadata.workspace_root)? != canonicalize(current_dir()?)? {
             return Err(Error::MustBeRunFromWorkspaceRoot);
         }
@@ -68,6 +85,17 @@ impl New {
             return Err(Error::PathAlreadyExists);
         }
 
+        // Asked for before anything is written so that it can be validated first
+        let name = match (&self.name, self.enable_interaction) {
+            (None, true) => Input::with_theme(&ColorfulTheme::default())
+                .default(self.default_name(&path))
+                .with_prompt("Name of the crate")
+                .interact_text_on(&TERM_ERR)?,
+            _ => self.default_name(&path),
+        };
+
+        validate_crate_name(metadata, &name)?;
+
         create_dir_all(&path)?;
 
         if !canonicalize(&path)?.starts_with(canonicalize(&metadata.workspace_root)?) {
@@ -79,28 +107,58 @@ impl New {
         let workspace_root = metadata.workspace_root.join("Cargo.toml");
         let backup = read_to_string(&workspace_root)?;
 
-        self.try_run(metadata).or_else(|e| {
+        self.try_run(metadata, &name).or_else(|e| {
             // cleanup itself may fail and we want to notify the user in that case
             // otherwise just propagate the error that caused the cleanup
             cleanup(&workspace_root, backup, &self.path).and(Err(e))
         })?;
 
+        if self.readme_index {
+            let metadata = ctx.resolved_metadata()?;
+
+            if update_readme(&metadata)? {
+                info!("updated", metadata.workspace_root.join("README.md"));
+            }
+        }
+
         info!("success", "ok");
 
         Ok(())
     }
 
-    fn try_run(&self, metadata: Metadata) -> Result {
-        self.add_workspace_toml_entry(&metadata)?;
+    fn try_run(&self, metadata: &Metadata, name: &str) -> Result {
+        self.add_workspace_toml_entry(metadata)?;
+
         if !self.enable_interaction {
-            self.create_default_new_workspace_member(&metadata)?;
+            self.create_default_new_workspace_member(metadata, name)?;
         } else {
-            self.create_new_workspace_member(&metadata)?;
+            self.create_new_workspace_member(metadata, name)?;
+        }
+
+        if self.workspace_dep {
+            self.add_workspace_dependency_entry(metadata, name)?;
         }
 
         Ok(())
     }
 
+    /// The given version, crates which other members can depend on right away need a
+    /// version which is not `0.0.0`
+    fn initial_version(&self) -> Version {
+        self.version.clone().unwrap_or_else(|| {
+            let version = if self.workspace_dep { "0.1.0" } else { "0.0.0" };
+            Version::parse(version).expect(INTERNAL_ERR)
+        })
+    }
+
+    /// The given name, or the last component of the path
+    fn default_name(&self, path: &Utf8PathBuf) -> String {
+        self.name
+            .clone()
+            .or_else(|| path.file_name().map(|s| s.to_owned()))
+            .unwrap_or_default()
+    }
+
     // adds info about new member to workspace's Cargo.toml file
     //
     // # Fails if
@@ -119,12 +177,33 @@ impl New {
         Ok(())
     }
 
-    fn create_default_new_workspace_member(&self, metadata: &Metadata) -> Result {
+    // adds the new member to the workspace's `[workspace.dependencies]`
+    //
+    // # Fails if
+    //
+    // - toml files are generally corrupted
+    // - a dependency with the same name already exists
+    fn add_workspace_dependency_entry(&self, metadata: &Metadata, name: &str) -> Result {
+        let workspace_root = metadata.workspace_root.join("Cargo.toml");
+        let mut workspace_manifest = read_to_string(&workspace_root)?.parse::<Document>()?;
+
+        add_workspace_dependency(
+            &mut workspace_manifest,
+            name,
+            &self.path,
+            &self.initial_version(),
+        )?;
+
+        write(workspace_root, workspace_manifest.to_string())?;
+
+        Ok(())
+    }
+
+    fn create_default_new_workspace_member(&self, metadata: &Metadata, name: &str) -> Result {
         let path = metadata.workspace_root.join(&self.path);
 
-        let name = path.file_name().map(|s| s.to_owned()).unwrap_or_default();
         let template = if self.lib { "--lib" } else { "--bin" };
-        let args = ["new", template, path.as_str()];
+        let args = ["new", template, "--name", name, path.as_str()];
 
         let (stdout, stderr) = cargo(&metadata.workspace_root, &args, &[])?;
 
@@ -142,14 +221,11 @@ impl New {
         let manifest = path.join("Cargo.toml");
         let mut versions = Map::new();
 
-        versions.insert(
-            name.to_owned(),
-            Version::parse("0.0.0").expect(INTERNAL_ERR),
-        );
+        versions.insert(name.to_owned(), self.initial_version());
 
         write(
             &manifest,
-            change_versions(read_to_string(&manifest)?, &name, &versions, false)?,
+            change_versions(read_to_string(&manifest)?, name, &versions, false)?,
         )?;
 
         Ok(())
@@ -162,18 +238,10 @@ impl New {
     // - conflicting options were chosen
     // - `cargo new` fails
     // - another package with the same name was already created somewhere
-    fn create_new_workspace_member(&self, metadata: &Metadata) -> Result {
+    fn create_new_workspace_member(&self, metadata: &Metadata, name: &str) -> Result {
         let theme = ColorfulTheme::default();
         let path = metadata.workspace_root.join(&self.path);
 
-        let name = match self.name.as_ref() {
-            Some(n) => n.to_owned(),
-            None => Input::with_theme(&theme)
-                .default(path.file_name().map(|s| s.to_owned()).unwrap_or_default())
-                .with_prompt("Name of the crate")
-                .interact_text_on(&TERM_ERR)?,
-        };
-
         let template = if self.lib {
             0
         } else if self.bin {
@@ -205,7 +273,7 @@ impl New {
                 .interact_on(&TERM_ERR)?,
         };
 
-        let mut args = vec!["new", "--name", &name, "--edition", editions[edition]];
+        let mut args = vec!["new", "--name", name, "--edition", editions[edition]];
 
         if template == 0 {
             args.push("--lib");
@@ -231,20 +299,44 @@ impl New {
         let manifest = path.join("Cargo.toml");
         let mut versions = Map::new();
 
-        versions.insert(
-            name.to_owned(),
-            Version::parse("0.0.0").expect(INTERNAL_ERR),
-        );
+        versions.insert(name.to_owned(), self.initial_version());
 
         write(
             &manifest,
-            change_versions(read_to_string(&manifest)?, &name, &versions, false)?,
+            change_versions(read_to_string(&manifest)?, name, &versions, false)?,
         )?;
 
         Ok(())
     }
 }
 
+// checks the crate name against the same rules as `cargo new`
+//
+// # Fails if
+//
+// - name is empty
+// - name does not start with a letter or underscore
+// - name contains characters other than alphanumerics, `-` and `_`
+// - name is a rust keyword
+// - name is already used by a workspace member
+fn validate_crate_name(metadata: &Metadata, name: &str) -> Result {
+    check_crate_name(name).map_err(|reason| Error::InvalidCrateName {
+        name: name.to_owned(),
+        reason: reason.to_owned(),
+    })?;
+
+    if metadata
+        .workspace_members
+        .iter()
+        .filter_map(|id| metadata.packages.iter().find(|x| x.id == *id))
+        .any(|x| x.name == name)
+    {
+        return Err(Error::DuplicatePackageName);
+    }
+
+    Ok(())
+}
+
 fn cleanup(workspace_root: &Utf8PathBuf, backup: String, path: &str) -> Result {
     // reset manifest doc
     remove_file(workspace_root)?;
@@ -305,6 +397,35 @@ fn add_workspace_member(
     Ok(())
 }
 
+fn add_workspace_dependency(
+    manifest: &mut Document,
+    name: &str,
+    path: &str,
+    version: &Version,
+) -> Result {
+    let dependencies = manifest["workspace"]
+        .as_table_mut()
+        .ok_or_else(|| Error::WorkspaceBadFormat("workspace manifest item must be a table".into()))?
+        .entry("dependencies")
+        .or_insert(Item::Table(Table::new()))
+        .as_table_like_mut()
+        .ok_or_else(|| {
+            Error::WorkspaceBadFormat("workspace.dependencies manifest item must be a table".into())
+        })?;
+
+    if dependencies.contains_key(name) {
+        return Err(Error::InWorkspaceDependencies(name.to_owned()));
+    }
+
+    let mut dependency = InlineTable::new();
+    dependency.insert("path", path.replace('\\', "/").trim_end_matches('/').into());
+    dependency.insert("version", version.to_string().into());
+
+    dependencies.insert(name, Item::Value(Value::InlineTable(dependency)));
+
+    Ok(())
+}
+
 fn exists_in_glob_list<'a>(
     metadata: &'a Metadata,
     array_item: &'a Item,
@@ -332,3 +453,28 @@ fn exists_in_glob_list<'a>(
 
     Ok(None)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add_workspace_dependency() {
+        let mut manifest = "[workspace]\nmembers = [\"crates/foo\"]\n"
+            .parse::<Document>()
+            .unwrap();
+        let version = Version::parse("0.1.0").unwrap();
+
+        add_workspace_dependency(&mut manifest, "foo", "crates/foo/", &version).unwrap();
+
+        assert_eq!(
+            manifest.to_string(),
+            "[workspace]\nmembers = [\"crates/foo\"]\n\n[workspace.dependencies]\nfoo = { path = \"crates/foo\", version = \"0.1.0\" }\n"
+        );
+
+        assert!(matches!(
+            add_workspace_dependency(&mut manifest, "foo", "crates/foo", &version),
+            Err(Error::InWorkspaceDependencies(_))
+        ));
+    }
+}
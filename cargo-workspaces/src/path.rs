@@ -0,0 +1,36 @@
+use crate::utils::{get_pkgs, unknown_member, Result};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+use oclif::term::TERM_OUT;
+
+/// Print the path to the directory of a crate
+#[derive(Debug, Parser)]
+pub struct Path {
+    /// Name of the crate
+    #[clap(value_name = "CRATE")]
+    name: String,
+
+    /// Print the path relative to the workspace root
+    #[clap(long)]
+    relative: bool,
+}
+
+impl Path {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let pkgs = get_pkgs(&metadata, true)?;
+
+        let Some(pkg) = pkgs.iter().find(|p| p.name == self.name) else {
+            return Err(unknown_member(&self.name, &pkgs));
+        };
+
+        let path = if !self.relative {
+            pkg.location.display().to_string()
+        } else {
+            pkg.path.display().to_string()
+        };
+
+        TERM_OUT.write_line(&path)?;
+        Ok(())
+    }
+}
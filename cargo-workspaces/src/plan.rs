@@ -1,6 +1,6 @@
 use crate::utils::{
-    create_http_client, dag, filter_private, get_pkgs, is_published, list, package_registry,
-    ListOpt, ListPublicOpt, RegistryOpt, Result, INTERNAL_ERR,
+    create_http_client, dag, filter_private, filter_skip_publish, get_pkgs, is_published, list,
+    package_registry, ListOpt, ListPublicOpt, RegistryOpt, Result, INTERNAL_ERR,
 };
 
 use cargo_metadata::Metadata;
@@ -28,11 +28,11 @@ impl Plan {
             .map(|x| (x.clone(), x.version.to_string()))
             .collect::<Vec<_>>();
 
-        let (names, visited) = dag(&pkgs);
+        let (names, visited) = dag(&pkgs)?;
 
         let http_client = create_http_client(&metadata.workspace_root, &self.registry.token)?;
 
-        let pkg_ids = filter_private(visited, &pkgs)
+        let pkg_ids = filter_skip_publish(filter_private(visited, &pkgs), &pkgs)?
             .into_iter()
             .map(|p| {
                 let (pkg, version) = names.get(&p).expect(INTERNAL_ERR);
@@ -1,14 +1,30 @@
-use std::{thread, time::Duration};
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    env, fs,
+    path::PathBuf,
+    process::Command,
+    thread,
+    time::Duration,
+};
 
 use crate::utils::{
-    basic_checks, cargo, create_http_client, dag, filter_private, info, is_published,
-    package_registry, should_remove_dev_deps, warn, DevDependencyRemover, Error, RegistryOpt,
-    Result, VersionOpt, INTERNAL_ERR,
+    available_versions, basic_checks, cargo, cargo_status, cargo_timeout, change_versions_with,
+    check_packaged_files, crate_checksum, create_http_client, dag_with, filter_private,
+    filter_skip_publish, get_dry_run, git, info, is_offline, is_private, is_published, log_command,
+    missing_metadata, package_registry, publish_registry, read_config, record_completed,
+    release_manifest_path, should_remove_dev_deps, stop_if_interrupted, unpublished_members, warn,
+    ChildGuard, DevDependencyRemover, Error, ForgeRelease, IgnoredDependency, PackageConfig,
+    RegistryOpt, ReleaseManifest, ReleasedCrate, Result, Sbom, SbomFormat, Timeout, TrustedToken,
+    VersionOpt, WorkspaceConfig, WorkspaceContext, INTERNAL_ERR,
 };
 
-use camino::Utf8PathBuf;
-use cargo_metadata::Metadata;
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{DependencyKind, Metadata, Package};
 use clap::Parser;
+use indexmap::IndexSet;
+use semver::Version;
+use tame_index::external::reqwest::blocking::Client;
+use toml_edit::Document;
 
 /// Publish crates in the project
 #[derive(Debug, Parser)]
@@ -38,6 +54,10 @@ pub struct Publish {
     #[clap(long)]
     no_remove_dev_deps: bool,
 
+    /// Skip private crates and list them (default behaviour)
+    #[clap(long)]
+    no_private: bool,
+
     /// Runs in dry-run mode
     #[clap(long)]
     dry_run: bool,
@@ -49,13 +69,146 @@ pub struct Publish {
     #[clap(long)]
     locked: bool,
 
+    /// Override a cargo configuration value for `cargo publish`, like
+    /// `net.git-fetch-with-cli=true` (can be repeated)
+    #[clap(
+        long,
+        value_name = "KEY=VALUE",
+        validator = validate_config,
+        multiple_occurrences(true)
+    )]
+    config: Vec<String>,
+
     /// Number of seconds to wait between publish attempts
     #[clap(long, value_name = "SECONDS")]
     publish_interval: Option<u64>,
+
+    /// Publish even when crates are missing metadata required by crates.io
+    #[clap(long)]
+    allow_incomplete_metadata: bool,
+
+    /// Publish the crates of a dependency cycle anyway, in the given order and without
+    /// verification, like `a,b` (can be repeated)
+    #[clap(long, value_name = "CRATES", multiple_occurrences(true))]
+    allow_cycle: Vec<String>,
+
+    /// Bump the patch version of crates whose new version is already published, which
+    /// doesn't apply to `--publish-as-is` where it is taken as published by an earlier run
+    #[clap(long)]
+    bump_patch_on_conflict: bool,
+
+    /// Exchange the OIDC token of the CI job for a short-lived crates.io token
+    #[clap(long, conflicts_with_all = &["token", "registry"])]
+    trusted_publishing: bool,
+
+    /// Create a GitHub or GitLab release for the tag of every published crate, using
+    /// `GITHUB_TOKEN` or `GITLAB_TOKEN`
+    #[clap(long, alias = "github-release")]
+    forge_release: bool,
+
+    /// Create the releases as drafts
+    #[clap(long, alias = "github-release-draft", requires = "forge-release")]
+    forge_release_draft: bool,
+
+    /// Where to record the published crates with their checksums [default: release-manifest.json]
+    #[clap(long, value_name = "PATH", parse(from_os_str))]
+    release_manifest: Option<PathBuf>,
+
+    /// Write a bill of materials with the resolved dependencies of every published crate
+    /// into the directory
+    #[clap(long, value_name = "DIR", parse(from_os_str))]
+    sbom: Option<PathBuf>,
+
+    /// The format of the bills of materials
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "FORMAT",
+        default_value = "cyclonedx",
+        requires = "sbom"
+    )]
+    sbom_format: SbomFormat,
+
+    /// Run the shell command in the directory of each crate after it is published, with
+    /// `WORKSPACES_CRATE_NAME`, `WORKSPACES_CRATE_VERSION` and `WORKSPACES_REGISTRY` set
+    #[clap(long, value_name = "CMD")]
+    post_publish_hook: Option<String>,
+
+    /// Fail the release when the post-publish hook fails instead of warning
+    #[clap(long, requires = "post-publish-hook")]
+    strict_hooks: bool,
+
+    /// Kill `cargo publish` for a crate if it runs longer than the duration (like 90s or 10m)
+    #[clap(long, value_name = "DURATION")]
+    timeout: Option<Timeout>,
+
+    /// Build the documentation of the crates with warnings denied before publishing any of them
+    #[clap(long)]
+    verify_documentation: bool,
+
+    /// Fix the readme and license files which would not be packaged, then stop
+    #[clap(long)]
+    fix_packaged_files: bool,
+}
+
+/// Accepts what cargo accepts for `--config KEY=VALUE`, a single dotted key set to a TOML value
+fn validate_config(config: &str) -> std::result::Result<(), String> {
+    let expected = || format!("expected KEY=VALUE with a TOML value, got {}", config);
+
+    // Table headers and multiple keys would parse too
+    if config.contains('\n') {
+        return Err(expected());
+    }
+
+    let doc = config.parse::<Document>().map_err(|_| expected())?;
+    let mut table = doc.as_table();
+
+    loop {
+        let mut entries = table.iter();
+
+        match (entries.next(), entries.next()) {
+            (Some((_, item)), None) => match item.as_table() {
+                Some(inner) => table = inner,
+                None => return Ok(()),
+            },
+            _ => return Err(expected()),
+        }
+    }
 }
 
 impl Publish {
-    pub fn run(mut self, metadata: Metadata) -> Result {
+    pub fn run(mut self, ctx: &WorkspaceContext) -> Result {
+        let metadata = &ctx.metadata;
+
+        // The global dry run takes precedence
+        if get_dry_run() {
+            self.dry_run = true;
+        }
+
+        if is_offline() && !self.dry_run {
+            return Err(Error::Offline("publish".to_string()));
+        }
+
+        if !self.allow_incomplete_metadata {
+            self.check_metadata(metadata)?;
+        }
+
+        let packaged_files =
+            check_packaged_files(metadata, self.fix_packaged_files && !self.dry_run)?;
+
+        if packaged_files.fixed > 0 {
+            warn!(
+                "fixed files are not committed",
+                "commit them before publishing"
+            );
+            return Ok(());
+        }
+
+        // Before versioning, which would leave a pushed release behind
+        if self.verify_documentation {
+            self.verify_publishable_documentation(metadata)?;
+        }
+
         if self.dry_run {
             warn!(
                 "Dry run doesn't check that all dependencies have been published.",
@@ -68,9 +221,14 @@ impl Publish {
             }
         }
 
+        let new_versions = if self.publish_as_is {
+            Map::new()
+        } else {
+            self.version.do_versioning(ctx)?
+        };
+
         let pkgs = if !self.publish_as_is {
-            self.version
-                .do_versioning(&metadata)?
+            new_versions
                 .iter()
                 .map(|x| {
                     (
@@ -92,42 +250,157 @@ impl Publish {
                 .collect()
         };
 
-        let (names, visited) = dag(&pkgs);
+        // Resolved with the versions and the lockfile the crates are ordered and verified with
+        let sbom = match &self.sbom {
+            Some(_) if !self.dry_run => Some(Sbom::resolve(ctx.resolved_metadata()?)?),
+            _ => None,
+        };
+
+        let allowed = self
+            .allow_cycle
+            .iter()
+            .map(|x| x.split(',').map(|x| x.trim().to_string()).collect())
+            .collect::<Vec<_>>();
+        let ((names, visited), ignored) = dag_with(&pkgs, &allowed)?;
+
+        for x in &ignored {
+            let reason = if x.optional {
+                "optional"
+            } else {
+                "allowed cycle"
+            };
+
+            warn!(
+                "ignoring dependency",
+                format!("{} -> {} to break a cycle ({})", x.name, x.dep, reason)
+            );
+        }
+
+        // Published on a best-effort basis, each one needs the others to be verified. Even
+        // an optional dependency is resolved when verifying.
+        let cycle_members = ignored
+            .iter()
+            .flat_map(|x| [x.name.clone(), x.dep.clone()])
+            .collect::<Set<_>>();
+
+        if self.no_private {
+            for p in &visited {
+                let (pkg, _) = names.get(p).expect(INTERNAL_ERR);
+
+                if is_private(pkg) {
+                    info!(
+                        "skipping private crate",
+                        format!("{} (publish = false)", pkg.name)
+                    );
+                }
+            }
+        }
 
         // Filter out private packages
         let visited = filter_private(visited, &pkgs);
+        let visited = filter_skip_publish(visited, &pkgs)?;
 
         let http_client = create_http_client(&metadata.workspace_root, &self.registry.token)?;
 
+        let mut trusted_token = if self.trusted_publishing && !self.dry_run {
+            Some(TrustedToken::new(&http_client)?)
+        } else {
+            None
+        };
+
+        let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
+
+        // Checked before anything is published
+        let forge_release = if self.forge_release {
+            ForgeRelease::new(
+                &create_http_client(&metadata.workspace_root, &None)?,
+                &metadata.workspace_root,
+                self.version.git.remote(),
+                self.forge_release_draft,
+                &config,
+                self.dry_run,
+            )?
+        } else {
+            None
+        };
+        let mut released = vec![];
+        let mut released_tags = Set::new();
+        // The versions of the dependencies on the registry, fetched once per crate
+        let mut available = Map::new();
+
+        // The versioning rewrote the requirements on the workspace crates
+        let requirements = if self.publish_as_is {
+            None
+        } else {
+            Some(self.version.versioned_metadata(metadata, &new_versions))
+        };
+
         for p in &visited {
+            stop_if_interrupted();
+
             let (pkg, version) = names.get(p).expect(INTERNAL_ERR);
             let name = pkg.name.clone();
+            let mut version = version.to_string();
+
+            let in_cycle = cycle_members.contains(&name);
 
             if self.dry_run {
                 info!("checking", name);
 
-                if !self.no_verify && !self.build(&metadata.workspace_root, p)? {
+                if !self.no_verify && !in_cycle && !self.build(&metadata.workspace_root, p)? {
                     warn!("build failed", "");
                 }
 
                 basic_checks(pkg)?;
             }
 
+            let token = match trusted_token.as_mut() {
+                Some(trusted_token) => Some(trusted_token.token()?.to_string()),
+                None => None,
+            };
+
             let mut args = vec!["publish"];
+            let mut env = vec![];
+
+            let mut name_ver = format!("{} v{}", name, version);
+            let index_url = package_registry(metadata, self.registry.registry.as_ref(), pkg)?;
+
+            if is_offline() {
+                warn!("skipping registry check in offline mode", name_ver);
+            } else if is_published(&http_client, index_url, &name, &version)? {
+                // With `--publish-as-is`, the version comes from an interrupted run rather
+                // than from versioning, so it was published by us
+                if !self.bump_patch_on_conflict || self.publish_as_is {
+                    info!("already published", name_ver);
+                    continue;
+                }
 
-            let name_ver = format!("{} v{}", name, version);
-            let index_url = package_registry(&metadata, self.registry.registry.as_ref(), pkg)?;
+                let new_version = self.bump_conflicting(metadata, &http_client, pkg, &version)?;
 
-            if is_published(&http_client, index_url, &name, version)? {
-                info!("already published", name_ver);
-                continue;
+                info!(
+                    "bumped conflicting version",
+                    format!("{} => {}", name_ver, new_version)
+                );
+                name_ver = format!("{} v{}", name, new_version);
+                version = new_version.to_string();
+            }
+
+            // The workspace crates it depends on are not published yet in a dry run
+            if !self.dry_run && !is_offline() {
+                self.check_dependencies_published(
+                    requirements.as_ref().unwrap_or(metadata),
+                    &http_client,
+                    &name,
+                    &ignored,
+                    &mut available,
+                )?;
             }
 
             if self.dry_run {
                 args.push("--dry-run");
             }
 
-            if self.no_verify || self.dry_run {
+            if self.no_verify || self.dry_run || in_cycle {
                 args.push("--no-verify");
             }
 
@@ -135,6 +408,11 @@ impl Publish {
                 args.push("--locked");
             }
 
+            for config in &self.config {
+                args.push("--config");
+                args.push(config);
+            }
+
             if let Some(ref registry) = self.registry.registry {
                 args.push("--registry");
                 args.push(registry);
@@ -145,6 +423,11 @@ impl Publish {
                 args.push(token);
             }
 
+            // Passed through the environment to keep it out of the process list
+            if let Some(ref token) = token {
+                env.push(("CARGO_REGISTRY_TOKEN", token.as_str()));
+            }
+
             if let Some(interval) = self.publish_interval
                 && interval > 0 && !self.dry_run {
                     info!(
@@ -165,14 +448,22 @@ impl Publish {
                     Some(DevDependencyRemover::remove_dev_deps(p.as_std_path())?)
                 };
 
-            if dev_deps_remover.is_some() || self.allow_dirty {
+            // Snapshot versions are never committed
+            if dev_deps_remover.is_some() || self.allow_dirty || self.version.snapshot {
                 args.push("--allow-dirty");
             }
 
             args.push("--manifest-path");
             args.push(p.as_str());
 
-            let (_, stderr) = cargo(&metadata.workspace_root, &args, &[])?;
+            let (_, stderr) =
+                match cargo_timeout(&metadata.workspace_root, &args, &env, self.timeout) {
+                    Err(err @ Error::TimedOut { .. }) if self.dry_run => {
+                        warn!("publish failed", format!("{}: {}", name_ver, err));
+                        continue;
+                    }
+                    result => result?,
+                };
 
             drop(dev_deps_remover);
 
@@ -186,13 +477,358 @@ impl Publish {
 
             if !self.dry_run {
                 info!("published", name_ver);
+
+                // The crates depending on it need to see the new version
+                available.remove(&name);
+
+                let released_crate = ReleasedCrate {
+                    checksum: crate_checksum(metadata, &name, &version)?,
+                    registry: publish_registry(self.registry.registry.as_ref(), pkg)
+                        .cloned()
+                        .unwrap_or_else(|| "crates-io".to_string()),
+                    name: name.clone(),
+                    version: version.clone(),
+                };
+
+                // Recorded before the hook since the crate is out whatever the hook does
+                released.push(released_crate);
+
+                if let Some(hook) = &self.post_publish_hook {
+                    let released_crate = released.last().expect(INTERNAL_ERR);
+
+                    if let Err(err) = self.post_publish_hook(
+                        hook,
+                        p.parent().expect(INTERNAL_ERR),
+                        released_crate,
+                    ) {
+                        self.write_release_manifest(metadata, &config, released)?;
+
+                        return Err(err);
+                    }
+                }
+
+                if let (Some(sbom), Some(dir)) = (&sbom, &self.sbom) {
+                    let path = sbom.write(dir, self.sbom_format, &name, &version)?;
+                    info!("wrote bill of materials", path.display());
+                }
+            }
+
+            if let Some(forge_release) = &forge_release {
+                let tag = self.release_tag(&config, pkg, &version)?;
+
+                // Crates sharing the workspace tag share its release
+                if released_tags.insert(tag.clone()) {
+                    forge_release.release(
+                        &tag,
+                        p.parent().expect(INTERNAL_ERR),
+                        &Version::parse(&version).expect(INTERNAL_ERR),
+                    )?;
+                }
             }
+
+            record_completed(name_ver);
+        }
+
+        if !self.dry_run {
+            self.write_release_manifest(metadata, &config, released)?;
         }
 
         info!("success", "ok");
         Ok(())
     }
 
+    /// The tag of the crate, which is the workspace tag when it is not tagged individually
+    fn release_tag(
+        &self,
+        config: &WorkspaceConfig,
+        pkg: &Package,
+        version: &str,
+    ) -> Result<String> {
+        let version = Version::parse(version).expect(INTERNAL_ERR);
+        let independent = read_config::<PackageConfig>(&pkg.metadata)?
+            .independent
+            .unwrap_or(false);
+
+        self.version
+            .git
+            .release_tag(config, &pkg.name, independent, &version)
+    }
+
+    fn write_release_manifest(
+        &self,
+        metadata: &Metadata,
+        config: &WorkspaceConfig,
+        crates: Vec<ReleasedCrate>,
+    ) -> Result {
+        let (_, sha, _) = git(&metadata.workspace_root, &["rev-parse", "HEAD"])?;
+        let manifest = ReleaseManifest { sha, crates };
+        let path = release_manifest_path(self.release_manifest.as_deref(), config);
+
+        fs::write(
+            &path,
+            format!("{}\n", serde_json::to_string_pretty(&manifest)?),
+        )?;
+
+        info!("wrote release manifest", path.display());
+        Ok(())
+    }
+
+    // checks that the publishable crates have the metadata required by crates.io,
+    // before versioning so that no version is burned
+    fn check_metadata(&self, metadata: &Metadata) -> Result {
+        let mut problems = vec![];
+
+        for pkg in &metadata.packages {
+            if !metadata.workspace_members.contains(&pkg.id) || is_private(pkg) {
+                continue;
+            }
+
+            let config: PackageConfig = read_config(&pkg.metadata)?;
+
+            // Other registries have their own requirements
+            if config.skip_publish.unwrap_or(false)
+                || publish_registry(self.registry.registry.as_ref(), pkg).is_some()
+            {
+                continue;
+            }
+
+            let missing = missing_metadata(pkg);
+
+            if !missing.is_empty() {
+                problems.push(format!(" - {}: {}", pkg.name, missing.join(", ")));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(Error::IncompleteMetadata(problems.join("\n")));
+        }
+
+        Ok(())
+    }
+
+    /// Documents every crate which can be published, since the changed ones are not known yet
+    fn verify_publishable_documentation(&self, metadata: &Metadata) -> Result {
+        let mut members = metadata
+            .packages
+            .iter()
+            .filter(|x| metadata.workspace_members.contains(&x.id))
+            .map(|x| (x.clone(), x.version.to_string()))
+            .collect::<Vec<_>>();
+
+        members.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+
+        let paths = members
+            .iter()
+            .map(|(x, _)| x.manifest_path.clone())
+            .collect::<IndexSet<_>>();
+        let paths = filter_skip_publish(filter_private(paths, &members), &members)?;
+
+        let manifests = members
+            .iter()
+            .filter(|(x, _)| paths.contains(&x.manifest_path))
+            .map(|(x, _)| (x.name.as_str(), &x.manifest_path))
+            .collect::<Vec<_>>();
+
+        self.verify_documentation(metadata, &manifests)
+    }
+
+    // builds the documentation of all the crates first, so that nothing is versioned or
+    // published when one of them would end up with broken docs
+    fn verify_documentation(&self, metadata: &Metadata, crates: &[(&str, &Utf8PathBuf)]) -> Result {
+        let rustdocflags = match env::var("RUSTDOCFLAGS") {
+            Ok(flags) if !flags.trim().is_empty() => format!("{} -D warnings", flags.trim()),
+            _ => "-D warnings".to_string(),
+        };
+
+        let mut problems = vec![];
+
+        for (name, manifest_path) in crates {
+            stop_if_interrupted();
+
+            info!("documenting", name);
+
+            let status = cargo_status(
+                &metadata.workspace_root,
+                &[
+                    "doc",
+                    "--no-deps",
+                    "--manifest-path",
+                    manifest_path.as_str(),
+                ],
+                &[("RUSTDOCFLAGS", &rustdocflags)],
+            )?;
+
+            if !status.success() {
+                let code = status.code().map_or("none".to_string(), |x| x.to_string());
+                let doc_dir = metadata
+                    .target_directory
+                    .join("doc")
+                    .join(name.replace('-', "_"));
+
+                problems.push(format!(" - {}: exit code {}, see {}", name, code, doc_dir));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(Error::Documentation(problems.join("\n")));
+        }
+
+        Ok(())
+    }
+
+    /// Runs the hook in the directory of the crate. A failing hook is only a warning since
+    /// the crate is already published, unless `--strict-hooks` is given
+    fn post_publish_hook(&self, hook: &str, dir: &Utf8Path, released: &ReleasedCrate) -> Result {
+        let mut cmd = shell(hook);
+
+        cmd.current_dir(dir)
+            .env("WORKSPACES_CRATE_NAME", &released.name)
+            .env("WORKSPACES_CRATE_VERSION", &released.version)
+            .env("WORKSPACES_REGISTRY", &released.registry);
+
+        log_command(&cmd);
+
+        // Not being able to start the hook is a failure of the hook like any other
+        let failure = match cmd.spawn() {
+            Ok(mut child) => {
+                let _guard = ChildGuard::new(child.id());
+
+                match child.wait() {
+                    Ok(status) if status.success() => return Ok(()),
+                    Ok(status) => status.to_string(),
+                    Err(err) => err.to_string(),
+                }
+            }
+            Err(err) => err.to_string(),
+        };
+
+        let name_ver = format!("{} v{}", released.name, released.version);
+
+        if self.strict_hooks {
+            return Err(Error::PostPublishHook(name_ver, failure));
+        }
+
+        warn!(
+            "post-publish hook failed",
+            format!("{} ({})", name_ver, failure)
+        );
+        Ok(())
+    }
+
+    /// Fails if a workspace crate the crate depends on has no version on the registry matching
+    /// the requirement, instead of finding out once `cargo publish` packaged and verified it.
+    /// Dev-dependencies are left out since they are not needed by the published crate.
+    fn check_dependencies_published(
+        &self,
+        metadata: &Metadata,
+        http_client: &Client,
+        name: &str,
+        ignored: &[IgnoredDependency],
+        available: &mut Map<String, Vec<Version>>,
+    ) -> Result {
+        let is_member = |x: &&Package| metadata.workspace_members.contains(&x.id);
+        let pkg = metadata
+            .packages
+            .iter()
+            .filter(is_member)
+            .find(|x| x.name == name)
+            .expect(INTERNAL_ERR);
+
+        for dep in &pkg.dependencies {
+            // The crates of a broken cycle can't wait for each other
+            if dep.kind == DependencyKind::Development
+                || dep.path.is_none()
+                || ignored.iter().any(|x| x.name == name && x.dep == dep.name)
+            {
+                continue;
+            }
+
+            let Some(member) = metadata
+                .packages
+                .iter()
+                .filter(is_member)
+                .find(|x| x.name == dep.name)
+            else {
+                continue;
+            };
+
+            if !available.contains_key(&dep.name) {
+                let index_url =
+                    package_registry(metadata, self.registry.registry.as_ref(), member)?;
+                let versions = available_versions(http_client, index_url, &dep.name)?
+                    .iter()
+                    .filter_map(|x| Version::parse(x).ok())
+                    .collect();
+
+                available.insert(dep.name.clone(), versions);
+            }
+
+            // Yanked versions can't be depended on by new releases
+            if !available[&dep.name].iter().any(|x| dep.req.matches(x)) {
+                return Err(Error::DependencyNotPublished {
+                    name: name.to_string(),
+                    dep: dep.name.clone(),
+                    req: dep.req.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn bump_conflicting(
+        &self,
+        metadata: &Metadata,
+        http_client: &Client,
+        pkg: &Package,
+        version: &str,
+    ) -> Result<Version> {
+        let mut new_version = Version::parse(version).expect(INTERNAL_ERR);
+
+        loop {
+            new_version.increment_patch();
+
+            let index_url = package_registry(metadata, self.registry.registry.as_ref(), pkg)?;
+
+            if !is_published(http_client, index_url, &pkg.name, &new_version.to_string())? {
+                break;
+            }
+        }
+
+        let mut new_versions = Map::new();
+        new_versions.insert(pkg.name.clone(), new_version.clone());
+
+        let unpublished = unpublished_members(metadata);
+
+        for p in &metadata.packages {
+            if p.name != pkg.name && p.dependencies.iter().all(|x| x.name != pkg.name) {
+                continue;
+            }
+
+            fs::write(
+                &p.manifest_path,
+                format!(
+                    "{}\n",
+                    change_versions_with(
+                        fs::read_to_string(&p.manifest_path)?,
+                        &p.name,
+                        &new_versions,
+                        self.version.exact,
+                        &unpublished,
+                    )?
+                ),
+            )?;
+        }
+
+        self.version.update_lock(&metadata.workspace_root)?;
+
+        self.version
+            .git
+            .commit_bump(&metadata.workspace_root, &pkg.name, &new_version)?;
+
+        Ok(new_version)
+    }
+
     fn build(&self, workspace_root: &Utf8PathBuf, manifest_path: &Utf8PathBuf) -> Result<bool> {
         let mut args = vec!["build"];
 
@@ -208,3 +844,71 @@ impl Publish {
         Ok(true)
     }
 }
+
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    let (program, flag) = ("cmd", "/C");
+    #[cfg(not(windows))]
+    let (program, flag) = ("sh", "-c");
+
+    let mut cmd = Command::new(program);
+    cmd.args([flag, command]);
+    cmd
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_config() {
+        assert!(validate_config("net.git-fetch-with-cli=true").is_ok());
+        assert!(validate_config("registries.my-registry.index=\"https://example.com\"").is_ok());
+        assert!(validate_config("build.rustflags = [\"-C\", \"debuginfo=1\"]").is_ok());
+        assert!(validate_config("net.git-fetch-with-cli").is_err());
+        assert_eq!(
+            validate_config("net.retry="),
+            Err("expected KEY=VALUE with a TOML value, got net.retry=".to_string())
+        );
+        assert!(validate_config("=true").is_err());
+        assert!(validate_config("a=1\nb=2").is_err());
+        assert!(validate_config("a=1 b=2").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_post_publish_hook() {
+        let released = ReleasedCrate {
+            name: "dep1".to_string(),
+            version: "0.1.0".to_string(),
+            registry: "crates-io".to_string(),
+            checksum: None,
+        };
+        let hook =
+            r#"test "$WORKSPACES_CRATE_NAME@$WORKSPACES_CRATE_VERSION" = dep1@0.1.0 && exit 3"#;
+        let dir = Utf8Path::new(".");
+
+        let publish = Publish::parse_from(["publish", "--post-publish-hook", hook]);
+        assert!(publish.post_publish_hook(hook, dir, &released).is_ok());
+
+        let publish =
+            Publish::parse_from(["publish", "--post-publish-hook", hook, "--strict-hooks"]);
+        assert!(matches!(
+            publish.post_publish_hook(hook, dir, &released),
+            Err(Error::PostPublishHook(..))
+        ));
+        assert!(publish.post_publish_hook("true", dir, &released).is_ok());
+
+        // A hook which cannot be started is treated the same way
+        let missing = Utf8Path::new("does-not-exist");
+        assert!(matches!(
+            publish.post_publish_hook("true", missing, &released),
+            Err(Error::PostPublishHook(..))
+        ));
+
+        let publish = Publish::parse_from(["publish", "--post-publish-hook", hook]);
+        assert!(publish
+            .post_publish_hook("true", missing, &released)
+            .is_ok());
+    }
+}
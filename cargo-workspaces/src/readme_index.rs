@@ -0,0 +1,34 @@
+use crate::utils::{info, readme_path, render_readme, update_readme, Error, Result};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+
+/// Update the index of workspace members in the root README.md
+#[derive(Debug, Parser)]
+pub struct ReadmeIndex {
+    /// Fail if the index is out of date instead of updating it
+    #[clap(long)]
+    check: bool,
+}
+
+impl ReadmeIndex {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let path = readme_path(&metadata);
+
+        if self.check {
+            let (_, changed) = render_readme(&metadata)?;
+
+            if changed {
+                return Err(Error::StaleReadmeIndex(path.to_string()));
+            }
+
+            info!("up to date", path);
+        } else if update_readme(&metadata)? {
+            info!("updated", path);
+        } else {
+            info!("up to date", path);
+        }
+
+        Ok(())
+    }
+}
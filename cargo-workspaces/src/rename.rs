@@ -1,10 +1,14 @@
-use crate::utils::{get_pkgs, rename_packages, validate_value_containing_name, Error};
+use crate::utils::{
+    check_crate_name, get_pkgs, rename_packages, validate_value_containing_name, Error,
+    Transaction, INTERNAL_ERR,
+};
 
 use cargo_metadata::Metadata;
 use clap::Parser;
 use globset::{Error as GlobsetError, Glob};
+use toml_edit::Document;
 
-use std::{collections::BTreeMap as Map, fs};
+use std::{collections::BTreeMap as Map, fs, path::PathBuf};
 
 /// Rename crates in the project
 #[derive(Debug, Parser)]
@@ -21,14 +25,27 @@ pub struct Rename {
     #[clap(short, long, value_name = "crate", conflicts_with_all = &["all", "ignore"])]
     pub from: Option<String>,
 
+    /// Rename the crates using a TOML or JSON file of `old_name = new_name` pairs
+    #[clap(
+        long,
+        value_name = "FILE",
+        parse(from_os_str),
+        conflicts_with_all = &["ignore", "from", "to"]
+    )]
+    pub mapping_file: Option<PathBuf>,
+
     /// The value that should be used as new name (should contain `%n`)
-    #[clap(forbid_empty_values(true))]
-    pub to: String,
+    #[clap(forbid_empty_values(true), required_unless_present = "mapping-file")]
+    pub to: Option<String>,
 }
 
 impl Rename {
     pub fn run(self, metadata: Metadata) -> Result<(), Error> {
-        let pkgs = get_pkgs(&metadata, self.all || self.from.is_some())?;
+        // Crates named explicitly are renamed even when private
+        let pkgs = get_pkgs(
+            &metadata,
+            self.all || self.from.is_some() || self.mapping_file.is_some(),
+        )?;
 
         let ignore = self
             .ignore
@@ -38,20 +55,30 @@ impl Rename {
 
         let mut rename_map = Map::new();
 
-        if let Some(from) = self.from {
+        if let Some(path) = &self.mapping_file {
+            rename_map = read_mapping_file(path)?;
+
+            for from in rename_map.keys() {
+                if !pkgs.iter().any(|p| &p.name == from) {
+                    return Err(Error::PackageNotFound { id: from.clone() });
+                }
+            }
+        } else if let Some(from) = self.from {
             if pkgs
                 .iter()
                 .map(|p| &p.name)
                 .collect::<Vec<&String>>()
                 .contains(&&from)
             {
-                rename_map.insert(from, self.to.clone());
+                rename_map.insert(from, self.to.clone().expect(INTERNAL_ERR));
             } else {
                 return Err(Error::PackageNotFound { id: from });
             }
         } else {
+            let to = self.to.clone().expect(INTERNAL_ERR);
+
             // Validate the `to` value
-            validate_value_containing_name(&self.to)
+            validate_value_containing_name(&to)
                 .map_err(|_| Error::MustContainPercentN("<TO>".into()))?;
 
             for pkg in pkgs {
@@ -60,12 +87,24 @@ impl Rename {
                         continue;
                     }
 
-                let new_name = self.to.replace("%n", &pkg.name);
+                let new_name = to.replace("%n", &pkg.name);
 
                 rename_map.insert(pkg.name, new_name);
             }
         }
 
+        for to in rename_map.values() {
+            check_crate_name(to).map_err(|reason| Error::InvalidCrateName {
+                name: to.clone(),
+                reason: reason.to_string(),
+            })?;
+        }
+
+        check_conflicts(&metadata, &rename_map)?;
+
+        // All the edits are computed before anything is written
+        let mut transaction = Transaction::new();
+
         for pkg in &metadata.packages {
             if rename_map.contains_key(&pkg.name)
                 || pkg
@@ -74,7 +113,7 @@ impl Rename {
                     .map(|p| &p.name)
                     .any(|p| rename_map.contains_key(p))
             {
-                fs::write(
+                transaction.write(
                     &pkg.manifest_path,
                     format!(
                         "{}\n",
@@ -84,19 +123,115 @@ impl Rename {
                             &rename_map,
                         )?
                     ),
-                )?;
+                );
             }
         }
 
         let workspace_root = metadata.workspace_root.join("Cargo.toml");
-        fs::write(
+        transaction.write(
             &workspace_root,
             format!(
                 "{}\n",
-                rename_packages(fs::read_to_string(&workspace_root)?, "", &rename_map)?
+                rename_packages(transaction.read(&workspace_root)?, "", &rename_map)?
             ),
-        )?;
+        );
+
+        transaction.commit()
+    }
+}
+
+// No two members can end up with the same name, whether renamed or not
+fn check_conflicts(metadata: &Metadata, rename_map: &Map<String, String>) -> Result<(), Error> {
+    let mut names = Map::<&String, Vec<&String>>::new();
+
+    for pkg in metadata
+        .packages
+        .iter()
+        .filter(|p| metadata.workspace_members.contains(&p.id))
+    {
+        let name = rename_map.get(&pkg.name).unwrap_or(&pkg.name);
+        names.entry(name).or_default().push(&pkg.name);
+    }
+
+    match names.into_iter().find(|(_, crates)| crates.len() > 1) {
+        Some((name, mut crates)) => {
+            crates.sort();
+
+            Err(Error::RenameConflict {
+                name: name.clone(),
+                crates: crates
+                    .iter()
+                    .map(|x| x.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+fn read_mapping_file(path: &PathBuf) -> Result<Map<String, String>, Error> {
+    let contents = fs::read_to_string(path)?;
+    let bad_format = |reason: String| Error::BadMappingFile {
+        path: path.display().to_string(),
+        reason,
+    };
+
+    if path.extension().is_some_and(|x| x == "json") {
+        return serde_json::from_str(&contents).map_err(|e| bad_format(e.to_string()));
+    }
+
+    let document = contents
+        .parse::<Document>()
+        .map_err(|e| bad_format(e.to_string()))?;
+
+    document
+        .iter()
+        .map(|(from, to)| {
+            to.as_str()
+                .map(|to| (from.to_string(), to.to_string()))
+                .ok_or_else(|| bad_format(format!("new name for {} is not a string", from)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_mapping_file() {
+        let dir = tempdir().unwrap();
+        let toml = dir.path().join("mapping.toml");
+        let json = dir.path().join("mapping.json");
+
+        fs::write(
+            &toml,
+            "acme-core = \"corp-core\"\nacme-cli = \"corp-cli\"\n",
+        )
+        .unwrap();
+        fs::write(
+            &json,
+            r#"{"acme-core": "corp-core", "acme-cli": "corp-cli"}"#,
+        )
+        .unwrap();
+
+        let mut expected = Map::new();
+        expected.insert("acme-cli".to_string(), "corp-cli".to_string());
+        expected.insert("acme-core".to_string(), "corp-core".to_string());
+
+        assert_eq!(read_mapping_file(&toml).unwrap(), expected);
+        assert_eq!(read_mapping_file(&json).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_read_mapping_file_bad_format() {
+        let dir = tempdir().unwrap();
+        let toml = dir.path().join("mapping.toml");
+
+        fs::write(&toml, "acme-core = 1\n").unwrap();
 
-        Ok(())
+        assert!(read_mapping_file(&toml).is_err());
     }
 }
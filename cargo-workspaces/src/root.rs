@@ -0,0 +1,16 @@
+use crate::utils::Result;
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+use oclif::term::TERM_OUT;
+
+/// Print the root directory of the workspace
+#[derive(Debug, Parser)]
+pub struct Root {}
+
+impl Root {
+    pub fn run(self, metadata: Metadata) -> Result {
+        TERM_OUT.write_line(metadata.workspace_root.as_str())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use crate::utils::{
+    create_http_client, get_dry_run, get_pkgs, git, info, is_offline, package_registry,
+    published_versions, read_config, warn, Error, Pkg, RegistryOpt, Result, TagOpt,
+    WorkspaceConfig, WorkspaceContext, INTERNAL_ERR,
+};
+
+use camino::Utf8PathBuf;
+use clap::{ArgGroup, Parser};
+use semver::Version;
+use toml_edit::Document;
+
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+
+/// Create the missing release tags of already published versions
+#[derive(Debug, Parser)]
+#[clap(group(ArgGroup::new("mode").required(true).args(&["backfill"])))]
+pub struct Tag {
+    /// Tag the commit where each version first appeared in the manifest of its crate
+    #[clap(long)]
+    backfill: bool,
+
+    /// Backfill every version published on the registry instead of the current ones
+    #[clap(long)]
+    from_registry: bool,
+
+    #[clap(flatten)]
+    tag: TagOpt,
+
+    #[clap(flatten)]
+    registry: RegistryOpt,
+}
+
+impl Tag {
+    pub fn run(self, ctx: &WorkspaceContext) -> Result {
+        let metadata = &ctx.metadata;
+        let root = &metadata.workspace_root;
+        let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
+
+        let (status, _, _) = git(root, &["rev-parse", "--verify", "HEAD"])?;
+
+        if !status.success() {
+            return Err(Error::NotGit);
+        }
+
+        // The commits introducing each tag, a grouped tag is introduced once per crate
+        let mut candidates: Map<String, Set<String>> = Map::new();
+
+        for pkg in get_pkgs(metadata, false)? {
+            let independent = pkg.config.independent.unwrap_or(false);
+
+            for version in self.versions(ctx, &pkg)? {
+                // Crates without an individual tag were released under the workspace one
+                let tag = self
+                    .tag
+                    .release_tag(&config, &pkg.name, independent, &version)?;
+                let commits = introductions(root, &pkg, &version.to_string())?;
+
+                if commits.is_empty() {
+                    warn!(
+                        "not found",
+                        format!("{} v{} never appeared in its manifest", pkg.name, version)
+                    );
+                    continue;
+                }
+
+                candidates.entry(tag).or_default().extend(commits);
+            }
+        }
+
+        let mut ambiguous = vec![];
+
+        for (tag, commits) in candidates {
+            let (status, _, _) = git(
+                root,
+                &["rev-parse", "--verify", &format!("refs/tags/{}", tag)],
+            )?;
+
+            if status.success() {
+                info!("already tagged", tag);
+                continue;
+            }
+
+            if commits.len() > 1 {
+                ambiguous.push(format!(
+                    " - {}: {}",
+                    tag,
+                    commits
+                        .iter()
+                        .map(|x| short(x))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+                continue;
+            }
+
+            let sha = commits.into_iter().next().expect(INTERNAL_ERR);
+
+            if get_dry_run() {
+                info!("would tag", format!("{} at {}", tag, short(&sha)));
+                continue;
+            }
+
+            let tagged = git(root, &["tag", "-a", &tag, &sha, "-m", &tag])?;
+
+            if !tagged.0.success() {
+                return Err(Error::NotTagged(tag, tagged.1, tagged.2));
+            }
+
+            info!("tagged", format!("{} at {}", tag, short(&sha)));
+        }
+
+        if !ambiguous.is_empty() {
+            warn!(
+                "ambiguous",
+                format!(
+                    "the versions appeared in several commits, tag them manually\n{}",
+                    ambiguous.join("\n")
+                )
+            );
+        }
+
+        info!("success", "ok");
+        Ok(())
+    }
+
+    fn versions(&self, ctx: &WorkspaceContext, pkg: &Pkg) -> Result<Vec<Version>> {
+        if !self.from_registry {
+            return Ok(vec![pkg.version.clone()]);
+        }
+
+        if is_offline() {
+            return Err(Error::Offline("look up the published versions".to_string()));
+        }
+
+        let metadata = &ctx.metadata;
+        let http_client = create_http_client(&metadata.workspace_root, &self.registry.token)?;
+        let package = metadata
+            .packages
+            .iter()
+            .find(|x| x.id == pkg.id)
+            .expect(INTERNAL_ERR);
+        let index_url = package_registry(metadata, self.registry.registry.as_ref(), package)?;
+
+        Ok(published_versions(&http_client, index_url, &pkg.name)?
+            .iter()
+            .filter_map(|x| Version::parse(x).ok())
+            .collect())
+    }
+}
+
+/// The commits which changed the version of the crate to the given one. More than one
+/// means that the version was set again later, like when reverting a release.
+fn introductions(root: &Utf8PathBuf, pkg: &Pkg, version: &str) -> Result<Vec<String>> {
+    let manifest = pkg.prefix().join("Cargo.toml");
+    let manifest = manifest.to_str().expect(INTERNAL_ERR);
+
+    // Narrow down the history to the commits touching the version, an inherited one
+    // is changed in the root manifest
+    let (_, out, _) = git(
+        root,
+        &[
+            "log",
+            "--format=%H",
+            "-S",
+            version,
+            "--",
+            manifest,
+            "Cargo.toml",
+        ],
+    )?;
+
+    let mut ret = vec![];
+
+    for sha in out.lines() {
+        if version_at(root, sha, manifest)?.as_deref() == Some(version)
+            && version_at(root, &format!("{}^", sha), manifest)?.as_deref() != Some(version)
+        {
+            ret.push(sha.to_string());
+        }
+    }
+
+    Ok(ret)
+}
+
+/// The version of the crate in its manifest at the revision
+fn version_at(root: &Utf8PathBuf, rev: &str, manifest: &str) -> Result<Option<String>> {
+    let Some(document) = document_at(root, rev, manifest)? else {
+        return Ok(None);
+    };
+
+    let Some(version) = document.get("package").and_then(|x| x.get("version")) else {
+        return Ok(None);
+    };
+
+    if version.get("workspace").and_then(|x| x.as_bool()) != Some(true) {
+        return Ok(version.as_str().map(|x| x.to_string()));
+    }
+
+    Ok(document_at(root, rev, "Cargo.toml")?
+        .as_ref()
+        .and_then(|x| x.get("workspace")?.get("package")?.get("version")?.as_str())
+        .map(|x| x.to_string()))
+}
+
+fn document_at(root: &Utf8PathBuf, rev: &str, path: &str) -> Result<Option<Document>> {
+    let (status, out, _) = git(root, &["show", &format!("{}:./{}", rev, path)])?;
+
+    if !status.success() {
+        return Ok(None);
+    }
+
+    Ok(out.parse().ok())
+}
+
+fn short(sha: &str) -> &str {
+    sha.get(..7).unwrap_or(sha)
+}
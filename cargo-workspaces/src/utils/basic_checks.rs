@@ -1,7 +1,11 @@
-use cargo_metadata::Package;
+use cargo_metadata::{Metadata, Package};
+use dunce::canonicalize;
+use toml_edit::{value, Document, Item, Table, TableLike};
 use url::Url;
 
-use crate::utils::{warn, Result};
+use std::{fmt, fs, path::Path};
+
+use crate::utils::{info, is_private, read_config, warn, Error, PackageConfig, Result};
 
 /// Performs basic checks to make sure that crate can be published.
 /// Returns `Ok(())` if no problems were found, otherwise returns a list of
@@ -72,6 +76,301 @@ pub fn basic_checks(pkg: &Package) -> Result {
     Ok(())
 }
 
+/// Returns the fields which crates.io requires but are missing from the package.
+pub fn missing_metadata(pkg: &Package) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+
+    if pkg.description.is_none() {
+        missing.push("description");
+    }
+    if pkg.license.is_none() && pkg.license_file.is_none() {
+        missing.push("license");
+    }
+    if pkg.repository.is_none() && pkg.homepage.is_none() {
+        missing.push("repository or homepage");
+    }
+
+    missing
+}
+
+/// Returns the names of the `path` dependencies which have no `version` key in the manifest.
+///
+/// Dependencies inherited with `workspace = true` are looked up in the workspace manifest.
+/// Dev-dependencies are skipped because they are removed when publishing.
+pub fn unversioned_path_deps(manifest: &Document, workspace: &Document) -> Vec<String> {
+    let workspace_deps = workspace
+        .get("workspace")
+        .and_then(|x| x.get("dependencies"))
+        .and_then(Item::as_table_like);
+
+    let mut tables = vec![];
+
+    for key in ["dependencies", "build-dependencies"] {
+        tables.extend(manifest.get(key));
+    }
+
+    if let Some(targets) = manifest.get("target").and_then(Item::as_table_like) {
+        for (_, target) in targets.iter() {
+            for key in ["dependencies", "build-dependencies"] {
+                tables.extend(target.get(key));
+            }
+        }
+    }
+
+    let mut names = vec![];
+
+    for (name, dep) in tables
+        .into_iter()
+        .filter_map(Item::as_table_like)
+        .flat_map(|x| x.iter())
+    {
+        let Some(dep) = dep.as_table_like() else {
+            continue;
+        };
+
+        let dep = if dep.get("workspace").and_then(Item::as_bool) == Some(true) {
+            match workspace_deps
+                .and_then(|x| x.get(name))
+                .and_then(Item::as_table_like)
+            {
+                Some(dep) => dep,
+                None => continue,
+            }
+        } else {
+            dep
+        };
+
+        if is_unversioned_path(dep) && !names.iter().any(|x| x == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    names
+}
+
+/// A readme or license file which would not end up in the published package
+#[derive(Debug, PartialEq)]
+pub enum UnpackagedFile {
+    /// The `readme` or `license-file` does not exist
+    Missing(&'static str, String),
+    /// There is no `license-file` and the license files are only at the workspace root
+    RootLicense(Vec<String>),
+    /// There is no readme while the workspace declares one which could be inherited
+    NoReadme,
+}
+
+impl fmt::Display for UnpackagedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(field, path) => write!(f, "{} {} does not exist", field, path),
+            Self::RootLicense(files) => write!(
+                f,
+                "{} only at the workspace root, without a license-file entry",
+                files.join(", ")
+            ),
+            Self::NoReadme => write!(
+                f,
+                "no readme, while the workspace one could be inherited with readme.workspace = true"
+            ),
+        }
+    }
+}
+
+/// Returns the readme and license files of the crate which would not be packaged.
+///
+/// Files outside of the crate are fine since cargo copies them to the root of the package.
+pub fn unpackaged_files(
+    manifest: &Document,
+    workspace: &Document,
+    crate_dir: &Path,
+    root: &Path,
+) -> Result<Vec<UnpackagedFile>> {
+    let package = manifest.get("package");
+    let field = |key| package.and_then(|x| x.get(key)).and_then(Item::as_str);
+
+    let mut problems = vec![];
+
+    if let Some(readme) = field("readme") {
+        if !crate_dir.join(readme).exists() {
+            problems.push(UnpackagedFile::Missing("readme", readme.to_string()));
+        }
+    } else if package.and_then(|x| x.get("readme")).is_none()
+        && workspace_package(workspace, "readme").is_some()
+        && default_readme(crate_dir).is_none()
+    {
+        problems.push(UnpackagedFile::NoReadme);
+    }
+
+    if let Some(license) = field("license-file") {
+        if !crate_dir.join(license).exists() {
+            problems.push(UnpackagedFile::Missing("license-file", license.to_string()));
+        }
+    } else if canonicalize(crate_dir)? != canonicalize(root)?
+        && license_files(crate_dir)?.is_empty()
+    {
+        let files = license_files(root)?;
+
+        if !files.is_empty() {
+            problems.push(UnpackagedFile::RootLicense(files));
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Fixes the file in the manifest or by copying it from the workspace root, returns whether
+/// it could be fixed
+pub fn fix_unpackaged_file(
+    problem: &UnpackagedFile,
+    manifest: &mut Document,
+    workspace: &Document,
+    crate_dir: &Path,
+    root: &Path,
+) -> Result<bool> {
+    match problem {
+        UnpackagedFile::RootLicense(files) => {
+            for file in files {
+                fs::copy(root.join(file), crate_dir.join(file))?;
+            }
+        }
+        UnpackagedFile::Missing(field, path) => {
+            if workspace_package(workspace, field).is_some() {
+                inherit(manifest, field);
+            } else if *field == "readme" {
+                match default_readme(crate_dir) {
+                    Some(readme) => manifest["package"]["readme"] = value(readme),
+                    None => return Ok(false),
+                }
+            } else {
+                // A license file of the same name at the root is copied into the crate
+                let Some(name) = Path::new(path).file_name() else {
+                    return Ok(false);
+                };
+
+                if !root.join(name).is_file() {
+                    return Ok(false);
+                }
+
+                fs::copy(root.join(name), crate_dir.join(name))?;
+                manifest["package"]["license-file"] = value(name.to_string_lossy().as_ref());
+            }
+        }
+        UnpackagedFile::NoReadme => inherit(manifest, "readme"),
+    }
+
+    Ok(true)
+}
+
+/// What [`check_packaged_files`] found, in numbers of files
+#[derive(Debug, Default)]
+pub struct PackagedFiles {
+    pub fixed: usize,
+    pub remaining: usize,
+}
+
+/// Warns about the readme and license files of the publishable crates which would not be
+/// packaged, fixing the ones it can when asked to
+pub fn check_packaged_files(metadata: &Metadata, fix: bool) -> Result<PackagedFiles> {
+    let root = metadata.workspace_root.as_std_path();
+    let workspace = fs::read_to_string(root.join("Cargo.toml"))?.parse::<Document>()?;
+    let mut ret = PackagedFiles::default();
+
+    for pkg in &metadata.packages {
+        if !metadata.workspace_members.contains(&pkg.id) || is_private(pkg) {
+            continue;
+        }
+
+        let config: PackageConfig = read_config(&pkg.metadata)?;
+
+        if config.skip_publish.unwrap_or(false) {
+            continue;
+        }
+
+        let crate_dir = pkg
+            .manifest_path
+            .parent()
+            .ok_or_else(|| Error::ManifestHasNoParent(pkg.name.clone()))?
+            .as_std_path();
+        let mut manifest = fs::read_to_string(&pkg.manifest_path)?.parse::<Document>()?;
+        let mut edited = false;
+
+        for problem in unpackaged_files(&manifest, &workspace, crate_dir, root)? {
+            let description = format!("{}: {}", pkg.name, problem);
+
+            if fix && fix_unpackaged_file(&problem, &mut manifest, &workspace, crate_dir, root)? {
+                info!("fixed", description);
+                ret.fixed += 1;
+                edited |= !matches!(problem, UnpackagedFile::RootLicense(_));
+            } else {
+                warn!("not packaged", description);
+                ret.remaining += 1;
+            }
+        }
+
+        if edited {
+            fs::write(&pkg.manifest_path, manifest.to_string())?;
+        }
+    }
+
+    Ok(ret)
+}
+
+/// The field of the `[workspace.package]` table, which the crates can inherit
+fn workspace_package<'a>(workspace: &'a Document, key: &str) -> Option<&'a Item> {
+    workspace
+        .get("workspace")
+        .and_then(|x| x.get("package"))
+        .and_then(|x| x.get(key))
+}
+
+/// Sets `key.workspace = true` in the package table
+fn inherit(manifest: &mut Document, key: &str) {
+    let mut table = Table::new();
+
+    table.insert("workspace", value(true));
+    table.set_dotted(true);
+    manifest["package"][key] = Item::Table(table);
+
+    // The space before the `=` of a replaced value would end up before the dot
+    if let Some(decor) = manifest["package"]
+        .as_table_mut()
+        .and_then(|x| x.key_decor_mut(key))
+    {
+        decor.clear();
+    }
+}
+
+/// The readme cargo picks up when the manifest has none
+fn default_readme(crate_dir: &Path) -> Option<&'static str> {
+    ["README.md", "README.txt", "README"]
+        .into_iter()
+        .find(|x| crate_dir.join(x).is_file())
+}
+
+/// Returns the names of the `LICENSE*` and `COPYING*` files in the directory
+fn license_files(dir: &Path) -> Result<Vec<String>> {
+    let mut files = vec![];
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let upper = name.to_uppercase();
+
+        if entry.file_type()?.is_file()
+            && (upper.starts_with("LICENSE") || upper.starts_with("COPYING"))
+        {
+            files.push(name);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn is_unversioned_path(dep: &dyn TableLike) -> bool {
+    dep.contains_key("path") && !dep.contains_key("version")
+}
+
 // Adapted from:
 // https://github.com/rust-lang/crates.io/blob/d507a12560ab923c2a1a061e5365fe6b1f1293a8/src/models/keyword.rs#L56
 fn valid_keyword(keyword: &str) -> bool {
@@ -104,3 +403,187 @@ fn validate_url(url: &Option<&str>, field: &str, problems: &mut Vec<String>) {
         problems.push(format!("`{field}` is not a valid url: `{url}`"));
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unversioned_path_deps() {
+        let manifest = r#"
+[package]
+name = "top"
+
+[dependencies]
+dep1 = { path = "../dep1" }
+dep2 = { path = "../dep2", version = "0.1.0" }
+dep3 = { workspace = true }
+dep4 = { workspace = true }
+serde = "1"
+
+[dev-dependencies]
+dep5 = { path = "../dep5" }
+
+[target.'cfg(unix)'.build-dependencies.dep6]
+path = "../dep6"
+"#
+        .parse::<Document>()
+        .unwrap();
+
+        let workspace = r#"
+[workspace.dependencies]
+dep3 = { path = "dep3" }
+dep4 = { path = "dep4", version = "0.2.0" }
+"#
+        .parse::<Document>()
+        .unwrap();
+
+        assert_eq!(
+            unversioned_path_deps(&manifest, &workspace),
+            vec!["dep1", "dep3", "dep6"]
+        );
+    }
+
+    #[test]
+    fn test_unpackaged_files() {
+        let root = tempfile::tempdir().unwrap();
+        let crate_dir = root.path().join("crates").join("a");
+
+        fs::create_dir_all(&crate_dir).unwrap();
+        fs::write(root.path().join("README.md"), "").unwrap();
+        fs::write(root.path().join("LICENSE-MIT"), "").unwrap();
+        fs::write(crate_dir.join("README.md"), "").unwrap();
+
+        let workspace = Document::new();
+        let check = |manifest: &str| {
+            unpackaged_files(
+                &manifest.parse().unwrap(),
+                &workspace,
+                &crate_dir,
+                root.path(),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            check("[package]\nreadme = \"../../README.md\"\n"),
+            vec![UnpackagedFile::RootLicense(vec!["LICENSE-MIT".to_string()])]
+        );
+        assert_eq!(
+            check("[package]\nreadme.workspace = true\nlicense-file = \"LICENSE\"\n"),
+            vec![UnpackagedFile::Missing(
+                "license-file",
+                "LICENSE".to_string()
+            )]
+        );
+        assert_eq!(
+            check("[package]\nreadme = \"../README.md\"\n"),
+            vec![
+                UnpackagedFile::Missing("readme", "../README.md".to_string()),
+                UnpackagedFile::RootLicense(vec!["LICENSE-MIT".to_string()])
+            ]
+        );
+
+        // Packaged at the root of the crate by cargo
+        assert!(check(
+            "[package]\nreadme = \"../../README.md\"\nlicense-file = \"../../LICENSE-MIT\"\n"
+        )
+        .is_empty());
+
+        fs::write(crate_dir.join("LICENSE-MIT"), "").unwrap();
+        assert!(check("[package]\nreadme = \"README.md\"\n").is_empty());
+    }
+
+    #[test]
+    fn test_unpackaged_readme_inherited() {
+        let root = tempfile::tempdir().unwrap();
+        let crate_dir = root.path().join("a");
+
+        fs::create_dir_all(&crate_dir).unwrap();
+        fs::write(crate_dir.join("LICENSE"), "").unwrap();
+
+        let workspace = "[workspace.package]\nreadme = \"README.md\"\n"
+            .parse::<Document>()
+            .unwrap();
+        let mut manifest = "[package]\nname = \"a\"\n".parse::<Document>().unwrap();
+
+        let problems = unpackaged_files(&manifest, &workspace, &crate_dir, root.path()).unwrap();
+        assert_eq!(problems, vec![UnpackagedFile::NoReadme]);
+
+        assert!(fix_unpackaged_file(
+            &problems[0],
+            &mut manifest,
+            &workspace,
+            &crate_dir,
+            root.path()
+        )
+        .unwrap());
+        assert_eq!(
+            manifest.to_string(),
+            "[package]\nname = \"a\"\nreadme.workspace = true\n"
+        );
+
+        // The readme of the crate is picked up by cargo
+        fs::write(crate_dir.join("README.md"), "").unwrap();
+        let manifest = "[package]\nname = \"a\"\n".parse::<Document>().unwrap();
+        assert!(
+            unpackaged_files(&manifest, &workspace, &crate_dir, root.path())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_fix_missing_files() {
+        let root = tempfile::tempdir().unwrap();
+        let crate_dir = root.path().join("a");
+
+        fs::create_dir_all(&crate_dir).unwrap();
+        fs::write(root.path().join("LICENSE-APACHE"), "apache").unwrap();
+        fs::write(crate_dir.join("README.md"), "").unwrap();
+
+        let workspace = Document::new();
+        let mut manifest =
+            "[package]\nreadme = \"../README.md\"\nlicense-file = \"../../LICENSE-APACHE\"\n"
+                .parse::<Document>()
+                .unwrap();
+
+        let problems = unpackaged_files(&manifest, &workspace, &crate_dir, root.path()).unwrap();
+        assert_eq!(problems.len(), 2);
+
+        for problem in &problems {
+            assert!(fix_unpackaged_file(
+                problem,
+                &mut manifest,
+                &workspace,
+                &crate_dir,
+                root.path()
+            )
+            .unwrap());
+        }
+
+        assert_eq!(
+            manifest.to_string(),
+            "[package]\nreadme = \"README.md\"\nlicense-file = \"LICENSE-APACHE\"\n"
+        );
+        assert_eq!(
+            fs::read_to_string(crate_dir.join("LICENSE-APACHE")).unwrap(),
+            "apache"
+        );
+        assert!(
+            unpackaged_files(&manifest, &workspace, &crate_dir, root.path())
+                .unwrap()
+                .is_empty()
+        );
+
+        // Nothing to take the file from
+        let mut manifest = "[package]\nlicense-file = \"COPYING\"\n"
+            .parse::<Document>()
+            .unwrap();
+        let problem = UnpackagedFile::Missing("license-file", "COPYING".to_string());
+        assert!(
+            !fix_unpackaged_file(&problem, &mut manifest, &workspace, &crate_dir, root.path())
+                .unwrap()
+        );
+    }
+}
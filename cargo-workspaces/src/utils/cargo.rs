@@ -1,20 +1,31 @@
-use crate::utils::{debug, get_debug, Error, Result, INTERNAL_ERR};
+use crate::utils::{
+    debug, get_debug, isolate, log_command, output_timeout_stderr, ChildGuard, Error, Result,
+    Timeout, INTERNAL_ERR,
+};
 
 use camino::Utf8Path;
+use cargo_metadata::MetadataCommand;
 use lazy_static::lazy_static;
 use oclif::term::TERM_ERR;
 use regex::{Captures, Regex};
 use semver::{Version, VersionReq};
+use toml_edit::{Document, Item, Value};
 
 use std::{
-    collections::BTreeMap as Map,
-    io::{BufRead, BufReader},
-    process::{Command, Stdio},
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    process::{Command, ExitStatus, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 const CRLF: &str = "\r\n";
 const LF: &str = "\n";
 
+lazy_static! {
+    static ref OFFLINE: AtomicBool = AtomicBool::new(false);
+    static ref FROZEN: AtomicBool = AtomicBool::new(false);
+    static ref LOCKED: AtomicBool = AtomicBool::new(false);
+}
+
 lazy_static! {
     static ref NAME: Regex =
         Regex::new(r#"^(\s*['"]?name['"]?\s*=\s*['"])([0-9A-Za-z-_]+)(['"].*)$"#).expect(INTERNAL_ERR);
@@ -60,15 +71,103 @@ lazy_static! {
         Regex::new(r#"['"]?workspace['"]?\s*=\s*true"#).expect(INTERNAL_ERR);
 }
 
+pub fn set_cargo_flags(offline: bool, frozen: bool, locked: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+    FROZEN.store(frozen, Ordering::Relaxed);
+    LOCKED.store(locked, Ordering::Relaxed);
+}
+
+/// Whether the registry must not be accessed (`--frozen` implies `--offline`)
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed) || FROZEN.load(Ordering::Relaxed)
+}
+
+/// The global flags which need to be forwarded to every cargo invocation
+pub fn cargo_flags() -> Vec<&'static str> {
+    flags(
+        OFFLINE.load(Ordering::Relaxed),
+        FROZEN.load(Ordering::Relaxed),
+        LOCKED.load(Ordering::Relaxed),
+    )
+}
+
+fn flags(offline: bool, frozen: bool, locked: bool) -> Vec<&'static str> {
+    let mut flags = vec![];
+
+    if frozen {
+        flags.push("--frozen");
+    } else {
+        if offline {
+            flags.push("--offline");
+        }
+
+        if locked {
+            flags.push("--locked");
+        }
+    }
+
+    flags
+}
+
+pub fn metadata_command() -> MetadataCommand {
+    let mut cmd = MetadataCommand::new();
+
+    cmd.other_options(
+        cargo_flags()
+            .into_iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>(),
+    );
+
+    cmd
+}
+
 pub fn cargo<'a>(
     root: &Utf8Path,
     args: &[&'a str],
     env: &[(&'a str, &'a str)],
 ) -> Result<(String, String)> {
+    cargo_timeout(root, args, env, None)
+}
+
+/// Runs cargo like [`cargo`], killing it if it does not finish within the timeout
+pub fn cargo_timeout<'a>(
+    root: &Utf8Path,
+    args: &[&'a str],
+    env: &[(&'a str, &'a str)],
+    timeout: Option<Timeout>,
+) -> Result<(String, String)> {
+    run_cargo(root, args, env, timeout).map(|(_, stdout, stderr)| (stdout, stderr))
+}
+
+/// Runs cargo like [`cargo`], also returning its exit status
+pub fn cargo_output<'a>(
+    root: &Utf8Path,
+    args: &[&'a str],
+    env: &[(&'a str, &'a str)],
+) -> Result<(ExitStatus, String, String)> {
+    run_cargo(root, args, env, None)
+}
+
+fn run_cargo<'a>(
+    root: &Utf8Path,
+    args: &[&'a str],
+    env: &[(&'a str, &'a str)],
+    timeout: Option<Timeout>,
+) -> Result<(ExitStatus, String, String)> {
     debug!("cargo", args.join(" "));
 
+    // Only the subcommand, the arguments might contain a token
+    let subcommand = format!("cargo {}", args.first().copied().unwrap_or_default());
+
     let mut args = args.to_vec();
 
+    for flag in cargo_flags() {
+        if !args.contains(&flag) {
+            args.push(flag);
+        }
+    }
+
     if TERM_ERR.features().colors_supported() {
         args.push("--color");
         args.push("always");
@@ -80,48 +179,84 @@ pub fn cargo<'a>(
 
     let args_text = args.iter().map(|x| x.to_string()).collect::<Vec<_>>();
 
-    let mut stderr_lines = vec![];
+    let mut cmd = Command::new("cargo");
 
-    let mut child = Command::new("cargo")
-        .current_dir(root)
+    cmd.current_dir(root)
         .args(&args)
         .envs(env.iter().copied())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|err| Error::Cargo {
-            err,
-            args: args_text.clone(),
-        })?;
-
-    {
-        let stderr = child.stderr.as_mut().expect(INTERNAL_ERR);
-
-        for line in BufReader::new(stderr).lines() {
-            let line = line?;
+        .stderr(Stdio::piped());
 
-            eprintln!("{}", line);
-            stderr_lines.push(line);
-        }
+    if timeout.is_some() {
+        isolate(&mut cmd);
     }
 
-    let output = child.wait_with_output().map_err(|err| Error::Cargo {
+    log_command(&cmd);
+
+    let child = cmd.spawn().map_err(|err| Error::Cargo {
         err,
-        args: args_text,
+        args: args_text.clone(),
     })?;
 
-    let output_stdout = String::from_utf8(output.stdout)?;
-    let output_stderr = stderr_lines.join("\n");
+    let _guard = ChildGuard::new(child.id());
+
+    // cargo's progress is shown as it comes, while the output is kept for the callers
+    let (status, stdout, stderr) =
+        output_timeout_stderr(child, timeout).map_err(|err| Error::Cargo {
+            err,
+            args: args_text,
+        })?;
+
+    let output_stdout = String::from_utf8(stdout)?;
+    let output_stderr = String::from_utf8(stderr)?;
+
+    let Some(status) = status else {
+        return Err(Error::TimedOut {
+            cmd: subcommand,
+            after: timeout.expect(INTERNAL_ERR).to_string(),
+        });
+    };
 
     debug!("cargo stderr", output_stderr);
     debug!("cargo stdout", output_stdout);
 
     Ok((
+        status,
         output_stdout.trim().to_owned(),
         output_stderr.trim().to_owned(),
     ))
 }
 
+/// Runs cargo with its output going straight to the terminal, returning its exit status
+pub fn cargo_status<'a>(
+    root: &Utf8Path,
+    args: &[&'a str],
+    env: &[(&'a str, &'a str)],
+) -> Result<ExitStatus> {
+    debug!("cargo", args.join(" "));
+
+    let mut args = args.to_vec();
+
+    for flag in cargo_flags() {
+        if !args.contains(&flag) {
+            args.push(flag);
+        }
+    }
+
+    let mut cmd = Command::new("cargo");
+    cmd.current_dir(root).args(&args).envs(env.iter().copied());
+    log_command(&cmd);
+
+    let mut child = cmd.spawn().map_err(|err| Error::Cargo {
+        err,
+        args: args.iter().map(|x| x.to_string()).collect(),
+    })?;
+
+    let _guard = ChildGuard::new(child.id());
+
+    Ok(child.wait()?)
+}
+
 pub fn cargo_config_get(root: &Utf8Path, name: &str) -> Result<String> {
     // You know how we sometimes have to make the best of an unfortunate
     // situation? This is one of those situations.
@@ -197,19 +332,41 @@ fn edit_version(
     new_lines: &mut Vec<String>,
     versions: &Map<String, Version>,
     exact: bool,
+    unpublished: &Set<String>,
     version_index: usize,
 ) -> Result {
     if let Some(new_version) = versions.get(&caps[version_index]) {
-        if exact {
-            new_lines.push(format!("{}={}{}", &caps[1], new_version, &caps[4]));
-        } else if !VersionReq::parse(&caps[3])?.matches(new_version) {
-            new_lines.push(format!("{}{}{}", &caps[1], new_version, &caps[4]));
+        let exact = exact && !unpublished.contains(&caps[version_index]);
+
+        if let Some(req) = bumped_requirement(&VersionReq::parse(&caps[3])?, new_version, exact) {
+            new_lines.push(format!("{}{}{}", &caps[1], req, &caps[4]));
         }
     }
 
     Ok(())
 }
 
+/// The requirement on a crate bumped to the new version, or `None` when it is kept. Exact
+/// requirements are always rewritten, the others only when they no longer match.
+pub fn bumped_requirement(req: &VersionReq, new_version: &Version, exact: bool) -> Option<String> {
+    let new_version = requirement(new_version);
+
+    if exact {
+        Some(format!("={}", new_version))
+    } else if !req.matches(&new_version) {
+        Some(new_version.to_string())
+    } else {
+        None
+    }
+}
+
+/// Build metadata is ignored when resolving, so cargo warns about requirements with it
+fn requirement(version: &Version) -> Version {
+    let mut version = version.clone();
+    version.build.clear();
+    version
+}
+
 fn rename_dep(
     caps: Captures,
     new_lines: &mut Vec<String>,
@@ -349,15 +506,134 @@ pub fn rename_packages(
     )
 }
 
+/// Whether the manifest inherits its version from `[workspace.package]`
+pub fn inherits_workspace_version(manifest: &str) -> Result<bool> {
+    let manifest = manifest.parse::<Document>()?;
+
+    Ok(manifest
+        .get("package")
+        .and_then(|x| x.get("version"))
+        .and_then(|x| x.get("workspace"))
+        .and_then(|x| x.as_bool())
+        .unwrap_or(false))
+}
+
+/// Sets `[workspace.package] version`, leaving the rest of the manifest as it is
+pub fn set_workspace_version(manifest: &str, version: &Version) -> Result<String> {
+    let mut manifest = manifest.parse::<Document>()?;
+
+    if let Some(field) = manifest
+        .get_mut("workspace")
+        .and_then(|x| x.get_mut("package"))
+        .and_then(|x| x.get_mut("version"))
+        .and_then(|x| x.as_value_mut())
+    {
+        let decor = field.decor().clone();
+        *field = version.to_string().into();
+        *field.decor_mut() = decor;
+    }
+
+    Ok(manifest.to_string())
+}
+
+const DEPENDENCY_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Entries of `[workspace.dependencies]`, normalized so that they only differ when their
+/// values do and not when their formatting does
+pub fn workspace_dependencies(manifest: &str) -> Result<Map<String, String>> {
+    let manifest = manifest.parse::<Document>()?;
+
+    Ok(manifest
+        .get("workspace")
+        .and_then(|x| x.get("dependencies"))
+        .and_then(|x| x.as_table_like())
+        .map(|x| {
+            x.iter()
+                .map(|(k, v)| (k.to_string(), normalize(v)))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Names of the dependencies inherited with `workspace = true`, in any kind and target
+pub fn inherited_dependencies(manifest: &str) -> Result<Set<String>> {
+    let manifest = manifest.parse::<Document>()?;
+
+    let targets = manifest
+        .get("target")
+        .and_then(|x| x.as_table_like())
+        .into_iter()
+        .flat_map(|x| x.iter().map(|(_, x)| x));
+
+    Ok(std::iter::once(manifest.as_item())
+        .chain(targets)
+        .flat_map(|x| {
+            DEPENDENCY_TABLES
+                .iter()
+                .filter_map(|t| x.get(t)?.as_table_like())
+        })
+        .flat_map(|x| x.iter())
+        .filter(|(_, dep)| {
+            dep.get("workspace")
+                .and_then(|x| x.as_bool())
+                .unwrap_or(false)
+        })
+        .map(|(name, _)| name.to_string())
+        .collect())
+}
+
+fn normalize(item: &Item) -> String {
+    if let Some(table) = item.as_table_like() {
+        // The order of the keys does not matter
+        let entries = table
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, normalize(v)))
+            .collect::<Set<_>>();
+
+        return format!("{{{}}}", entries.into_iter().collect::<Vec<_>>().join(","));
+    }
+
+    item.as_value().map(normalize_value).unwrap_or_default()
+}
+
+fn normalize_value(value: &Value) -> String {
+    match value {
+        Value::Array(x) => format!(
+            "[{}]",
+            x.iter().map(normalize_value).collect::<Vec<_>>().join(",")
+        ),
+        Value::String(x) => format!("{:?}", x.value()),
+        x => {
+            let mut x = x.clone();
+            x.decor_mut().clear();
+            x.to_string()
+        }
+    }
+}
+
 pub fn change_versions(
     manifest: String,
     pkg_name: &str,
     versions: &Map<String, Version>,
     exact: bool,
 ) -> Result<String> {
+    change_versions_with(manifest, pkg_name, versions, exact, &Set::new())
+}
+
+/// Changes the versions as [`change_versions`] does, except that the requirements on the
+/// unpublished crates are never made exact. They are only rewritten when they no longer
+/// match, and path dependencies without a requirement are left alone as always.
+pub fn change_versions_with(
+    manifest: String,
+    pkg_name: &str,
+    versions: &Map<String, Version>,
+    exact: bool,
+    unpublished: &Set<String>,
+) -> Result<String> {
+    // Dev-dependencies are included so that requirements in dev-dependency cycles are kept valid
     parse(
         manifest,
-        false,
+        true,
         |line, new_lines| {
             if let Some(new_version) = versions.get(pkg_name)
                 && let Some(caps) = VERSION.captures(line) {
@@ -368,13 +644,13 @@ pub fn change_versions(
         },
         |line, new_lines| {
             if let Some(caps) = DEP_DIRECT_VERSION.captures(line) {
-                edit_version(caps, new_lines, versions, exact, 2)?;
+                edit_version(caps, new_lines, versions, exact, unpublished, 2)?;
             } else if let Some(caps) = DEP_OBJ_RENAME_VERSION.captures(line) {
-                edit_version(caps, new_lines, versions, exact, 5)?;
+                edit_version(caps, new_lines, versions, exact, unpublished, 5)?;
             } else if let Some(caps) = DEP_OBJ_RENAME_BEFORE_VERSION.captures(line) {
-                edit_version(caps, new_lines, versions, exact, 2)?;
+                edit_version(caps, new_lines, versions, exact, unpublished, 2)?;
             } else if let Some(caps) = DEP_OBJ_VERSION.captures(line) {
-                edit_version(caps, new_lines, versions, exact, 2)?;
+                edit_version(caps, new_lines, versions, exact, unpublished, 2)?;
             }
 
             Ok(())
@@ -384,10 +660,12 @@ pub fn change_versions(
                 return Ok(Some(Context::DependencyEntry(caps[2].to_string())));
             } else if let Some(caps) = VERSION.captures(line)
                 && let Some(new_version) = versions.get(dep) {
-                    if exact {
-                        new_lines.push(format!("{}={}{}", &caps[1], new_version, &caps[3]));
-                    } else if !VersionReq::parse(&caps[2])?.matches(new_version) {
-                        new_lines.push(format!("{}{}{}", &caps[1], new_version, &caps[3]));
+                    let exact = exact && !unpublished.contains(dep);
+
+                    if let Some(req) =
+                        bumped_requirement(&VersionReq::parse(&caps[2])?, new_version, exact)
+                    {
+                        new_lines.push(format!("{}{}{}", &caps[1], req, &caps[3]));
                     }
                 }
 
@@ -402,6 +680,69 @@ mod test {
     use super::*;
     use indoc::indoc;
 
+    #[test]
+    fn test_workspace_dependencies() {
+        let old = indoc! {r#"
+            [workspace.dependencies]
+            serde = { version = "1.0", features = ["derive"] }
+            log = "0.4"
+            rand = '0.8'
+        "#};
+        let new = indoc! {r#"
+            [workspace.dependencies]
+            log = "0.4.20"
+            rand = "0.8" # formatting only
+
+            [workspace.dependencies.serde]
+            features = ["derive"]
+            version = "1.0"
+        "#};
+
+        let (old, new) = (
+            workspace_dependencies(old).unwrap(),
+            workspace_dependencies(new).unwrap(),
+        );
+
+        assert_eq!(old["serde"], new["serde"]);
+        assert_eq!(old["rand"], new["rand"]);
+        assert_ne!(old["log"], new["log"]);
+    }
+
+    #[test]
+    fn test_inherited_dependencies() {
+        let manifest = indoc! {r#"
+            [package]
+            name = "dep1"
+
+            [dependencies]
+            serde = { workspace = true, features = ["rc"] }
+            log = "0.4"
+
+            [dev-dependencies.rand]
+            workspace = true
+
+            [target.'cfg(unix)'.build-dependencies]
+            cc.workspace = true
+        "#};
+
+        assert_eq!(
+            inherited_dependencies(manifest).unwrap(),
+            ["cc", "rand", "serde"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Set<_>>()
+        );
+    }
+
+    #[test]
+    fn test_flags() {
+        assert!(flags(false, false, false).is_empty());
+        assert_eq!(flags(true, false, false), vec!["--offline"]);
+        assert_eq!(flags(false, false, true), vec!["--locked"]);
+        assert_eq!(flags(true, false, true), vec!["--offline", "--locked"]);
+        assert_eq!(flags(true, true, true), vec!["--frozen"]);
+    }
+
     #[test]
     fn test_version() {
         let m = indoc! {r#"
@@ -736,6 +1077,77 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_inherits_workspace_version() {
+        let m = indoc! {r#"
+            [package]
+            name = "this"
+            version.workspace = true
+        "#};
+
+        assert!(inherits_workspace_version(m).unwrap());
+
+        let m = indoc! {r#"
+            [package]
+            name = "this"
+            version = { workspace = true }
+        "#};
+
+        assert!(inherits_workspace_version(m).unwrap());
+
+        let m = indoc! {r#"
+            [package]
+            name = "this"
+            version = "0.1.0"
+
+            [dependencies]
+            another.workspace = true
+        "#};
+
+        assert!(!inherits_workspace_version(m).unwrap());
+    }
+
+    #[test]
+    fn test_set_workspace_version() {
+        let m = indoc! {r#"
+            [workspace]
+            members = ["this"]
+
+            [workspace.package]
+            version = "0.1.0" # shared
+            edition = "2021"
+
+            [package]
+            name = "root"
+            version = "1.0.0"
+            publish = false
+        "#};
+
+        assert_eq!(
+            set_workspace_version(m, &Version::parse("0.2.0").unwrap()).unwrap(),
+            m.replace("version = \"0.1.0\"", "version = \"0.2.0\"")
+        );
+    }
+
+    #[test]
+    fn test_version_dev_dependencies() {
+        let m = indoc! {r#"
+            [dev-dependencies]
+            this = { path = "../", version = "0.0.1" } # hello
+        "#};
+
+        let mut v = Map::new();
+        v.insert("this".to_string(), Version::parse("0.3.0").unwrap());
+
+        assert_eq!(
+            change_versions(m.into(), "another", &v, false).unwrap(),
+            indoc! {r#"
+                [dev-dependencies]
+                this = { path = "../", version = "0.3.0" } # hello"#
+            }
+        );
+    }
+
     #[test]
     fn test_exact() {
         let m = indoc! {r#"
@@ -755,6 +1167,83 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_bumped_requirement() {
+        let req = |x| VersionReq::parse(x).unwrap();
+        let v = Version::parse("0.3.0+build").unwrap();
+
+        assert_eq!(bumped_requirement(&req("0.2"), &v, false).as_deref(), Some("0.3.0"));
+        assert_eq!(bumped_requirement(&req("0.3"), &v, false), None);
+        assert_eq!(bumped_requirement(&req("0.3"), &v, true).as_deref(), Some("=0.3.0"));
+    }
+
+    #[test]
+    fn test_exact_unpublished() {
+        let m = indoc! {r#"
+            [dependencies]
+            this = { path = "../this", version = "0.3.0" }
+            that = { path = "../that", version = "0.1" }
+            other = { path = "../other" }
+
+            [dependencies.renamed]
+            package = "this"
+            path = "../this"
+            version = "0.1.0"
+        "#};
+
+        let mut v = Map::new();
+        v.insert("this".to_string(), Version::parse("0.3.1").unwrap());
+        v.insert("that".to_string(), Version::parse("0.2.0").unwrap());
+        v.insert("other".to_string(), Version::parse("0.2.0").unwrap());
+
+        let unpublished = ["this", "that", "other"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+
+        assert_eq!(
+            change_versions_with(m.into(), "another", &v, true, &unpublished).unwrap(),
+            indoc! {r#"
+                [dependencies]
+                this = { path = "../this", version = "0.3.0" }
+                that = { path = "../that", version = "0.2.0" }
+                other = { path = "../other" }
+
+                [dependencies.renamed]
+                package = "this"
+                path = "../this"
+                version = "0.3.1""#
+            }
+        );
+    }
+
+    #[test]
+    fn test_build_metadata() {
+        let m = indoc! {r#"
+            [package]
+            version = "0.1.0"
+
+            [dependencies]
+            this = { path = "../", version = "0.0.1" }
+        "#};
+
+        let version = Version::parse("0.3.0+git.abc1234").unwrap();
+        let mut v = Map::new();
+        v.insert("this".to_string(), version.clone());
+        v.insert("another".to_string(), version);
+
+        assert_eq!(
+            change_versions(m.into(), "another", &v, true).unwrap(),
+            indoc! {r#"
+                [package]
+                version = "0.3.0+git.abc1234"
+
+                [dependencies]
+                this = { path = "../", version = "=0.3.0" }"#
+            }
+        );
+    }
+
     #[test]
     fn test_name() {
         let m = indoc! {r#"
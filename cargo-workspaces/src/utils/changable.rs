@@ -1,8 +1,16 @@
-use crate::utils::{get_pkgs, git, info, Error, Pkg, INTERNAL_ERR};
-use cargo_metadata::Metadata;
+use crate::utils::{
+    debug, get_pkgs, git, git_remote, info, inherited_dependencies, is_git_repo, is_offline,
+    package_files, parse_remote_tags, read_config, relative_path, warn, workspace_dependencies,
+    Baseline, Error, Pkg, WorkspaceConfig, WorkspaceContext, INTERNAL_ERR,
+};
 use clap::Parser;
+use glob::Pattern;
 use globset::{Error as GlobsetError, Glob};
-use std::path::Path;
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    fs,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Parser)]
 pub struct ChangeOpt {
@@ -11,15 +19,34 @@ pub struct ChangeOpt {
     #[clap(long, value_name = "PATTERN")]
     pub force: Option<String>,
 
+    /// Consider all the crates changed when files matched by glob changed
+    #[clap(long, value_name = "GLOB")]
+    pub force_all_on: Option<String>,
+
     /// Ignore changes in files matched by glob
     #[clap(long, value_name = "PATTERN")]
     pub ignore_changes: Option<String>,
 
+    /// Ignore changes in files matched by glob, relative to the crate root (can be repeated)
+    #[clap(long, value_name = "GLOB", multiple_occurrences(true))]
+    pub ignore_path: Vec<String>,
+
     /// Use this git reference instead of the last tag
     #[clap(long, forbid_empty_values(true))]
     pub since: Option<String>,
+
+    /// Fetch the release tags from the `origin` remote when there are none locally
+    #[clap(long)]
+    pub fetch_tags: bool,
+
+    /// Attribute changed files to crates by the files they package instead of their directory
+    #[clap(long)]
+    pub precise: bool,
 }
 
+// Remote holding the release tags in shallow or single branch clones
+const TAGS_REMOTE: &str = "origin";
+
 #[derive(Debug, Default)]
 pub struct ChangeData {
     pub since: Option<String>,
@@ -28,13 +55,11 @@ pub struct ChangeData {
 }
 
 impl ChangeData {
-    pub fn new(metadata: &Metadata, _change: &ChangeOpt) -> Result<Self, Error> {
-        let (_, sha, _) = git(
-            &metadata.workspace_root,
-            &["rev-list", "--tags", "--max-count=1"],
-        )?;
+    pub fn new(ctx: &WorkspaceContext, change: &ChangeOpt) -> Result<Self, Error> {
+        // Without history every crate is as good as unreleased
+        if !is_git_repo(&ctx.metadata.workspace_root) {
+            warn!("not a git repository", "considering every crate as changed");
 
-        if sha.is_empty() {
             return Ok(Self {
                 count: "1".to_string(),
                 since: None,
@@ -42,83 +67,408 @@ impl ChangeData {
             });
         }
 
+        change.check_remote_tags(ctx)?;
+
+        let Some(last_tag) = ctx.last_tag()? else {
+            return Ok(Self {
+                count: "1".to_string(),
+                since: None,
+                ..Default::default()
+            });
+        };
+
         let (_, count, _) = git(
-            &metadata.workspace_root,
-            &["rev-list", "--count", &format!("HEAD...{sha}")],
+            &ctx.metadata.workspace_root,
+            &["rev-list", "--count", &format!("HEAD...{}", last_tag.sha)],
         )?;
 
-        let since = git(
-            &metadata.workspace_root,
-            &["describe", "--exact-match", "--tags", &sha],
-        )
-        .ok()
-        .map(|x| x.1);
-
         Ok(Self {
             count,
-            since,
+            since: last_tag.name,
             ..Default::default()
         })
     }
 }
 
 impl ChangeOpt {
+    // Clones which did not fetch the tags would otherwise consider every crate as changed
+    fn check_remote_tags(&self, ctx: &WorkspaceContext) -> Result<(), Error> {
+        // An explicit reference does not need any tag
+        if self.since.is_some() {
+            return Ok(());
+        }
+
+        let config: WorkspaceConfig = read_config(&ctx.metadata.workspace_metadata)?;
+
+        // Globs of the release tags, any tag counts as one without `tag_patterns`
+        let globs = config
+            .tag_patterns
+            .unwrap_or_default()
+            .iter()
+            .map(|x| x.replace("%n", "*"))
+            .collect::<Vec<_>>();
+
+        let root = &ctx.metadata.workspace_root;
+
+        let mut args = vec!["tag", "--list"];
+        args.extend(globs.iter().map(|x| x.as_str()));
+
+        let (_, release_tags, _) = git(root, &args)?;
+
+        if !release_tags.is_empty() {
+            return Ok(());
+        }
+
+        if is_offline() {
+            debug!("offline", "not looking for tags on the remote");
+            return Ok(());
+        }
+
+        let (status, out, err) = git_remote(root, &["ls-remote", "--tags", "--refs", TAGS_REMOTE])?;
+
+        if !status.success() {
+            debug!("unable to list remote tags", err);
+            return Ok(());
+        }
+
+        let patterns = globs
+            .iter()
+            .map(|x| Pattern::new(x))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (_, local_tags, _) = git(root, &["tag", "--list"])?;
+        let local_tags = local_tags.lines().collect::<Set<_>>();
+
+        // Only the release tags which are missing locally
+        let tags = parse_remote_tags(&out)
+            .into_iter()
+            .filter(|x| patterns.is_empty() || patterns.iter().any(|p| p.matches(x)))
+            .filter(|x| !local_tags.contains(x.as_str()))
+            .collect::<Vec<_>>();
+
+        if tags.is_empty() {
+            return Ok(());
+        }
+
+        if !self.fetch_tags {
+            return Err(Error::RemoteTagsNotFetched {
+                remote: TAGS_REMOTE.to_string(),
+                tags: tags.join(", "),
+            });
+        }
+
+        info!("fetching tags from", TAGS_REMOTE);
+
+        let refspecs = tags
+            .iter()
+            .map(|t| format!("refs/tags/{0}:refs/tags/{0}", t))
+            .collect::<Vec<_>>();
+
+        let mut args = vec!["fetch", "--no-tags", TAGS_REMOTE];
+        args.extend(refspecs.iter().map(|x| x.as_str()));
+
+        let (status, out, err) = git_remote(root, &args)?;
+
+        if !status.success() {
+            return Err(Error::NotFetched(out, err));
+        }
+
+        Ok(())
+    }
+
     pub fn get_changed_pkgs(
         &self,
-        metadata: &Metadata,
+        ctx: &WorkspaceContext,
         // Optional because there can be no tags
         since: &Option<String>,
         private: bool,
     ) -> Result<(Vec<Pkg>, Vec<Pkg>), Error> {
-        let pkgs = get_pkgs(metadata, private)?;
-
-        let pkgs = if let Some(since) = since {
-            info!("looking for changes since", since);
-
-            let (_, changed_files, _) = git(
-                &metadata.workspace_root,
-                &["diff", "--name-only", "--relative", since],
-            )?;
-
-            let changed_files = changed_files
-                .split('\n')
-                .filter(|f| !f.is_empty())
-                .map(Path::new)
-                .collect::<Vec<_>>();
-
-            let force = self
-                .force
-                .clone()
-                .map(|x| Glob::new(&x))
-                .map_or::<Result<_, GlobsetError>, _>(Ok(None), |x| Ok(x.ok()))?;
-            let ignore_changes = self
-                .ignore_changes
-                .clone()
-                .map(|x| Glob::new(&x))
-                .map_or::<Result<_, GlobsetError>, _>(Ok(None), |x| Ok(x.ok()))?;
-
-            pkgs.into_iter().partition(|p: &Pkg| {
-                if let Some(pattern) = &force
-                    && pattern.compile_matcher().is_match(&p.name) {
-                        return true;
+        let pkgs = get_pkgs(&ctx.metadata, private)?;
+
+        let Some(since) = since else {
+            return Ok(unreleased(pkgs));
+        };
+
+        let config: WorkspaceConfig = read_config(&ctx.metadata.workspace_metadata)?;
+
+        // An explicit reference applies to every crate
+        let patterns = match config.tag_patterns {
+            Some(patterns) if self.since.is_none() => patterns,
+            _ => return self.changed_since(ctx, since, pkgs),
+        };
+
+        let order = pkgs
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.name.clone(), i))
+            .collect::<Map<_, _>>();
+        let mut groups = Map::<Option<String>, Vec<Pkg>>::new();
+
+        for mut pkg in pkgs {
+            pkg.baseline = baseline(ctx, &pkg.name, &patterns)?;
+
+            match &pkg.baseline {
+                Some(x) => debug!(
+                    "baseline",
+                    format!("{} since {} ({})", pkg.name, x.tag, x.pattern)
+                ),
+                None => debug!("baseline", format!("{} has no release tag", pkg.name)),
+            }
+
+            groups
+                .entry(pkg.baseline.as_ref().map(|x| x.tag.clone()))
+                .or_default()
+                .push(pkg);
+        }
+
+        let (mut changed, mut unchanged) = (vec![], vec![]);
+
+        for (since, pkgs) in groups {
+            let (c, u) = match since {
+                Some(since) => self.changed_since(ctx, &since, pkgs)?,
+                None => unreleased(pkgs),
+            };
+
+            changed.extend(c);
+            unchanged.extend(u);
+        }
+
+        changed.sort_by_key(|p| order[&p.name]);
+        unchanged.sort_by_key(|p| order[&p.name]);
+
+        Ok((changed, unchanged))
+    }
+
+    fn changed_since(
+        &self,
+        ctx: &WorkspaceContext,
+        since: &str,
+        pkgs: Vec<Pkg>,
+    ) -> Result<(Vec<Pkg>, Vec<Pkg>), Error> {
+        info!("looking for changes since", since);
+
+        let changed_files = ctx.changed_files(since)?;
+        let changed_files = changed_files.iter().map(Path::new).collect::<Vec<_>>();
+
+        if let Some(pattern) = &self.force_all_on {
+            let pattern = Glob::new(pattern)?.compile_matcher();
+
+            if let Some(f) = changed_files.iter().find(|f| pattern.is_match(f)) {
+                info!("all crates changed because of", f.display());
+                return Ok((pkgs, vec![]));
+            }
+        }
+
+        let force = self
+            .force
+            .clone()
+            .map(|x| Glob::new(&x))
+            .map_or::<Result<_, GlobsetError>, _>(Ok(None), |x| Ok(x.ok()))?;
+        let ignore_changes = self
+            .ignore_changes
+            .clone()
+            .map(|x| Glob::new(&x))
+            .map_or::<Result<_, GlobsetError>, _>(Ok(None), |x| Ok(x.ok()))?;
+        let ignore_path = self
+            .ignore_path
+            .iter()
+            .map(|x| Pattern::new(x))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let package_files = if self.precise {
+            Some(package_files(&ctx.metadata, &pkgs)?)
+        } else {
+            None
+        };
+
+        let inheriting = if changed_files.contains(&Path::new("Cargo.toml"))
+            && !ignore_changes
+                .as_ref()
+                .is_some_and(|x| x.compile_matcher().is_match("Cargo.toml"))
+        {
+            inheriting_pkgs(ctx, since, &pkgs)?
+        } else {
+            Set::new()
+        };
+
+        let member_paths = member_paths(ctx);
+
+        // Files not packaged or watched by any crate are attributed by directory. The watched paths
+        // can be directories, hence the ancestors of each file are looked up too
+        let claimed = package_files
+            .iter()
+            .flat_map(|x| x.values().flatten())
+            .map(|x| x.as_path())
+            .collect::<Set<_>>();
+
+        Ok(pkgs.into_iter().partition(|p: &Pkg| {
+            if let Some(pattern) = &force
+                && pattern.compile_matcher().is_match(&p.name)
+            {
+                return true;
+            }
+
+            // Crates which opted out are never considered changed by their edits
+            if p.config.changed_ignore.unwrap_or(false) {
+                return false;
+            }
+
+            if inheriting.contains(&p.name) {
+                return true;
+            }
+
+            changed_files.iter().any(|f| {
+                if let Some(pattern) = &ignore_changes
+                    && pattern
+                        .compile_matcher()
+                        .is_match(f.to_str().expect(INTERNAL_ERR))
+                {
+                    return false;
+                }
+
+                if let Some(package_files) = &package_files
+                    && f.ancestors().any(|x| claimed.contains(x))
+                {
+                    if !f.ancestors().any(|x| package_files[&p.name].contains(x)) {
+                        return false;
                     }
 
-                changed_files.iter().any(|f| {
-                    if let Some(pattern) = &ignore_changes
-                        && pattern
-                            .compile_matcher()
-                            .is_match(f.to_str().expect(INTERNAL_ERR))
-                        {
-                            return false;
-                        }
-
-                    f.starts_with(&p.path)
-                })
+                    let rel = f.strip_prefix(p.prefix()).unwrap_or(*f);
+                    return !ignore_path.iter().any(|x| x.matches_path(rel));
+                }
+
+                match f.strip_prefix(p.prefix()) {
+                    Ok(_) if in_nested_member(&member_paths, p.prefix(), f) => false,
+                    Ok(rel) => !ignore_path.iter().any(|x| x.matches_path(rel)),
+                    Err(_) => false,
+                }
             })
-        } else {
-            (pkgs, vec![])
+        }))
+    }
+}
+
+/// Crates without a release to compare with, all changed unless they opted out
+fn unreleased(pkgs: Vec<Pkg>) -> (Vec<Pkg>, Vec<Pkg>) {
+    pkgs.into_iter()
+        .partition(|p| !p.config.changed_ignore.unwrap_or(false))
+}
+
+/// The most recent release tag of the crate among the ones matching the patterns. A tag
+/// only wins over the one of a pattern with precedence if it comes after it in history.
+fn baseline(
+    ctx: &WorkspaceContext,
+    name: &str,
+    patterns: &[String],
+) -> Result<Option<Baseline>, Error> {
+    let root = &ctx.metadata.workspace_root;
+    let mut ret: Option<(Baseline, String)> = None;
+
+    for pattern in patterns {
+        let Some(tag) = ctx.last_tag_matching(&pattern.replace("%n", name))? else {
+            continue;
         };
 
-        Ok(pkgs)
+        if let Some((_, sha)) = &ret {
+            let (status, _, _) = git(root, &["merge-base", "--is-ancestor", sha, &tag.sha])?;
+
+            if *sha == tag.sha || !status.success() {
+                continue;
+            }
+        }
+
+        let baseline = Baseline {
+            tag: tag.name.expect(INTERNAL_ERR),
+            pattern: pattern.clone(),
+        };
+
+        ret = Some((baseline, tag.sha));
+    }
+
+    Ok(ret.map(|(x, _)| x))
+}
+
+/// Names of the crates inheriting a `[workspace.dependencies]` entry of the root manifest
+/// which changed since the reference
+fn inheriting_pkgs(
+    ctx: &WorkspaceContext,
+    since: &str,
+    pkgs: &[Pkg],
+) -> Result<Set<String>, Error> {
+    let root = &ctx.metadata.workspace_root;
+
+    // The manifest is read relative to the workspace root, which is where git runs
+    let (status, old, _) = git(root, &["show", &format!("{}:./Cargo.toml", since)])?;
+
+    // The workspace might not have existed yet
+    let old = if status.success() {
+        workspace_dependencies(&old).unwrap_or_default()
+    } else {
+        Map::new()
+    };
+    let new = workspace_dependencies(&fs::read_to_string(root.join("Cargo.toml"))?)?;
+
+    let deps = old
+        .keys()
+        .chain(new.keys())
+        .filter(|x| old.get(*x) != new.get(*x))
+        .cloned()
+        .collect::<Set<_>>();
+
+    if deps.is_empty() {
+        return Ok(Set::new());
+    }
+
+    info!(
+        "workspace dependencies changed",
+        deps.iter().cloned().collect::<Vec<_>>().join(", ")
+    );
+
+    let mut ret = Set::new();
+
+    for pkg in pkgs {
+        let manifest = fs::read_to_string(pkg.location.join("Cargo.toml"))?;
+
+        if !inherited_dependencies(&manifest)?.is_disjoint(&deps) {
+            ret.insert(pkg.name.clone());
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Paths of all the members relative to the workspace root, including the private ones
+fn member_paths(ctx: &WorkspaceContext) -> Vec<PathBuf> {
+    let metadata = &ctx.metadata;
+
+    metadata
+        .packages
+        .iter()
+        .filter(|x| metadata.workspace_members.contains(&x.id))
+        .filter_map(|x| relative_path(x.manifest_path.parent()?, &metadata.workspace_root))
+        .map(|x| x.into_std_path_buf())
+        .collect()
+}
+
+/// Whether the file belongs to a member nested in the crate at `path`, like any member
+/// is nested in a root package
+fn in_nested_member(member_paths: &[PathBuf], path: &Path, file: &Path) -> bool {
+    member_paths
+        .iter()
+        .any(|x| x != path && x.starts_with(path) && file.starts_with(x))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_in_nested_member() {
+        let paths = vec![PathBuf::new(), PathBuf::from("dep1")];
+        let (root, dep1) = (Path::new(""), Path::new("dep1"));
+        let file = Path::new("dep1/src/lib.rs");
+
+        assert!(in_nested_member(&paths, root, file));
+        assert!(!in_nested_member(&paths, root, Path::new("src/main.rs")));
+        assert!(!in_nested_member(&paths, dep1, file));
     }
 }
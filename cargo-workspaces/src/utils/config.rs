@@ -1,11 +1,22 @@
-use crate::utils::{Error, Result};
+use crate::utils::{suggest, Error, Forge, Result};
 
-use serde::Deserialize;
-use serde_json::{from_value, Value};
+use cargo_metadata::Metadata;
+use serde::{
+    de::{self, DeserializeOwned, Visitor},
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+use serde_json::{from_value, Map, Value};
+
+use std::result::Result as StdResult;
+
+/// The tables the config is read from, `cargo-workspaces` is accepted as well
+pub const CONFIG_TABLES: &[&str] = &["workspaces", "cargo-workspaces"];
 
 #[derive(Deserialize, Default)]
 struct MetadataWorkspaces<T> {
     pub workspaces: Option<T>,
+    #[serde(rename = "cargo-workspaces")]
+    pub cargo_workspaces: Option<T>,
 }
 
 // TODO: Validation of conflicting options (hard to tell conflicts if between cli and option)
@@ -15,16 +26,302 @@ where
 {
     from_value::<Option<MetadataWorkspaces<T>>>(value.clone())
         .map_err(Error::BadMetadata)
-        .map(|v| v.unwrap_or_default().workspaces.unwrap_or_default())
+        .map(|v| {
+            let v = v.unwrap_or_default();
+            v.workspaces.or(v.cargo_workspaces).unwrap_or_default()
+        })
 }
 
 #[derive(Deserialize, Default, Debug, Clone, Ord, Eq, PartialOrd, PartialEq)]
 pub struct PackageConfig {
     pub independent: Option<bool>,
+    pub skip_version: Option<bool>,
+    pub skip_publish: Option<bool>,
+    pub changed_ignore: Option<bool>,
+    pub entry_point: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Clone, Ord, Eq, PartialOrd, PartialEq)]
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
 pub struct WorkspaceConfig {
     pub allow_branch: Option<String>,
     pub no_individual_tags: Option<bool>,
+    /// Where `--forge-release` creates the releases
+    pub forge: Option<Forge>,
+    /// API of a self-hosted forge, like `https://ghe.example.com/api/v3`
+    pub api_url: Option<String>,
+    /// Patterns of the release tags, by precedence, which `changed` looks up for each crate
+    /// with `%n` replaced by its name, like `["%n@*", "v*"]`
+    pub tag_patterns: Option<Vec<String>>,
+    /// Where `publish` records the released crates, `release-manifest.json` by default
+    pub release_manifest: Option<String>,
+    /// Read by `init` from the manifest itself, only deserialized to be validated
+    #[serde(default)]
+    pub init: Option<InitTable>,
+}
+
+/// The `init` table of the workspace config, see `InitConfig` in the init command
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct InitTable {
+    resolver: Option<ResolverValue>,
+    members: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    generate_cargo_config: Option<bool>,
+    readme_index: Option<bool>,
+    editorconfig: Option<bool>,
+}
+
+/// `resolver` is accepted both as `"2"` and `2`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged, expecting = "a string or an integer")]
+enum ResolverValue {
+    String(String),
+    Integer(u64),
+}
+
+/// The problems found in the `workspaces` tables, one message each
+#[derive(Debug, Default)]
+pub struct ConfigProblems {
+    /// Keys which are not read by any command, most likely typos
+    pub unknown: Vec<String>,
+    /// Values of the wrong type, which would fail the commands reading them
+    pub invalid: Vec<String>,
+}
+
+/// Checks the `workspaces` tables of the workspace and of its members
+pub fn validate_config(metadata: &Metadata) -> ConfigProblems {
+    let root = metadata.workspace_root.join("Cargo.toml");
+    let mut ret = ConfigProblems::default();
+
+    validate_workspace(&metadata.workspace_metadata, root.as_str(), &mut ret);
+
+    for pkg in &metadata.packages {
+        if metadata.workspace_members.contains(&pkg.id) {
+            for (path, value) in config_tables(&pkg.metadata, "package.metadata") {
+                check_table::<PackageConfig>(
+                    value,
+                    &path,
+                    &[],
+                    pkg.manifest_path.as_str(),
+                    &mut ret,
+                );
+            }
+        }
+    }
+
+    ret
+}
+
+fn validate_workspace(metadata: &Value, manifest: &str, ret: &mut ConfigProblems) {
+    for (path, value) in config_tables(metadata, "workspace.metadata") {
+        check_table::<WorkspaceConfig>(value, &path, &["init"], manifest, ret);
+
+        if let Some(init) = value.get("init").filter(|x| x.is_object()) {
+            check_table::<InitTable>(init, &format!("{}.init", path), &[], manifest, ret);
+        }
+    }
+}
+
+fn config_tables<'a>(metadata: &'a Value, prefix: &str) -> Vec<(String, &'a Value)> {
+    CONFIG_TABLES
+        .iter()
+        .filter_map(|table| Some((format!("{}.{}", prefix, table), metadata.get(table)?)))
+        .collect()
+}
+
+fn check_table<T: DeserializeOwned>(
+    value: &Value,
+    path: &str,
+    nested: &[&str],
+    manifest: &str,
+    ret: &mut ConfigProblems,
+) {
+    let Some(table) = value.as_object() else {
+        ret.invalid.push(format!(
+            "{}: expected a table for `{}`, found {}",
+            manifest,
+            path,
+            describe(value)
+        ));
+        return;
+    };
+
+    let keys = struct_fields::<T>();
+
+    for (key, value) in table {
+        if !keys.contains(&key.as_str()) {
+            let mut msg = format!("{}: unknown key `{}.{}`", manifest, path, key);
+
+            if let Some(x) = suggest(key, keys.iter().copied()) {
+                msg.push_str(&format!(", did you mean `{}`?", x));
+            }
+
+            ret.unknown.push(msg);
+            continue;
+        }
+
+        // Nested tables are checked on their own, to name the faulty key
+        if nested.contains(&key.as_str()) && value.is_object() {
+            continue;
+        }
+
+        // Each key on its own, so that the message can name it
+        let mut single = Map::new();
+        single.insert(key.clone(), value.clone());
+
+        if let Err(err) = from_value::<T>(Value::Object(single)) {
+            ret.invalid
+                .push(format!("{}: invalid `{}.{}`: {}", manifest, path, key, err));
+        }
+    }
+}
+
+/// The names of the fields of the struct, as serde reads them. They are recorded from
+/// the derived `Deserialize`, which passes them to `deserialize_struct`.
+fn struct_fields<T: DeserializeOwned>() -> &'static [&'static str] {
+    struct Fields<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for Fields<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> StdResult<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only the fields are needed"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+            identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(Fields(&mut fields));
+    fields
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "nothing".to_string(),
+        Value::Bool(_) => "boolean".to_string(),
+        Value::Number(x) if x.is_f64() => "float".to_string(),
+        Value::Number(_) => "integer".to_string(),
+        Value::String(x) => format!("string \"{}\"", x),
+        Value::Array(_) => "array".to_string(),
+        Value::Object(_) => "table".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_config_cargo_workspaces() {
+        let value = json!({ "cargo-workspaces": { "independent": true } });
+        let config: PackageConfig = read_config(&value).unwrap();
+
+        assert_eq!(config.independent, Some(true));
+
+        // The regular table wins when both are present
+        let value = json!({
+            "workspaces": { "independent": false },
+            "cargo-workspaces": { "independent": true },
+        });
+        let config: PackageConfig = read_config(&value).unwrap();
+
+        assert_eq!(config.independent, Some(false));
+    }
+
+    fn validate(value: Value) -> ConfigProblems {
+        let mut ret = ConfigProblems::default();
+        validate_workspace(&value, "Cargo.toml", &mut ret);
+        ret
+    }
+
+    #[test]
+    fn test_struct_fields() {
+        assert_eq!(
+            struct_fields::<PackageConfig>(),
+            [
+                "independent",
+                "skip_version",
+                "skip_publish",
+                "changed_ignore",
+                "entry_point"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_unknown_key() {
+        let problems = validate(json!({
+            "workspaces": { "allow_brnach": "main", "other": 1, "init": { "exlude": [] } }
+        }));
+
+        assert_eq!(
+            problems.unknown,
+            vec![
+                "Cargo.toml: unknown key `workspace.metadata.workspaces.allow_brnach`, did you mean `allow_branch`?",
+                "Cargo.toml: unknown key `workspace.metadata.workspaces.other`",
+                "Cargo.toml: unknown key `workspace.metadata.workspaces.init.exlude`, did you mean `exclude`?",
+            ]
+        );
+        assert!(problems.invalid.is_empty());
+
+        // Unknown keys don't prevent reading the config
+        let value = json!({ "workspaces": { "allow_brnach": "main", "allow_branch": "main" } });
+        let config: WorkspaceConfig = read_config(&value).unwrap();
+
+        assert_eq!(config.allow_branch.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_validate_types() {
+        let value = json!({
+            "cargo-workspaces": {
+                "no_individual_tags": "yes",
+                "forge": "bitbucket",
+                "init": { "exclude": "examples", "resolver": 2 },
+            }
+        });
+        let problems = validate(value);
+
+        assert!(problems.unknown.is_empty());
+        assert_eq!(
+            problems.invalid,
+            vec![
+                "Cargo.toml: invalid `workspace.metadata.cargo-workspaces.forge`: unknown variant `bitbucket`, expected `github` or `gitlab`",
+                "Cargo.toml: invalid `workspace.metadata.cargo-workspaces.no_individual_tags`: invalid type: string \"yes\", expected a boolean",
+                "Cargo.toml: invalid `workspace.metadata.cargo-workspaces.init.exclude`: invalid type: string \"examples\", expected a sequence",
+            ]
+        );
+        assert_eq!(
+            validate(json!({ "workspaces": ["a"] })).invalid,
+            vec!["Cargo.toml: expected a table for `workspace.metadata.workspaces`, found array"]
+        );
+    }
+
+    #[test]
+    fn test_validated_config_reads() {
+        let value = json!({ "workspaces": { "forge": "gitlab", "init": { "members": ["a"], "resolver": "2" } } });
+        let problems = validate(value.clone());
+
+        assert!(problems.unknown.is_empty());
+        assert!(problems.invalid.is_empty());
+        assert_eq!(
+            read_config::<WorkspaceConfig>(&value).unwrap().forge,
+            Some(Forge::Gitlab)
+        );
+    }
 }
@@ -0,0 +1,321 @@
+//! Per-invocation cache of the expensive lookups shared by the commands.
+
+use crate::utils::{
+    debug, git, is_git_repo, metadata_command, validate_config, warn, DependencyGraph, Error,
+    Result,
+};
+
+use cargo_metadata::{Metadata, MetadataCommand};
+
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::BTreeMap as Map,
+    rc::Rc,
+    time::Instant,
+};
+
+/// The last tag found in the repository
+#[derive(Debug, Clone)]
+pub struct LastTag {
+    pub sha: String,
+    /// Name of the tag, if it points exactly to `sha`
+    pub name: Option<String>,
+}
+
+/// Loads the cargo metadata once and memoizes the git lookups done on top of it.
+pub struct WorkspaceContext {
+    pub metadata: Metadata,
+    graph: OnceCell<DependencyGraph>,
+    resolved: OnceCell<Rc<Metadata>>,
+    last_tag: OnceCell<Option<LastTag>>,
+    last_tags_matching: RefCell<Map<String, Option<LastTag>>>,
+    changed_files: RefCell<Map<String, Vec<String>>>,
+}
+
+impl WorkspaceContext {
+    pub fn new(metadata: Metadata) -> Self {
+        Self {
+            metadata,
+            graph: OnceCell::new(),
+            resolved: OnceCell::new(),
+            last_tag: OnceCell::new(),
+            last_tags_matching: RefCell::new(Map::new()),
+            changed_files: RefCell::new(Map::new()),
+        }
+    }
+
+    pub fn load(cmd: &MetadataCommand) -> Result<Self> {
+        let metadata = exec_timed(cmd)?;
+
+        // Typos in the config would otherwise be silently ignored
+        let problems = validate_config(&metadata);
+
+        for msg in problems.unknown {
+            warn!("config", msg);
+        }
+
+        if !problems.invalid.is_empty() {
+            return Err(Error::InvalidConfig(problems.invalid));
+        }
+
+        Ok(Self::new(metadata))
+    }
+
+    /// The dependencies of the members on each other
+    pub fn dependency_graph(&self) -> &DependencyGraph {
+        self.graph.get_or_init(|| {
+            let pkgs = self
+                .metadata
+                .packages
+                .iter()
+                .map(|x| (x.clone(), x.version.to_string()))
+                .collect::<Vec<_>>();
+
+            DependencyGraph::new(&pkgs)
+        })
+    }
+
+    /// The metadata with the dependencies resolved for the default features. It is loaded
+    /// on first use, so that it sees the manifests as rewritten by the command.
+    pub fn resolved_metadata(&self) -> Result<Rc<Metadata>> {
+        self.resolved_metadata_with(|| {
+            exec_timed(
+                metadata_command().manifest_path(self.metadata.workspace_root.join("Cargo.toml")),
+            )
+        })
+    }
+
+    fn resolved_metadata_with<F>(&self, f: F) -> Result<Rc<Metadata>>
+    where
+        F: FnOnce() -> Result<Metadata>,
+    {
+        if let Some(metadata) = self.resolved.get() {
+            debug!("cached", "resolved metadata");
+            return Ok(metadata.clone());
+        }
+
+        let metadata = f()?;
+
+        Ok(self.resolved.get_or_init(|| Rc::new(metadata)).clone())
+    }
+
+    pub fn last_tag(&self) -> Result<Option<LastTag>> {
+        if let Some(last_tag) = self.last_tag.get() {
+            debug!("cached", "last tag");
+            return Ok(last_tag.clone());
+        }
+
+        let start = Instant::now();
+        let root = &self.metadata.workspace_root;
+
+        let (_, sha, _) = git(root, &["rev-list", "--tags", "--max-count=1"])?;
+
+        let last_tag = if sha.is_empty() {
+            None
+        } else {
+            let name = git(root, &["describe", "--exact-match", "--tags", &sha])
+                .ok()
+                .map(|x| x.1);
+
+            Some(LastTag { sha, name })
+        };
+
+        debug!(
+            "timing",
+            format!("last tag lookup took {:?}", start.elapsed())
+        );
+
+        Ok(self.last_tag.get_or_init(|| last_tag).clone())
+    }
+
+    /// The tag matching the glob which is the closest ancestor of `HEAD`
+    pub fn last_tag_matching(&self, pattern: &str) -> Result<Option<LastTag>> {
+        if let Some(last_tag) = self.last_tags_matching.borrow().get(pattern) {
+            debug!("cached", format!("last tag matching {}", pattern));
+            return Ok(last_tag.clone());
+        }
+
+        let root = &self.metadata.workspace_root;
+
+        let (status, name, _) = git(
+            root,
+            &[
+                "describe",
+                "--tags",
+                "--abbrev=0",
+                "--match",
+                pattern,
+                "HEAD",
+            ],
+        )?;
+
+        let last_tag = if status.success() && !name.is_empty() {
+            let (_, sha, _) = git(root, &["rev-list", "--max-count=1", &name])?;
+
+            Some(LastTag {
+                sha,
+                name: Some(name),
+            })
+        } else {
+            None
+        };
+
+        self.last_tags_matching
+            .borrow_mut()
+            .insert(pattern.to_string(), last_tag.clone());
+
+        Ok(last_tag)
+    }
+
+    /// Files changed since the given git reference, relative to the workspace root
+    pub fn changed_files(&self, since: &str) -> Result<Vec<String>> {
+        self.changed_files_with(since, || {
+            let root = &self.metadata.workspace_root;
+
+            if !is_git_repo(root) {
+                return Err(Error::NotGit);
+            }
+
+            // Location of the workspace root inside the repository, like `app/server/`
+            let (_, prefix, _) = git(root, &["rev-parse", "--show-prefix"])?;
+            // Renamed files are only reported at their current path, so that they are
+            // attributed to the crate they live in now instead of their old location. This
+            // is explicit since `diff.renames` can turn the detection off.
+            let (_, changed_files, _) =
+                git(root, &["diff", "--name-only", "--find-renames", since])?;
+
+            Ok(workspace_relative(&changed_files, &prefix))
+        })
+    }
+
+    fn changed_files_with<F>(&self, since: &str, f: F) -> Result<Vec<String>>
+    where
+        F: FnOnce() -> Result<Vec<String>>,
+    {
+        if let Some(files) = self.changed_files.borrow().get(since) {
+            debug!("cached", format!("changed files since {}", since));
+            return Ok(files.clone());
+        }
+
+        let start = Instant::now();
+        let files = f()?;
+
+        debug!(
+            "timing",
+            format!("changed files since {} took {:?}", since, start.elapsed())
+        );
+
+        self.changed_files
+            .borrow_mut()
+            .insert(since.to_string(), files.clone());
+
+        Ok(files)
+    }
+}
+
+fn exec_timed(cmd: &MetadataCommand) -> Result<Metadata> {
+    let start = Instant::now();
+    let metadata = cmd.exec()?;
+
+    debug!(
+        "timing",
+        format!("cargo metadata took {:?}", start.elapsed())
+    );
+
+    Ok(metadata)
+}
+
+/// Translates the repository relative paths printed by git into workspace relative ones,
+/// dropping the files which are outside of the workspace
+fn workspace_relative(files: &str, prefix: &str) -> Vec<String> {
+    files
+        .split('\n')
+        .filter(|f| !f.is_empty())
+        .filter_map(|f| f.strip_prefix(prefix))
+        .map(|f| f.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_workspace_relative() {
+        let files = "README.md\napp/server/Cargo.toml\napp/server/dep1/src/lib.rs\ninfra/a.rs";
+
+        assert_eq!(
+            workspace_relative(files, "app/server/"),
+            vec!["Cargo.toml", "dep1/src/lib.rs"]
+        );
+        assert_eq!(workspace_relative(files, "").len(), 4);
+        assert!(workspace_relative("", "app/server/").is_empty());
+    }
+
+    fn context() -> WorkspaceContext {
+        let metadata = serde_json::from_str(
+            r#"{
+                "packages": [],
+                "workspace_members": [],
+                "resolve": null,
+                "workspace_root": "/ws",
+                "target_directory": "/ws/target",
+                "version": 1
+            }"#,
+        )
+        .unwrap();
+
+        WorkspaceContext::new(metadata)
+    }
+
+    #[test]
+    fn test_changed_files_memoized() {
+        let ctx = context();
+        let calls = RefCell::new(0);
+
+        for _ in 0..3 {
+            let files = ctx
+                .changed_files_with("v1.0.0", || {
+                    *calls.borrow_mut() += 1;
+                    Ok(vec!["a/src/lib.rs".to_string()])
+                })
+                .unwrap();
+
+            assert_eq!(files, vec!["a/src/lib.rs"]);
+        }
+
+        assert_eq!(*calls.borrow(), 1);
+    }
+
+    #[test]
+    fn test_resolved_metadata_memoized() {
+        let ctx = context();
+        let calls = RefCell::new(0);
+
+        for _ in 0..3 {
+            ctx.resolved_metadata_with(|| {
+                *calls.borrow_mut() += 1;
+                Ok(context().metadata)
+            })
+            .unwrap();
+        }
+
+        assert_eq!(*calls.borrow(), 1);
+    }
+
+    #[test]
+    fn test_changed_files_keyed_by_ref() {
+        let ctx = context();
+        let calls = RefCell::new(0);
+
+        for since in ["v1.0.0", "v1.1.0", "v1.0.0"] {
+            ctx.changed_files_with(since, || {
+                *calls.borrow_mut() += 1;
+                Ok(vec![])
+            })
+            .unwrap();
+        }
+
+        assert_eq!(*calls.borrow(), 2);
+    }
+}
@@ -0,0 +1,128 @@
+// https://doc.rust-lang.org/reference/keywords.html
+const KEYWORDS: &[&str] = &[
+    "Self", "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+    "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if",
+    "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+// Names refused by cargo since they clash with the standard library or the target directory
+const RESERVED: &[&str] = &[
+    "alloc",
+    "build",
+    "core",
+    "deps",
+    "examples",
+    "incremental",
+    "proc-macro",
+    "proc_macro",
+    "std",
+    "test",
+];
+
+// Device names which can't be used as file names on Windows, the ports are numbered 1 to 9
+const WINDOWS_DEVICES: &[&str] = &["aux", "con", "nul", "prn"];
+const WINDOWS_PORTS: &[&str] = &["com", "lpt"];
+
+// https://github.com/rust-lang/crates.io/blob/main/crates/crates_io_database/src/models/krate.rs
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Checks the name against the same rules as `cargo new`
+pub fn check_crate_name(name: &str) -> Result<(), &'static str> {
+    let first = name.chars().next().ok_or("name cannot be empty")?;
+
+    if !first.is_ascii_alphabetic() && first != '_' {
+        return Err("name must start with a letter or underscore");
+    }
+
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err("name can only contain alphanumeric characters, `-` or `_`");
+    }
+
+    if KEYWORDS.contains(&name) {
+        return Err("name cannot be a rust keyword");
+    }
+
+    if RESERVED.contains(&name) || is_windows_device(name) {
+        return Err("name is reserved by cargo");
+    }
+
+    if name.len() > MAX_NAME_LENGTH {
+        return Err("name cannot be longer than 64 characters");
+    }
+
+    Ok(())
+}
+
+fn is_windows_device(name: &str) -> bool {
+    let name = name.to_lowercase();
+
+    WINDOWS_DEVICES.contains(&name.as_str())
+        || WINDOWS_PORTS.iter().any(|x| {
+            name.strip_prefix(x)
+                .is_some_and(|n| matches!(n.as_bytes(), [b'1'..=b'9']))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_crate_name() {
+        assert!(check_crate_name("foo").is_ok());
+        assert!(check_crate_name("foo-bar_2").is_ok());
+        assert!(check_crate_name("_foo").is_ok());
+    }
+
+    #[test]
+    fn test_check_crate_name_empty() {
+        assert_eq!(check_crate_name(""), Err("name cannot be empty"));
+    }
+
+    #[test]
+    fn test_check_crate_name_start() {
+        assert_eq!(
+            check_crate_name("1foo"),
+            Err("name must start with a letter or underscore")
+        );
+        assert_eq!(
+            check_crate_name("-foo"),
+            Err("name must start with a letter or underscore")
+        );
+    }
+
+    #[test]
+    fn test_check_crate_name_chars() {
+        assert_eq!(
+            check_crate_name("foo.bar"),
+            Err("name can only contain alphanumeric characters, `-` or `_`")
+        );
+    }
+
+    #[test]
+    fn test_check_crate_name_keyword() {
+        assert_eq!(check_crate_name("fn"), Err("name cannot be a rust keyword"));
+        assert_eq!(
+            check_crate_name("self"),
+            Err("name cannot be a rust keyword")
+        );
+    }
+
+    #[test]
+    fn test_check_crate_name_reserved() {
+        assert_eq!(check_crate_name("std"), Err("name is reserved by cargo"));
+        assert_eq!(check_crate_name("Nul"), Err("name is reserved by cargo"));
+        assert_eq!(check_crate_name("com1"), Err("name is reserved by cargo"));
+        assert!(check_crate_name("com").is_ok());
+        assert!(check_crate_name("lpt10").is_ok());
+        assert_eq!(
+            check_crate_name(&"a".repeat(65)),
+            Err("name cannot be longer than 64 characters")
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Looks up who owns crate names on crates.io, within the limits of its crawler policy.
+//!
+//! See https://crates.io/data-access and https://crates.io/policies#crawlers
+
+use crate::utils::{debug, Result};
+
+use serde::{Deserialize, Serialize};
+use tame_index::external::reqwest::{
+    blocking::{Client, RequestBuilder},
+    header::USER_AGENT,
+    StatusCode,
+};
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+const API_URL: &str = "https://crates.io/api/v1";
+
+// At most one request per second
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether a crate name is free to use on crates.io
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "status", content = "owners", rename_all = "lowercase")]
+pub enum NameStatus {
+    Available,
+    /// Owned by one of the given owners
+    Yours,
+    /// Owned by someone else, with the logins of the owners
+    Conflict(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct OwnersResponse {
+    users: Vec<Owner>,
+}
+
+#[derive(Deserialize)]
+struct Owner {
+    login: String,
+}
+
+pub struct CratesIo {
+    client: Client,
+    last_request: Option<Instant>,
+}
+
+impl CratesIo {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            last_request: None,
+        }
+    }
+
+    /// Checks the name against the owners which are considered the same entity, like
+    /// `alice` or the team `github:acme:core`
+    pub fn name_status(&mut self, name: &str, owners: &[String]) -> Result<NameStatus> {
+        if let Some(elapsed) = self.last_request.map(|x| x.elapsed())
+            && elapsed < REQUEST_INTERVAL
+        {
+            thread::sleep(REQUEST_INTERVAL - elapsed);
+        }
+
+        let url = format!("{}/crates/{}/owners", API_URL, name);
+        debug!("checking name", url);
+
+        let res = with_user_agent(self.client.get(&url)).send();
+        self.last_request = Some(Instant::now());
+        let res = res?;
+
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(NameStatus::Available);
+        }
+
+        let body = res.error_for_status()?.text()?;
+        let users = serde_json::from_str::<OwnersResponse>(&body)?.users;
+
+        Ok(classify(users, owners))
+    }
+}
+
+fn classify(users: Vec<Owner>, owners: &[String]) -> NameStatus {
+    if users
+        .iter()
+        .any(|x| owners.iter().any(|o| o.eq_ignore_ascii_case(&x.login)))
+    {
+        NameStatus::Yours
+    } else {
+        NameStatus::Conflict(users.into_iter().map(|x| x.login).collect())
+    }
+}
+
+// crates.io rejects requests without a user agent
+pub fn with_user_agent(builder: RequestBuilder) -> RequestBuilder {
+    builder.header(
+        USER_AGENT,
+        concat!("cargo-workspaces/", env!("CARGO_PKG_VERSION")),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn users(body: &str) -> Vec<Owner> {
+        serde_json::from_str::<OwnersResponse>(body).unwrap().users
+    }
+
+    #[test]
+    fn test_classify() {
+        let body = r#"{"users":[{"id":1,"login":"alice","kind":"user"},{"id":2,"login":"github:acme:core","kind":"team"}]}"#;
+
+        assert_eq!(
+            classify(users(body), &["Alice".to_string()]),
+            NameStatus::Yours
+        );
+        assert_eq!(
+            classify(users(body), &["github:acme:core".to_string()]),
+            NameStatus::Yours
+        );
+        assert_eq!(
+            classify(users(body), &["bob".to_string()]),
+            NameStatus::Conflict(vec!["alice".to_string(), "github:acme:core".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_name_status_json() {
+        assert_eq!(
+            serde_json::to_string(&NameStatus::Available).unwrap(),
+            r#"{"status":"available"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&NameStatus::Conflict(vec!["bob".to_string()])).unwrap(),
+            r#"{"status":"conflict","owners":["bob"]}"#
+        );
+    }
+}
@@ -1,38 +1,590 @@
+use crate::utils::{Error, Result, INTERNAL_ERR};
+
 use camino::Utf8PathBuf;
 use cargo_metadata::{DependencyKind, Package};
-use indexmap::IndexSet as Set;
+use indexmap::{IndexMap, IndexSet as Set};
+use serde::Serialize;
 
-use std::collections::BTreeMap as Map;
+use std::collections::{BTreeMap as Map, VecDeque};
+
+/// The packages by manifest path along with their manifest paths in publishing order
+type Dag<'a> = (
+    Map<&'a Utf8PathBuf, (&'a Package, &'a String)>,
+    Set<Utf8PathBuf>,
+);
+
+/// A dependency left out of the publishing order to break a cycle
+#[derive(Debug, PartialEq)]
+pub struct IgnoredDependency {
+    pub name: String,
+    pub dep: String,
+    /// Whether it could be left out because it is optional, otherwise the cycle was allowed
+    pub optional: bool,
+}
+
+/// Orders the packages so that dependencies come before their dependents.
+///
+/// Dev-dependencies are not considered for the ordering because they are allowed to
+/// form cycles (`foo` -> `foo-test-util` -> `foo`), other cycles are reported as errors
+/// unless they only exist through optional dependencies.
+pub fn dag(pkgs: &[(Package, String)]) -> Result<Dag<'_>> {
+    Ok(dag_with(pkgs, &[])?.0)
+}
+
+/// Orders the packages like [`dag`], also breaking the cycles made of the crates of one of
+/// the allowed cycles, which are ordered as given. Returns the dependencies left out.
+pub fn dag_with<'a>(
+    pkgs: &'a [(Package, String)],
+    allowed: &[Vec<String>],
+) -> Result<(Dag<'a>, Vec<IgnoredDependency>)> {
+    let mut graph = graph(pkgs);
+    let mut ignored = vec![];
+
+    for component in components(&graph) {
+        let inside = |j: &usize| component.contains(j);
+        let is_cycle = component.len() > 1 || graph[component[0]].iter().any(|(j, _)| inside(j));
+
+        if !is_cycle {
+            continue;
+        }
+
+        // The cycles remaining without the optional dependencies
+        let hard = graph
+            .iter()
+            .enumerate()
+            .map(|(i, deps)| {
+                deps.iter()
+                    .filter(|(j, hard)| *hard && component.contains(&i) && inside(j))
+                    .cloned()
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        let hard_cycle = components(&hard)
+            .into_iter()
+            .find(|x| x.len() > 1 || hard[x[0]].iter().any(|(j, _)| *j == x[0]));
+
+        let order = match hard_cycle {
+            None => None,
+            Some(cycle) => {
+                let names = component
+                    .iter()
+                    .map(|i| pkgs[*i].0.name.as_str())
+                    .collect::<Vec<_>>();
+
+                match allowed
+                    .iter()
+                    .find(|x| names.iter().all(|n| x.iter().any(|a| a == n)))
+                {
+                    Some(order) => Some(order),
+                    None => return Err(cycle_error(pkgs, &hard, &cycle)),
+                }
+            }
+        };
+
+        for &i in &component {
+            graph[i].retain(|&(j, hard)| {
+                if !inside(&j) || (hard && order.is_none()) {
+                    return true;
+                }
+
+                ignored.push(IgnoredDependency {
+                    name: pkgs[i].0.name.clone(),
+                    dep: pkgs[j].0.name.clone(),
+                    optional: order.is_none(),
+                });
+
+                false
+            });
+        }
+
+        // Each crate of an allowed cycle comes after the previous one
+        if let Some(order) = order {
+            let order = order
+                .iter()
+                .filter_map(|n| component.iter().find(|i| pkgs[**i].0.name == *n))
+                .collect::<Vec<_>>();
+
+            for pair in order.windows(2) {
+                graph[*pair[1]].push((*pair[0], true));
+            }
+        }
+    }
 
-pub fn dag(
-    pkgs: &[(Package, String)],
-) -> (Map<&Utf8PathBuf, (&Package, &String)>, Set<Utf8PathBuf>) {
     let mut names = Map::new();
     let mut visited = Set::new();
 
-    for (pkg, version) in pkgs {
+    for (i, (pkg, version)) in pkgs.iter().enumerate() {
         names.insert(&pkg.manifest_path, (pkg, version));
-        dag_insert(pkgs, pkg, &mut visited);
+        dag_insert(pkgs, &graph, i, &mut visited);
     }
 
-    (names, visited)
+    Ok(((names, visited), ignored))
 }
 
-fn dag_insert(pkgs: &[(Package, String)], pkg: &Package, visited: &mut Set<Utf8PathBuf>) {
-    if visited.contains(&pkg.manifest_path) {
+fn dag_insert(
+    pkgs: &[(Package, String)],
+    graph: &[Vec<(usize, bool)>],
+    i: usize,
+    visited: &mut Set<Utf8PathBuf>,
+) {
+    let manifest_path = &pkgs[i].0.manifest_path;
+
+    if visited.contains(manifest_path) {
         return;
     }
 
-    for d in &pkg.dependencies {
-        if let Some((dep, _)) = pkgs.iter().find(|(p, _)| d.name == p.name) {
-            match d.kind {
-                DependencyKind::Normal | DependencyKind::Build => {
-                    dag_insert(pkgs, dep, visited);
+    for (j, _) in &graph[i] {
+        dag_insert(pkgs, graph, *j, visited);
+    }
+
+    visited.insert(manifest_path.clone());
+}
+
+/// The normal and build dependencies of each package on the other members, in the order
+/// they are declared, with whether any of the declarations is not optional
+fn graph(pkgs: &[(Package, String)]) -> Vec<Vec<(usize, bool)>> {
+    pkgs.iter()
+        .map(|(pkg, _)| {
+            let mut deps: Vec<(usize, bool)> = vec![];
+
+            for d in &pkg.dependencies {
+                if !matches!(d.kind, DependencyKind::Normal | DependencyKind::Build) {
+                    continue;
+                }
+
+                let Some(j) = pkgs.iter().position(|(p, _)| d.name == p.name) else {
+                    continue;
+                };
+
+                match deps.iter_mut().find(|(x, _)| *x == j) {
+                    Some((_, hard)) => *hard |= !d.optional,
+                    None => deps.push((j, !d.optional)),
                 }
+            }
+
+            deps
+        })
+        .collect()
+}
+
+/// The strongly connected components of the graph, using Tarjan's algorithm
+fn components(graph: &[Vec<(usize, bool)>]) -> Vec<Vec<usize>> {
+    struct State {
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        stack: Vec<usize>,
+        on_stack: Vec<bool>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    fn visit(graph: &[Vec<(usize, bool)>], state: &mut State, i: usize) {
+        state.index[i] = Some(state.next);
+        state.low[i] = state.next;
+        state.next += 1;
+        state.stack.push(i);
+        state.on_stack[i] = true;
+
+        for &(j, _) in &graph[i] {
+            match state.index[j] {
+                None => {
+                    visit(graph, state, j);
+                    state.low[i] = state.low[i].min(state.low[j]);
+                }
+                Some(index) if state.on_stack[j] => state.low[i] = state.low[i].min(index),
                 _ => {}
             }
         }
+
+        if Some(state.low[i]) == state.index[i] {
+            let mut component = vec![];
+
+            while let Some(j) = state.stack.pop() {
+                state.on_stack[j] = false;
+                component.push(j);
+
+                if j == i {
+                    break;
+                }
+            }
+
+            component.sort_unstable();
+            state.components.push(component);
+        }
+    }
+
+    let mut state = State {
+        index: vec![None; graph.len()],
+        low: vec![0; graph.len()],
+        stack: vec![],
+        on_stack: vec![false; graph.len()],
+        next: 0,
+        components: vec![],
+    };
+
+    for i in 0..graph.len() {
+        if state.index[i].is_none() {
+            visit(graph, &mut state, i);
+        }
+    }
+
+    state.components
+}
+
+/// The dependencies of the package which are workspace members, with their kind
+fn workspace_deps<'a>(
+    pkgs: &'a [(Package, String)],
+    pkg: &'a Package,
+) -> impl Iterator<Item = (&'a Package, DependencyKind)> + 'a {
+    pkg.dependencies.iter().filter_map(move |d| {
+        pkgs.iter()
+            .find(|(p, _)| d.name == p.name)
+            .map(|(p, _)| (p, d.kind))
+    })
+}
+
+/// A workspace member depending on the crate, directly or transitively
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Dependent {
+    pub name: String,
+    /// The crate it depends on, on the shortest path to the crate
+    pub via: String,
+    /// Kinds of its dependency on `via`
+    pub kinds: Vec<&'static str>,
+    /// Length of the shortest path to the crate
+    pub depth: usize,
+}
+
+/// The dependencies of the members on each other, in the order of the packages
+#[derive(Debug)]
+pub struct DependencyGraph {
+    /// The sorted kinds of every dependency of a member on another member
+    deps: IndexMap<String, IndexMap<String, Vec<&'static str>>>,
+}
+
+impl DependencyGraph {
+    pub fn new(pkgs: &[(Package, String)]) -> Self {
+        let deps = pkgs
+            .iter()
+            .map(|(pkg, _)| {
+                let mut deps = IndexMap::<String, Vec<_>>::new();
+
+                for (dep, kind) in workspace_deps(pkgs, pkg) {
+                    deps.entry(dep.name.clone())
+                        .or_default()
+                        .push(kind_name(kind));
+                }
+
+                for kinds in deps.values_mut() {
+                    kinds.sort_unstable();
+                    kinds.dedup();
+                }
+
+                (pkg.name.clone(), deps)
+            })
+            .collect();
+
+        Self { deps }
+    }
+
+    /// The members depending directly on the crate, with the kinds of their dependency
+    pub fn direct_dependents<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a [&'static str])> + 'a {
+        self.deps.iter().filter_map(move |(pkg, deps)| {
+            deps.get(name)
+                .filter(|_| pkg != name)
+                .map(|kinds| (pkg.as_str(), kinds.as_slice()))
+        })
+    }
+
+    /// Returns the members which depend on the crate, in breadth first order. Every member
+    /// is listed once, with its shortest path, even if it reaches the crate multiple times.
+    pub fn dependents(&self, name: &str, direct_only: bool) -> Vec<Dependent> {
+        let mut ret: Vec<Dependent> = vec![];
+        let mut queue = VecDeque::from([(name.to_string(), 0)]);
+
+        while let Some((current, depth)) = queue.pop_front() {
+            if direct_only && depth == 1 {
+                break;
+            }
+
+            for (pkg, kinds) in self.direct_dependents(&current) {
+                if pkg == name || ret.iter().any(|x| x.name == pkg) {
+                    continue;
+                }
+
+                ret.push(Dependent {
+                    name: pkg.to_string(),
+                    via: current.clone(),
+                    kinds: kinds.to_vec(),
+                    depth: depth + 1,
+                });
+                queue.push_back((pkg.to_string(), depth + 1));
+            }
+        }
+
+        ret
+    }
+}
+
+fn kind_name(kind: DependencyKind) -> &'static str {
+    match kind {
+        DependencyKind::Development => "dev",
+        DependencyKind::Build => "build",
+        _ => "normal",
+    }
+}
+
+/// Describes one of the shortest cycles through the first crate of the component, along with
+/// the declarations forming it
+fn cycle_error(
+    pkgs: &[(Package, String)],
+    graph: &[Vec<(usize, bool)>],
+    component: &[usize],
+) -> Error {
+    let start = component[0];
+    let mut previous = Map::new();
+    let mut queue = VecDeque::from([start]);
+
+    'search: while let Some(i) = queue.pop_front() {
+        for &(j, _) in &graph[i] {
+            if previous.contains_key(&j) {
+                continue;
+            }
+
+            previous.insert(j, i);
+
+            if j == start {
+                break 'search;
+            }
+
+            queue.push_back(j);
+        }
+    }
+
+    let mut cycle = vec![start];
+
+    while let Some(&i) = previous.get(cycle.last().expect(INTERNAL_ERR)) {
+        if i == start {
+            break;
+        }
+
+        cycle.push(i);
     }
 
-    visited.insert(pkg.manifest_path.clone());
+    // Walked backwards from the start
+    cycle.push(start);
+    cycle.reverse();
+
+    let path = cycle
+        .iter()
+        .map(|i| pkgs[*i].0.name.as_str())
+        .collect::<Vec<_>>()
+        .join(" -> ");
+
+    let declarations = cycle
+        .windows(2)
+        .flat_map(|pair| {
+            let (pkg, dep) = (&pkgs[pair[0]].0, &pkgs[pair[1]].0);
+
+            pkg.dependencies
+                .iter()
+                .filter(move |d| {
+                    d.name == dep.name
+                        && !d.optional
+                        && matches!(d.kind, DependencyKind::Normal | DependencyKind::Build)
+                })
+                .map(move |d| {
+                    let table = match d.kind {
+                        DependencyKind::Build => "build-dependencies",
+                        _ => "dependencies",
+                    };
+                    let table = match &d.target {
+                        Some(target) => format!("target.'{}'.{}", target, table),
+                        None => table.to_string(),
+                    };
+
+                    format!(
+                        " - {} depends on {} in [{}] of {}",
+                        pkg.name, dep.name, table, pkg.manifest_path
+                    )
+                })
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // The crate depended upon comes first
+    let mut order = cycle[1..]
+        .iter()
+        .map(|i| pkgs[*i].0.name.as_str())
+        .collect::<Vec<_>>();
+    order.reverse();
+
+    Error::DependencyCycle {
+        path,
+        declarations,
+        order: order.join(","),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn pkg(name: &str, deps: &[(&str, &str)]) -> (Package, String) {
+        let deps = deps
+            .iter()
+            .map(|(dep, kind)| {
+                json!({
+                    "name": dep,
+                    "source": null,
+                    "req": "*",
+                    "kind": if matches!(*kind, "normal" | "optional") { None } else { Some(kind) },
+                    "rename": null,
+                    "optional": *kind == "optional",
+                    "uses_default_features": true,
+                    "features": [],
+                    "target": null,
+                    "registry": null
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let pkg = serde_json::from_value(json!({
+            "name": name,
+            "version": "0.1.0",
+            "id": format!("{} 0.1.0 (path+file:///ws/{})", name, name),
+            "license": null,
+            "license_file": null,
+            "description": null,
+            "source": null,
+            "dependencies": deps,
+            "targets": [],
+            "features": {},
+            "manifest_path": format!("/ws/{}/Cargo.toml", name),
+            "categories": [],
+            "keywords": [],
+            "readme": null,
+            "repository": null,
+            "homepage": null,
+            "documentation": null,
+            "edition": "2021",
+            "links": null,
+            "publish": null,
+            "authors": [],
+            "metadata": null
+        }))
+        .unwrap();
+
+        (pkg, "0.1.0".to_string())
+    }
+
+    fn order(dag: &Dag) -> Vec<String> {
+        dag.1
+            .iter()
+            .map(|x| dag.0.get(x).unwrap().0.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_dag() {
+        let pkgs = vec![
+            pkg("top", &[("a", "normal"), ("core", "build")]),
+            pkg("a", &[("core", "normal")]),
+            pkg("core", &[("top", "dev")]),
+        ];
+
+        let (dag, ignored) = dag_with(&pkgs, &[]).unwrap();
+
+        assert_eq!(order(&dag), vec!["core", "a", "top"]);
+        assert!(ignored.is_empty());
+    }
+
+    #[test]
+    fn test_dag_optional_cycle() {
+        let pkgs = vec![pkg("a", &[("b", "optional")]), pkg("b", &[("a", "normal")])];
+
+        let (dag, ignored) = dag_with(&pkgs, &[]).unwrap();
+
+        assert_eq!(order(&dag), vec!["a", "b"]);
+        assert_eq!(
+            ignored,
+            vec![IgnoredDependency {
+                name: "a".to_string(),
+                dep: "b".to_string(),
+                optional: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_dag_hard_cycle() {
+        let pkgs = vec![
+            pkg("a", &[("b", "normal")]),
+            pkg("b", &[("c", "build"), ("a", "optional")]),
+            pkg("c", &[("a", "normal")]),
+        ];
+
+        let err = dag(&pkgs).unwrap_err().to_string();
+
+        assert!(err.starts_with("dependency cycle a -> b -> c -> a\n"));
+        assert!(err.contains(" - b depends on c in [build-dependencies] of /ws/b/Cargo.toml\n"));
+        assert!(!err.contains("b depends on a"));
+        assert!(err.contains("`--allow-cycle a,c,b`"));
+    }
+
+    #[test]
+    fn test_dag_allowed_cycle() {
+        let pkgs = vec![
+            pkg("top", &[("a", "normal")]),
+            pkg("a", &[("b", "normal")]),
+            pkg("b", &[("a", "normal")]),
+        ];
+        let allowed = vec![vec!["b".to_string(), "a".to_string()]];
+
+        let (dag, ignored) = dag_with(&pkgs, &allowed).unwrap();
+
+        assert_eq!(order(&dag), vec!["b", "a", "top"]);
+        assert_eq!(ignored.len(), 2);
+        assert!(ignored.iter().all(|x| !x.optional));
+
+        let allowed = vec![vec!["a".to_string(), "c".to_string()]];
+        assert!(dag_with(&pkgs, &allowed).is_err());
+    }
+
+    #[test]
+    fn test_dependents() {
+        // core <- (a, b) <- top, with a diamond through a and b
+        let pkgs = vec![
+            pkg("core", &[]),
+            pkg("a", &[("core", "normal")]),
+            pkg("b", &[("core", "normal"), ("core", "dev")]),
+            pkg("top", &[("a", "normal"), ("b", "build")]),
+        ];
+
+        let dependents = DependencyGraph::new(&pkgs).dependents("core", false);
+        let names = dependents.iter().map(|x| &x.name).collect::<Vec<_>>();
+
+        assert_eq!(names, vec!["a", "b", "top"]);
+        assert_eq!(dependents[1].kinds, vec!["dev", "normal"]);
+        assert_eq!(dependents[2].via, "a");
+        assert_eq!(dependents[2].depth, 2);
+    }
+
+    #[test]
+    fn test_dependents_direct_only() {
+        let pkgs = vec![
+            pkg("core", &[]),
+            pkg("a", &[("core", "normal")]),
+            pkg("top", &[("a", "normal")]),
+        ];
+
+        let dependents = DependencyGraph::new(&pkgs).dependents("core", true);
+
+        assert_eq!(dependents.len(), 1);
+        assert_eq!(dependents[0].name, "a");
+    }
 }
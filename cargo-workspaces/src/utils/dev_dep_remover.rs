@@ -7,12 +7,13 @@ use cargo_metadata::{Dependency, DependencyKind, Package};
 use semver::VersionReq;
 use toml_edit::Document;
 
-use crate::utils::Result;
+use crate::utils::{on_interrupt, CleanupGuard, Result};
 
 /// Removes all dev-dependencies from a Cargo.toml then restores the file when dropped.
 pub struct DevDependencyRemover {
     manifest_path: std::path::PathBuf,
     original_toml: String,
+    _cleanup: CleanupGuard,
 }
 
 impl DevDependencyRemover {
@@ -31,11 +32,22 @@ impl DevDependencyRemover {
                 });
             }
 
+        // Restore the manifest even if the process is interrupted before being dropped
+        let cleanup = {
+            let manifest_path = manifest_path.to_owned();
+            let original_toml = original_toml.clone();
+
+            on_interrupt(move || {
+                let _ = write(manifest_path, original_toml);
+            })
+        };
+
         write(manifest_path, document.to_string())?;
 
         Ok(Self {
             manifest_path: manifest_path.to_owned(),
             original_toml,
+            _cleanup: cleanup,
         })
     }
 }
@@ -1,22 +1,100 @@
 use lazy_static::lazy_static;
-use oclif::{term::ERR_YELLOW, CliError};
+use oclif::{
+    term::{ERR_GREEN, ERR_MAGENTA, ERR_YELLOW, TERM_ERR},
+    CliError,
+};
 use thiserror::Error;
 
 use std::{
     io,
-    sync::atomic::{AtomicBool, Ordering},
+    process::Command,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
 };
 
+/// `-vv` logs the commands being run along with their working directory
+pub const COMMANDS_VERBOSITY: u8 = 2;
+
+/// `-vvv` also logs the raw output of the commands
+pub const OUTPUT_VERBOSITY: u8 = 3;
+
 lazy_static! {
-    static ref DEBUG: AtomicBool = AtomicBool::new(false);
+    static ref VERBOSITY: AtomicU8 = AtomicU8::new(0);
+    static ref DRY_RUN: AtomicBool = AtomicBool::new(false);
 }
 
 pub fn get_debug() -> bool {
-    DEBUG.load(Ordering::Relaxed)
+    get_verbosity() >= 1
+}
+
+pub fn get_verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
 }
 
-pub fn set_debug() {
-    DEBUG.store(true, Ordering::Relaxed);
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+pub fn get_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+pub fn set_dry_run() {
+    DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+/// Logs the command line and the working directory of the command about to be spawned
+pub fn log_command(cmd: &Command) {
+    if get_verbosity() < COMMANDS_VERBOSITY {
+        return;
+    }
+
+    // Tokens passed on the command line are not worth leaking into CI logs
+    let mut secret = false;
+    let args = cmd
+        .get_args()
+        .map(|x| {
+            let arg = if secret {
+                "***".to_string()
+            } else {
+                x.to_string_lossy().to_string()
+            };
+
+            secret = x == "--token";
+            arg
+        })
+        .collect::<Vec<_>>();
+
+    let dir = cmd
+        .get_current_dir()
+        .map(|x| x.display().to_string())
+        .unwrap_or_else(|| ".".to_string());
+
+    let _ = TERM_ERR.write_line(&format!(
+        "{} {} {} {} (in {})",
+        ERR_GREEN.apply_to("debug"),
+        ERR_MAGENTA.apply_to("running"),
+        cmd.get_program().to_string_lossy(),
+        args.join(" "),
+        dir
+    ));
+}
+
+/// Logs the raw output of a finished command
+pub fn log_output(stdout: &[u8], stderr: &[u8]) {
+    if get_verbosity() < OUTPUT_VERBOSITY {
+        return;
+    }
+
+    for (name, output) in [("stdout", stdout), ("stderr", stderr)] {
+        if !output.is_empty() {
+            let _ = TERM_ERR.write_line(&format!(
+                "{} {}\n{}",
+                ERR_GREEN.apply_to("debug"),
+                ERR_MAGENTA.apply_to(name),
+                String::from_utf8_lossy(output).trim_end()
+            ));
+        }
+    }
 }
 
 macro_rules! _debug {
@@ -67,6 +145,8 @@ pub enum Error {
     PackageNotInWorkspace { id: String, ws: String },
     #[error("unable to find package {id}")]
     PackageNotFound { id: String },
+    #[error("{name} is not a workspace member{suggestion}")]
+    UnknownMember { name: String, suggestion: String },
     #[error("did not find any public packages (use -a to include private packages)")]
     NoPublicPackages,
     #[error("did not find any package")]
@@ -75,18 +155,63 @@ pub enum Error {
     ManifestHasNoParent(String),
     #[error("unable to read metadata specified in Cargo.toml: {0}")]
     BadMetadata(serde_json::Error),
+    #[error("invalid workspaces config\n{}", .0.join("\n"))]
+    InvalidConfig(Vec<String>),
+    #[error("found {0} unknown key(s) in the workspaces config")]
+    UnknownConfigKeys(usize),
     #[error("command needs to be run from the workspace root")]
     MustBeRunFromWorkspaceRoot,
 
+    #[error("dependency cycle {path}\n{declarations}\npublish them anyway with `--allow-cycle {order}`, in that order and without verification")]
+    DependencyCycle {
+        path: String,
+        declarations: String,
+        order: String,
+    },
+
     #[error("unable to verify package {0}")]
     Verify(String),
     #[error("unable to publish package {0}")]
     Publish(String),
+    #[error("post-publish hook failed for {0}: {1}")]
+    PostPublishHook(String, String),
+    #[error("{name} depends on {dep} {req}, which has no matching version on the registry. Publish {dep} first")]
+    DependencyNotPublished {
+        name: String,
+        dep: String,
+        req: String,
+    },
+    #[error("documentation failed to build\n{0}")]
+    Documentation(String),
+    #[error("crates are missing metadata required for publishing\n{0}")]
+    IncompleteMetadata(String),
+    #[error("path dependencies are missing a version\n{0}")]
+    UnversionedPathDeps(String),
     #[error("unable to update Cargo.lock")]
     Update,
+    #[error("unable to {0} in offline mode")]
+    Offline(String),
+    #[error("crates do not compile with their rust-version\n{0}")]
+    MsrvCheck(String),
+    #[error("unable to install toolchain {0}: {1}")]
+    Toolchain(String, String),
+    #[error("{0}")]
+    IncompatibleResolver(String),
+    #[error("trusted publishing failed: {0}")]
+    TrustedPublishing(String),
+    #[error("unable to create the release: {0}")]
+    ForgeRelease(String),
+    #[error("the release does not match the registry\n{0}")]
+    ReleaseMismatch(String),
+    #[error("found {0} problem(s) in the manifests")]
+    LintManifests(usize),
 
     #[error("{0} value must contain '%n'")]
     MustContainPercentN(String),
+    #[error("unable to read mapping file {path}: {reason}")]
+    BadMappingFile { path: String, reason: String },
+    #[error("crates {crates} would all be named {name}")]
+    RenameConflict { name: String, crates: String },
 
     #[error("unable to create crate")]
     Create,
@@ -96,21 +221,41 @@ pub enum Error {
     InvalidMemberPath,
     #[error("the workspace already contains a package with this name")]
     DuplicatePackageName,
+    #[error("invalid crate name {name}: {reason}")]
+    InvalidCrateName { name: String, reason: String },
     #[error("path for crate is in workspace.exclude list ({0})")]
     InWorkspaceExclude(String),
+    #[error("{0} is already in workspace.dependencies")]
+    InWorkspaceDependencies(String),
 
     #[error("given path {0} is not a folder")]
     WorkspaceRootNotDir(String),
     #[error("unable to initialize workspace: {0}")]
     Init(String),
+    #[error("did not find any crates in {0}")]
+    NoCratesFound(String),
+    #[error("all the crates found in {path} were left out ({crates})")]
+    AllCratesLeftOut { path: String, crates: String },
+    #[error("{0} needs a terminal to prompt in")]
+    NotInteractive(String),
+
+    #[error("invalid exec arguments: {0}")]
+    ExecArgs(String),
+
+    #[error("README index is out of date in {0} (run `cargo workspaces readme-index`)")]
+    StaleReadmeIndex(String),
 
     #[error("unable to run cargo command with args {args:?}, got {err}")]
     Cargo { err: io::Error, args: Vec<String> },
     #[error("unable to run git command with args {args:?}, got {err}")]
     Git { err: io::Error, args: Vec<String> },
 
+    #[error("`{cmd}` timed out after {after}")]
+    TimedOut { cmd: String, after: String },
+
+    /// Carries the exit code the process should end with
     #[error("child command failed to exit successfully")]
-    Bail,
+    Bail(i32),
 
     #[error("not a git repository")]
     NotGit,
@@ -132,9 +277,31 @@ pub enum Error {
     NotTagged(String, String, String),
     #[error("unable to push to remote, out = {0}, err = {1}")]
     NotPushed(String, String),
+    #[error("unable to push to {remote}: {err}\nthe release was committed and tagged locally, once the problem is fixed, push it with:\n{commands}")]
+    PushFailed {
+        remote: String,
+        err: String,
+        commands: String,
+    },
+    #[error("HEAD is not a commit created by the version command, refusing to undo it")]
+    NotReleaseCommit,
+    #[error("the work tree has uncommitted changes, which undoing the release would discard")]
+    UncommittedChanges,
+    #[error("unable to undo the release commit, out = {0}, err = {1}")]
+    NotUndone(String, String),
+    #[error("unable to fetch tags from remote, out = {0}, err = {1}")]
+    NotFetched(String, String),
+    #[error("git command `{args}` did not finish within {secs} seconds")]
+    GitTimeout { args: String, secs: u64 },
+    #[error("no release tags found locally, but remote {remote} has {tags} (run `git fetch {remote} --tags` or pass --fetch-tags)")]
+    RemoteTagsNotFetched { remote: String, tags: String },
 
     #[error("no changes detected")]
     NoChanges,
+    #[error("invalid new versions\n{0}")]
+    InvalidVersions(String),
+    #[error("invalid build metadata {0}: {1}")]
+    BuildMetadata(String, String),
 
     #[error("could not understand 'cargo config get' output: {0}")]
     BadConfigGetOutput(String),
@@ -142,12 +309,18 @@ pub enum Error {
     CratesRegistry(#[from] tame_index::Error),
     #[error("unsupported crates index type")]
     UnsupportedCratesIndexType,
+    #[error("unable to download {0}")]
+    Download(String),
+    #[error("unable to read the crates index at {0}: {1}")]
+    LocalIndex(String, String),
     #[error("crates index error: {0}")]
     CratesReqwest(#[from] tame_index::external::reqwest::Error),
 
     #[error("the workspace manifest has bad format: {0}")]
     WorkspaceBadFormat(String),
 
+    #[error("{0}")]
+    Metadata(#[from] cargo_metadata::Error),
     #[error("{0}")]
     Semver(#[from] semver::ReqParseError),
     #[error("{0}")]
@@ -176,14 +349,40 @@ impl CliError for Error {
             Self::PackageNotFound { id } => Self::PackageNotFound {
                 id: format!("{}", ERR_YELLOW.apply_to(id)),
             },
+            Self::UnknownMember { name, suggestion } => Self::UnknownMember {
+                name: format!("{}", ERR_YELLOW.apply_to(name)),
+                suggestion,
+            },
+            Self::InvalidCrateName { name, reason } => Self::InvalidCrateName {
+                name: format!("{}", ERR_YELLOW.apply_to(name)),
+                reason,
+            },
             Self::Verify(pkg) => Self::Verify(format!("{}", ERR_YELLOW.apply_to(pkg))),
             Self::Publish(pkg) => Self::Publish(format!("{}", ERR_YELLOW.apply_to(pkg))),
+            Self::PostPublishHook(pkg, status) => {
+                Self::PostPublishHook(format!("{}", ERR_YELLOW.apply_to(pkg)), status)
+            }
+            Self::DependencyNotPublished { name, dep, req } => Self::DependencyNotPublished {
+                name: format!("{}", ERR_YELLOW.apply_to(name)),
+                dep: format!("{}", ERR_YELLOW.apply_to(dep)),
+                req,
+            },
             Self::MustContainPercentN(val) => {
                 Self::MustContainPercentN(format!("{}", ERR_YELLOW.apply_to(val)))
             }
             Self::WorkspaceRootNotDir(path) => {
                 Self::WorkspaceRootNotDir(format!("{}", ERR_YELLOW.apply_to(path)))
             }
+            Self::NoCratesFound(path) => {
+                Self::NoCratesFound(format!("{}", ERR_YELLOW.apply_to(path)))
+            }
+            Self::AllCratesLeftOut { path, crates } => Self::AllCratesLeftOut {
+                path: format!("{}", ERR_YELLOW.apply_to(path)),
+                crates,
+            },
+            Self::StaleReadmeIndex(path) => {
+                Self::StaleReadmeIndex(format!("{}", ERR_YELLOW.apply_to(path)))
+            }
             Self::NoRemote { remote, branch } => Self::NoRemote {
                 remote: format!("{}", ERR_YELLOW.apply_to(remote)),
                 branch: format!("{}", ERR_YELLOW.apply_to(branch)),
@@ -196,6 +395,15 @@ impl CliError for Error {
                 branch: format!("{}", ERR_YELLOW.apply_to(branch)),
                 pattern: format!("{}", ERR_YELLOW.apply_to(pattern)),
             },
+            Self::PushFailed {
+                remote,
+                err,
+                commands,
+            } => Self::PushFailed {
+                remote: format!("{}", ERR_YELLOW.apply_to(remote)),
+                err,
+                commands,
+            },
             Self::NotTagged(tag, out, err) => {
                 Self::NotTagged(format!("{}", ERR_YELLOW.apply_to(tag)), out, err)
             }
@@ -0,0 +1,440 @@
+//! Creates a release on the forge hosting the repository (GitHub or GitLab) for the tags of the
+//! released crates, with the notes taken from the changelog.
+//!
+//! See https://docs.github.com/en/rest/releases/releases#create-a-release and
+//! https://docs.gitlab.com/ee/api/releases/#create-a-release
+
+use crate::utils::{debug, git, info, warn, Error, Result, WorkspaceConfig};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use semver::Version;
+use serde::Deserialize;
+use serde_json::Value;
+use tame_index::external::reqwest::{
+    blocking::Client,
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT},
+    StatusCode,
+};
+
+use std::{env, fs};
+
+const CHANGELOG: &str = "CHANGELOG.md";
+
+/// The kind of forge the releases are created on
+#[derive(Deserialize, Debug, Clone, Copy, Default, Ord, Eq, PartialOrd, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Forge {
+    #[default]
+    Github,
+    Gitlab,
+}
+
+impl Forge {
+    fn name(&self) -> &'static str {
+        match self {
+            Forge::Github => "GitHub",
+            Forge::Gitlab => "GitLab",
+        }
+    }
+
+    fn token_env(&self) -> &'static str {
+        match self {
+            Forge::Github => "GITHUB_TOKEN",
+            Forge::Gitlab => "GITLAB_TOKEN",
+        }
+    }
+
+    // Set by GitHub Actions and GitLab CI, which also point the API to self-hosted servers
+    fn repository_env(&self) -> &'static str {
+        match self {
+            Forge::Github => "GITHUB_REPOSITORY",
+            Forge::Gitlab => "CI_PROJECT_PATH",
+        }
+    }
+
+    fn api_url_env(&self) -> &'static str {
+        match self {
+            Forge::Github => "GITHUB_API_URL",
+            Forge::Gitlab => "CI_API_V4_URL",
+        }
+    }
+
+    fn api_url(&self) -> &'static str {
+        match self {
+            Forge::Github => "https://api.github.com",
+            Forge::Gitlab => "https://gitlab.com/api/v4",
+        }
+    }
+}
+
+pub struct ForgeRelease {
+    client: Client,
+    forge: Forge,
+    token: String,
+    api_url: String,
+    /// The `owner/repo` of the repository, GitLab allows nested groups
+    repository: String,
+    root: Utf8PathBuf,
+    draft: bool,
+    dry_run: bool,
+}
+
+impl ForgeRelease {
+    /// Returns `None` with a warning if the token or the repository is missing, so that they
+    /// are found out before anything is released without holding up the release
+    pub fn new(
+        client: &Client,
+        root: &Utf8PathBuf,
+        remote: &str,
+        draft: bool,
+        config: &WorkspaceConfig,
+        dry_run: bool,
+    ) -> Result<Option<Self>> {
+        let forge = config.forge.unwrap_or_default();
+
+        let token = match env::var(forge.token_env()) {
+            Ok(token) => token,
+            Err(_) if dry_run => String::new(),
+            Err(_) => {
+                warn!(
+                    "skipping releases",
+                    format!("{} is not set", forge.token_env())
+                );
+                return Ok(None);
+            }
+        };
+
+        let api_url = config
+            .api_url
+            .clone()
+            .or_else(|| env::var(forge.api_url_env()).ok())
+            .unwrap_or_else(|| forge.api_url().to_string());
+
+        let repository = match env::var(forge.repository_env()) {
+            Ok(repository) => repository,
+            Err(_) => {
+                let (_, url, _) = git(root, &["remote", "get-url", remote])?;
+
+                match parse_repository(forge, &url, &web_host(&api_url)) {
+                    Some(repository) => repository,
+                    None => {
+                        warn!(
+                            "skipping releases",
+                            format!(
+                                "the {} remote is not a {} repository, set {}",
+                                remote,
+                                forge.name(),
+                                forge.repository_env()
+                            )
+                        );
+                        return Ok(None);
+                    }
+                }
+            }
+        };
+
+        debug!("forge repository", repository);
+
+        if draft && forge == Forge::Gitlab {
+            warn!(
+                "GitLab has no draft releases",
+                "creating them as regular ones"
+            );
+        }
+
+        Ok(Some(Self {
+            client: client.clone(),
+            forge,
+            token,
+            api_url,
+            repository,
+            root: root.clone(),
+            draft,
+            dry_run,
+        }))
+    }
+
+    /// Creates the release of the tag at the current commit, with the notes of the version
+    /// from the changelog of the crate. Failures are only warned about since the crates
+    /// are already released by then.
+    pub fn release(&self, tag: &str, crate_dir: &Utf8Path, version: &Version) -> Result {
+        let notes = release_notes(crate_dir, &self.root, &version.to_string())?;
+        let prerelease = !version.pre.is_empty();
+
+        if self.dry_run {
+            let (url, payload) = self.request(tag, &notes, prerelease)?;
+            info!("would create release", format!("POST {}\n{}", url, payload));
+            return Ok(());
+        }
+
+        match self.create(tag, &notes, prerelease) {
+            Ok(Some(url)) => info!("created release", url),
+            Ok(None) => info!("release already exists", tag),
+            Err(err) => warn!("release failed", err),
+        }
+
+        Ok(())
+    }
+
+    /// The URL and the payload of the API call creating the release
+    fn request(&self, tag: &str, notes: &str, prerelease: bool) -> Result<(String, Value)> {
+        let (_, sha, _) = git(&self.root, &["rev-parse", "HEAD"])?;
+        let api_url = self.api_url.trim_end_matches('/');
+
+        Ok(match self.forge {
+            Forge::Github => (
+                format!("{}/repos/{}/releases", api_url, self.repository),
+                serde_json::json!({
+                    "tag_name": tag,
+                    "target_commitish": sha,
+                    "name": tag,
+                    "body": notes,
+                    "draft": self.draft,
+                    "prerelease": prerelease,
+                }),
+            ),
+            // GitLab has no notion of prereleases
+            Forge::Gitlab => (
+                format!(
+                    "{}/projects/{}/releases",
+                    api_url,
+                    self.repository.replace('/', "%2F")
+                ),
+                serde_json::json!({
+                    "tag_name": tag,
+                    "ref": sha,
+                    "name": tag,
+                    "description": notes,
+                }),
+            ),
+        })
+    }
+
+    /// Returns the URL of the created release, or `None` if the tag already has a release
+    fn create(&self, tag: &str, notes: &str, prerelease: bool) -> Result<Option<String>> {
+        let (url, payload) = self.request(tag, notes, prerelease)?;
+
+        let req = self
+            .client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                USER_AGENT,
+                concat!("cargo-workspaces/", env!("CARGO_PKG_VERSION")),
+            );
+
+        let req = match self.forge {
+            Forge::Github => req
+                .header(ACCEPT, "application/vnd.github+json")
+                .header(AUTHORIZATION, format!("Bearer {}", self.token)),
+            Forge::Gitlab => req.header("PRIVATE-TOKEN", &self.token),
+        };
+
+        let res = req.body(payload.to_string()).send()?;
+
+        let status = res.status();
+        let body = res.text()?;
+
+        let exists = match self.forge {
+            Forge::Github => status == StatusCode::UNPROCESSABLE_ENTITY,
+            Forge::Gitlab => status == StatusCode::CONFLICT,
+        };
+
+        if exists && body.contains("already") {
+            return Ok(None);
+        }
+
+        if !status.is_success() {
+            return Err(Error::ForgeRelease(format!(
+                "{} rejected the release of {} ({}): {}",
+                self.forge.name(),
+                tag,
+                status,
+                body
+            )));
+        }
+
+        let res = serde_json::from_str::<ReleaseResponse>(&body)?;
+
+        Ok(res.html_url.or(res.links.map(|x| x.link)))
+    }
+}
+
+#[derive(Deserialize)]
+struct ReleaseResponse {
+    /// Given by GitHub
+    html_url: Option<String>,
+    /// Given by GitLab
+    #[serde(rename = "_links")]
+    links: Option<ReleaseLinks>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseLinks {
+    #[serde(rename = "self")]
+    link: String,
+}
+
+/// The notes of the version from the changelog of the crate, or else of the workspace
+pub fn release_notes(crate_dir: &Utf8Path, root: &Utf8Path, version: &str) -> Result<String> {
+    for dir in [crate_dir, root] {
+        let changelog = match fs::read_to_string(dir.join(CHANGELOG)) {
+            Ok(changelog) => changelog,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        if let Some(section) = changelog_section(&changelog, version) {
+            return Ok(section);
+        }
+    }
+
+    Ok(format!("Release v{}", version))
+}
+
+/// The host of the web pages of the forge, where the repositories are cloned from
+fn web_host(api_url: &str) -> String {
+    let url = api_url.split_once("://").map_or(api_url, |x| x.1);
+    let host = url.split('/').next().unwrap_or(url);
+
+    host.strip_prefix("api.").unwrap_or(host).to_string()
+}
+
+/// The `owner/repo` of a remote on the forge, over HTTPS or SSH
+fn parse_repository(forge: Forge, url: &str, host: &str) -> Option<String> {
+    let url = url.trim();
+    let path = url
+        .strip_prefix(&format!("https://{}/", host))
+        .or_else(|| url.strip_prefix(&format!("http://{}/", host)))
+        .or_else(|| url.strip_prefix(&format!("ssh://git@{}/", host)))
+        .or_else(|| url.strip_prefix(&format!("git@{}:", host)))?;
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let parts = path.split('/').collect::<Vec<_>>();
+
+    let valid = match forge {
+        Forge::Github => parts.len() == 2,
+        // Projects can be in nested groups
+        Forge::Gitlab => parts.len() >= 2,
+    };
+
+    (valid && parts.iter().all(|x| !x.is_empty())).then(|| path.to_string())
+}
+
+/// The body of the first heading mentioning the version, up to the next heading of the same
+/// or a higher level
+fn changelog_section(changelog: &str, version: &str) -> Option<String> {
+    let mut lines = changelog.lines();
+
+    let level = loop {
+        let line = lines.next()?;
+        let level = heading_level(line);
+
+        if level > 0 && mentions(line, version) {
+            break level;
+        }
+    };
+
+    let section = lines
+        .take_while(|x| !(1..=level).contains(&heading_level(x)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let section = section.trim();
+
+    (!section.is_empty()).then(|| section.to_string())
+}
+
+fn heading_level(line: &str) -> usize {
+    let level = line.chars().take_while(|x| *x == '#').count();
+
+    if level > 0 && line[level..].starts_with(' ') {
+        level
+    } else {
+        0
+    }
+}
+
+/// Whether the line has the version on its own, so that `1.2.3` is not found in `1.2.30`
+fn mentions(line: &str, version: &str) -> bool {
+    let part = |x: Option<char>| x.is_some_and(|x| x.is_ascii_alphanumeric() || x == '.');
+
+    line.match_indices(version).any(|(i, _)| {
+        let before = &line[..i];
+
+        !part(
+            before
+                .strip_suffix('v')
+                .unwrap_or(before)
+                .chars()
+                .next_back(),
+        ) && !part(line[i + version.len()..].chars().next())
+            && !line[i + version.len()..].starts_with('-')
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_repository() {
+        let github = |url| parse_repository(Forge::Github, url, "github.com");
+
+        for url in [
+            "https://github.com/owner/repo.git",
+            "https://github.com/owner/repo\n",
+            "git@github.com:owner/repo.git",
+            "ssh://git@github.com/owner/repo",
+        ] {
+            assert_eq!(github(url), Some("owner/repo".to_string()));
+        }
+
+        assert_eq!(github("https://gitlab.com/owner/repo.git"), None);
+        assert_eq!(github("https://github.com/owner"), None);
+        assert_eq!(github("https://github.com/group/sub/repo"), None);
+
+        assert_eq!(
+            parse_repository(
+                Forge::Gitlab,
+                "git@gitlab.com:group/sub/repo.git",
+                "gitlab.com"
+            ),
+            Some("group/sub/repo".to_string())
+        );
+    }
+
+    #[test]
+    fn test_web_host() {
+        assert_eq!(web_host("https://api.github.com"), "github.com");
+        assert_eq!(
+            web_host("https://ghe.example.com/api/v3"),
+            "ghe.example.com"
+        );
+        assert_eq!(web_host("https://gitlab.com/api/v4"), "gitlab.com");
+    }
+
+    #[test]
+    fn test_changelog_section() {
+        let changelog = "# Changelog\n\n## [1.2.30]\n\nNewer\n\n## [1.2.3] - 2024-01-15\n\n### Added\n\n- Things\n\n## 1.2.2\n\nOlder\n";
+
+        assert_eq!(
+            changelog_section(changelog, "1.2.3"),
+            Some("### Added\n\n- Things".to_string())
+        );
+        assert_eq!(
+            changelog_section(changelog, "1.2.2"),
+            Some("Older".to_string())
+        );
+        assert_eq!(changelog_section(changelog, "1.2"), None);
+        assert_eq!(changelog_section("## 1.0.0\n\n## 0.9.0\n", "1.0.0"), None);
+    }
+
+    #[test]
+    fn test_mentions() {
+        assert!(mentions("## v1.2.3", "1.2.3"));
+        assert!(mentions("## [1.2.3] - 2024-01-15", "1.2.3"));
+        assert!(!mentions("## 1.2.30", "1.2.3"));
+        assert!(!mentions("## 11.2.3", "1.2.3"));
+        assert!(!mentions("## 1.2.3-rc.1", "1.2.3"));
+    }
+}
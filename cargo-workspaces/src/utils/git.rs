@@ -1,5 +1,6 @@
 use crate::utils::{
-    debug, info, validate_value_containing_name, Error, WorkspaceConfig, INTERNAL_ERR,
+    debug, info, isolate, log_command, log_output, output_timeout, validate_value_containing_name,
+    warn, ChildGuard, Error, Timeout, WorkspaceConfig,
 };
 
 use camino::Utf8PathBuf;
@@ -9,20 +10,32 @@ use semver::Version;
 
 use std::{
     collections::BTreeMap as Map,
-    process::{Command, ExitStatus},
+    env,
+    process::{Command, ExitStatus, Stdio},
 };
 
+// How long to wait for a remote before giving up
+const REMOTE_TIMEOUT: Timeout = Timeout::from_secs(60);
+
+// Trailer of the commits created by the version command
+const GENERATED_BY: &str = "Generated by cargo-workspaces";
+
+// First git version supporting `git push --atomic`
+const ATOMIC_PUSH_VERSION: (u64, u64) = (2, 4);
+
 pub fn git(root: &Utf8PathBuf, args: &[&str]) -> Result<(ExitStatus, String, String), Error> {
     debug!("git", args.to_vec().join(" "));
 
-    let output = Command::new("git")
-        .current_dir(root)
-        .args(args)
-        .output()
-        .map_err(|err| Error::Git {
-            err,
-            args: args.iter().map(|x| x.to_string()).collect(),
-        })?;
+    let mut cmd = Command::new("git");
+    cmd.current_dir(root).args(args);
+    log_command(&cmd);
+
+    let output = cmd.output().map_err(|err| Error::Git {
+        err,
+        args: args.iter().map(|x| x.to_string()).collect(),
+    })?;
+
+    log_output(&output.stdout, &output.stderr);
 
     Ok((
         output.status,
@@ -31,6 +44,74 @@ pub fn git(root: &Utf8PathBuf, args: &[&str]) -> Result<(ExitStatus, String, Str
     ))
 }
 
+/// Whether the directory is inside the work tree of a git repository, which vendored source
+/// trees or unpacked crates are not
+pub fn is_git_repo(root: &Utf8PathBuf) -> bool {
+    matches!(
+        git(root, &["rev-parse", "--is-inside-work-tree"]),
+        Ok((status, out, _)) if status.success() && out == "true"
+    )
+}
+
+/// Runs a git command which talks to a remote. Credential prompts are disabled so that
+/// it fails instead of hanging, and it is killed if it takes too long.
+pub fn git_remote(
+    root: &Utf8PathBuf,
+    args: &[&str],
+) -> Result<(ExitStatus, String, String), Error> {
+    debug!("git", args.to_vec().join(" "));
+
+    let to_err = |err| Error::Git {
+        err,
+        args: args.iter().map(|x| x.to_string()).collect(),
+    };
+
+    let mut cmd = Command::new("git");
+
+    cmd.current_dir(root)
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    if env::var_os("GIT_SSH_COMMAND").is_none() {
+        cmd.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+    }
+
+    isolate(&mut cmd);
+    log_command(&cmd);
+
+    let child = cmd.spawn().map_err(to_err)?;
+    let _guard = ChildGuard::new(child.id());
+
+    let (status, stdout, stderr) = output_timeout(child, Some(REMOTE_TIMEOUT)).map_err(to_err)?;
+
+    let Some(status) = status else {
+        return Err(Error::GitTimeout {
+            args: args.join(" "),
+            secs: REMOTE_TIMEOUT.duration().as_secs(),
+        });
+    };
+
+    log_output(&stdout, &stderr);
+
+    Ok((
+        status,
+        String::from_utf8(stdout)?.trim().to_owned(),
+        String::from_utf8(stderr)?.trim().to_owned(),
+    ))
+}
+
+/// Returns the tag names listed by `git ls-remote --tags --refs`
+pub fn parse_remote_tags(out: &str) -> Vec<String> {
+    out.lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .filter_map(|r| r.strip_prefix("refs/tags/"))
+        .map(|x| x.to_string())
+        .collect()
+}
+
 #[derive(Debug, Parser)]
 #[clap(next_help_heading = "GIT OPTIONS")]
 pub struct GitOpt {
@@ -38,7 +119,7 @@ pub struct GitOpt {
     #[clap(long, conflicts_with_all = &[
         "allow-branch", "amend", "message", "no-git-tag",
         "tag-prefix", "individual-tag-prefix", "no-individual-tags",
-        "no-git-push", "git-remote", "no-global-tag"
+        "no-git-push", "git-remote", "push", "no-global-tag", "skip-tag-for"
     ])]
     pub no_git_commit: bool,
 
@@ -60,16 +141,53 @@ pub struct GitOpt {
     pub message: Option<String>,
 
     /// Do not tag generated commit
-    #[clap(long, conflicts_with_all = &["tag-prefix", "individual-tag-prefix", "no-individual-tags"])]
+    #[clap(long, conflicts_with_all = &["tag-prefix", "individual-tag-prefix", "no-individual-tags", "skip-tag-for"])]
     pub no_git_tag: bool,
 
+    /// Do not create a global tag for a workspace
+    #[clap(long)]
+    pub no_global_tag: bool,
+
+    #[clap(flatten)]
+    pub tag: TagOpt,
+
+    /// Do not push generated commit and tags to git remote
+    #[clap(long, conflicts_with_all = &["git-remote", "push"])]
+    pub no_git_push: bool,
+
+    /// Push the release commit and the created tags atomically to the remote [default: origin]
+    #[clap(
+        long,
+        value_name = "REMOTE",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "origin",
+        conflicts_with_all = &["git-remote"],
+        forbid_empty_values(true)
+    )]
+    pub push: Option<String>,
+
+    /// Push git changes to the specified remote
+    #[clap(
+        long,
+        default_value = "origin",
+        value_name = "REMOTE",
+        forbid_empty_values(true)
+    )]
+    pub git_remote: String,
+}
+
+/// How the release tags are named, which is all `tag` needs to know
+#[derive(Debug, Parser)]
+#[clap(next_help_heading = "GIT OPTIONS")]
+pub struct TagOpt {
     /// Do not tag individual versions for crates
     #[clap(long, conflicts_with_all = &["individual-tag-prefix"])]
     pub no_individual_tags: bool,
 
-    /// Do not create a global tag for a workspace
-    #[clap(long)]
-    pub no_global_tag: bool,
+    /// Do not tag individual versions for crates matched by glob
+    #[clap(long, value_name = "PATTERN", conflicts_with_all = &["no-individual-tags"])]
+    pub skip_tag_for: Option<String>,
 
     /// Customize tag prefix (can be empty)
     #[clap(long, default_value = "v", value_name = "PREFIX")]
@@ -84,19 +202,61 @@ pub struct GitOpt {
         forbid_empty_values(true)
     )]
     pub individual_tag_prefix: String,
+}
 
-    /// Do not push generated commit and tags to git remote
-    #[clap(long, conflicts_with_all = &["git-remote"])]
-    pub no_git_push: bool,
+impl TagOpt {
+    /// Whether the crates share the workspace tag instead of being tagged individually
+    pub fn grouped(&self, config: &WorkspaceConfig) -> bool {
+        self.no_individual_tags || config.no_individual_tags.unwrap_or_default()
+    }
 
-    /// Push git changes to the specified remote
-    #[clap(
-        long,
-        default_value = "origin",
-        value_name = "REMOTE",
-        forbid_empty_values(true)
-    )]
-    pub git_remote: String,
+    /// Returns the individual tag of the crate, if it gets one. Independent crates are
+    /// tagged even when the workspace is grouped, since the workspace tag isn't theirs.
+    pub fn crate_tag(
+        &self,
+        config: &WorkspaceConfig,
+        name: &str,
+        independent: bool,
+        version: &Version,
+    ) -> Result<Option<String>, Error> {
+        if self.grouped(config) && !independent {
+            return Ok(None);
+        }
+
+        if let Some(pattern) = &self.skip_tag_for
+            && Glob::new(pattern)?.compile_matcher().is_match(name)
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(self.individual_tag(name, version)))
+    }
+
+    /// The tag of the crate, which is the workspace tag when it is not tagged individually
+    pub fn release_tag(
+        &self,
+        config: &WorkspaceConfig,
+        name: &str,
+        independent: bool,
+        version: &Version,
+    ) -> Result<String, Error> {
+        Ok(match self.crate_tag(config, name, independent, version)? {
+            Some(tag) => tag,
+            None => self.global_tag(version),
+        })
+    }
+
+    pub fn global_tag(&self, version: &Version) -> String {
+        format!("{}{}", &self.tag_prefix, version)
+    }
+
+    pub fn individual_tag(&self, name: &str, version: &Version) -> String {
+        format!(
+            "{}{}",
+            self.individual_tag_prefix.replace("%n", name),
+            version
+        )
+    }
 }
 
 impl GitOpt {
@@ -108,12 +268,16 @@ impl GitOpt {
         let mut ret = None;
 
         if !self.no_git_commit {
-            let (_, out, err) = git(root, &["rev-list", "--count", "--all", "--max-count=1"])?;
-
-            if err.contains("not a git repository") {
-                return Err(Error::NotGit);
+            if !is_git_repo(root) {
+                warn!(
+                    "not a git repository",
+                    "skipping the commit and the tags of the versions"
+                );
+                return Ok(None);
             }
 
+            let (_, out, _) = git(root, &["rev-list", "--count", "--all", "--max-count=1"])?;
+
             if out == "0" {
                 return Err(Error::NoCommits);
             }
@@ -152,7 +316,7 @@ impl GitOpt {
             }
 
             if !self.no_git_push {
-                let remote_branch = format!("{}/{}", self.git_remote, branch);
+                let remote_branch = format!("{}/{}", self.remote(), branch);
 
                 let (_, out, _) = git(
                     root,
@@ -165,7 +329,7 @@ impl GitOpt {
 
                 if out.is_empty() {
                     return Err(Error::NoRemote {
-                        remote: self.git_remote.clone(),
+                        remote: self.remote().to_string(),
                         branch,
                     });
                 }
@@ -199,13 +363,14 @@ impl GitOpt {
         root: &Utf8PathBuf,
         new_version: &Option<Version>,
         new_versions: &Map<String, Version>,
+        independent: &[String],
         branch: Option<String>,
         config: &WorkspaceConfig,
     ) -> Result<(), Error> {
-        if !self.no_git_commit {
+        // There is no branch when not committing or outside of a git repository
+        if let Some(branch) = branch {
             info!("version", "committing changes");
 
-            let branch = branch.expect(INTERNAL_ERR);
             let added = git(root, &["add", "-u"])?;
 
             if !added.0.success() {
@@ -244,37 +409,217 @@ impl GitOpt {
                 return Err(Error::NotCommitted(committed.1, committed.2));
             }
 
-            if !self.no_git_tag {
+            let tags = self.tags(new_version, new_versions, independent, config)?;
+
+            if !tags.is_empty() {
                 info!("version", "tagging");
+            }
 
-                if !self.no_global_tag
-                    && let Some(version) = new_version {
-                        let tag = format!("{}{}", &self.tag_prefix, version);
-                        self.tag(root, &tag, &tag)?;
-                    }
-
-                if !(self.no_individual_tags || config.no_individual_tags.unwrap_or_default()) {
-                    for (p, v) in new_versions {
-                        let tag = format!("{}{}", self.individual_tag_prefix.replace("%n", p), v);
-                        self.tag(root, &tag, &tag)?;
-                    }
-                }
+            for tag in &tags {
+                self.tag(root, tag, tag)?;
             }
 
-            if !self.no_git_push {
-                info!("git", "pushing");
+            self.push(root, &branch, &tags)?;
+        }
 
-                let pushed = git(root, &["push", "--follow-tags", &self.git_remote, &branch])?;
+        Ok(())
+    }
 
-                if !pushed.0.success() {
-                    return Err(Error::NotPushed(pushed.1, pushed.2));
-                }
+    /// Returns the tags the version commit gets
+    pub fn tags(
+        &self,
+        new_version: &Option<Version>,
+        new_versions: &Map<String, Version>,
+        independent: &[String],
+        config: &WorkspaceConfig,
+    ) -> Result<Vec<String>, Error> {
+        let mut ret = vec![];
+
+        if self.no_git_tag {
+            return Ok(ret);
+        }
+
+        if !self.no_global_tag
+            && let Some(version) = new_version
+        {
+            ret.push(self.tag.global_tag(version));
+        }
+
+        for (p, v) in new_versions {
+            ret.extend(self.crate_tag(config, p, independent.contains(p), v)?);
+        }
+
+        Ok(ret)
+    }
+
+    /// Returns the individual tag of the crate, if it gets one, see [`TagOpt::crate_tag`]
+    pub fn crate_tag(
+        &self,
+        config: &WorkspaceConfig,
+        name: &str,
+        independent: bool,
+        version: &Version,
+    ) -> Result<Option<String>, Error> {
+        if self.no_git_tag {
+            return Ok(None);
+        }
+
+        self.tag.crate_tag(config, name, independent, version)
+    }
+
+    /// Commits and tags the bump of a single crate, without pushing. Returns the created tag.
+    pub fn commit_crate(
+        &self,
+        root: &Utf8PathBuf,
+        config: &WorkspaceConfig,
+        name: &str,
+        version: &Version,
+    ) -> Result<Option<String>, Error> {
+        info!("version", format!("committing {}", name));
+
+        let added = git(root, &["add", "-u"])?;
+
+        if !added.0.success() {
+            return Err(Error::NotAdded(added.1, added.2));
+        }
+
+        let mut new_versions = Map::new();
+        new_versions.insert(name.to_string(), version.clone());
+
+        let msg = self.message.as_deref().unwrap_or("Release %v");
+        let msg = self
+            .commit_msg(msg, &new_versions)
+            .replace("%v", &format!("{}@{}", name, version));
+        let committed = git(root, &["commit", "-m", &msg])?;
+
+        if !committed.0.success() {
+            return Err(Error::NotCommitted(committed.1, committed.2));
+        }
+
+        // Only independent crates can be committed separately
+        let tag = self.crate_tag(config, name, true, version)?;
+
+        if let Some(tag) = &tag {
+            self.tag(root, tag, tag)?;
+        }
+
+        Ok(tag)
+    }
+
+    /// The remote which the release is pushed to
+    pub fn remote(&self) -> &str {
+        self.push.as_deref().unwrap_or(&self.git_remote)
+    }
+
+    /// Returns the commands pushing the branch along with the given tags. It is a single
+    /// atomic push when git supports it, otherwise the refs are pushed one by one.
+    pub fn push_commands(
+        &self,
+        root: &Utf8PathBuf,
+        branch: &str,
+        tags: &[String],
+    ) -> Result<Vec<Vec<String>>, Error> {
+        let refs = push_refs(branch, tags);
+
+        if supports_atomic_push(root)? {
+            Ok(vec![atomic_push_args(self.remote(), &refs)])
+        } else {
+            Ok(sequential_push_args(self.remote(), &refs))
+        }
+    }
+
+    /// Pushes the branch and the tags created during this run, without touching the other
+    /// local tags like `--follow-tags` would
+    pub fn push(&self, root: &Utf8PathBuf, branch: &str, tags: &[String]) -> Result<(), Error> {
+        if self.no_git_push {
+            return Ok(());
+        }
+
+        info!("git", "pushing");
+
+        let commands = self.push_commands(root, branch, tags)?;
+
+        match self.run_pushes(root, &commands) {
+            // The remote may not support atomic pushes even if the local git does
+            Err(Error::PushFailed { err, .. })
+                if commands.len() == 1 && err.contains("does not support --atomic") =>
+            {
+                let refs = push_refs(branch, tags);
+                self.run_pushes(root, &sequential_push_args(self.remote(), &refs))
+            }
+            x => x,
+        }
+    }
+
+    fn run_pushes(&self, root: &Utf8PathBuf, commands: &[Vec<String>]) -> Result<(), Error> {
+        for (i, args) in commands.iter().enumerate() {
+            let args = args.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+            let (status, _, err) = git(root, &args)?;
+
+            if !status.success() {
+                return Err(push_failed(self.remote(), err, &commands[i..]));
             }
         }
 
         Ok(())
     }
 
+    /// Commits (and pushes) a version bump done outside of the regular versioning
+    pub fn commit_bump(
+        &self,
+        root: &Utf8PathBuf,
+        name: &str,
+        version: &Version,
+    ) -> Result<(), Error> {
+        if self.no_git_commit {
+            return Ok(());
+        }
+
+        info!("version", "committing changes");
+
+        let added = git(root, &["add", "-u"])?;
+
+        if !added.0.success() {
+            return Err(Error::NotAdded(added.1, added.2));
+        }
+
+        let mut new_versions = Map::new();
+        new_versions.insert(name.to_string(), version.clone());
+
+        let msg = self.commit_msg(&format!("Bump {} to {}", name, version), &new_versions);
+        let committed = git(root, &["commit", "-m", &msg])?;
+
+        if !committed.0.success() {
+            return Err(Error::NotCommitted(committed.1, committed.2));
+        }
+
+        if !self.no_git_push {
+            info!("git", "pushing");
+
+            let pushed = git(root, &["push", self.remote(), "HEAD"])?;
+
+            if !pushed.0.success() {
+                return Err(Error::NotPushed(pushed.1, pushed.2));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The tag of the crate, which is the workspace tag when it is not tagged individually
+    pub fn release_tag(
+        &self,
+        config: &WorkspaceConfig,
+        name: &str,
+        independent: bool,
+        version: &Version,
+    ) -> Result<String, Error> {
+        Ok(match self.crate_tag(config, name, independent, version)? {
+            Some(tag) => tag,
+            None => self.tag.global_tag(version),
+        })
+    }
+
     fn tag(&self, root: &Utf8PathBuf, tag: &str, msg: &str) -> Result<(), Error> {
         let tagged = git(root, &["tag", tag, "-m", msg])?;
 
@@ -287,13 +632,182 @@ impl GitOpt {
 
     fn commit_msg(&self, msg: &str, new_versions: &Map<String, Version>) -> String {
         format!(
-            "{}\n\n{}\n\nGenerated by cargo-workspaces",
+            "{}\n\n{}\n\n{}",
             msg,
             new_versions
                 .iter()
                 .map(|x| format!("{}@{}", x.0, x.1))
                 .collect::<Vec<_>>()
-                .join("\n")
+                .join("\n"),
+            GENERATED_BY
         )
     }
 }
+
+fn push_refs(branch: &str, tags: &[String]) -> Vec<String> {
+    let mut ret = vec![branch.to_string()];
+    ret.extend(tags.iter().map(|x| format!("refs/tags/{}", x)));
+    ret
+}
+
+fn atomic_push_args(remote: &str, refs: &[String]) -> Vec<String> {
+    let mut ret = vec![
+        "push".to_string(),
+        "--atomic".to_string(),
+        remote.to_string(),
+    ];
+    ret.extend(refs.iter().cloned());
+    ret
+}
+
+fn sequential_push_args(remote: &str, refs: &[String]) -> Vec<Vec<String>> {
+    refs.iter()
+        .map(|x| vec!["push".to_string(), remote.to_string(), x.clone()])
+        .collect()
+}
+
+fn supports_atomic_push(root: &Utf8PathBuf) -> Result<bool, Error> {
+    let (_, out, _) = git(root, &["--version"])?;
+
+    Ok(parse_git_version(&out).is_some_and(|x| x >= ATOMIC_PUSH_VERSION))
+}
+
+/// Returns the major and minor version from the output of `git --version`
+fn parse_git_version(out: &str) -> Option<(u64, u64)> {
+    let mut parts = out.strip_prefix("git version ")?.split('.');
+
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts
+        .next()?
+        .chars()
+        .take_while(|x| x.is_ascii_digit())
+        .collect::<String>()
+        .parse()
+        .ok()?;
+
+    Some((major, minor))
+}
+
+/// Lists the commands which still need to be run once the push problem is solved
+fn push_failed(remote: &str, err: String, remaining: &[Vec<String>]) -> Error {
+    Error::PushFailed {
+        remote: remote.to_string(),
+        err,
+        commands: remaining
+            .iter()
+            .map(|x| format!("    git {}", x.join(" ")))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Deletes the tags pointing to `HEAD` and drops it with `git reset --hard`, after making
+/// sure that it is a commit created by the version command. Returns the deleted tags.
+pub fn undo_release(root: &Utf8PathBuf) -> Result<Vec<String>, Error> {
+    let (_, msg, _) = git(root, &["log", "-1", "--format=%B"])?;
+
+    if !is_release_commit(&msg) {
+        return Err(Error::NotReleaseCommit);
+    }
+
+    // The commit is removed with `git reset --hard`
+    let (_, status, _) = git(root, &["status", "--porcelain", "--untracked-files=no"])?;
+
+    if !status.is_empty() {
+        return Err(Error::UncommittedChanges);
+    }
+
+    let (_, tags, _) = git(root, &["tag", "--points-at", "HEAD"])?;
+    let tags = tags.lines().map(|x| x.to_string()).collect::<Vec<_>>();
+
+    for tag in &tags {
+        let deleted = git(root, &["tag", "-d", tag])?;
+
+        if !deleted.0.success() {
+            return Err(Error::NotUndone(deleted.1, deleted.2));
+        }
+    }
+
+    let reset = git(root, &["reset", "--hard", "HEAD~1"])?;
+
+    if !reset.0.success() {
+        return Err(Error::NotUndone(reset.1, reset.2));
+    }
+
+    Ok(tags)
+}
+
+fn is_release_commit(msg: &str) -> bool {
+    msg.trim_end().ends_with(GENERATED_BY)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote_tags() {
+        let out = "1a2b3c\trefs/tags/v0.1.0\n4d5e6f\trefs/tags/dep1@0.1.0\n7a8b9c\trefs/heads/main";
+
+        assert_eq!(parse_remote_tags(out), vec!["v0.1.0", "dep1@0.1.0"]);
+        assert!(parse_remote_tags("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_git_version() {
+        assert_eq!(parse_git_version("git version 2.39.2"), Some((2, 39)));
+        assert_eq!(
+            parse_git_version("git version 2.37.1 (Apple Git-137.1)"),
+            Some((2, 37))
+        );
+        assert_eq!(
+            parse_git_version("git version 2.41.0.windows.1"),
+            Some((2, 41))
+        );
+        assert_eq!(parse_git_version("git version 1.9.rc0"), Some((1, 9)));
+        assert_eq!(parse_git_version("hub version 2.14.2"), None);
+    }
+
+    #[test]
+    fn test_push_args() {
+        let refs = push_refs("main", &["v1.0.0".to_string(), "dep1@1.0.0".to_string()]);
+
+        assert_eq!(
+            atomic_push_args("upstream", &refs),
+            vec![
+                "push",
+                "--atomic",
+                "upstream",
+                "main",
+                "refs/tags/v1.0.0",
+                "refs/tags/dep1@1.0.0"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remote() {
+        assert_eq!(GitOpt::parse_from(["git"]).remote(), "origin");
+        assert_eq!(GitOpt::parse_from(["git", "--push"]).remote(), "origin");
+        assert_eq!(
+            GitOpt::parse_from(["git", "--push=upstream"]).remote(),
+            "upstream"
+        );
+        assert_eq!(
+            GitOpt::parse_from(["git", "--git-remote", "upstream"]).remote(),
+            "upstream"
+        );
+    }
+
+    #[test]
+    fn test_is_release_commit() {
+        let mut new_versions = Map::new();
+        new_versions.insert("dep1".to_string(), Version::parse("1.0.0").unwrap());
+
+        let msg = GitOpt::parse_from(["git"]).commit_msg("Release 1.0.0", &new_versions);
+
+        assert!(is_release_commit(&msg));
+        assert!(is_release_commit(&format!("{}\n", msg)));
+        assert!(!is_release_commit("Fix the build"));
+    }
+}
@@ -0,0 +1,204 @@
+//! Graceful handling of Ctrl-C and termination signals.
+//!
+//! Once interrupted, no new work is scheduled, the running children are terminated and
+//! waited for, the registered cleanups are run and a summary of what was completed is
+//! printed before exiting with [`INTERRUPTED_EXIT_CODE`].
+
+use lazy_static::lazy_static;
+use oclif::term::{ERR_GREEN, ERR_MAGENTA, ERR_YELLOW, TERM_ERR};
+
+use std::{
+    collections::BTreeMap as Map,
+    process::{exit, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Mimics the exit code of a process killed by `SIGINT`
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+// How long to wait for the children to exit after terminating them
+const REAP_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Cleanup = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    static ref INTERRUPTED: AtomicBool = AtomicBool::new(false);
+    static ref CHILDREN: Mutex<Vec<u32>> = Mutex::new(vec![]);
+    static ref CLEANUPS: Mutex<Map<usize, Cleanup>> = Mutex::new(Map::new());
+    static ref NEXT_CLEANUP: AtomicUsize = AtomicUsize::new(0);
+    static ref COMPLETED: Mutex<Vec<String>> = Mutex::new(vec![]);
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Blocks the calling thread forever once interrupted, so that it does not start new
+/// work while the signal handler cleans up and exits the process
+pub fn stop_if_interrupted() {
+    if is_interrupted() {
+        loop {
+            thread::park();
+        }
+    }
+}
+
+/// Records a finished unit of work for the summary printed when interrupted
+pub fn record_completed<S: Into<String>>(desc: S) {
+    COMPLETED.lock().unwrap().push(desc.into());
+}
+
+/// Tracks a spawned child process until it is dropped, which should happen after the
+/// child has been waited for
+pub struct ChildGuard {
+    pid: u32,
+}
+
+impl ChildGuard {
+    pub fn new(pid: u32) -> Self {
+        let mut children = CHILDREN.lock().unwrap();
+
+        // The handler might have already terminated the other children
+        if is_interrupted() {
+            terminate(pid);
+        }
+
+        children.push(pid);
+        Self { pid }
+    }
+}
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        CHILDREN.lock().unwrap().retain(|x| *x != self.pid);
+        stop_if_interrupted();
+    }
+}
+
+/// Runs the given function if interrupted before the returned guard is dropped
+pub fn on_interrupt<F: FnOnce() + Send + 'static>(f: F) -> CleanupGuard {
+    let id = NEXT_CLEANUP.fetch_add(1, Ordering::SeqCst);
+
+    CLEANUPS.lock().unwrap().insert(id, Box::new(f));
+    CleanupGuard { id }
+}
+
+pub struct CleanupGuard {
+    id: usize,
+}
+
+impl Drop for CleanupGuard {
+    fn drop(&mut self) {
+        CLEANUPS.lock().unwrap().remove(&self.id);
+    }
+}
+
+pub fn set_interrupt_handler() {
+    ctrlc::set_handler(|| {
+        handle_interrupt();
+        exit(INTERRUPTED_EXIT_CODE);
+    })
+    .expect("Error setting Ctrl-C handler");
+}
+
+fn handle_interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+
+    // https://github.com/console-rs/dialoguer/issues/77
+    let _ = TERM_ERR.clear_line();
+    let _ = TERM_ERR.show_cursor();
+    let _ = dialoguer::console::Term::stdout().show_cursor();
+
+    let _ = TERM_ERR.write_line(&format!(
+        "{} {}",
+        ERR_YELLOW.apply_to("warn"),
+        ERR_MAGENTA.apply_to("interrupted, cleaning up")
+    ));
+
+    reap_children();
+    run_cleanups();
+
+    let completed = COMPLETED.lock().unwrap();
+    let summary = if completed.is_empty() {
+        "nothing".to_string()
+    } else {
+        completed.join(", ")
+    };
+
+    let _ = TERM_ERR.write_line(&format!(
+        "{} {} {}",
+        ERR_GREEN.apply_to("info"),
+        ERR_MAGENTA.apply_to("completed before interruption"),
+        summary
+    ));
+}
+
+fn reap_children() {
+    for pid in CHILDREN.lock().unwrap().iter() {
+        terminate(*pid);
+    }
+
+    let start = Instant::now();
+
+    // The threads waiting on the children unregister them once they have exited
+    while !CHILDREN.lock().unwrap().is_empty() && start.elapsed() < REAP_TIMEOUT {
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn run_cleanups() {
+    let cleanups = std::mem::take(&mut *CLEANUPS.lock().unwrap());
+
+    for (_, cleanup) in cleanups {
+        cleanup();
+    }
+}
+
+fn terminate(pid: u32) {
+    let pid = pid.to_string();
+
+    #[cfg(not(windows))]
+    let mut cmd = Command::new("kill");
+    #[cfg(not(windows))]
+    cmd.args(["-TERM", &pid]);
+
+    #[cfg(windows)]
+    let mut cmd = Command::new("taskkill");
+    #[cfg(windows)]
+    cmd.args(["/PID", &pid, "/T", "/F"]);
+
+    let _ = cmd.stdout(Stdio::null()).stderr(Stdio::null()).status();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn test_run_cleanups() {
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let first = calls.clone();
+        let _guard = on_interrupt(move || {
+            first.fetch_add(1, Ordering::SeqCst);
+        });
+
+        // Cleanups whose work already finished normally are not run
+        let second = calls.clone();
+        drop(on_interrupt(move || {
+            second.fetch_add(10, Ordering::SeqCst);
+        }));
+
+        run_cleanups();
+        run_cleanups();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,280 @@
+//! JUnit XML reports of the commands run by `exec`, one testcase per crate.
+//!
+//! The report is shared with the interrupt handler so that it can be written even when the
+//! run is interrupted or stopped by an error, in which case the crate being run is reported
+//! as an error.
+
+use crate::utils::{on_interrupt, CleanupGuard};
+
+use oclif::console::strip_ansi_codes;
+
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How many bytes of the stderr of a failed crate are kept for the report
+pub const STDERR_TAIL: usize = 8 * 1024;
+
+// How many lines of the kept stderr end up in the failure message
+const TAIL_LINES: usize = 20;
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Passed,
+    Failed { reason: String, stderr: String },
+    Skipped(String),
+    Error(String),
+}
+
+#[derive(Debug)]
+struct TestCase {
+    name: String,
+    time: Duration,
+    outcome: Outcome,
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    suite: String,
+    cases: Vec<TestCase>,
+    running: Option<(String, Instant)>,
+}
+
+/// A JUnit report written to the given path once the run finished or was interrupted
+pub struct Junit {
+    path: PathBuf,
+    report: Arc<Mutex<Report>>,
+    _guard: CleanupGuard,
+}
+
+impl Junit {
+    pub fn new(path: PathBuf, suite: String) -> Self {
+        let report = Arc::new(Mutex::new(Report {
+            suite,
+            ..Default::default()
+        }));
+
+        let (cleanup_path, cleanup_report) = (path.clone(), report.clone());
+        let _guard = on_interrupt(move || {
+            if let Ok(mut report) = cleanup_report.lock() {
+                report.interrupt();
+                let _ = fs::write(&cleanup_path, report.to_xml());
+            }
+        });
+
+        Self {
+            path,
+            report,
+            _guard,
+        }
+    }
+
+    /// Marks the crate as running until its outcome is recorded
+    pub fn start(&self, name: &str) {
+        self.report.lock().unwrap().running = Some((name.to_string(), Instant::now()));
+    }
+
+    pub fn pass(&self) {
+        self.finish(Outcome::Passed);
+    }
+
+    pub fn fail(&self, reason: &str, stderr: &[u8]) {
+        self.finish(Outcome::Failed {
+            reason: reason.to_string(),
+            stderr: tail(stderr),
+        });
+    }
+
+    /// Records the crate being run as an error, when the run stopped before it finished
+    pub fn error(&self, message: &str) {
+        self.finish(Outcome::Error(message.to_string()));
+    }
+
+    pub fn skip(&self, name: &str, reason: &str) {
+        self.report.lock().unwrap().cases.push(TestCase {
+            name: name.to_string(),
+            time: Duration::ZERO,
+            outcome: Outcome::Skipped(reason.to_string()),
+        });
+    }
+
+    pub fn write(&self) -> io::Result<()> {
+        fs::write(&self.path, self.report.lock().unwrap().to_xml())
+    }
+
+    fn finish(&self, outcome: Outcome) {
+        let mut report = self.report.lock().unwrap();
+
+        if let Some((name, start)) = report.running.take() {
+            report.cases.push(TestCase {
+                name,
+                time: start.elapsed(),
+                outcome,
+            });
+        }
+    }
+}
+
+impl Report {
+    fn interrupt(&mut self) {
+        if let Some((name, start)) = self.running.take() {
+            self.cases.push(TestCase {
+                name,
+                time: start.elapsed(),
+                outcome: Outcome::Error("interrupted".to_string()),
+            });
+        }
+    }
+
+    fn to_xml(&self) -> String {
+        let count = |f: fn(&Outcome) -> bool| self.cases.iter().filter(|x| f(&x.outcome)).count();
+        let time = self.cases.iter().map(|x| x.time).sum::<Duration>();
+        let suite = escape(&self.suite);
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+            suite,
+            self.cases.len(),
+            count(|x| matches!(x, Outcome::Failed { .. })),
+            count(|x| matches!(x, Outcome::Error(_))),
+            count(|x| matches!(x, Outcome::Skipped(_))),
+            time.as_secs_f64()
+        );
+
+        for case in &self.cases {
+            let _ = write!(
+                xml,
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+                escape(&case.name),
+                suite,
+                case.time.as_secs_f64()
+            );
+
+            let _ = match &case.outcome {
+                Outcome::Passed => writeln!(xml, "/>"),
+                Outcome::Failed { reason, stderr } => writeln!(
+                    xml,
+                    ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                    escape(reason),
+                    escape(stderr)
+                ),
+                Outcome::Skipped(reason) => writeln!(
+                    xml,
+                    ">\n      <skipped message=\"{}\"/>\n    </testcase>",
+                    escape(reason)
+                ),
+                Outcome::Error(message) => writeln!(
+                    xml,
+                    ">\n      <error message=\"{}\"/>\n    </testcase>",
+                    escape(message)
+                ),
+            };
+        }
+
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+}
+
+/// The last lines of the output, with invalid UTF-8 and terminal colors replaced
+fn tail(output: &[u8]) -> String {
+    let start = output.len().saturating_sub(STDERR_TAIL);
+    let output = strip_ansi_codes(&String::from_utf8_lossy(&output[start..])).to_string();
+    let lines = output.lines().collect::<Vec<_>>();
+
+    lines[lines.len().saturating_sub(TAIL_LINES)..].join("\n")
+}
+
+/// Escapes the text for attributes and contents, replacing the characters XML 1.0 forbids
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&apos;"),
+            '\t' | '\n' | '\r' => ret.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => ret.push(char::REPLACEMENT_CHARACTER),
+            c => ret.push(c),
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("a < b && \"c\""),
+            "a &lt; b &amp;&amp; &quot;c&quot;"
+        );
+        assert_eq!(escape("bell\u{7}\ttab"), "bell\u{fffd}\ttab");
+    }
+
+    #[test]
+    fn test_tail() {
+        assert_eq!(
+            tail(b"\x1b[31merror\x1b[0m: \xffbad\n"),
+            "error: \u{fffd}bad"
+        );
+
+        let long = (0..50).map(|x| format!("line {}\n", x)).collect::<String>();
+        assert_eq!(tail(long.as_bytes()).lines().next(), Some("line 30"));
+    }
+
+    #[test]
+    fn test_to_xml() {
+        let case = |name: &str, outcome| TestCase {
+            name: name.to_string(),
+            time: Duration::from_millis(1500),
+            outcome,
+        };
+
+        let report = Report {
+            suite: "exec".to_string(),
+            cases: vec![
+                case("dep1", Outcome::Passed),
+                case(
+                    "dep2",
+                    Outcome::Failed {
+                        reason: "exit code 101".to_string(),
+                        stderr: "assertion `left == right` failed".to_string(),
+                    },
+                ),
+                case("top", Outcome::Error("interrupted".to_string())),
+            ],
+            running: None,
+        };
+
+        assert_eq!(
+            report.to_xml(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="exec" tests="3" failures="1" errors="1" skipped="0" time="4.500">
+    <testcase name="dep1" classname="exec" time="1.500"/>
+    <testcase name="dep2" classname="exec" time="1.500">
+      <failure message="exit code 101">assertion `left == right` failed</failure>
+    </testcase>
+    <testcase name="top" classname="exec" time="1.500">
+      <error message="interrupted"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
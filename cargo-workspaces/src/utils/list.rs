@@ -4,7 +4,7 @@ use clap::Parser;
 use oclif::{console::style, term::TERM_OUT};
 use serde_json::to_string_pretty;
 
-use std::{cmp::max, path::Path};
+use std::collections::BTreeMap as Map;
 
 #[derive(Debug, Parser)]
 #[clap(next_help_heading = "LIST OPTIONS")]
@@ -34,6 +34,11 @@ pub fn list(pkgs: &[Pkg], list: ListOpt) -> Result {
         return Ok(TERM_OUT.write_line(&to_string_pretty(pkgs)?)?);
     }
 
+    list_with(pkgs, list, &Map::new())
+}
+
+/// Prints the crates as [`list`] does, appending the given text to the row of each crate
+pub fn list_with(pkgs: &[Pkg], list: ListOpt, extra: &Map<String, String>) -> Result {
     if pkgs.is_empty() {
         return Ok(());
     }
@@ -46,7 +51,7 @@ pub fn list(pkgs: &[Pkg], list: ListOpt) -> Result {
         .expect(INTERNAL_ERR);
     let third = pkgs
         .iter()
-        .map(|x| max(1, x.path.as_os_str().len()))
+        .map(|x| x.path.as_os_str().len())
         .max()
         .expect(INTERNAL_ERR);
 
@@ -59,18 +64,12 @@ pub fn list(pkgs: &[Pkg], list: ListOpt) -> Result {
         let mut width = first - pkg.name.len();
 
         if list.list.long {
-            let path = if pkg.path.as_os_str().is_empty() {
-                Path::new(".")
-            } else {
-                pkg.path.as_path()
-            };
-
             TERM_OUT.write_str(&format!(
                 "{:f$} {}{:s$} {}",
                 "",
                 style(format!("v{}", pkg.version)).green(),
                 "",
-                style(path.display()).black().bright(),
+                style(pkg.path.display()).black().bright(),
                 f = width,
                 s = second - pkg.version.to_string().len() - 1,
             ))?;
@@ -85,6 +84,12 @@ pub fn list(pkgs: &[Pkg], list: ListOpt) -> Result {
                 style("PRIVATE").red(),
                 w = width
             ))?;
+
+            width = 0;
+        }
+
+        if let Some(extra) = extra.get(&pkg.name) {
+            TERM_OUT.write_str(&format!("{:w$} {}", "", extra, w = width))?;
         }
 
         TERM_OUT.write_line("")?;
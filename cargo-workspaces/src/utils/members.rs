@@ -0,0 +1,172 @@
+use crate::utils::{git, is_git_repo, Result};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use toml_edit::{Array, Formatted, Value};
+
+use std::{collections::HashSet, fs, path::Path};
+
+/// Finds the directories with a `Cargo.toml` under the root. Build outputs, the git
+/// directory and the directories ignored by git are not descended into.
+pub fn manifest_dirs(root: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let ignored = ignored_dirs(root)?;
+
+    let mut ret = vec![];
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        if dir.join("Cargo.toml").is_file() {
+            ret.push(dir.clone());
+        }
+
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+
+            // Symlinks are not followed, they could point back up the tree
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let Ok(path) = Utf8PathBuf::from_path_buf(entry.path()) else {
+                continue;
+            };
+
+            if matches!(path.file_name(), Some(".git" | "target")) || ignored.contains(&path) {
+                continue;
+            }
+
+            stack.push(path);
+        }
+    }
+
+    ret.sort();
+    Ok(ret)
+}
+
+/// The directories ignored by git, empty outside of a git repository
+fn ignored_dirs(root: &Utf8Path) -> Result<HashSet<Utf8PathBuf>> {
+    let root = root.to_path_buf();
+
+    if !is_git_repo(&root) {
+        return Ok(HashSet::new());
+    }
+
+    let (status, out, _) = git(
+        &root,
+        &[
+            "ls-files",
+            "--others",
+            "--ignored",
+            "--exclude-standard",
+            "--directory",
+        ],
+    )?;
+
+    if !status.success() {
+        return Ok(HashSet::new());
+    }
+
+    Ok(out
+        .lines()
+        .filter_map(|x| x.strip_suffix('/'))
+        .map(|x| root.join(x))
+        .collect())
+}
+
+/// Whether the workspace relative member path is inside one of the excluded paths
+pub fn is_excluded(member: &str, exclude: &[String]) -> bool {
+    exclude
+        .iter()
+        .map(|x| x.trim_start_matches("./"))
+        .any(|x| !x.is_empty() && Path::new(member).starts_with(Path::new(x)))
+}
+
+/// Adds the members to the array, one per line
+pub fn extend_members(array: &mut Array, members: Vec<String>) {
+    let max_member = members.len().saturating_sub(1);
+
+    // An empty inline array like `[ ]` keeps its inner whitespace as trailing decor
+    array.set_trailing("");
+    array.set_trailing_comma(false);
+
+    // The existing members get their own line too, and the closing newline of a previous
+    // extension now comes after the new members
+    for value in array.iter_mut() {
+        let decor = value.decor_mut();
+        let own_line = decor
+            .prefix()
+            .and_then(|x| x.as_str())
+            .is_some_and(|x| x.contains('\n'));
+
+        if !own_line {
+            decor.set_prefix("\n    ");
+        }
+
+        decor.set_suffix("");
+    }
+
+    array.extend(members.into_iter().enumerate().map(|(i, val)| {
+        let prefix = "\n    ";
+        let suffix = if i == max_member { ",\n" } else { "" };
+        Value::String(Formatted::new(val)).decorated(prefix, suffix)
+    }));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_excluded() {
+        let exclude = vec!["examples".to_string(), "./tools/gen/".to_string()];
+
+        assert!(is_excluded("examples", &exclude));
+        assert!(is_excluded("examples/foo", &exclude));
+        assert!(is_excluded("tools/gen", &exclude));
+        assert!(!is_excluded("examples-foo", &exclude));
+        assert!(!is_excluded("tools", &exclude));
+        assert!(!is_excluded("", &exclude));
+    }
+
+    #[test]
+    fn test_extend_members_twice() {
+        let mut array = Array::new();
+
+        extend_members(&mut array, vec!["a".to_string()]);
+        extend_members(&mut array, vec!["b".to_string(), "c".to_string()]);
+
+        assert_eq!(
+            array.to_string(),
+            "[\n    \"a\",\n    \"b\",\n    \"c\",\n]"
+        );
+    }
+
+    #[test]
+    fn test_extend_members_multiline() {
+        let mut array = "x = [\n    \"a\",\n]"
+            .parse::<toml_edit::Document>()
+            .unwrap()["x"]
+            .as_array()
+            .unwrap()
+            .clone();
+
+        extend_members(&mut array, vec!["b".to_string()]);
+
+        // The space after `=` is the decor of the array itself
+        assert_eq!(array.to_string(), " [\n    \"a\",\n    \"b\",\n]");
+    }
+
+    #[test]
+    fn test_extend_members_single_line() {
+        let mut array = "x = [\"a\", \"b\"]".parse::<toml_edit::Document>().unwrap()["x"]
+            .as_array()
+            .unwrap()
+            .clone();
+
+        extend_members(&mut array, vec!["c".to_string()]);
+
+        assert_eq!(
+            array.to_string(),
+            " [\n    \"a\",\n    \"b\",\n    \"c\",\n]"
+        );
+    }
+}
@@ -2,30 +2,84 @@ mod basic_checks;
 mod cargo;
 mod changable;
 mod config;
+mod context;
+mod crate_name;
+mod crates_io;
 mod dag;
 mod dev_dep_remover;
 mod error;
+mod forge_release;
 mod git;
+mod interrupt;
+mod junit;
 mod list;
+mod members;
+mod package_files;
+mod path;
 mod pkg;
 mod publish;
+mod readme;
+mod sbom;
+mod step_summary;
+mod timeout;
+mod toolchain;
+mod transaction;
+mod trusted_publishing;
 mod version;
 
-pub use basic_checks::basic_checks;
-pub use cargo::{cargo, cargo_config_get, change_versions, rename_packages};
+pub use basic_checks::{
+    basic_checks, check_packaged_files, missing_metadata, unversioned_path_deps,
+};
+pub use cargo::{
+    bumped_requirement, cargo, cargo_config_get, cargo_flags, cargo_output, cargo_status,
+    cargo_timeout, change_versions, change_versions_with, inherited_dependencies,
+    inherits_workspace_version, is_offline, metadata_command, rename_packages, set_cargo_flags,
+    set_workspace_version, workspace_dependencies,
+};
 pub use changable::{ChangeData, ChangeOpt};
-pub use config::{read_config, PackageConfig, WorkspaceConfig};
-pub use dag::dag;
+pub use config::{read_config, validate_config, PackageConfig, WorkspaceConfig, CONFIG_TABLES};
+pub use context::WorkspaceContext;
+pub use crate_name::check_crate_name;
+pub use crates_io::{with_user_agent, CratesIo, NameStatus};
+pub use dag::{dag, dag_with, DependencyGraph, Dependent, IgnoredDependency};
 pub use dev_dep_remover::{should_remove_dev_deps, DevDependencyRemover};
 pub(crate) use error::{debug, info, warn};
-pub use error::{get_debug, set_debug, Error};
-pub use git::{git, GitOpt};
-pub use list::{list, ListOpt, ListPublicOpt};
-pub use pkg::{get_pkgs, is_private, Pkg};
+pub use error::{
+    get_debug, get_dry_run, log_command, log_output, set_dry_run, set_verbosity, Error,
+};
+pub use forge_release::{Forge, ForgeRelease};
+pub use git::{git, git_remote, is_git_repo, parse_remote_tags, undo_release, GitOpt, TagOpt};
+pub use interrupt::{
+    on_interrupt, record_completed, set_interrupt_handler, stop_if_interrupted, ChildGuard,
+    CleanupGuard,
+};
+pub use junit::{Junit, STDERR_TAIL};
+pub use list::{list, list_with, ListOpt, ListPublicOpt};
+pub use members::{extend_members, is_excluded, manifest_dirs};
+pub use package_files::package_files;
+pub use path::relative_path;
+pub use pkg::{
+    get_pkgs, is_private, suggest, unknown_member, unpublished_members, Baseline, Pkg,
+};
 pub use publish::{
-    create_http_client, filter_private, is_published, package_registry, RegistryOpt,
+    available_versions, crate_checksum, create_http_client, download_checksum, filter_private,
+    filter_skip_publish, is_published, package_registry, publish_registry, published_checksum,
+    published_versions, registry_index_url, release_manifest_path, RegistryOpt, ReleaseManifest,
+    ReleasedCrate,
+};
+pub use readme::{readme_path, render_readme, update_readme};
+pub use sbom::{Sbom, SbomFormat};
+pub use step_summary::{version_summary, write_step_summary, VersionBump};
+pub use timeout::{
+    isolate, output_timeout, output_timeout_stderr, tee_timeout, wait_timeout, wait_timeout_stderr,
+    Timeout, TIMED_OUT_EXIT_CODE,
+};
+pub use toolchain::{
+    check_resolver, parse_rust_version, rust_version, MemberToolchain, Resolver, RustVersion,
 };
-pub use version::VersionOpt;
+pub use transaction::Transaction;
+pub use trusted_publishing::TrustedToken;
+pub use version::{utc_time, VersionOpt};
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
 
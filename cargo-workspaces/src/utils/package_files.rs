@@ -0,0 +1,254 @@
+//! The files packaged by each crate, used by `--precise` to attribute changed files.
+//!
+//! The lists come from `cargo package --list`, along with the paths a build script watches with
+//! `cargo:rerun-if-changed`. They are cached under `target/cargo-workspaces/`, keyed by the
+//! modification time of the crate manifest and build script.
+
+use crate::utils::{cargo, debug, Pkg, Result, INTERNAL_ERR};
+
+use cargo_metadata::Metadata;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use toml_edit::Document;
+
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    fs,
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+const CACHE_FILE: &str = "cargo-workspaces/package-files.json";
+
+// Files generated by `cargo package` which do not exist in the crate
+const GENERATED: &[&str] = &[".cargo_vcs_info.json", "Cargo.toml"];
+
+lazy_static! {
+    // Only literal paths, the formatted ones cannot be known without running the script
+    static ref RERUN_IF_CHANGED: Regex =
+        Regex::new(r#"cargo::?rerun-if-changed=([^"{}\\]+)""#).expect(INTERNAL_ERR);
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Cache {
+    crates: Map<String, CachedFiles>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedFiles {
+    /// Latest modification time of the manifest and build script in milliseconds since the epoch
+    mtime: u64,
+    files: Vec<PathBuf>,
+}
+
+/// Returns the workspace relative paths of the files packaged by each crate, and of the files or
+/// directories watched by its build script
+pub fn package_files(metadata: &Metadata, pkgs: &[Pkg]) -> Result<Map<String, Set<PathBuf>>> {
+    let cache_path = metadata.target_directory.join(CACHE_FILE);
+    let mut cache = fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|x| serde_json::from_str::<Cache>(&x).ok())
+        .unwrap_or_default();
+
+    let mut ret = Map::new();
+    let mut updated = false;
+
+    for p in pkgs {
+        let manifest_path = p.location.join("Cargo.toml");
+        let manifest = fs::read_to_string(&manifest_path)?.parse::<Document>()?;
+        let build_script = build_script(&p.location, &manifest);
+
+        let mut mtime = modified(&manifest_path)?;
+
+        if let Some(path) = &build_script {
+            mtime = mtime.max(modified(path)?);
+        }
+
+        let files = match cache.crates.get(&p.name) {
+            Some(cached) if cached.mtime == mtime => {
+                debug!("cached", format!("package files of {}", p.name));
+                cached.files.clone()
+            }
+            _ => {
+                let mut files = list_files(metadata, p, &manifest_path, &manifest)?;
+
+                if let Some(path) = &build_script {
+                    files.extend(watched_paths(&fs::read_to_string(path)?, &p.path));
+                }
+
+                cache.crates.insert(
+                    p.name.clone(),
+                    CachedFiles {
+                        mtime,
+                        files: files.clone(),
+                    },
+                );
+                updated = true;
+                files
+            }
+        };
+
+        ret.insert(p.name.clone(), files.into_iter().collect());
+    }
+
+    if updated {
+        if let Some(parent) = cache_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&cache_path, serde_json::to_string(&cache)?)?;
+    }
+
+    Ok(ret)
+}
+
+fn modified(path: &Path) -> Result<u64> {
+    Ok(fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or_default())
+}
+
+/// The build script of the crate, `build.rs` unless the manifest says otherwise
+fn build_script(dir: &Path, manifest: &Document) -> Option<PathBuf> {
+    match manifest.get("package").and_then(|x| x.get("build")) {
+        Some(build) => build.as_str().map(|x| dir.join(x)),
+        None => Some(dir.join("build.rs")).filter(|x| x.exists()),
+    }
+}
+
+fn list_files(
+    metadata: &Metadata,
+    pkg: &Pkg,
+    manifest_path: &Path,
+    manifest: &Document,
+) -> Result<Vec<PathBuf>> {
+    let manifest_path = manifest_path.to_string_lossy();
+
+    let (stdout, _) = cargo(
+        &metadata.workspace_root,
+        &[
+            "package",
+            "--list",
+            "--allow-dirty",
+            "--manifest-path",
+            &manifest_path,
+        ],
+        &[],
+    )?;
+
+    // The readme and license can live outside of the crate but are packaged at its root
+    let outside = ["readme", "license-file"]
+        .iter()
+        .filter_map(|key| manifest.get("package")?.get(key)?.as_str())
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+
+    Ok(workspace_files(&stdout, &pkg.path, &outside))
+}
+
+/// Maps the entries listed by `cargo package --list` to workspace relative paths
+fn workspace_files(list: &str, crate_path: &Path, outside: &[String]) -> Vec<PathBuf> {
+    list.lines()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && !GENERATED.contains(x))
+        // The lock file is copied from the workspace root
+        .filter(|x| *x != "Cargo.lock")
+        .map(|entry| {
+            let entry = if entry == "Cargo.toml.orig" {
+                "Cargo.toml"
+            } else {
+                entry
+            };
+
+            let source = outside
+                .iter()
+                .find(|x| Path::new(x).file_name() == Some(entry.as_ref()))
+                .map(|x| x.as_str())
+                .unwrap_or(entry);
+
+            normalize(&crate_path.join(source))
+        })
+        .collect()
+}
+
+/// The workspace relative paths given to `cargo:rerun-if-changed` in the build script source,
+/// which are relative to the crate
+fn watched_paths(source: &str, crate_path: &Path) -> Vec<PathBuf> {
+    RERUN_IF_CHANGED
+        .captures_iter(source)
+        .map(|caps| PathBuf::from(caps[1].trim()))
+        .filter(|x| x.is_relative())
+        .map(|x| normalize(&crate_path.join(x)))
+        .collect()
+}
+
+/// Resolves the `.` and `..` components without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut ret = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                ret.pop();
+            }
+            c => ret.push(c),
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new("crates/a/../../docs/./a.md")),
+            PathBuf::from("docs/a.md")
+        );
+        assert_eq!(
+            normalize(Path::new("a/src/lib.rs")),
+            PathBuf::from("a/src/lib.rs")
+        );
+    }
+
+    #[test]
+    fn test_watched_paths() {
+        let source = r#"
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-changed=../../proto");
+    println!("cargo:rerun-if-changed={}", schema.display());
+    println!("cargo:rerun-if-changed=/etc/hosts");
+    println!("cargo:rerun-if-env-changed=PROTOC");
+}
+"#;
+
+        assert_eq!(
+            watched_paths(source, Path::new("crates/a")),
+            vec![PathBuf::from("crates/a/build.rs"), PathBuf::from("proto")]
+        );
+    }
+
+    #[test]
+    fn test_workspace_files() {
+        let list = ".cargo_vcs_info.json\nCargo.lock\nCargo.toml\nCargo.toml.orig\nREADME.md\nassets/logo.png\nsrc/lib.rs\n";
+        let outside = vec!["../docs/README.md".to_string()];
+
+        assert_eq!(
+            workspace_files(list, Path::new("crates/a"), &outside),
+            vec![
+                PathBuf::from("crates/a/Cargo.toml"),
+                PathBuf::from("crates/docs/README.md"),
+                PathBuf::from("crates/a/assets/logo.png"),
+                PathBuf::from("crates/a/src/lib.rs"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Helper functions for computing member paths relative to the workspace root.
+//!
+//! On Windows, canonicalized paths may carry verbatim (`\\?\C:\`) or verbatim UNC
+//! (`\\?\UNC\server\share`) prefixes on one side only, and drive letters may differ in
+//! case, which makes `strip_prefix` fail even though both paths point to the same place.
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+const VERBATIM_UNC: &str = r"\\?\UNC\";
+const VERBATIM: &str = r"\\?\";
+
+/// Removes verbatim prefixes, upper-cases the drive letter and unifies separators.
+fn normalize(path: &str) -> String {
+    let path = if let Some(rest) = path.strip_prefix(VERBATIM_UNC) {
+        format!(r"\\{}", rest)
+    } else if let Some(rest) = path.strip_prefix(VERBATIM) {
+        rest.to_string()
+    } else {
+        path.to_string()
+    };
+
+    let mut path = path.replace('\\', "/");
+    let bytes = path.as_bytes();
+
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        path[..1].make_ascii_uppercase();
+    }
+
+    path
+}
+
+fn components(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|x| !x.is_empty() && *x != ".")
+        .collect()
+}
+
+/// Returns the path relative to `base`, or `None` if it is not inside `base`.
+pub fn relative_path(path: &Utf8Path, base: &Utf8Path) -> Option<Utf8PathBuf> {
+    if let Ok(rel) = path.strip_prefix(base) {
+        return Some(rel.to_path_buf());
+    }
+
+    // Fallback to comparing the normalized components one by one
+    let path = normalize(path.as_str());
+    let base = normalize(base.as_str());
+
+    // Keep UNC paths from matching drive paths with the same components
+    if path.starts_with("//") != base.starts_with("//") {
+        return None;
+    }
+
+    let path = components(&path);
+    let base = components(&base);
+
+    if path.len() < base.len() || path[..base.len()] != base[..] {
+        return None;
+    }
+
+    Some(path[base.len()..].join("/").into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rel(path: &str, base: &str) -> Option<String> {
+        relative_path(Utf8Path::new(path), Utf8Path::new(base)).map(|x| x.to_string())
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(rel("/ws/crates/a", "/ws"), Some("crates/a".to_string()));
+        assert_eq!(rel("/ws", "/ws"), Some("".to_string()));
+        assert_eq!(rel("/other/a", "/ws"), None);
+    }
+
+    #[test]
+    fn test_relative_path_verbatim() {
+        assert_eq!(
+            rel(r"\\?\C:\ws\crates\a", r"C:\ws"),
+            Some("crates/a".to_string())
+        );
+        assert_eq!(
+            rel(r"C:\ws\crates\a", r"\\?\C:\ws"),
+            Some("crates/a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_relative_path_drive_case() {
+        assert_eq!(
+            rel(r"c:\ws\crates\a", r"C:\ws"),
+            Some("crates/a".to_string())
+        );
+        assert_eq!(rel(r"\\?\d:\ws\a", r"D:\ws"), Some("a".to_string()));
+        assert_eq!(rel(r"C:\ws\a", r"D:\ws"), None);
+    }
+
+    #[test]
+    fn test_relative_path_mapped_drive() {
+        assert_eq!(
+            rel(r"\\?\UNC\server\share\ws\a", r"\\server\share\ws"),
+            Some("a".to_string())
+        );
+        assert_eq!(rel(r"\\server\share\ws\a", r"C:\server\share\ws"), None);
+    }
+}
@@ -1,11 +1,14 @@
-use crate::utils::{read_config, Error, PackageConfig, Result, INTERNAL_ERR};
+use crate::utils::{read_config, relative_path, Error, PackageConfig, Result, INTERNAL_ERR};
 
 use cargo_metadata::{Metadata, Package, PackageId};
 use oclif::CliError;
 use semver::Version;
 use serde::Serialize;
 
-use std::path::PathBuf;
+use std::{
+    collections::BTreeSet as Set,
+    path::{Path, PathBuf},
+};
 
 #[derive(Serialize, Debug, Clone, Ord, Eq, PartialOrd, PartialEq)]
 pub struct Pkg {
@@ -19,12 +22,49 @@ pub struct Pkg {
     pub private: bool,
     #[serde(skip)]
     pub config: PackageConfig,
+    /// The release tag the changes were looked up from, when it depends on the crate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<Baseline>,
+}
+
+/// A release tag found with one of the `tag_patterns`
+#[derive(Serialize, Debug, Clone, Ord, Eq, PartialOrd, PartialEq)]
+pub struct Baseline {
+    pub tag: String,
+    /// The pattern as configured, before `%n` was replaced
+    pub pattern: String,
+}
+
+impl Pkg {
+    /// Whether this is the package of the workspace manifest, whose path is `.`
+    pub fn is_root(&self) -> bool {
+        self.path == Path::new(".")
+    }
+
+    /// The path to strip from the workspace relative files of the crate, empty for the root
+    pub fn prefix(&self) -> &Path {
+        if self.is_root() {
+            Path::new("")
+        } else {
+            &self.path
+        }
+    }
 }
 
 pub fn is_private(pkg: &Package) -> bool {
     pkg.publish.is_some() && pkg.publish.as_ref().expect(INTERNAL_ERR).is_empty()
 }
 
+/// Names of the members which are never published, so their requirements don't need care
+pub fn unpublished_members(metadata: &Metadata) -> Set<String> {
+    metadata
+        .packages
+        .iter()
+        .filter(|x| metadata.workspace_members.contains(&x.id) && is_private(x))
+        .map(|x| x.name.clone())
+        .collect()
+}
+
 pub fn get_pkgs(metadata: &Metadata, all: bool) -> Result<Vec<Pkg>> {
     let mut pkgs = vec![];
 
@@ -36,9 +76,9 @@ pub fn get_pkgs(metadata: &Metadata, all: bool) -> Result<Vec<Pkg>> {
                 continue;
             }
 
-            let loc = pkg.manifest_path.strip_prefix(&metadata.workspace_root);
+            let loc = relative_path(&pkg.manifest_path, &metadata.workspace_root);
 
-            if loc.is_err() {
+            if loc.is_none() {
                 return Err(Error::PackageNotInWorkspace {
                     id: pkg.id.repr.clone(),
                     ws: metadata.workspace_root.to_string(),
@@ -46,20 +86,29 @@ pub fn get_pkgs(metadata: &Metadata, all: bool) -> Result<Vec<Pkg>> {
             }
 
             let loc = loc.expect(INTERNAL_ERR);
-            let loc = if loc.is_file() {
+            // Checked from the root, the current directory might be inside a crate
+            let loc = if metadata.workspace_root.join(&loc).is_file() {
                 loc.parent().expect(INTERNAL_ERR)
             } else {
-                loc
+                &loc
+            };
+
+            // Joining the empty path of the root package would leave a trailing separator
+            let (location, path) = if loc.as_str().is_empty() {
+                (metadata.workspace_root.clone(), ".".into())
+            } else {
+                (metadata.workspace_root.join(loc), loc.into())
             };
 
             pkgs.push(Pkg {
                 id: pkg.id.clone(),
                 name: pkg.name.clone(),
                 version: pkg.version.clone(),
-                location: metadata.workspace_root.join(loc).into(),
-                path: loc.into(),
+                location: location.into(),
+                path,
                 private,
                 config: read_config(&pkg.metadata)?,
+                baseline: None,
             });
         } else {
             Error::PackageNotFound {
@@ -82,3 +131,56 @@ pub fn get_pkgs(metadata: &Metadata, all: bool) -> Result<Vec<Pkg>> {
     pkgs.sort();
     Ok(pkgs)
 }
+
+/// The error for a name which is not a member, suggesting the closest one
+pub fn unknown_member(name: &str, members: &[Pkg]) -> Error {
+    let suggestion = suggest(name, members.iter().map(|p| p.name.as_str()))
+        .map(|x| format!(" (did you mean {}?)", x))
+        .unwrap_or_default();
+
+    Error::UnknownMember {
+        name: name.to_string(),
+        suggestion,
+    }
+}
+
+/// Returns the candidate closest to the name, if it is close enough to be a typo
+pub fn suggest<'a, I: Iterator<Item = &'a str>>(name: &str, candidates: I) -> Option<&'a str> {
+    candidates
+        .map(|x| (edit_distance(name, x), x))
+        .filter(|(d, _)| *d <= 3)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, x)| x)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+
+        prev = cur;
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_suggest() {
+        let names = ["dep1", "dep2", "top"];
+
+        assert_eq!(suggest("dpe1", names.into_iter()), Some("dep1"));
+        assert_eq!(suggest("tpo", names.into_iter()), Some("top"));
+        assert_eq!(suggest("something-else", names.into_iter()), None);
+    }
+}
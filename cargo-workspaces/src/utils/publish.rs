@@ -1,22 +1,32 @@
 //! Helper functions useful when publishing (or preparing for publishing) crates.
 
-use std::convert::TryFrom;
+use std::{
+    convert::TryFrom,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
 
-use crate::utils::{cargo_config_get, is_private, Error, Result};
+use crate::utils::{
+    cargo_config_get, git, is_private, read_config, Error, PackageConfig, Result, WorkspaceConfig,
+};
 
 use camino::Utf8PathBuf;
 use cargo_metadata::{Metadata, Package};
 use clap::Parser;
 use indexmap::IndexSet as Set;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tame_index::{
     external::{
         http::{HeaderMap, HeaderValue},
         reqwest::{blocking::Client, header::AUTHORIZATION, Certificate},
     },
-    index::{ComboIndex, ComboIndexCache, RemoteGitIndex, RemoteSparseIndex},
+    index::{ComboIndex, ComboIndexCache, IndexConfig, RemoteGitIndex, RemoteSparseIndex},
     utils::flock::LockOptions,
     IndexLocation, IndexUrl, KrateName,
 };
+use url::Url;
 
 #[derive(Debug, Parser)]
 #[clap(next_help_heading = "REGISTRY OPTIONS")]
@@ -42,14 +52,49 @@ pub fn filter_private(visited: Set<Utf8PathBuf>, pkgs: &[(Package, String)]) ->
         .collect()
 }
 
+/// Removes the crates which opted out with `skip_publish`
+pub fn filter_skip_publish(
+    visited: Set<Utf8PathBuf>,
+    pkgs: &[(Package, String)],
+) -> Result<Set<Utf8PathBuf>> {
+    let mut ret = Set::new();
+
+    for x in visited {
+        if let Some((pkg, _)) = pkgs.iter().find(|(p, _)| p.manifest_path == x) {
+            let config: PackageConfig = read_config(&pkg.metadata)?;
+
+            if config.skip_publish.unwrap_or(false) {
+                continue;
+            }
+        }
+
+        ret.insert(x);
+    }
+
+    Ok(ret)
+}
+
+/// The name of the registry the package is published to, if it is not crates.io
+pub fn publish_registry<'a>(registry: Option<&'a String>, pkg: &'a Package) -> Option<&'a String> {
+    registry
+        .or_else(|| pkg.publish.as_deref().and_then(|x| x.first()))
+        .filter(|x| *x != "crates-io")
+}
+
 pub fn package_registry<'a>(
     metadata: &Metadata,
     registry: Option<&'a String>,
-    pkg: &Package,
+    pkg: &'a Package,
 ) -> Result<IndexUrl<'a>> {
-    let url = if let Some(registry) =
-        registry.or_else(|| pkg.publish.as_deref().and_then(|x| x.first()))
-    {
+    registry_index_url(metadata, publish_registry(registry, pkg))
+}
+
+/// The index of the named registry, or of crates.io if there is none
+pub fn registry_index_url<'a>(
+    metadata: &Metadata,
+    registry: Option<&'a String>,
+) -> Result<IndexUrl<'a>> {
+    let url = if let Some(registry) = registry {
         let registry_url = cargo_config_get(
             &metadata.workspace_root,
             &format!("registries.{}.index", registry),
@@ -88,6 +133,102 @@ pub fn is_published(
     name: &str,
     version: &str,
 ) -> Result<bool> {
+    Ok(published_checksum(client, index_url, name, version)?.is_some())
+}
+
+/// The checksum of the `.crate` file recorded in the index, if the version is published
+pub fn published_checksum(
+    client: &Client,
+    index_url: IndexUrl,
+    name: &str,
+    version: &str,
+) -> Result<Option<String>> {
+    Ok(index_versions(client, index_url, name)?
+        .into_iter()
+        .find(|x| x.version == version)
+        .map(|x| x.checksum))
+}
+
+/// The SHA-256 of the `.crate` file downloaded from the registry, for when there is no
+/// checksum to compare with on one side
+pub fn download_checksum(
+    client: &Client,
+    index_url: IndexUrl,
+    name: &str,
+    version: &str,
+) -> Result<String> {
+    let url = index_config(client, index_url)?.download_url(KrateName::try_from(name)?, version);
+
+    // Registries rehearsing a release on disk serve the files directly
+    let contents = match Url::parse(&url).ok().filter(|x| x.scheme() == "file") {
+        Some(file) => {
+            let path = file.to_file_path().map_err(|_| Error::Download(url))?;
+            fs::read(path)?
+        }
+        None => {
+            let response = client.get(&url).send()?.error_for_status()?;
+            response.bytes()?.to_vec()
+        }
+    };
+
+    Ok(sha256(&contents))
+}
+
+/// The `config.json` of the index, which has the template of the download URLs
+fn index_config(client: &Client, index_url: IndexUrl) -> Result<IndexConfig> {
+    if let Some(path) = local_index(&index_url) {
+        let contents = fs::read(path.join("config.json"))?;
+        return Ok(serde_json::from_slice(&contents)?);
+    }
+
+    let index_cache = ComboIndexCache::new(IndexLocation::new(index_url))?;
+
+    match index_cache {
+        ComboIndexCache::Git(git) => {
+            let lock = LockOptions::cargo_package_lock(None)?.try_lock()?;
+            let mut rgi = RemoteGitIndex::new(git, &lock)?;
+
+            rgi.fetch(&lock)?;
+            Ok(rgi.index_config()?)
+        }
+        ComboIndexCache::Sparse(sparse) => {
+            let url = format!("{}/config.json", sparse.url().trim_end_matches('/'));
+            let contents = client.get(url).send()?.error_for_status()?.bytes()?;
+            Ok(serde_json::from_slice(&contents)?)
+        }
+        _ => Err(Error::UnsupportedCratesIndexType),
+    }
+}
+
+/// Every version of the crate on the registry, including the yanked ones
+pub fn published_versions(client: &Client, index_url: IndexUrl, name: &str) -> Result<Vec<String>> {
+    Ok(index_versions(client, index_url, name)?
+        .into_iter()
+        .map(|x| x.version)
+        .collect())
+}
+
+/// The versions of the crate on the registry which new releases can still depend on
+pub fn available_versions(client: &Client, index_url: IndexUrl, name: &str) -> Result<Vec<String>> {
+    Ok(index_versions(client, index_url, name)?
+        .into_iter()
+        .filter(|x| !x.yanked)
+        .map(|x| x.version)
+        .collect())
+}
+
+/// A version of a crate in the registry index
+struct IndexEntry {
+    version: String,
+    checksum: String,
+    yanked: bool,
+}
+
+fn index_versions(client: &Client, index_url: IndexUrl, name: &str) -> Result<Vec<IndexEntry>> {
+    if let Some(path) = local_index(&index_url) {
+        return index_versions_local(&path, name);
+    }
+
     let index_cache = ComboIndexCache::new(IndexLocation::new(index_url))?;
     let lock = LockOptions::cargo_package_lock(None)?.try_lock()?;
 
@@ -104,8 +245,257 @@ pub fn is_published(
 
     let index_crate = index.krate(KrateName::try_from(name)?, false, &lock);
     match index_crate {
-        Ok(Some(crate_data)) => Ok(crate_data.versions.iter().any(|v| v.version == version)),
-        Ok(None) | Err(tame_index::Error::NoCrateVersions) => Ok(false),
+        Ok(Some(crate_data)) => Ok(crate_data
+            .versions
+            .iter()
+            .map(|v| IndexEntry {
+                version: v.version.to_string(),
+                checksum: v.checksum.to_string(),
+                yanked: v.yanked,
+            })
+            .collect()),
+        Ok(None) | Err(tame_index::Error::NoCrateVersions) => Ok(vec![]),
         Err(e) => Err(e.into()),
     }
 }
+
+/// The directory of an index given as a `file://` URL, if it is on disk.
+///
+/// Such registries are mostly used to rehearse releases, so the index is read directly
+/// instead of going through a git fetch.
+fn local_index(index_url: &IndexUrl) -> Option<Utf8PathBuf> {
+    let IndexUrl::NonCratesIo(url) = index_url else {
+        return None;
+    };
+
+    let url = Url::parse(url).ok()?;
+
+    if url.scheme() != "file" {
+        return None;
+    }
+
+    let path = Utf8PathBuf::from_path_buf(url.to_file_path().ok()?).ok()?;
+
+    if path.join("config.json").is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// The versions of the crate committed to an index on disk. Like cargo, which clones
+/// the index, entries which are not committed yet are not seen.
+fn index_versions_local(index: &Utf8PathBuf, name: &str) -> Result<Vec<IndexEntry>> {
+    let path = index_path(name);
+    let (status, contents, err) = git(index, &["show", &format!("HEAD:{}", path)])?;
+
+    if !status.success() {
+        // The file of a crate which was never published does not exist
+        if err.contains(&path) {
+            return Ok(vec![]);
+        }
+
+        return Err(Error::LocalIndex(index.to_string(), err));
+    }
+
+    let mut ret = vec![];
+
+    for line in contents.lines().filter(|x| !x.trim().is_empty()) {
+        let entry: serde_json::Value = serde_json::from_str(line)?;
+
+        if let Some(version) = entry.get("vers").and_then(|x| x.as_str()) {
+            let cksum = entry
+                .get("cksum")
+                .and_then(|x| x.as_str())
+                .unwrap_or_default();
+            ret.push(IndexEntry {
+                version: version.to_string(),
+                checksum: cksum.to_string(),
+                yanked: entry.get("yanked").and_then(|x| x.as_bool()) == Some(true),
+            });
+        }
+    }
+
+    Ok(ret)
+}
+
+/// What was shipped by a publish run, see `--release-manifest`
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ReleaseManifest {
+    /// The commit the crates were published from
+    pub sha: String,
+    pub crates: Vec<ReleasedCrate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ReleasedCrate {
+    pub name: String,
+    pub version: String,
+    /// Name of the registry, `crates-io` for the default one
+    pub registry: String,
+    /// SHA-256 of the `.crate` file, as recorded in the registry index
+    pub checksum: Option<String>,
+}
+
+/// The release manifest given on the command line, or else the one of the config
+pub fn release_manifest_path(path: Option<&Path>, config: &WorkspaceConfig) -> PathBuf {
+    path.map(Path::to_path_buf)
+        .or_else(|| config.release_manifest.as_ref().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("release-manifest.json"))
+}
+
+/// The SHA-256 of the `.crate` file cargo packaged under `target/package`, if it exists
+pub fn crate_checksum(metadata: &Metadata, name: &str, version: &str) -> Result<Option<String>> {
+    let file = format!("{}-{}.crate", name, version);
+    let package = metadata.target_directory.join("package");
+
+    // Newer versions of `cargo publish` leave it in a subdirectory
+    for path in [package.join("tmp-crate").join(&file), package.join(&file)] {
+        match fs::read(&path) {
+            Ok(contents) => return Ok(Some(sha256(&contents))),
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(None)
+}
+
+fn sha256(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
+
+// https://doc.rust-lang.org/cargo/reference/registry-index.html#index-files
+fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_release_manifest() {
+        let manifest = ReleaseManifest {
+            sha: "abc".to_string(),
+            crates: vec![ReleasedCrate {
+                name: "alpha".to_string(),
+                version: "0.1.0".to_string(),
+                registry: "crates-io".to_string(),
+                checksum: None,
+            }],
+        };
+
+        let json = serde_json::to_string(&manifest).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"sha":"abc","crates":[{"name":"alpha","version":"0.1.0","registry":"crates-io","checksum":null}]}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ReleaseManifest>(&json).unwrap(),
+            manifest
+        );
+    }
+
+    #[test]
+    fn test_index_path() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("abc"), "3/a/abc");
+        assert_eq!(index_path("Cargo-Workspaces"), "ca/rg/cargo-workspaces");
+    }
+
+    #[test]
+    fn test_published_checksum_local() {
+        let dir = tempdir().unwrap();
+        let index = dir.path();
+
+        fs::write(index.join("config.json"), "{}").unwrap();
+        fs::create_dir_all(index.join("al/ph")).unwrap();
+        fs::write(
+            index.join("al/ph/alpha"),
+            "{\"name\":\"alpha\",\"vers\":\"0.1.0\",\"cksum\":\"abc\"}\n{\"name\":\"alpha\",\"vers\":\"0.2.0\"}\n",
+        )
+        .unwrap();
+
+        let root = Utf8PathBuf::from_path_buf(index.to_path_buf()).unwrap();
+        git(&root, &["init", "-q"]).unwrap();
+        git(&root, &["add", "-A"]).unwrap();
+        git(
+            &root,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-q",
+                "-m",
+                "index",
+            ],
+        )
+        .unwrap();
+
+        // Not committed yet
+        fs::create_dir_all(index.join("be/ta")).unwrap();
+        fs::write(
+            index.join("be/ta/beta"),
+            "{\"name\":\"beta\",\"vers\":\"0.1.0\"}\n",
+        )
+        .unwrap();
+
+        let url = Url::from_directory_path(index).unwrap().to_string();
+        let url = || IndexUrl::NonCratesIo(url.clone().into());
+        let client = Client::new();
+
+        assert_eq!(
+            published_checksum(&client, url(), "alpha", "0.2.0").unwrap(),
+            Some("".to_string())
+        );
+        assert_eq!(
+            published_checksum(&client, url(), "alpha", "0.1.0").unwrap(),
+            Some("abc".to_string())
+        );
+        assert_eq!(
+            published_checksum(&client, url(), "alpha", "0.3.0").unwrap(),
+            None
+        );
+        assert_eq!(
+            published_checksum(&client, url(), "beta", "0.1.0").unwrap(),
+            None
+        );
+        assert_eq!(
+            published_versions(&client, url(), "alpha").unwrap(),
+            vec!["0.1.0".to_string(), "0.2.0".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_local_index_not_checked_out() {
+        let url = IndexUrl::NonCratesIo("file:///does/not/exist".into());
+        assert_eq!(local_index(&url), None);
+
+        let url = IndexUrl::NonCratesIo("https://example.com/index".into());
+        assert_eq!(local_index(&url), None);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::utils::{get_pkgs, Result, INTERNAL_ERR};
+
+use camino::Utf8PathBuf;
+use cargo_metadata::Metadata;
+
+use std::{
+    fs::{read_to_string, write},
+    io::ErrorKind,
+};
+
+const START: &str = "<!-- cargo-workspaces start -->";
+const END: &str = "<!-- cargo-workspaces end -->";
+const PRIVATE_BADGE: &str = "![private](https://img.shields.io/badge/-private-lightgrey)";
+
+pub fn readme_path(metadata: &Metadata) -> Utf8PathBuf {
+    metadata.workspace_root.join("README.md")
+}
+
+/// Builds the markdown table listing the workspace members
+pub fn readme_index(metadata: &Metadata) -> Result<String> {
+    let mut lines = vec![
+        "| Crate | Version | Path | Description | |".to_string(),
+        "| --- | --- | --- | --- | --- |".to_string(),
+    ];
+
+    for pkg in get_pkgs(metadata, true)? {
+        let description = metadata
+            .packages
+            .iter()
+            .find(|x| x.id == pkg.id)
+            .expect(INTERNAL_ERR)
+            .description
+            .as_deref()
+            .and_then(|x| x.lines().next())
+            .unwrap_or_default();
+
+        lines.push(format!(
+            "| {} | {} | {} | {} | {} |",
+            pkg.name,
+            pkg.version,
+            pkg.path.display(),
+            description.trim().replace('|', "\\|"),
+            if pkg.private { PRIVATE_BADGE } else { "" }
+        ));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Replaces the delimited section in the readme with the given index, appending it if
+/// the readme does not have the section yet
+fn replace_section(readme: &str, index: &str) -> String {
+    let section = format!("{}\n{}\n{}", START, index, END);
+
+    if let Some(start) = readme.find(START)
+        && let Some(end) = readme[start..].find(END)
+    {
+        let end = start + end + END.len();
+        return format!("{}{}{}", &readme[..start], section, &readme[end..]);
+    }
+
+    if readme.trim().is_empty() {
+        format!("{}\n", section)
+    } else {
+        format!("{}\n\n{}\n", readme.trim_end(), section)
+    }
+}
+
+/// Returns the readme contents with an up to date index and whether they changed
+pub fn render_readme(metadata: &Metadata) -> Result<(String, bool)> {
+    let readme = match read_to_string(readme_path(metadata)) {
+        Ok(readme) => readme,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let new_readme = replace_section(&readme, &readme_index(metadata)?);
+    let changed = new_readme != readme;
+
+    Ok((new_readme, changed))
+}
+
+/// Writes the index into the workspace readme, returning whether anything changed
+pub fn update_readme(metadata: &Metadata) -> Result<bool> {
+    let (readme, changed) = render_readme(metadata)?;
+
+    if changed {
+        write(readme_path(metadata), readme)?;
+    }
+
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_replace_section_append() {
+        assert_eq!(
+            replace_section("# Title\n\nIntro\n", "table"),
+            format!("# Title\n\nIntro\n\n{}\ntable\n{}\n", START, END)
+        );
+        assert_eq!(
+            replace_section("", "table"),
+            format!("{}\ntable\n{}\n", START, END)
+        );
+    }
+
+    #[test]
+    fn test_replace_section_existing() {
+        let readme = format!("# Title\n\n{}\nold\n{}\n\n## Footer\n", START, END);
+
+        assert_eq!(
+            replace_section(&readme, "new"),
+            format!("# Title\n\n{}\nnew\n{}\n\n## Footer\n", START, END)
+        );
+    }
+}
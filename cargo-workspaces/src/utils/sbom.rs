@@ -0,0 +1,454 @@
+//! Software bills of materials of the published crates, which record the dependencies each
+//! crate was resolved against with their licenses and the checksums from `Cargo.lock`.
+
+use crate::utils::{utc_time, Result, INTERNAL_ERR};
+
+use cargo_metadata::{DependencyKind, Metadata};
+use clap::ArgEnum;
+use serde_json::{json, Value};
+use toml_edit::Document;
+
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set, VecDeque},
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub enum SbomFormat {
+    Cyclonedx,
+    Spdx,
+}
+
+impl SbomFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            SbomFormat::Cyclonedx => "cdx.json",
+            SbomFormat::Spdx => "spdx.json",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Component {
+    name: String,
+    version: String,
+    license: Option<String>,
+    checksum: Option<String>,
+    /// The purls of the dependencies
+    depends_on: Vec<String>,
+}
+
+impl Component {
+    fn purl(&self) -> String {
+        purl(&self.name, &self.version)
+    }
+}
+
+fn purl(name: &str, version: &str) -> String {
+    format!("pkg:cargo/{}@{}", name, version)
+}
+
+/// The resolved dependencies of the workspace
+pub struct Sbom {
+    metadata: Rc<Metadata>,
+    checksums: Map<(String, String), String>,
+}
+
+impl Sbom {
+    /// Takes the dependencies resolved for the default features along with the checksums of the
+    /// lockfile as it is now, so this should happen once the versions of the crates are final
+    pub fn resolve(metadata: Rc<Metadata>) -> Result<Self> {
+        let lock_path = metadata.workspace_root.join("Cargo.lock");
+
+        let checksums = match fs::read_to_string(lock_path) {
+            Ok(lock) => lock_checksums(&lock.parse()?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Map::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            metadata,
+            checksums,
+        })
+    }
+
+    /// Writes the bill of materials of the crate into the directory and returns its path
+    pub fn write(
+        &self,
+        dir: &Path,
+        format: SbomFormat,
+        name: &str,
+        version: &str,
+    ) -> Result<PathBuf> {
+        let components = self.components(name, version);
+        let created = rfc3339(SystemTime::now());
+
+        let doc = match format {
+            SbomFormat::Cyclonedx => cyclonedx(&components, &created),
+            SbomFormat::Spdx => spdx(&components, &created),
+        };
+
+        fs::create_dir_all(dir)?;
+
+        let path = dir.join(format!("{}-{}.{}", name, version, format.extension()));
+        fs::write(&path, format!("{}\n", serde_json::to_string_pretty(&doc)?))?;
+
+        Ok(path)
+    }
+
+    /// The crate followed by every crate needed to build it, without the dev-dependencies
+    fn components(&self, name: &str, version: &str) -> Vec<Component> {
+        let root = self
+            .metadata
+            .packages
+            .iter()
+            .find(|x| x.name == name && self.metadata.workspace_members.contains(&x.id))
+            .expect(INTERNAL_ERR);
+        let nodes = self
+            .metadata
+            .resolve
+            .as_ref()
+            .expect(INTERNAL_ERR)
+            .nodes
+            .iter()
+            .map(|x| (&x.id, x))
+            .collect::<Map<_, _>>();
+
+        let mut visited = Set::from([&root.id]);
+        let mut queue = VecDeque::from([&root.id]);
+        let mut components = vec![];
+
+        while let Some(id) = queue.pop_front() {
+            let pkg = &self.metadata[id];
+            let version = if id == &root.id {
+                // The version may have been bumped after resolving
+                version.to_string()
+            } else {
+                pkg.version.to_string()
+            };
+
+            let mut depends_on = vec![];
+
+            for dep in &nodes.get(id).expect(INTERNAL_ERR).deps {
+                let kinds = &dep.dep_kinds;
+
+                if !kinds.is_empty() && kinds.iter().all(|x| x.kind == DependencyKind::Development)
+                {
+                    continue;
+                }
+
+                let dep_pkg = &self.metadata[&dep.pkg];
+                depends_on.push(purl(&dep_pkg.name, &dep_pkg.version.to_string()));
+
+                if visited.insert(&dep.pkg) {
+                    queue.push_back(&dep.pkg);
+                }
+            }
+
+            depends_on.sort();
+
+            components.push(Component {
+                checksum: self
+                    .checksums
+                    .get(&(pkg.name.clone(), version.clone()))
+                    .cloned(),
+                name: pkg.name.clone(),
+                license: pkg.license.clone(),
+                version,
+                depends_on,
+            });
+        }
+
+        components[1..].sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        components
+    }
+}
+
+/// The checksums of the registry crates in the lockfile by their name and version
+fn lock_checksums(lock: &Document) -> Map<(String, String), String> {
+    let Some(packages) = lock.get("package").and_then(|x| x.as_array_of_tables()) else {
+        return Map::new();
+    };
+
+    packages
+        .iter()
+        .filter_map(|x| {
+            let field = |key| x.get(key).and_then(|x| x.as_str()).map(|x| x.to_string());
+
+            Some(((field("name")?, field("version")?), field("checksum")?))
+        })
+        .collect()
+}
+
+fn rfc3339(now: SystemTime) -> String {
+    let [year, month, day, hours, minutes, seconds] = utc_time(now);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hours, minutes, seconds
+    )
+}
+
+/// CycloneDX 1.5 with the crate as the subject of the metadata
+fn cyclonedx(components: &[Component], created: &str) -> Value {
+    let component = |c: &Component| {
+        let mut ret = json!({
+            "type": "library",
+            "bom-ref": c.purl(),
+            "name": c.name,
+            "version": c.version,
+            "purl": c.purl(),
+        });
+
+        if let Some(license) = &c.license {
+            ret["licenses"] = json!([{ "expression": license }]);
+        }
+
+        if let Some(checksum) = &c.checksum {
+            ret["hashes"] = json!([{ "alg": "SHA-256", "content": checksum }]);
+        }
+
+        ret
+    };
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": created,
+            "tools": [{ "name": "cargo-workspaces", "version": env!("CARGO_PKG_VERSION") }],
+            "component": component(&components[0]),
+        },
+        "components": components[1..].iter().map(component).collect::<Vec<_>>(),
+        "dependencies": components
+            .iter()
+            .map(|c| json!({ "ref": c.purl(), "dependsOn": c.depends_on }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// SPDX 2.3 describing the crate
+fn spdx(components: &[Component], created: &str) -> Value {
+    let ids = components
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (c.purl(), format!("SPDXRef-Package-{}", i)))
+        .collect::<Map<_, _>>();
+    let root = &components[0];
+
+    let packages = components
+        .iter()
+        .map(|c| {
+            let mut ret = json!({
+                "SPDXID": ids[&c.purl()],
+                "name": c.name,
+                "versionInfo": c.version,
+                "downloadLocation": "NOASSERTION",
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": c.license.as_deref().unwrap_or("NOASSERTION"),
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": c.purl(),
+                }],
+            });
+
+            if let Some(checksum) = &c.checksum {
+                ret["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": checksum }]);
+            }
+
+            ret
+        })
+        .collect::<Vec<_>>();
+
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": ids[&root.purl()],
+    })];
+
+    for c in components {
+        for dep in &c.depends_on {
+            relationships.push(json!({
+                "spdxElementId": ids[&c.purl()],
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": ids[dep],
+            }));
+        }
+    }
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-{}", root.name, root.version),
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/{}-{}-{}",
+            root.name, root.version, created
+        ),
+        "creationInfo": {
+            "created": created,
+            "creators": [format!("Tool: cargo-workspaces-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::metadata_command;
+
+    use std::{fs::write, time::Duration};
+    use tempfile::tempdir;
+
+    fn component(name: &str, depends_on: &[&str]) -> Component {
+        Component {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            license: Some("MIT".to_string()),
+            checksum: None,
+            depends_on: depends_on.iter().map(|x| purl(x, "0.1.0")).collect(),
+        }
+    }
+
+    #[test]
+    fn test_lock_checksums() {
+        let lock = r#"
+version = 3
+
+[[package]]
+name = "dep1"
+version = "0.1.0"
+
+[[package]]
+name = "serde"
+version = "1.0.188"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "cf9e0fcba69a370eed61bcf2b728575f726b50b55cba78064753d708ddc7549e"
+"#;
+
+        assert_eq!(
+            lock_checksums(&lock.parse().unwrap()),
+            Map::from([(
+                ("serde".to_string(), "1.0.188".to_string()),
+                "cf9e0fcba69a370eed61bcf2b728575f726b50b55cba78064753d708ddc7549e".to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1705314600);
+        assert_eq!(rfc3339(at), "2024-01-15T10:30:00Z");
+    }
+
+    #[test]
+    fn test_components() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+
+        write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"a\", \"b\", \"c\", \"d\"]\n",
+        )
+        .unwrap();
+
+        for (name, deps) in [
+            ("a", "[dependencies]\nb = { path = \"../b\" }\n\n[dev-dependencies]\nc = { path = \"../c\" }\n"),
+            ("b", "[build-dependencies]\nc = { path = \"../c\" }\n\n[dependencies]\nd = { path = \"../d\", optional = true }\n"),
+            ("c", ""),
+            ("d", ""),
+        ] {
+            fs::create_dir_all(root.join(name).join("src")).unwrap();
+            write(
+                root.join(name).join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nlicense = \"MIT\"\n\n{}",
+                    name, deps
+                ),
+            )
+            .unwrap();
+            write(root.join(name).join("src/lib.rs"), "").unwrap();
+        }
+
+        let metadata = metadata_command()
+            .manifest_path(root.join("Cargo.toml"))
+            .exec()
+            .unwrap();
+        let mut sbom = Sbom::resolve(Rc::new(metadata)).unwrap();
+        sbom.checksums
+            .insert(("c".to_string(), "0.1.0".to_string()), "abc".to_string());
+
+        let mut a = component("a", &["b"]);
+        a.version = "0.2.0".to_string();
+        let c = || Component {
+            checksum: Some("abc".to_string()),
+            ..component("c", &[])
+        };
+
+        // The dev-dependency of the crate is still needed by the build script of `b`, while the
+        // optional dependency of `b` is not enabled
+        assert_eq!(
+            sbom.components("a", "0.2.0"),
+            vec![a, component("b", &["c"]), c()]
+        );
+        assert_eq!(sbom.components("c", "0.1.0"), vec![c()]);
+    }
+
+    #[test]
+    fn test_spdx() {
+        let components = [component("a", &["b"]), component("b", &[])];
+        let doc = spdx(&components, "2024-01-15T10:30:00Z");
+
+        assert_eq!(doc["packages"][1]["SPDXID"], "SPDXRef-Package-1");
+        assert_eq!(doc["packages"][1]["licenseDeclared"], "MIT");
+        assert_eq!(
+            doc["relationships"],
+            json!([
+                {
+                    "spdxElementId": "SPDXRef-DOCUMENT",
+                    "relationshipType": "DESCRIBES",
+                    "relatedSpdxElement": "SPDXRef-Package-0",
+                },
+                {
+                    "spdxElementId": "SPDXRef-Package-0",
+                    "relationshipType": "DEPENDS_ON",
+                    "relatedSpdxElement": "SPDXRef-Package-1",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_cyclonedx() {
+        let mut components = [component("a", &["b"]), component("b", &[])];
+        components[1].checksum = Some("abc".to_string());
+        let doc = cyclonedx(&components, "2024-01-15T10:30:00Z");
+
+        assert_eq!(doc["metadata"]["component"]["purl"], "pkg:cargo/a@0.1.0");
+        assert_eq!(
+            doc["components"],
+            json!([{
+                "type": "library",
+                "bom-ref": "pkg:cargo/b@0.1.0",
+                "name": "b",
+                "version": "0.1.0",
+                "purl": "pkg:cargo/b@0.1.0",
+                "licenses": [{ "expression": "MIT" }],
+                "hashes": [{ "alg": "SHA-256", "content": "abc" }],
+            }])
+        );
+        assert_eq!(
+            doc["dependencies"][0],
+            json!({ "ref": "pkg:cargo/a@0.1.0", "dependsOn": ["pkg:cargo/b@0.1.0"] })
+        );
+    }
+}
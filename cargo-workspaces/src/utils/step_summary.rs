@@ -0,0 +1,95 @@
+//! Adds a summary of the release to the page of the GitHub Actions run.
+//!
+//! See https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions#adding-a-job-summary
+
+use crate::utils::Result;
+
+use semver::Version;
+
+use std::{env, fs::OpenOptions, io::Write};
+
+const STEP_SUMMARY_ENV: &str = "GITHUB_STEP_SUMMARY";
+
+/// A crate whose version was changed
+pub struct VersionBump<'a> {
+    pub name: &'a str,
+    pub old: &'a Version,
+    pub new: &'a Version,
+}
+
+/// Appends the Markdown to the step summary, if running in GitHub Actions
+pub fn write_step_summary(markdown: &str) -> Result {
+    let Some(path) = env::var_os(STEP_SUMMARY_ENV).filter(|x| !x.is_empty()) else {
+        return Ok(());
+    };
+
+    // Every step of the job appends to the same file
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(markdown.as_bytes())?;
+
+    Ok(())
+}
+
+/// The table of the versions, followed by the commit and the tags linked to the repository
+pub fn version_summary(bumps: &[VersionBump], commit: Option<&str>, tags: &[String]) -> String {
+    // Both set by GitHub Actions
+    let repository = match (env::var("GITHUB_SERVER_URL"), env::var("GITHUB_REPOSITORY")) {
+        (Ok(server), Ok(repo)) => Some(format!("{}/{}", server.trim_end_matches('/'), repo)),
+        _ => None,
+    };
+
+    let link = |text: &str, path: String| match &repository {
+        Some(repository) => format!("[`{}`]({}/{})", text, repository, path),
+        None => format!("`{}`", text),
+    };
+
+    let mut ret = "### Versions\n\n| Crate | Old | New |\n| --- | --- | --- |\n".to_string();
+
+    for bump in bumps {
+        ret.push_str(&format!(
+            "| {} | {} | {} |\n",
+            bump.name, bump.old, bump.new
+        ));
+    }
+
+    if let Some(commit) = commit {
+        let short = commit.get(..7).unwrap_or(commit);
+
+        ret.push_str(&format!(
+            "\nCommit: {}\n",
+            link(short, format!("commit/{}", commit))
+        ));
+    }
+
+    if !tags.is_empty() {
+        let tags = tags
+            .iter()
+            .map(|x| link(x, format!("releases/tag/{}", x)))
+            .collect::<Vec<_>>();
+
+        ret.push_str(&format!("\nTags: {}\n", tags.join(", ")));
+    }
+
+    ret.push('\n');
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_version_summary() {
+        let (old, new) = (Version::new(0, 1, 0), Version::new(0, 2, 0));
+        let bumps = [VersionBump {
+            name: "dep1",
+            old: &old,
+            new: &new,
+        }];
+
+        assert_eq!(
+            version_summary(&bumps, None, &[]),
+            "### Versions\n\n| Crate | Old | New |\n| --- | --- | --- |\n| dep1 | 0.1.0 | 0.2.0 |\n\n"
+        );
+    }
+}
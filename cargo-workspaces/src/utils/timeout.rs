@@ -0,0 +1,312 @@
+//! Time limits for the child processes spawned for each crate.
+//!
+//! Children run with a limit are put in their own process group on Unix, so that whatever
+//! they spawned is killed along with them. On Windows, the whole process tree is killed.
+
+use crate::utils::INTERNAL_ERR;
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Mimics the exit code of coreutils `timeout`
+pub const TIMED_OUT_EXIT_CODE: i32 = 124;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A duration like `90s`, `10m` or `1h`, plain numbers are seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeout(Duration);
+
+impl Timeout {
+    pub const fn from_secs(secs: u64) -> Self {
+        Timeout(Duration::from_secs(secs))
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl FromStr for Timeout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => s.split_at(i),
+            None => (s, "s"),
+        };
+
+        let multiplier = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            _ => {
+                return Err(format!(
+                    "expected a duration like 90s, 10m or 1h, got {}",
+                    s
+                ))
+            }
+        };
+
+        match value.parse::<u64>() {
+            Ok(value) if value > 0 => value
+                .checked_mul(multiplier)
+                .map(|x| Timeout(Duration::from_secs(x)))
+                .ok_or_else(|| format!("the duration {} is too long", s)),
+            _ => Err(format!("expected a positive duration, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs();
+
+        if secs.is_multiple_of(3600) {
+            write!(f, "{}h", secs / 3600)
+        } else if secs.is_multiple_of(60) {
+            write!(f, "{}m", secs / 60)
+        } else {
+            write!(f, "{}s", secs)
+        }
+    }
+}
+
+/// Prepares the command so that the children it spawns can be killed along with it. On
+/// Windows there is no process group, the tree is found from the parent process ids instead.
+pub fn isolate(cmd: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        cmd.process_group(0);
+    }
+
+    #[cfg(not(unix))]
+    let _ = cmd;
+}
+
+/// Waits for the child, killing it along with its descendants once the timeout elapsed.
+/// Returns `None` if it was killed.
+pub fn wait_timeout(child: &mut Child, timeout: Option<Timeout>) -> io::Result<Option<ExitStatus>> {
+    let Some(timeout) = timeout else {
+        return child.wait().map(Some);
+    };
+
+    let start = Instant::now();
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if start.elapsed() >= timeout.duration() {
+            kill_tree(child.id());
+
+            let _ = child.kill();
+            child.wait()?;
+
+            return Ok(None);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Like [`wait_timeout`], but collects the piped stdout and stderr of the child while
+/// waiting so that it never blocks on a full pipe
+pub fn output_timeout(
+    mut child: Child,
+    timeout: Option<Timeout>,
+) -> io::Result<(Option<ExitStatus>, Vec<u8>, Vec<u8>)> {
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let status = wait_timeout(&mut child, timeout)?;
+
+    let stdout = stdout.join().expect(INTERNAL_ERR)?;
+    let stderr = stderr.join().expect(INTERNAL_ERR)?;
+
+    Ok((status, stdout, stderr))
+}
+
+/// Like [`output_timeout`], but also forwards the piped stderr of the child to ours as it comes
+pub fn output_timeout_stderr(
+    mut child: Child,
+    timeout: Option<Timeout>,
+) -> io::Result<(Option<ExitStatus>, Vec<u8>, Vec<u8>)> {
+    let stdout = drain(child.stdout.take());
+    let stderr = forward(child.stderr.take(), io::stderr, usize::MAX);
+
+    let status = wait_timeout(&mut child, timeout)?;
+
+    let stdout = stdout.join().expect(INTERNAL_ERR)?;
+    let (_, stderr) = stderr.join().expect(INTERNAL_ERR)?;
+
+    Ok((status, stdout, stderr))
+}
+
+/// Like [`wait_timeout`], but forwards the piped stderr of the child to ours while keeping
+/// its last `limit` bytes
+pub fn wait_timeout_stderr(
+    mut child: Child,
+    timeout: Option<Timeout>,
+    limit: usize,
+) -> io::Result<(Option<ExitStatus>, Vec<u8>)> {
+    let stderr = forward(child.stderr.take(), io::stderr, limit);
+
+    let status = wait_timeout(&mut child, timeout)?;
+
+    Ok((status, stderr.join().expect(INTERNAL_ERR)?.1))
+}
+
+/// What went through the pipes of a child run by [`tee_timeout`]
+pub struct Tee {
+    pub status: Option<ExitStatus>,
+    pub stdout_bytes: u64,
+    pub stderr_bytes: u64,
+    pub stderr_tail: Vec<u8>,
+    /// The last byte written to stdout, to know whether it ended with a newline
+    pub stdout_last: Option<u8>,
+}
+
+/// Like [`wait_timeout_stderr`], but forwards the piped stdout as well and also returns the
+/// number of bytes the child wrote to each
+pub fn tee_timeout(mut child: Child, timeout: Option<Timeout>, limit: usize) -> io::Result<Tee> {
+    let stdout = forward(child.stdout.take(), io::stdout, 1);
+    let stderr = forward(child.stderr.take(), io::stderr, limit);
+
+    let status = wait_timeout(&mut child, timeout)?;
+
+    let (stdout_bytes, stdout_tail) = stdout.join().expect(INTERNAL_ERR)?;
+    let (stderr_bytes, stderr_tail) = stderr.join().expect(INTERNAL_ERR)?;
+
+    Ok(Tee {
+        status,
+        stdout_bytes,
+        stderr_bytes,
+        stderr_tail,
+        stdout_last: stdout_tail.last().copied(),
+    })
+}
+
+/// Copies the pipe to the sink as it comes, returning how many bytes went through along
+/// with the last `limit` of them
+fn forward<R, W, F>(
+    pipe: Option<R>,
+    sink: F,
+    limit: usize,
+) -> thread::JoinHandle<io::Result<(u64, Vec<u8>)>>
+where
+    R: Read + Send + 'static,
+    W: Write,
+    F: FnOnce() -> W + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut count = 0;
+        let mut tail = vec![];
+
+        if let Some(mut pipe) = pipe {
+            let mut sink = sink();
+            let mut buf = [0; 4096];
+
+            loop {
+                let n = pipe.read(&mut buf)?;
+
+                if n == 0 {
+                    break;
+                }
+
+                sink.write_all(&buf[..n])?;
+                count += n as u64;
+
+                tail.extend_from_slice(&buf[..n]);
+                tail.drain(..tail.len().saturating_sub(limit));
+            }
+        }
+
+        Ok((count, tail))
+    })
+}
+
+fn drain<R>(pipe: Option<R>) -> thread::JoinHandle<io::Result<Vec<u8>>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buf = vec![];
+
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+
+        Ok(buf)
+    })
+}
+
+/// Kills the process group on Unix. Windows uses `taskkill /T`, which walks the tree from the
+/// parent process ids, so descendants whose parent already exited are not killed.
+fn kill_tree(pid: u32) {
+    #[cfg(unix)]
+    let mut cmd = Command::new("kill");
+    #[cfg(unix)]
+    cmd.args(["-KILL", "--", &format!("-{}", pid)]);
+
+    #[cfg(not(unix))]
+    let mut cmd = Command::new("taskkill");
+    #[cfg(not(unix))]
+    cmd.args(["/PID", &pid.to_string(), "/T", "/F"]);
+
+    let _ = cmd.stdout(Stdio::null()).stderr(Stdio::null()).status();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("90s".parse(), Ok(Timeout(Duration::from_secs(90))));
+        assert_eq!("10m".parse(), Ok(Timeout(Duration::from_secs(600))));
+        assert_eq!("1h".parse(), Ok(Timeout(Duration::from_secs(3600))));
+        assert_eq!("30".parse(), Ok(Timeout(Duration::from_secs(30))));
+        assert!("0s".parse::<Timeout>().is_err());
+        assert!("10d".parse::<Timeout>().is_err());
+        assert!("m".parse::<Timeout>().is_err());
+        assert!(format!("{}h", u64::MAX / 60).parse::<Timeout>().is_err());
+        assert!(format!("{}s", u64::MAX).parse::<Timeout>().is_ok());
+    }
+
+    #[test]
+    fn test_display() {
+        let display = |s: &str| s.parse::<Timeout>().unwrap().to_string();
+
+        assert_eq!(display("90s"), "90s");
+        assert_eq!(display("120s"), "2m");
+        assert_eq!(display("60m"), "1h");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wait_timeout() {
+        let mut cmd = Command::new("sleep");
+        cmd.arg("30");
+        isolate(&mut cmd);
+
+        let mut child = cmd.spawn().unwrap();
+        let start = Instant::now();
+        let status = wait_timeout(&mut child, Some("1s".parse().unwrap())).unwrap();
+
+        assert_eq!(status, None);
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let status = wait_timeout(&mut Command::new("true").spawn().unwrap(), None).unwrap();
+        assert!(status.unwrap().success());
+    }
+}
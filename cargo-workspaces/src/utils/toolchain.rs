@@ -0,0 +1,300 @@
+//! The toolchain requirements declared in the manifests: the `rust-version` and edition of
+//! the members, and the feature resolver of the workspace.
+
+use crate::utils::{warn, Error, Result, INTERNAL_ERR};
+
+use clap::ArgEnum;
+use semver::Version;
+use toml_edit::{Document, Item};
+
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum)]
+pub enum Resolver {
+    #[clap(name = "1")]
+    V1,
+    #[clap(name = "2")]
+    V2,
+    #[clap(name = "3")]
+    V3,
+}
+
+impl Resolver {
+    /// The first cargo version supporting each resolver, from the oldest resolver
+    const MIN_CARGO: [(Resolver, (u64, u64)); 3] = [
+        (Resolver::V1, (1, 0)),
+        (Resolver::V2, (1, 51)),
+        (Resolver::V3, (1, 84)),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resolver::V1 => "1",
+            Resolver::V2 => "2",
+            Resolver::V3 => "3",
+        }
+    }
+
+    pub fn min_cargo(&self) -> Version {
+        let (_, (major, minor)) = Self::MIN_CARGO
+            .iter()
+            .find(|(x, _)| x == self)
+            .expect(INTERNAL_ERR);
+
+        Version::new(*major, *minor, 0)
+    }
+
+    /// The newest resolver supported by the toolchain
+    pub fn highest_for(toolchain: &Version) -> Resolver {
+        Self::MIN_CARGO
+            .iter()
+            .rev()
+            .find(|(x, _)| x.min_cargo() <= *toolchain)
+            .map_or(Resolver::V1, |(x, _)| *x)
+    }
+}
+
+/// Where the `rust-version` of a crate is declared
+#[derive(Debug, PartialEq)]
+pub enum RustVersion {
+    Package(String),
+    /// Inherited from `[workspace.package]`
+    Workspace(String),
+}
+
+impl RustVersion {
+    pub fn toolchain(&self) -> &str {
+        match self {
+            RustVersion::Package(v) | RustVersion::Workspace(v) => v,
+        }
+    }
+}
+
+pub fn rust_version(manifest: &Document, workspace: &Document) -> Option<RustVersion> {
+    let field = manifest.get("package")?.get("rust-version")?;
+
+    if let Some(v) = field.as_str() {
+        return Some(RustVersion::Package(v.to_string()));
+    }
+
+    inherited(field, workspace, "rust-version").map(|v| RustVersion::Workspace(v.to_string()))
+}
+
+/// `rust-version` may omit the minor and patch components
+pub fn parse_rust_version(v: &str) -> Option<Version> {
+    let padded = match v.matches('.').count() {
+        0 => format!("{}.0.0", v),
+        1 => format!("{}.0", v),
+        _ => v.to_string(),
+    };
+
+    Version::parse(&padded).ok()
+}
+
+/// The first cargo version supporting the edition
+fn edition_min_cargo(edition: &str) -> Version {
+    match edition {
+        "2018" => Version::new(1, 31, 0),
+        "2021" => Version::new(1, 56, 0),
+        "2024" => Version::new(1, 85, 0),
+        _ => Version::new(1, 0, 0),
+    }
+}
+
+fn inherited<'a>(field: &Item, workspace: &'a Document, key: &str) -> Option<&'a str> {
+    if field.get("workspace").and_then(|x| x.as_bool()) != Some(true) {
+        return None;
+    }
+
+    workspace
+        .get("workspace")?
+        .get("package")?
+        .get(key)?
+        .as_str()
+}
+
+/// The toolchains a member claims to support
+#[derive(Debug)]
+pub struct MemberToolchain {
+    pub name: String,
+    pub rust_version: Option<Version>,
+    /// The first cargo version supporting the edition of the member
+    pub edition: Version,
+}
+
+impl MemberToolchain {
+    pub fn read(manifest: &Document, workspace: &Document) -> Option<Self> {
+        let package = manifest.get("package")?;
+        let edition = match package.get("edition") {
+            Some(field) => field
+                .as_str()
+                .or_else(|| inherited(field, workspace, "edition"))
+                .unwrap_or_default(),
+            None => "2015",
+        };
+
+        Some(Self {
+            name: package.get("name")?.as_str()?.to_string(),
+            rust_version: rust_version(manifest, workspace)
+                .and_then(|x| parse_rust_version(x.toolchain())),
+            edition: edition_min_cargo(edition),
+        })
+    }
+}
+
+/// The newest toolchain the members claim to support, which is their highest `rust-version`
+/// unless the edition of a member already needs a newer one. Members which do not declare
+/// any `rust-version` claim nothing.
+fn newest_toolchain(members: &[MemberToolchain]) -> Option<(&str, Version)> {
+    members.iter().find(|x| x.rust_version.is_some())?;
+
+    members
+        .iter()
+        .map(|x| {
+            let toolchain = x
+                .rust_version
+                .as_ref()
+                .map_or(&x.edition, |v| v.max(&x.edition));
+            (x.name.as_str(), toolchain.clone())
+        })
+        .max_by(|a, b| a.1.cmp(&b.1))
+}
+
+/// Warns, or fails when strict, if the resolver needs a newer cargo than the members support
+pub fn check_resolver(resolver: Resolver, members: &[MemberToolchain], strict: bool) -> Result {
+    let Some((name, newest)) = newest_toolchain(members) else {
+        return Ok(());
+    };
+
+    let min_cargo = resolver.min_cargo();
+
+    if min_cargo <= newest {
+        return Ok(());
+    }
+
+    let problem = format!(
+        "resolver {} needs cargo {}.{} but the members support {}.{} at most ({}), use resolver {}",
+        resolver.name(),
+        min_cargo.major,
+        min_cargo.minor,
+        newest.major,
+        newest.minor,
+        name,
+        Resolver::highest_for(&newest).name()
+    );
+
+    if strict {
+        return Err(Error::IncompatibleResolver(problem));
+    }
+
+    warn!("incompatible resolver", problem);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn doc(s: &str) -> Document {
+        s.parse().unwrap()
+    }
+
+    fn member(name: &str, rust_version: Option<&str>, edition: &str) -> MemberToolchain {
+        MemberToolchain {
+            name: name.to_string(),
+            rust_version: rust_version.and_then(parse_rust_version),
+            edition: edition_min_cargo(edition),
+        }
+    }
+
+    #[test]
+    fn test_min_cargo() {
+        assert_eq!(Resolver::V1.min_cargo(), Version::new(1, 0, 0));
+        assert_eq!(Resolver::V2.min_cargo(), Version::new(1, 51, 0));
+        assert_eq!(Resolver::V3.min_cargo(), Version::new(1, 84, 0));
+    }
+
+    #[test]
+    fn test_highest_for() {
+        assert_eq!(Resolver::highest_for(&Version::new(1, 50, 0)), Resolver::V1);
+        assert_eq!(Resolver::highest_for(&Version::new(1, 70, 0)), Resolver::V2);
+        assert_eq!(Resolver::highest_for(&Version::new(1, 84, 0)), Resolver::V3);
+    }
+
+    #[test]
+    fn test_rust_version() {
+        let workspace = doc("[workspace.package]\nrust-version = \"1.70\"\n");
+
+        assert_eq!(
+            rust_version(
+                &doc("[package]\nname = \"a\"\nrust-version = \"1.65\"\n"),
+                &workspace
+            ),
+            Some(RustVersion::Package("1.65".to_string()))
+        );
+        assert_eq!(
+            rust_version(
+                &doc("[package]\nname = \"a\"\nrust-version.workspace = true\n"),
+                &workspace
+            ),
+            Some(RustVersion::Workspace("1.70".to_string()))
+        );
+        assert_eq!(
+            rust_version(&doc("[package]\nname = \"a\"\n"), &workspace),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_rust_version() {
+        assert_eq!(parse_rust_version("1"), Version::parse("1.0.0").ok());
+        assert_eq!(parse_rust_version("1.70"), Version::parse("1.70.0").ok());
+        assert_eq!(parse_rust_version("1.70.1"), Version::parse("1.70.1").ok());
+        assert_eq!(parse_rust_version("stable"), None);
+    }
+
+    #[test]
+    fn test_member_toolchain() {
+        let workspace = doc("[workspace.package]\nedition = \"2024\"\n");
+        let member = MemberToolchain::read(
+            &doc("[package]\nname = \"a\"\nedition.workspace = true\nrust-version = \"1.70\"\n"),
+            &workspace,
+        )
+        .unwrap();
+
+        assert_eq!(member.name, "a");
+        assert_eq!(member.rust_version, Some(Version::new(1, 70, 0)));
+        assert_eq!(member.edition, Version::new(1, 85, 0));
+
+        let member = MemberToolchain::read(&doc("[package]\nname = \"b\"\n"), &workspace).unwrap();
+        assert_eq!(member.rust_version, None);
+        assert_eq!(member.edition, Version::new(1, 0, 0));
+    }
+
+    #[test]
+    fn test_check_resolver() {
+        let pinned = [
+            member("a", Some("1.70"), "2021"),
+            member("b", Some("1.65"), "2021"),
+            member("c", None, "2021"),
+        ];
+
+        assert!(check_resolver(Resolver::V2, &pinned, true).is_ok());
+
+        let err = check_resolver(Resolver::V3, &pinned, true)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(
+            "resolver 3 needs cargo 1.84 but the members support 1.70 at most (a), use resolver 2"
+        ));
+
+        // Not strict
+        assert!(check_resolver(Resolver::V3, &pinned, false).is_ok());
+
+        // An edition 2024 member needs a toolchain supporting resolver 3 anyway
+        let edition = [member("a", Some("1.70"), "2021"), member("d", None, "2024")];
+        assert!(check_resolver(Resolver::V3, &edition, true).is_ok());
+
+        // Nothing is claimed without any rust-version
+        let unpinned = [member("c", None, "2021")];
+        assert!(check_resolver(Resolver::V3, &unpinned, true).is_ok());
+    }
+}
@@ -0,0 +1,202 @@
+//! Groups manifest writes so that they are either all applied or none of them are.
+
+use crate::utils::{Error, Result};
+
+use toml_edit::Document;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A set of pending manifest writes.
+///
+/// Every new content is validated as TOML before anything is touched. Files are then
+/// written one by one through a temporary file and a rename, and if any write fails,
+/// the files which were already written are restored to their original contents.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    writes: Vec<(PathBuf, String)>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a write, replacing any previously queued write to the same path
+    pub fn write<P: Into<PathBuf>, C: Into<String>>(&mut self, path: P, contents: C) {
+        let path = path.into();
+        let contents = contents.into();
+
+        if let Some(write) = self.writes.iter_mut().find(|(p, _)| *p == path) {
+            write.1 = contents;
+        } else {
+            self.writes.push((path, contents));
+        }
+    }
+
+    /// Returns the queued contents of the path, or its current contents if no write is queued
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        let path = path.as_ref();
+
+        match self.writes.iter().find(|(p, _)| p == path) {
+            Some((_, contents)) => Ok(contents.clone()),
+            None => Ok(fs::read_to_string(path)?),
+        }
+    }
+
+    pub fn commit(self) -> Result {
+        self.commit_with(write_atomic)
+    }
+
+    fn commit_with<F>(self, mut write_f: F) -> Result
+    where
+        F: FnMut(&Path, &str) -> Result,
+    {
+        for (path, contents) in &self.writes {
+            contents.parse::<Document>().map_err(|e| {
+                Error::WorkspaceBadFormat(format!("{} would be invalid: {}", path.display(), e))
+            })?;
+        }
+
+        let mut originals = vec![];
+
+        for (path, _) in &self.writes {
+            let original = match fs::read_to_string(path) {
+                Ok(original) => Some(original),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err.into()),
+            };
+
+            originals.push((path.as_path(), original));
+        }
+
+        for (i, (path, contents)) in self.writes.iter().enumerate() {
+            if let Err(err) = write_f(path, contents) {
+                // The failed write may have partially happened too
+                rollback(&originals[..=i])?;
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_atomic(path: &Path, contents: &str) -> Result {
+    let file_name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.cargo-ws.tmp", file_name));
+
+    fs::write(&tmp, contents)?;
+
+    if let Err(err) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+fn rollback(originals: &[(&Path, Option<String>)]) -> Result {
+    for (path, original) in originals {
+        match original {
+            Some(original) => write_atomic(path, original)?,
+            None => {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_commit() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.toml");
+        let b = dir.path().join("b.toml");
+
+        fs::write(&a, "a = 1\n").unwrap();
+
+        let mut transaction = Transaction::new();
+        transaction.write(&a, "a = 2\n");
+        transaction.write(&b, "b = 2\n");
+        transaction.commit().unwrap();
+
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a = 2\n");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "b = 2\n");
+    }
+
+    #[test]
+    fn test_read() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.toml");
+        let b = dir.path().join("b.toml");
+
+        fs::write(&a, "a = 1\n").unwrap();
+        fs::write(&b, "b = 1\n").unwrap();
+
+        let mut transaction = Transaction::new();
+        transaction.write(&a, "a = 2\n");
+
+        assert_eq!(transaction.read(&a).unwrap(), "a = 2\n");
+        assert_eq!(transaction.read(&b).unwrap(), "b = 1\n");
+    }
+
+    #[test]
+    fn test_commit_invalid_toml() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.toml");
+
+        fs::write(&a, "a = 1\n").unwrap();
+
+        let mut transaction = Transaction::new();
+        transaction.write(&a, "a = \n");
+
+        assert!(transaction.commit().is_err());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a = 1\n");
+    }
+
+    #[test]
+    fn test_commit_rollback() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.toml");
+        let b = dir.path().join("b.toml");
+        let c = dir.path().join("c.toml");
+
+        fs::write(&a, "a = 1\n").unwrap();
+        fs::write(&c, "c = 1\n").unwrap();
+
+        let mut transaction = Transaction::new();
+        transaction.write(&a, "a = 2\n");
+        transaction.write(&b, "b = 2\n");
+        transaction.write(&c, "c = 2\n");
+
+        let result = transaction.commit_with(|path, contents| {
+            if path.ends_with("c.toml") {
+                // Simulate a write which was interrupted halfway
+                fs::write(path, &contents[..2])?;
+                return Err(io::Error::other("interrupted").into());
+            }
+
+            write_atomic(path, contents)
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a = 1\n");
+        assert!(!b.exists());
+        assert_eq!(fs::read_to_string(&c).unwrap(), "c = 1\n");
+    }
+}
@@ -0,0 +1,169 @@
+//! Exchanges the OIDC token provided by the CI for a short-lived crates.io token.
+//!
+//! See https://crates.io/docs/trusted-publishing
+
+use crate::utils::{debug, with_user_agent, Error, Result};
+
+use serde::Deserialize;
+use tame_index::external::reqwest::{
+    blocking::Client,
+    header::{AUTHORIZATION, CONTENT_TYPE},
+};
+
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+const TOKENS_URL: &str = "https://crates.io/api/v1/trusted_publishing/tokens";
+const AUDIENCE: &str = "crates.io";
+
+// Set by GitHub Actions when the job has the `id-token: write` permission
+const REQUEST_URL_ENV: &str = "ACTIONS_ID_TOKEN_REQUEST_URL";
+const REQUEST_TOKEN_ENV: &str = "ACTIONS_ID_TOKEN_REQUEST_TOKEN";
+
+// The tokens are valid for 30 minutes, renew them a bit before
+const TOKEN_LIFETIME: Duration = Duration::from_secs(25 * 60);
+
+#[derive(Deserialize)]
+struct IdTokenResponse {
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: String,
+}
+
+/// A crates.io token obtained through trusted publishing, revoked when dropped
+pub struct TrustedToken {
+    client: Client,
+    token: String,
+    obtained: Instant,
+}
+
+impl TrustedToken {
+    pub fn new(client: &Client) -> Result<Self> {
+        let token = exchange(client)?;
+
+        debug!("trusted publishing", "obtained a crates.io token");
+
+        Ok(Self {
+            client: client.clone(),
+            token,
+            obtained: Instant::now(),
+        })
+    }
+
+    /// Returns the token, renewing it first if it is about to expire
+    pub fn token(&mut self) -> Result<&str> {
+        if self.obtained.elapsed() >= TOKEN_LIFETIME {
+            let _ = self.revoke();
+
+            self.token = exchange(&self.client)?;
+            self.obtained = Instant::now();
+
+            debug!("trusted publishing", "renewed the crates.io token");
+        }
+
+        Ok(&self.token)
+    }
+
+    fn revoke(&self) -> Result {
+        with_user_agent(self.client.delete(TOKENS_URL))
+            .header(AUTHORIZATION, format!("Bearer {}", self.token))
+            .send()?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+impl Drop for TrustedToken {
+    fn drop(&mut self) {
+        // The token expires on its own anyway
+        let _ = self.revoke();
+    }
+}
+
+fn exchange(client: &Client) -> Result<String> {
+    let jwt = id_token(client)?;
+
+    let res = with_user_agent(client.post(TOKENS_URL))
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "jwt": jwt }).to_string())
+        .send()?;
+
+    let status = res.status();
+    let body = res.text()?;
+
+    if !status.is_success() {
+        return Err(Error::TrustedPublishing(format!(
+            "crates.io rejected the token exchange ({}): {}",
+            status, body
+        )));
+    }
+
+    Ok(serde_json::from_str::<TokenResponse>(&body)?.token)
+}
+
+/// Requests the OIDC token of the job from the CI provider
+fn id_token(client: &Client) -> Result<String> {
+    let (url, token) = match (env::var(REQUEST_URL_ENV), env::var(REQUEST_TOKEN_ENV)) {
+        (Ok(url), Ok(token)) => (url, token),
+        _ => {
+            return Err(Error::TrustedPublishing(format!(
+                "{} and {} are not set, make sure the job has the `id-token: write` permission",
+                REQUEST_URL_ENV, REQUEST_TOKEN_ENV
+            )));
+        }
+    };
+
+    let res = with_user_agent(client.get(id_token_url(&url)))
+        .header(AUTHORIZATION, format!("bearer {}", token))
+        .send()?;
+
+    let status = res.status();
+    let body = res.text()?;
+
+    if !status.is_success() {
+        return Err(Error::TrustedPublishing(format!(
+            "unable to get the OIDC token ({}): {}",
+            status, body
+        )));
+    }
+
+    Ok(serde_json::from_str::<IdTokenResponse>(&body)?.value)
+}
+
+fn id_token_url(url: &str) -> String {
+    let sep = if url.contains('?') { '&' } else { '?' };
+
+    format!("{}{}audience={}", url, sep, AUDIENCE)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_id_token_url() {
+        assert_eq!(
+            id_token_url("https://token.actions/abc?api-version=2.0"),
+            "https://token.actions/abc?api-version=2.0&audience=crates.io"
+        );
+        assert_eq!(
+            id_token_url("https://token.actions/abc"),
+            "https://token.actions/abc?audience=crates.io"
+        );
+    }
+
+    #[test]
+    fn test_responses() {
+        let id = serde_json::from_str::<IdTokenResponse>(r#"{"count":1,"value":"jwt"}"#);
+        let token = serde_json::from_str::<TokenResponse>(r#"{"token":"cio_abc"}"#);
+
+        assert_eq!(id.unwrap().value, "jwt");
+        assert_eq!(token.unwrap().token, "cio_abc");
+    }
+}
@@ -1,18 +1,27 @@
 use crate::utils::{
-    cargo, change_versions, info, read_config, ChangeData, ChangeOpt, Error, GitOpt, Pkg, Result,
-    WorkspaceConfig, INTERNAL_ERR,
+    bumped_requirement, cargo, change_versions_with, dag, debug, get_dry_run, git, info,
+    inherits_workspace_version, is_git_repo, read_config, set_workspace_version,
+    unpublished_members, warn, ChangeData, ChangeOpt, Error, GitOpt, Pkg, Result, WorkspaceConfig,
+    WorkspaceContext, INTERNAL_ERR,
 };
 
+use camino::Utf8PathBuf;
 use cargo_metadata::Metadata;
 use clap::{ArgEnum, Parser};
 use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+use globset::Glob;
 use oclif::{
     console::Style,
     term::{TERM_ERR, TERM_OUT},
 };
-use semver::{Identifier, Version};
+use semver::{Identifier, Version, VersionReq};
 
-use std::{collections::BTreeMap as Map, fs, process::exit};
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    fs,
+    process::exit,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 #[derive(Debug, Clone, ArgEnum)]
 pub enum Bump {
@@ -59,6 +68,10 @@ pub struct VersionOpt {
     #[clap(long, value_name = "IDENTIFIER", forbid_empty_values(true))]
     pub pre_id: Option<String>,
 
+    /// Append build metadata to the new versions, `%h` is replaced by the short commit hash
+    #[clap(long, value_name = "STR", forbid_empty_values(true))]
+    pub build_metadata: Option<String>,
+
     #[clap(flatten)]
     pub change: ChangeOpt,
 
@@ -76,16 +89,52 @@ pub struct VersionOpt {
     /// Skip confirmation prompt
     #[clap(short, long)]
     pub yes: bool,
+
+    /// Apply a patch bump to independent crates whose dependency requirements get rewritten,
+    /// and to their dependents
+    #[clap(long)]
+    pub bump_patch_dependents: bool,
+
+    /// Allow new versions which are not greater than the current ones
+    #[clap(long)]
+    pub allow_downgrade: bool,
+
+    /// Include `Cargo.lock` in the version commit, even if it is not tracked yet
+    #[clap(long)]
+    pub update_lock: bool,
+
+    /// Commit and tag the bump of each crate separately (requires independent crates)
+    #[clap(long, conflicts_with_all = &["amend", "no-git-commit"])]
+    pub commit_per_crate: bool,
+
+    /// Also bump the root package of the workspace, even if it did not change
+    #[clap(long)]
+    pub include_root: bool,
+
+    /// Set the crates to a prerelease of their next version suffixed with the current time,
+    /// like `1.3.0-dev.20240115103000`, without committing, tagging or pushing
+    #[clap(long, conflicts_with_all = &["custom", "commit-per-crate", "amend", "push"])]
+    pub snapshot: bool,
 }
 
 impl VersionOpt {
-    pub fn do_versioning(&self, metadata: &Metadata) -> Result<Map<String, Version>> {
+    pub fn do_versioning(&self, ctx: &WorkspaceContext) -> Result<Map<String, Version>> {
+        let metadata = &ctx.metadata;
         let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
-        let branch = self.git.validate(&metadata.workspace_root, &config)?;
+        // Snapshots are never committed, so they can be built from any branch
+        let branch = if self.snapshot {
+            None
+        } else if self.commit_per_crate && !is_git_repo(&metadata.workspace_root) {
+            // There is nothing to split the bump into
+            return Err(Error::NotGit);
+        } else {
+            self.git.validate(&metadata.workspace_root, &config)?
+        };
+        let build = self.build_metadata(&metadata.workspace_root)?;
         let mut since = self.change.since.clone();
 
         if self.change.since.is_none() {
-            let change_data = ChangeData::new(metadata, &self.change)?;
+            let change_data = ChangeData::new(ctx, &self.change)?;
 
             if self.change.force.is_none() && change_data.count == "0" && !change_data.dirty {
                 TERM_OUT.write_line("Current HEAD is already released, skipping versioning")?;
@@ -96,18 +145,61 @@ impl VersionOpt {
         }
 
         let (mut changed_p, mut unchanged_p) =
-            self.change.get_changed_pkgs(metadata, &since, self.all)?;
+            self.change.get_changed_pkgs(ctx, &since, self.all)?;
+
+        if self.include_root {
+            match unchanged_p.iter().position(|p| p.is_root()) {
+                Some(index) => changed_p.push(unchanged_p.remove(index)),
+                None if !changed_p.iter().any(|p| p.is_root()) => {
+                    warn!("no root package", "the workspace manifest is virtual");
+                }
+                None => {}
+            }
+        }
+
+        let inherited = inherits_all(changed_p.iter().chain(&unchanged_p))?;
+
+        // Crates which opted out are never bumped, but their requirements are still updated
+        changed_p.retain(|p| !p.config.skip_version.unwrap_or(false));
+        unchanged_p.retain(|p| !p.config.skip_version.unwrap_or(false));
 
         if changed_p.is_empty() {
             TERM_OUT.write_line("No changes detected, skipping versioning")?;
             return Ok(Map::new());
         }
 
+        let independent = changed_p
+            .iter()
+            .chain(&unchanged_p)
+            .filter(|p| p.config.independent.unwrap_or(false))
+            .map(|p| p.name.clone())
+            .collect::<Vec<_>>();
+
         let mut new_version = None;
         let mut new_versions = vec![];
+        let mut dependents = false;
+        // Crates bumped because of their dependencies, which are re-released as well
+        let mut cascaded = Set::new();
+        let unpublished = unpublished_members(metadata);
 
         while !changed_p.is_empty() {
+            if dependents && self.bump_patch_dependents {
+                cascaded.extend(changed_p.iter().map(|p| p.name.clone()));
+
+                let (independent_pkgs, same_pkgs) = changed_p
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|p| p.config.independent.unwrap_or(false));
+
+                for p in independent_pkgs {
+                    let version = inc_patch(p.version.clone());
+                    new_versions.push((p.name, version, p.version));
+                }
+
+                changed_p = same_pkgs;
+            }
+
             self.get_new_versions(metadata, changed_p, &mut new_version, &mut new_versions)?;
+            dependents = true;
 
             let pkgs = unchanged_p.into_iter().partition::<Vec<_>, _>(|p| {
                 let pkg = metadata
@@ -119,7 +211,11 @@ impl VersionOpt {
                 pkg.dependencies.iter().any(|x| {
                     if let Some(version) = new_versions.iter().find(|y| x.name == y.0).map(|y| &y.1)
                     {
+                        // Exact requirements are always rewritten, except on unpublished crates
                         !x.req.matches(version)
+                            || (self.bump_patch_dependents
+                                && (cascaded.contains(&x.name)
+                                    || (self.exact && !unpublished.contains(&x.name))))
                     } else {
                         false
                     }
@@ -130,8 +226,158 @@ impl VersionOpt {
             unchanged_p = pkgs.1;
         }
 
+        if self.snapshot {
+            let pre = snapshot_pre(&self.pre_id, SystemTime::now());
+
+            for version in new_version
+                .iter_mut()
+                .chain(new_versions.iter_mut().map(|x| &mut x.1))
+            {
+                version.pre.clone_from(&pre);
+            }
+        }
+
+        if !build.is_empty() {
+            for version in new_version
+                .iter_mut()
+                .chain(new_versions.iter_mut().map(|x| &mut x.1))
+            {
+                version.build.clone_from(&build);
+            }
+        }
+
+        let (_, tags, _) = git(&metadata.workspace_root, &["tag", "--list"])?;
+        let tags = tags.lines().collect::<Vec<_>>();
+
+        self.check_versions(&config, &new_version, &new_versions, &independent, &tags)?;
+
         let new_versions = self.confirm_versions(new_versions)?;
 
+        if get_dry_run() {
+            if let Some(branch) = &branch
+                && !self.git.no_git_push
+            {
+                let tags = if self.commit_per_crate {
+                    new_versions
+                        .iter()
+                        .map(|(name, version)| self.git.crate_tag(&config, name, true, version))
+                        .filter_map(|x| x.transpose())
+                        .collect::<Result<Vec<_>>>()?
+                } else {
+                    self.git
+                        .tags(&new_version, &new_versions, &independent, &config)?
+                };
+
+                for args in self
+                    .git
+                    .push_commands(&metadata.workspace_root, branch, &tags)?
+                {
+                    info!("would run", format!("git {}", args.join(" ")));
+                }
+            }
+
+            warn!(
+                "Dry run doesn't write manifests or commit the versions.",
+                ""
+            );
+            return Ok(new_versions);
+        }
+
+        if self.commit_per_crate {
+            self.commit_each(metadata, &config, &new_versions, branch)?;
+            return Ok(new_versions);
+        }
+
+        self.write_manifests(metadata, &new_versions)?;
+
+        if let Some(new_version) = &new_version {
+            let workspace_root = metadata.workspace_root.join("Cargo.toml");
+            let manifest = fs::read_to_string(&workspace_root)?;
+
+            let manifest = if inherited {
+                info!(
+                    "inherited version",
+                    "only writing it to `[workspace.package]`"
+                );
+
+                // A root package which does not inherit keeps its own version
+                let manifest = change_versions_with(
+                    manifest,
+                    "",
+                    &new_versions,
+                    self.exact,
+                    &unpublished_members(metadata),
+                )?;
+                set_workspace_version(&manifest, new_version)?
+            } else {
+                let mut new_versions = new_versions.clone();
+                new_versions.insert("".to_string(), new_version.clone());
+
+                change_versions_with(
+                    manifest,
+                    "",
+                    &new_versions,
+                    self.exact,
+                    &unpublished_members(metadata),
+                )?
+            };
+
+            fs::write(&workspace_root, format!("{}\n", manifest))?;
+        }
+
+        self.update_lock(&metadata.workspace_root)?;
+
+        if self.snapshot {
+            info!(
+                "snapshot",
+                "not committing, tagging or pushing the versions"
+            );
+            return Ok(new_versions);
+        }
+
+        self.git.commit(
+            &metadata.workspace_root,
+            &new_version,
+            &new_versions,
+            &independent,
+            branch,
+            &config,
+        )?;
+
+        Ok(new_versions)
+    }
+
+    /// Expands the placeholders of `--build-metadata` and parses it
+    fn build_metadata(&self, root: &Utf8PathBuf) -> Result<Vec<Identifier>> {
+        let Some(build) = &self.build_metadata else {
+            return Ok(vec![]);
+        };
+
+        let mut build = build.clone();
+
+        if build.contains("%h") {
+            let (status, hash, stderr) = git(root, &["rev-parse", "--short", "HEAD"])?;
+
+            if !status.success() {
+                return Err(Error::BuildMetadata(
+                    build,
+                    format!("unable to get the commit hash: {}", stderr),
+                ));
+            }
+
+            build = build.replace("%h", &hash);
+        }
+
+        parse_build_metadata(&build)
+    }
+
+    pub fn write_manifests(
+        &self,
+        metadata: &Metadata,
+        new_versions: &Map<String, Version>,
+    ) -> Result {
+        let unpublished = unpublished_members(metadata);
+
         for p in &metadata.packages {
             if !new_versions.contains_key(&p.name)
                 && p.dependencies
@@ -145,51 +391,143 @@ impl VersionOpt {
                 &p.manifest_path,
                 format!(
                     "{}\n",
-                    change_versions(
+                    change_versions_with(
                         fs::read_to_string(&p.manifest_path)?,
                         &p.name,
-                        &new_versions,
+                        new_versions,
                         self.exact,
+                        &unpublished,
                     )?
                 ),
             )?;
         }
 
-        if let Some(new_version) = &new_version {
-            let workspace_root = metadata.workspace_root.join("Cargo.toml");
-            let mut new_versions = new_versions.clone();
+        Ok(())
+    }
 
-            new_versions.insert("".to_string(), new_version.clone());
+    /// The metadata as it reads once the manifests are written with the new versions, which
+    /// spares loading it again after versioning
+    pub fn versioned_metadata(
+        &self,
+        metadata: &Metadata,
+        new_versions: &Map<String, Version>,
+    ) -> Metadata {
+        let unpublished = unpublished_members(metadata);
+        let any = VersionReq::parse("*").expect(INTERNAL_ERR);
+        let mut metadata = metadata.clone();
+
+        for pkg in &mut metadata.packages {
+            if let Some(version) = new_versions.get(&pkg.name) {
+                pkg.version = version.clone();
+            }
 
-            fs::write(
-                &workspace_root,
-                format!(
-                    "{}\n",
-                    change_versions(
-                        fs::read_to_string(&workspace_root)?,
-                        "",
-                        &new_versions,
-                        self.exact
-                    )?
-                ),
-            )?;
+            for dep in &mut pkg.dependencies {
+                // Path dependencies without a requirement are left alone
+                let Some(new_version) = new_versions.get(&dep.name) else {
+                    continue;
+                };
+
+                if dep.req == any {
+                    continue;
+                }
+
+                let exact = self.exact && !unpublished.contains(&dep.name);
+
+                if let Some(req) = bumped_requirement(&dep.req, new_version, exact) {
+                    dep.req = VersionReq::parse(&req).expect(INTERNAL_ERR);
+                }
+            }
         }
 
-        let output = cargo(&metadata.workspace_root, &["update", "-w"], &[])?;
+        metadata
+    }
 
-        if output.1.contains("error:") {
-            return Err(Error::Update);
+    /// Bumps the crates one at a time, each in its own tagged commit. Dependencies are
+    /// bumped before their dependents, so that every commit only requires versions which
+    /// already exist in the history.
+    fn commit_each(
+        &self,
+        metadata: &Metadata,
+        config: &WorkspaceConfig,
+        new_versions: &Map<String, Version>,
+        branch: Option<String>,
+    ) -> Result {
+        let pkgs = metadata
+            .packages
+            .iter()
+            .map(|x| (x.clone(), x.version.to_string()))
+            .collect::<Vec<_>>();
+
+        let (names, visited) = dag(&pkgs)?;
+        let mut tags = vec![];
+
+        for p in &visited {
+            let (pkg, _) = names.get(p).expect(INTERNAL_ERR);
+
+            if let Some(version) = new_versions.get(&pkg.name) {
+                let mut new_versions = Map::new();
+                new_versions.insert(pkg.name.clone(), version.clone());
+
+                self.write_manifests(metadata, &new_versions)?;
+                self.update_lock(&metadata.workspace_root)?;
+                tags.extend(self.git.commit_crate(
+                    &metadata.workspace_root,
+                    config,
+                    &pkg.name,
+                    version,
+                )?);
+            }
         }
 
-        self.git.commit(
+        self.git.push(
             &metadata.workspace_root,
-            &new_version,
-            &new_versions,
-            branch,
-            &config,
+            &branch.expect(INTERNAL_ERR),
+            &tags,
         )?;
 
-        Ok(new_versions)
+        Ok(())
+    }
+
+    /// Regenerates the entries of the members in `Cargo.lock` after the manifests were written
+    /// and stages it for the version commit, since `git add -u` ignores untracked files.
+    /// Workspaces which do not track their lockfile are skipped unless it is requested.
+    pub fn update_lock(&self, root: &Utf8PathBuf) -> Result {
+        let (tracked, _, _) = git(root, &["ls-files", "--error-unmatch", "--", "Cargo.lock"])?;
+
+        if !tracked.success() && !self.update_lock {
+            debug!("skipping lockfile", "Cargo.lock is not tracked");
+            return Ok(());
+        }
+
+        // Only the versions of the members change, so the registries are not needed unless
+        // the lockfile was missing or other dependencies changed meanwhile
+        let update =
+            |args: &[&str]| -> Result<bool> { Ok(!cargo(root, args, &[])?.1.contains("error:")) };
+
+        let updated =
+            root.join("Cargo.lock").exists() && update(&["update", "--workspace", "--offline"])?;
+
+        if !updated && !update(&["update", "--workspace"])? {
+            return Err(Error::Update);
+        }
+
+        if !self.git.no_git_commit && !self.snapshot && is_git_repo(root) {
+            // Tracked files are never reported as ignored
+            let (ignored, _, _) = git(root, &["check-ignore", "-q", "--", "Cargo.lock"])?;
+
+            if ignored.success() {
+                debug!("not committing lockfile", "Cargo.lock is ignored by git");
+                return Ok(());
+            }
+
+            let added = git(root, &["add", "--", "Cargo.lock"])?;
+
+            if !added.0.success() {
+                return Err(Error::NotAdded(added.1, added.2));
+            }
+        }
+
+        Ok(())
     }
 
     fn get_new_versions(
@@ -199,9 +537,41 @@ impl VersionOpt {
         new_version: &mut Option<Version>,
         new_versions: &mut Vec<(String, Version, Version)>,
     ) -> Result {
-        let (independent_pkgs, same_pkgs) = pkgs
-            .into_iter()
-            .partition::<Vec<_>, _>(|p| p.config.independent.unwrap_or(false));
+        let mut independent_pkgs = vec![];
+        let mut same_pkgs = vec![];
+
+        for p in pkgs {
+            // Crates inheriting `[workspace.package] version` can only move along with it
+            if p.config.independent.unwrap_or(false) && !inherits_version(&p)? {
+                independent_pkgs.push(p);
+            } else {
+                same_pkgs.push(p);
+            }
+        }
+
+        // A lockstep bump given on the command line only moves independent crates which
+        // were explicitly targeted
+        if self.bump.is_some() && (!same_pkgs.is_empty() || new_version.is_some()) {
+            let targeted = self
+                .change
+                .force
+                .as_ref()
+                .map(|x| Glob::new(x).map(|x| x.compile_matcher()))
+                .transpose()?;
+
+            let (kept, skipped) = independent_pkgs
+                .into_iter()
+                .partition::<Vec<_>, _>(|p| targeted.as_ref().is_some_and(|x| x.is_match(&p.name)));
+
+            for p in skipped {
+                info!(
+                    "skipping independent",
+                    format!("{} (target it with --force to bump it too)", p.name)
+                );
+            }
+
+            independent_pkgs = kept;
+        }
 
         if !same_pkgs.is_empty() {
             let cur_version = same_pkgs
@@ -241,7 +611,71 @@ impl VersionOpt {
         Ok(())
     }
 
-    fn confirm_versions(
+    // reports all the new versions which would be a downgrade, clash with an existing tag
+    // or diverge from the common version at once
+    fn check_versions(
+        &self,
+        config: &WorkspaceConfig,
+        new_version: &Option<Version>,
+        new_versions: &[(String, Version, Version)],
+        independent: &[String],
+        tags: &[&str],
+    ) -> Result {
+        let mut problems = vec![];
+
+        if let Some(version) = new_version {
+            let tag = self.git.tag.global_tag(version);
+
+            if !self.git.no_git_tag && !self.git.no_global_tag && tags.contains(&tag.as_str()) {
+                problems.push(format!(" - tag {} already exists", tag));
+            }
+        }
+
+        for (name, version, cur_version) in new_versions {
+            if !self.allow_downgrade && version <= cur_version {
+                problems.push(format!(
+                    " - {}: {} is not greater than the current version {}",
+                    name, version, cur_version
+                ));
+            }
+
+            if let Some(tag) =
+                self.git
+                    .crate_tag(config, name, independent.contains(name), version)?
+                && tags.contains(&tag.as_str())
+            {
+                problems.push(format!(" - {}: tag {} already exists", name, tag));
+            }
+
+            if let Some(common) = new_version
+                && !independent.contains(name)
+                && version != common
+            {
+                problems.push(format!(
+                    " - {}: {} diverges from the common version {}",
+                    name, version, common
+                ));
+            }
+
+            // Crates moving along with the common version can't be committed separately
+            if self.commit_per_crate
+                && (!independent.contains(name) || new_version.as_ref() == Some(version))
+            {
+                problems.push(format!(
+                    " - {}: is not independent, which --commit-per-crate requires",
+                    name
+                ));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(Error::InvalidVersions(problems.join("\n")));
+        }
+
+        Ok(())
+    }
+
+    pub fn confirm_versions(
         &self,
         versions: Vec<(String, Version, Version)>,
     ) -> Result<Map<String, Version>> {
@@ -296,6 +730,19 @@ impl VersionOpt {
 
         let theme = ColorfulTheme::default();
 
+        // The timestamped prerelease is added once every version is known
+        if self.snapshot {
+            let mut version = cur_version.clone();
+
+            match self.bump {
+                Some(Bump::Major | Bump::Premajor) => version.increment_major(),
+                Some(Bump::Minor | Bump::Preminor) => version.increment_minor(),
+                _ => version.increment_patch(),
+            }
+
+            return Ok(Some(version));
+        }
+
         let selected = if let Some(bump) = &self.bump {
             bump.selected()
         } else {
@@ -348,6 +795,87 @@ impl VersionOpt {
     }
 }
 
+fn inherits_version(pkg: &Pkg) -> Result<bool> {
+    inherits_workspace_version(&fs::read_to_string(pkg.location.join("Cargo.toml"))?)
+}
+
+/// Whether every publishable crate inherits `[workspace.package] version`, warning when only
+/// some of them do
+fn inherits_all<'a>(pkgs: impl Iterator<Item = &'a Pkg>) -> Result<bool> {
+    let (mut inheriting, mut own) = (vec![], vec![]);
+
+    for p in pkgs.filter(|p| !p.private) {
+        if inherits_version(p)? {
+            inheriting.push(p.name.as_str());
+        } else {
+            own.push(p.name.as_str());
+        }
+    }
+
+    if !inheriting.is_empty() && !own.is_empty() {
+        warn!(
+            "mixed versions",
+            format!(
+                "{} inherit `[workspace.package] version` but not {}, consider `version.workspace = true` for every crate",
+                inheriting.join(", "),
+                own.join(", ")
+            )
+        );
+    }
+
+    Ok(!inheriting.is_empty() && own.is_empty())
+}
+
+fn parse_build_metadata(build: &str) -> Result<Vec<Identifier>> {
+    let valid =
+        |id: &str| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+
+    if !build.split('.').all(valid) {
+        return Err(Error::BuildMetadata(
+            build.to_string(),
+            "identifiers must be non-empty and only contain [0-9A-Za-z-]".to_string(),
+        ));
+    }
+
+    // Kept alphanumeric so that the leading zeros of a hash like `0123abc` are preserved
+    Ok(build
+        .split('.')
+        .map(|x| Identifier::AlphaNumeric(x.to_string()))
+        .collect())
+}
+
+/// The prerelease of snapshot versions, the time in UTC as `YYYYMMDDhhmmss` keeps them sortable
+fn snapshot_pre(pre_id: &Option<String>, now: SystemTime) -> Vec<Identifier> {
+    let timestamp = utc_time(now).into_iter().fold(0, |acc, x| acc * 100 + x);
+
+    vec![
+        Identifier::AlphaNumeric(pre_id.clone().unwrap_or_else(|| "dev".to_string())),
+        Identifier::Numeric(timestamp),
+    ]
+}
+
+/// The year, month, day, hours, minutes and seconds of the time in UTC
+pub fn utc_time(now: SystemTime) -> [u64; 6] {
+    let secs = now
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+    let (days, time) = (secs / 86400, secs % 86400);
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    [year, month, day, time / 3600, time % 3600 / 60, time % 60]
+}
+
 fn inc_pre(pre: &[Identifier], preid: &Option<String>) -> Vec<Identifier> {
     match pre.first() {
         Some(Identifier::AlphaNumeric(id)) => {
@@ -649,4 +1177,137 @@ mod test_super {
         assert_eq!(v.0, Identifier::Numeric(11));
         assert_eq!(v.1.to_string(), "3.0.0-11.20.a.56.c");
     }
+
+    fn check_versions(args: &[&str], new_versions: &[(&str, &str, &str)]) -> Result {
+        check_versions_with(&WorkspaceConfig::default(), args, new_versions)
+    }
+
+    fn check_versions_with(
+        config: &WorkspaceConfig,
+        args: &[&str],
+        new_versions: &[(&str, &str, &str)],
+    ) -> Result {
+        let opt = VersionOpt::try_parse_from([&["version"][..], args].concat()).unwrap();
+        let new_versions = new_versions
+            .iter()
+            .map(|(n, v, c)| {
+                (
+                    n.to_string(),
+                    Version::parse(v).unwrap(),
+                    Version::parse(c).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        opt.check_versions(
+            config,
+            &Some(Version::parse("1.0.0").unwrap()),
+            &new_versions,
+            &["ind".to_string()],
+            &["v0.9.0", "dep1@1.0.0", "ind@2.0.0"],
+        )
+    }
+
+    #[test]
+    fn test_check_versions() {
+        assert!(check_versions(&[], &[("dep2", "1.0.0", "0.9.0")]).is_ok());
+        assert!(check_versions(&[], &[("ind", "2.1.0", "1.0.0")]).is_ok());
+    }
+
+    #[test]
+    fn test_check_versions_problems() {
+        let err = check_versions(
+            &[],
+            &[
+                ("dep1", "1.0.0", "0.9.0"),
+                ("dep2", "1.0.0", "1.1.0"),
+                ("dep3", "1.1.0", "1.0.0"),
+                ("ind", "2.0.0", "2.0.0"),
+            ],
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("dep1: tag dep1@1.0.0 already exists"));
+        assert!(err.contains("dep2: 1.0.0 is not greater than the current version 1.1.0"));
+        assert!(err.contains("dep3: 1.1.0 diverges from the common version 1.0.0"));
+        assert!(err.contains("ind: 2.0.0 is not greater than the current version 2.0.0"));
+        assert!(err.contains("ind: tag ind@2.0.0 already exists"));
+    }
+
+    #[test]
+    fn test_check_versions_no_individual_tags() {
+        let new_versions = [("dep1", "1.0.0", "0.9.0")];
+
+        assert!(check_versions(&["--no-individual-tags"], &new_versions).is_ok());
+        assert!(check_versions(&[], &new_versions).is_err());
+
+        let config = WorkspaceConfig {
+            no_individual_tags: Some(true),
+            ..Default::default()
+        };
+
+        assert!(check_versions_with(&config, &[], &new_versions).is_ok());
+
+        // Independent crates are still tagged
+        let new_versions = [("ind", "2.0.0", "1.0.0")];
+
+        assert!(check_versions(&["--no-individual-tags"], &new_versions).is_err());
+        assert!(check_versions_with(&config, &[], &new_versions).is_err());
+    }
+
+    #[test]
+    fn test_check_versions_allow_downgrade() {
+        let new_versions = [("dep2", "1.0.0", "1.1.0")];
+
+        assert!(check_versions(&["--allow-downgrade"], &new_versions).is_ok());
+    }
+
+    #[test]
+    fn test_check_versions_commit_per_crate() {
+        let args = ["--commit-per-crate"];
+
+        assert!(check_versions(&args, &[("ind", "2.1.0", "1.0.0")]).is_ok());
+
+        let err = check_versions(&args, &[("dep2", "1.0.0", "0.9.0")])
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("dep2: is not independent, which --commit-per-crate requires"));
+    }
+
+    #[test]
+    fn test_parse_build_metadata() {
+        let build = parse_build_metadata("git.0123abc").unwrap();
+        let mut version = Version::parse("1.3.0").unwrap();
+        version.build = build;
+
+        assert_eq!(version.to_string(), "1.3.0+git.0123abc");
+        assert!(parse_build_metadata("git..abc").is_err());
+        assert!(parse_build_metadata("git_abc").is_err());
+    }
+
+    #[test]
+    fn test_snapshot_pre() {
+        let at = |secs| UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        let mut version = Version::parse("1.3.0").unwrap();
+
+        version.pre = snapshot_pre(&None, at(1705314600));
+        assert_eq!(version.to_string(), "1.3.0-dev.20240115103000");
+        assert_eq!(Version::parse(&version.to_string()).unwrap(), version);
+
+        version.pre = snapshot_pre(&Some("ci".to_string()), at(951825599));
+        assert_eq!(version.to_string(), "1.3.0-ci.20000229115959");
+
+        // Later snapshots sort after earlier ones
+        let later = Version {
+            pre: snapshot_pre(&None, at(1705314601)),
+            ..version.clone()
+        };
+        let earlier = Version {
+            pre: snapshot_pre(&None, at(1705314600)),
+            ..version
+        };
+        assert!(earlier < later);
+    }
 }
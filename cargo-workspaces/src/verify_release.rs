@@ -0,0 +1,96 @@
+use crate::utils::{
+    create_http_client, download_checksum, info, published_checksum, read_config,
+    registry_index_url, release_manifest_path, Error, ReleaseManifest, Result, WorkspaceConfig,
+};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+
+use std::{fs, path::PathBuf};
+
+/// Verify that the crates recorded by `publish` in the release manifest are on the registry
+#[derive(Debug, Parser)]
+pub struct VerifyRelease {
+    /// Path to the release manifest [default: release-manifest.json]
+    #[clap(parse(from_os_str))]
+    manifest: Option<PathBuf>,
+}
+
+impl VerifyRelease {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
+        let path = release_manifest_path(self.manifest.as_deref(), &config);
+        let manifest: ReleaseManifest = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let http_client = create_http_client(&metadata.workspace_root, &None)?;
+
+        info!("verifying", format!("release of {}", manifest.sha));
+
+        let mut problems = vec![];
+
+        for c in &manifest.crates {
+            let name_ver = format!("{} v{}", c.name, c.version);
+            let registry = Some(&c.registry).filter(|x| *x != "crates-io");
+            let index_url = registry_index_url(&metadata, registry)?;
+
+            let published = match published_checksum(&http_client, index_url, &c.name, &c.version)?
+            {
+                Some(checksum) => checksum,
+                None => {
+                    problems.push(format!(
+                        " - {} is not published to {}",
+                        name_ver, c.registry
+                    ));
+                    continue;
+                }
+            };
+
+            // The published crate itself stands in for the side which has no checksum, like
+            // index entries written by hand
+            let download = || {
+                let index_url = registry_index_url(&metadata, registry)?;
+                download_checksum(&http_client, index_url, &c.name, &c.version)
+            };
+
+            let mismatch = match (&c.checksum, Some(published).filter(|x| !x.is_empty())) {
+                (None, None) => {
+                    info!("published (no checksum to compare)", name_ver);
+                    continue;
+                }
+                (Some(recorded), Some(published)) => (*recorded != published)
+                    .then(|| format!("checksum {} does not match {} on", recorded, published)),
+                (Some(recorded), None) => {
+                    let downloaded = download()?;
+                    (*recorded != downloaded).then(|| {
+                        format!(
+                            "checksum {} does not match {} downloaded from",
+                            recorded, downloaded
+                        )
+                    })
+                }
+                (None, Some(published)) => {
+                    let downloaded = download()?;
+                    (downloaded != published).then(|| {
+                        format!(
+                            "downloaded checksum {} does not match {} on",
+                            downloaded, published
+                        )
+                    })
+                }
+            };
+
+            match mismatch {
+                Some(mismatch) => {
+                    problems.push(format!(" - {}: {} {}", name_ver, mismatch, c.registry))
+                }
+                None => info!("verified", name_ver),
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(Error::ReleaseMismatch(problems.join("\n")));
+        }
+
+        info!("success", "ok");
+        Ok(())
+    }
+}
@@ -1,19 +1,288 @@
-use crate::utils::{info, Result, VersionOpt};
-use cargo_metadata::Metadata;
+use crate::utils::{
+    change_versions, create_http_client, get_dry_run, git, info, is_git_repo, is_private,
+    read_config, undo_release, unversioned_path_deps, version_summary, warn, write_step_summary,
+    Error, ForgeRelease, PackageConfig, Result, VersionBump, VersionOpt, WorkspaceConfig,
+    WorkspaceContext, INTERNAL_ERR,
+};
+
+use camino::Utf8PathBuf;
+use cargo_metadata::{Metadata, Package};
 use clap::Parser;
+use globset::Glob;
+use semver::Version as SemVersion;
+use toml_edit::Document;
+
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    fs::{read_to_string, write},
+};
 
 /// Bump version of crates
 #[derive(Debug, Parser)]
 pub struct Version {
     #[clap(flatten)]
     version: VersionOpt,
+
+    /// Fail if any `path` dependency of the crates is missing a `version`
+    #[clap(long)]
+    require_clean_deps: bool,
+
+    /// Set the crates to the given version, regardless of their current one (requires --yes)
+    #[clap(long, value_name = "VERSION", requires = "yes")]
+    reset_to: Option<SemVersion>,
+
+    /// Only reset the crates matched by glob
+    #[clap(long, value_name = "PATTERN", requires = "reset-to")]
+    scope: Option<String>,
+
+    /// Delete the commit and tags created by the previous version command before resetting
+    #[clap(long, requires = "reset-to")]
+    undo_git: bool,
+
+    /// Create a GitHub or GitLab release for every created tag, using `GITHUB_TOKEN` or
+    /// `GITLAB_TOKEN`
+    #[clap(long, conflicts_with_all = &["reset-to", "snapshot"])]
+    forge_release: bool,
+
+    /// Create the releases as drafts
+    #[clap(long, requires = "forge-release")]
+    forge_release_draft: bool,
 }
 
 impl Version {
-    pub fn run(self, metadata: Metadata) -> Result {
-        self.version.do_versioning(&metadata)?;
+    pub fn run(self, ctx: &WorkspaceContext) -> Result {
+        if self.require_clean_deps {
+            self.check_deps(&ctx.metadata)?;
+        }
+
+        if let Some(version) = &self.reset_to {
+            return self.reset(&ctx.metadata, version);
+        }
+
+        let new_versions = self.version.do_versioning(ctx)?;
+
+        if self.forge_release {
+            self.forge_releases(&ctx.metadata, &new_versions)?;
+        }
+
+        self.step_summary(&ctx.metadata, &new_versions)?;
 
         info!("success", "ok");
         Ok(())
     }
+
+    fn forge_releases(
+        &self,
+        metadata: &Metadata,
+        new_versions: &Map<String, SemVersion>,
+    ) -> Result {
+        let root = &metadata.workspace_root;
+        let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
+        let dry_run = get_dry_run();
+
+        let Some(forge_release) = ForgeRelease::new(
+            &create_http_client(root, &None)?,
+            root,
+            self.version.git.remote(),
+            self.forge_release_draft,
+            &config,
+            dry_run,
+        )?
+        else {
+            return Ok(());
+        };
+
+        for (tag, pkg, version) in self.release_tags(metadata, &config, new_versions)? {
+            if !dry_run && !tag_exists(root, &tag)? {
+                warn!("not releasing", format!("{} was not tagged", tag));
+                continue;
+            }
+
+            forge_release.release(
+                &tag,
+                pkg.manifest_path.parent().expect(INTERNAL_ERR),
+                version,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The tags of the new versions, once for the crates sharing the workspace tag
+    fn release_tags<'a>(
+        &self,
+        metadata: &'a Metadata,
+        config: &WorkspaceConfig,
+        new_versions: &'a Map<String, SemVersion>,
+    ) -> Result<Vec<(String, &'a Package, &'a SemVersion)>> {
+        let mut tags = Set::new();
+        let mut ret = vec![];
+
+        for (name, version) in new_versions {
+            let pkg = member(metadata, name);
+            let independent = read_config::<PackageConfig>(&pkg.metadata)?
+                .independent
+                .unwrap_or(false);
+            let tag = self
+                .version
+                .git
+                .release_tag(config, name, independent, version)?;
+
+            if tags.insert(tag.clone()) {
+                ret.push((tag, pkg, version));
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Adds the new versions to the summary of the GitHub Actions job
+    fn step_summary(&self, metadata: &Metadata, new_versions: &Map<String, SemVersion>) -> Result {
+        if new_versions.is_empty() || get_dry_run() {
+            return Ok(());
+        }
+
+        let root = &metadata.workspace_root;
+        let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
+
+        // The metadata was read before the versions were changed
+        let bumps = new_versions
+            .iter()
+            .map(|(name, new)| VersionBump {
+                name,
+                old: &member(metadata, name).version,
+                new,
+            })
+            .collect::<Vec<_>>();
+
+        let mut commit = None;
+        let mut tags = vec![];
+
+        if is_git_repo(root) {
+            if !self.version.git.no_git_commit && !self.version.snapshot {
+                commit = Some(git(root, &["rev-parse", "HEAD"])?.1);
+            }
+
+            for (tag, _, _) in self.release_tags(metadata, &config, new_versions)? {
+                if tag_exists(root, &tag)? {
+                    tags.push(tag);
+                }
+            }
+        }
+
+        write_step_summary(&version_summary(&bumps, commit.as_deref(), &tags))
+    }
+
+    fn check_deps(&self, metadata: &Metadata) -> Result {
+        let workspace =
+            read_to_string(metadata.workspace_root.join("Cargo.toml"))?.parse::<Document>()?;
+
+        let mut problems = vec![];
+
+        for pkg in &metadata.packages {
+            if !metadata.workspace_members.contains(&pkg.id)
+                || (!self.version.all && is_private(pkg))
+            {
+                continue;
+            }
+
+            let manifest = read_to_string(&pkg.manifest_path)?.parse::<Document>()?;
+            let deps = unversioned_path_deps(&manifest, &workspace);
+
+            if !deps.is_empty() {
+                problems.push(format!(" - {}: {}", pkg.name, deps.join(", ")));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(Error::UnversionedPathDeps(problems.join("\n")));
+        }
+
+        Ok(())
+    }
+
+    /// Emergency rollback of a bad version bump which was not published yet
+    fn reset(&self, metadata: &Metadata, version: &SemVersion) -> Result {
+        let scope = self
+            .scope
+            .as_ref()
+            .map(|x| Glob::new(x).map(|x| x.compile_matcher()))
+            .transpose()?;
+
+        let versions = metadata
+            .packages
+            .iter()
+            .filter(|x| metadata.workspace_members.contains(&x.id))
+            .filter(|x| self.version.all || !is_private(x))
+            .filter(|x| scope.as_ref().is_none_or(|s| s.is_match(&x.name)))
+            .map(|x| (x.name.clone(), version.clone(), x.version.clone()))
+            .collect::<Vec<_>>();
+
+        if versions.is_empty() {
+            return Err(Error::EmptyWorkspace);
+        }
+
+        let new_versions = self.version.confirm_versions(versions)?;
+
+        if get_dry_run() {
+            warn!("Dry run doesn't undo git changes or write manifests.", "");
+            return Ok(());
+        }
+
+        if self.undo_git {
+            let tags = undo_release(&metadata.workspace_root)?;
+            info!(
+                "undone",
+                format!("release commit and tags {}", tags.join(", "))
+            );
+        }
+
+        self.version.write_manifests(metadata, &new_versions)?;
+
+        let workspace_root = metadata.workspace_root.join("Cargo.toml");
+        let mut root_versions = new_versions.clone();
+
+        // `[workspace.package] version` is shared, only reset it along with every crate
+        if self.scope.is_none() {
+            root_versions.insert("".to_string(), version.clone());
+        }
+
+        write(
+            &workspace_root,
+            format!(
+                "{}\n",
+                change_versions(
+                    read_to_string(&workspace_root)?,
+                    "",
+                    &root_versions,
+                    self.version.exact
+                )?
+            ),
+        )?;
+
+        self.version.update_lock(&metadata.workspace_root)?;
+
+        warn!(
+            "reset versions are not committed",
+            "review them before committing"
+        );
+        Ok(())
+    }
+}
+
+fn member<'a>(metadata: &'a Metadata, name: &str) -> &'a Package {
+    metadata
+        .packages
+        .iter()
+        .find(|x| x.name == name && metadata.workspace_members.contains(&x.id))
+        .expect(INTERNAL_ERR)
+}
+
+fn tag_exists(root: &Utf8PathBuf, tag: &str) -> Result<bool> {
+    let (status, _, _) = git(
+        root,
+        &["rev-parse", "--verify", &format!("refs/tags/{}", tag)],
+    )?;
+
+    Ok(status.success())
 }
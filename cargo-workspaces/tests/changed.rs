@@ -0,0 +1,405 @@
+mod utils;
+use std::{
+    fs::{create_dir_all, write},
+    path::Path,
+};
+use tempfile::tempdir;
+use utils::{commit, git, member, workspace};
+
+#[test]
+fn test_changed_ignore() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"dep1\", \"dep2\"]\n",
+    )
+    .unwrap();
+    member(root, "dep1", "");
+    member(
+        root,
+        "dep2",
+        "\n[package.metadata.workspaces]\nchanged_ignore = true\n",
+    );
+
+    git(root, &["init", "-q"]);
+    commit(root, "init");
+    git(root, &["tag", "v0.1.0"]);
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    write(root.join("dep2/src/lib.rs"), "pub fn b() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed"]);
+    assert_eq!(out, "dep1\n");
+
+    // Forcing still includes the crate
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--force", "dep2"],
+    );
+    assert_eq!(out, "dep1\ndep2\n");
+}
+
+#[test]
+fn test_ignore_path() {
+    let dir = workspace(&[("dep1", ""), ("dep2", "")], "");
+    let root = dir.path();
+
+    create_dir_all(root.join("dep1/benches")).unwrap();
+    write(root.join("dep1/README.md"), "# dep1\n").unwrap();
+    write(root.join("dep1/benches/a.rs"), "").unwrap();
+    write(root.join("dep2/src/lib.rs"), "pub fn b() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed"]);
+    assert_eq!(out, "dep1\ndep2\n");
+
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "changed",
+            "--ignore-path",
+            "*.md",
+            "--ignore-path",
+            "benches/*",
+        ],
+    );
+    assert_eq!(out, "dep2\n");
+
+    // The globs are relative to the crate root
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--ignore-path", "dep2/**"],
+    );
+    assert_eq!(out, "dep1\ndep2\n");
+}
+
+#[test]
+fn test_find_renames() {
+    let dir = workspace(&[("dep1", ""), ("dep2", "")], "");
+    let root = dir.path();
+
+    write(root.join("dep1/src/moved.rs"), "pub fn moved() {}\n").unwrap();
+    commit(root, "add");
+    git(root, &["tag", "-f", "v0.1.0"]);
+
+    // Without rename detection, the move would also count as a change of dep1
+    git(root, &["config", "diff.renames", "false"]);
+    git(root, &["mv", "dep1/src/moved.rs", "dep2/src/moved.rs"]);
+    commit(root, "move");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed"]);
+    assert_eq!(out, "dep2\n");
+}
+
+#[test]
+fn test_remote_tags() {
+    let dir = workspace(
+        &[("dep1", "")],
+        "\n[workspace.metadata.workspaces]\ntag_patterns = [\"v*\"]\n",
+    );
+    let root = dir.path();
+    let remote = tempdir().unwrap();
+    let remote_root = remote.path().to_str().unwrap();
+    let clone = tempdir().unwrap();
+    let clone_root = clone.path().to_str().unwrap();
+
+    git(root, &["clone", "-q", "--bare", ".", remote_root]);
+    git(root, &["clone", "-q", "--no-tags", remote_root, clone_root]);
+
+    // A tag which is not a release does not hide the missing ones
+    git(clone.path(), &["tag", "nightly"]);
+
+    let err = utils::run_err(clone_root, &["ws", "changed"]);
+    assert!(err.contains("no release tags found locally, but remote origin has v0.1.0"));
+
+    // An explicit reference does not need the tags
+    let (out, _) = utils::run(clone_root, &["ws", "changed", "--since", "HEAD"]);
+    assert_eq!(out, "");
+
+    // The fetched tag marks `HEAD` as released
+    let (out, err) = utils::run(clone_root, &["ws", "changed", "--fetch-tags"]);
+    assert!(out.contains("already released"));
+    assert!(err.contains("fetching tags from origin"));
+    assert_eq!(git(clone.path(), &["tag", "--list", "v*"]), "v0.1.0");
+
+    // Nothing is looked up once the release tags are there
+    let (out, err) = utils::run(clone_root, &["ws", "-v", "changed"]);
+    assert!(out.contains("already released"));
+    assert!(!err.contains("ls-remote"));
+}
+
+#[test]
+fn test_force_all_on() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"dep1\", \"dep2\"]\n",
+    )
+    .unwrap();
+    member(root, "dep1", "");
+    member(root, "dep2", "");
+
+    git(root, &["init", "-q"]);
+    commit(root, "init");
+    git(root, &["tag", "v0.1.0"]);
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--force-all-on", "rust-toolchain.toml"],
+    );
+    assert_eq!(out, "dep1\n");
+
+    write(root.join("rust-toolchain.toml"), "[toolchain]\n").unwrap();
+    commit(root, "toolchain");
+
+    let (out, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--force-all-on", "rust-toolchain.toml"],
+    );
+    assert_eq!(out, "dep1\ndep2\n");
+    assert!(err.contains("all crates changed because of rust-toolchain.toml"));
+}
+
+#[test]
+fn test_workspace_dependencies() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    let manifest = |deps: &str| {
+        format!(
+            "[workspace]\nmembers = [\"dep1\", \"dep2\", \"dep3\"]\n\n[workspace.dependencies]\n{}",
+            deps
+        )
+    };
+
+    write(
+        root.join("Cargo.toml"),
+        manifest("log = \"0.4\"\nrand = \"0.8\"\n"),
+    )
+    .unwrap();
+    member(root, "dep1", "\n[dependencies]\nlog.workspace = true\n");
+    member(
+        root,
+        "dep2",
+        "\n[dev-dependencies]\nrand = { workspace = true }\n",
+    );
+    member(root, "dep3", "");
+
+    git(root, &["init", "-q"]);
+    commit(root, "init");
+    git(root, &["tag", "v0.1.0"]);
+
+    // Only reformatting `rand` does not change anything
+    write(
+        root.join("Cargo.toml"),
+        manifest("log = \"0.4.20\"\nrand = '0.8'\n"),
+    )
+    .unwrap();
+    commit(root, "bump log");
+
+    let (out, err) = utils::run(root.to_str().unwrap(), &["ws", "changed"]);
+    assert_eq!(out, "dep1\n");
+    assert!(err.contains("workspace dependencies changed log"));
+
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--ignore-changes", "Cargo.toml"],
+    );
+    assert_eq!(out, "");
+}
+
+#[test]
+fn test_tag_patterns() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"dep1\", \"dep2\"]\n\n[workspace.metadata.workspaces]\ntag_patterns = [\"%n@*\", \"v*\"]\n",
+    )
+    .unwrap();
+    member(root, "dep1", "");
+    member(root, "dep2", "");
+
+    git(root, &["init", "-q"]);
+    commit(root, "init");
+    git(root, &["tag", "v0.1.0"]);
+
+    write(root.join("dep1/src/lib.rs"), "// 1").unwrap();
+    commit(root, "dep1");
+    git(root, &["tag", "dep1@0.2.0"]);
+
+    write(root.join("dep2/src/lib.rs"), "// 1").unwrap();
+    commit(root, "dep2");
+
+    // dep1 was released on its own after its change, dep2 only with the workspace
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed", "--json"]);
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["name"], "dep2");
+    assert_eq!(
+        json[0]["baseline"],
+        serde_json::json!({ "tag": "v0.1.0", "pattern": "v*" })
+    );
+
+    write(root.join("dep1/src/lib.rs"), "// 2").unwrap();
+    commit(root, "dep1 again");
+    git(root, &["tag", "v0.3.0"]);
+
+    write(root.join("README.md"), "").unwrap();
+    commit(root, "readme");
+
+    // The grouped release descends from the per-crate one, so it wins for dep1
+    let (out, err) = utils::run(root.to_str().unwrap(), &["ws", "-v", "changed"]);
+    assert_eq!(out, "");
+    assert!(err.contains("dep1 since v0.3.0 (v*)"));
+    assert!(err.contains("dep2 since v0.3.0 (v*)"));
+
+    // An explicit reference applies to every crate
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--since", "dep1@0.2.0"],
+    );
+    assert_eq!(out, "dep1\ndep2\n");
+}
+
+#[test]
+fn test_precise() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"dep1\", \"dep2\"]\n",
+    )
+    .unwrap();
+    member(root, "dep1", "readme = \"../docs/dep1.md\"\n");
+    member(root, "dep2", "");
+
+    create_dir_all(root.join("docs")).unwrap();
+    write(root.join("docs/dep1.md"), "# dep1\n").unwrap();
+    write(root.join(".gitignore"), "/target\n").unwrap();
+
+    // The build script of the second crate reads a directory outside of it
+    create_dir_all(root.join("proto")).unwrap();
+    write(root.join("proto/dep2.proto"), "syntax = \"proto3\";\n").unwrap();
+    write(
+        root.join("dep2/build.rs"),
+        "fn main() {\n    println!(\"cargo:rerun-if-changed=../proto\");\n}\n",
+    )
+    .unwrap();
+
+    git(root, &["init", "-q"]);
+    commit(root, "init");
+    git(root, &["tag", "v0.1.0"]);
+
+    write(root.join("docs/dep1.md"), "# dep1\n\nUpdated\n").unwrap();
+    commit(root, "edit");
+
+    // The shared docs are outside of every crate directory
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed"]);
+    assert_eq!(out, "");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed", "--precise"]);
+    assert_eq!(out, "dep1\n");
+
+    write(root.join("proto/dep2.proto"), "syntax = \"proto2\";\n").unwrap();
+    commit(root, "proto");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed", "--precise"]);
+    assert_eq!(out, "dep1\ndep2\n");
+
+    // The package lists are cached
+    let cache = root.join("target/cargo-workspaces/package-files.json");
+    assert!(cache.exists());
+}
+
+#[test]
+fn test_against_registry() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().join("ws");
+    let registry = utils::Registry::start();
+
+    create_dir_all(&root).unwrap();
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"dep1\", \"dep2\", \"dep3\"]\n",
+    )
+    .unwrap();
+    member(&root, "dep1", "");
+    member(&root, "dep2", "");
+    member(&root, "dep3", "publish = false\n");
+
+    // The index already has the first crate
+    registry.add("dep1", "0.1.0", false);
+    registry.configure(&root);
+
+    // No git history is needed
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--against-registry", "--registry", "local"],
+    );
+    assert_eq!(out, "dep2\n");
+}
+
+#[test]
+fn test_root_package_fixture() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    utils::copy_dir(Path::new("../fixtures/root_package"), root);
+
+    git(root, &["init", "-q"]);
+    commit(root, "init");
+    git(root, &["tag", "v0.1.0"]);
+
+    write(root.join("src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit root");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed"]);
+    assert_eq!(out, "root_package\n");
+
+    write(root.join("dep1/src/lib.rs"), "pub fn b() {}\n").unwrap();
+    commit(root, "edit dep1");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed", "-l"]);
+    assert_eq!(out, "root_package v0.1.0 .\ndep1         v0.1.0 dep1\n");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed", "--root-only"]);
+    assert_eq!(out, "root_package\n");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed", "--exclude-root"]);
+    assert_eq!(out, "dep1\n");
+}
+
+#[test]
+fn test_verbosity() {
+    let dir = utils::workspace(&[("dep1", "")], "");
+    let root = dir.path();
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    // The output of cargo is shown without the commands
+    let (out, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "-v", "changed", "--precise"],
+    );
+    assert_eq!(out, "dep1\n");
+    assert!(err.contains("debug cargo stdout "));
+    assert!(!err.contains("debug running"));
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &["ws", "-vv", "changed"]);
+    assert!(err.contains("debug running git "));
+    assert!(err.contains(&format!(" (in {})", root.display())));
+    assert!(!err.contains("debug stdout"));
+}
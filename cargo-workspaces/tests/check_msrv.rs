@@ -0,0 +1,56 @@
+mod utils;
+
+#[test]
+fn test_no_rust_version() {
+    let err = utils::run_err("../fixtures/normal", &["ws", "check-msrv"]);
+
+    assert!(err.contains("warn no rust-version dep1"));
+    assert!(err.contains("warn no rust-version top"));
+    assert!(err.contains("info success ok"));
+}
+
+#[test]
+fn test_incompatible_resolver() {
+    use std::fs::{create_dir_all, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+
+    create_dir_all(root.join("a/src")).unwrap();
+    write(root.join("a/src/lib.rs"), "").unwrap();
+    write(
+        root.join("a/Cargo.toml"),
+        "[package]\nname = \"a\"\nversion = \"0.1.0\"\nedition = \"2021\"\nrust-version = \"1.70\"\n",
+    )
+    .unwrap();
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"a\"]\nresolver = \"3\"\n",
+    )
+    .unwrap();
+
+    let err = utils::run_err(root.to_str().unwrap(), &["ws", "check-msrv", "--strict"]);
+
+    assert!(err.contains(
+        "error: resolver 3 needs cargo 1.84 but the members support 1.70 at most (a), use resolver 2"
+    ));
+}
+
+#[test]
+fn test_offline_missing_toolchain() {
+    let dir = utils::workspace(
+        &[("a", ""), ("b", "rust-version.workspace = true\n")],
+        "\n[workspace.package]\nrust-version = \"1.56\"\n",
+    );
+
+    let err = utils::run_err(
+        dir.path().to_str().unwrap(),
+        &["ws", "--offline", "check-msrv"],
+    );
+
+    assert!(err.contains("warn no rust-version a"));
+    assert!(err.contains(
+        "error: unable to install toolchain 1.56: it is not installed and cannot be downloaded in offline mode"
+    ));
+    assert!(!err.contains("installing toolchain"));
+}
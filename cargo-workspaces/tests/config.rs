@@ -0,0 +1,84 @@
+mod utils;
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+};
+use tempfile::tempdir;
+
+fn append(path: &Path, toml: &str) {
+    let manifest = read_to_string(path).unwrap();
+    write(path, format!("{}\n{}", manifest, toml)).unwrap();
+}
+
+#[test]
+fn test_validate() {
+    let dir = tempdir().unwrap();
+    utils::copy_dir(Path::new("../fixtures/normal"), dir.path());
+
+    append(
+        &dir.path().join("Cargo.toml"),
+        "[workspace.metadata.workspaces]\nallow_branch = \"main\"\n",
+    );
+    append(
+        &dir.path().join("dep1/Cargo.toml"),
+        "[package.metadata.workspaces]\nindependent = true\n",
+    );
+
+    let (_, err) = utils::run(
+        dir.path().to_str().unwrap(),
+        &["ws", "config", "--validate"],
+    );
+    assert!(err.contains("config of the workspace and its 3 crates"));
+}
+
+#[test]
+fn test_validate_errors() {
+    let dir = tempdir().unwrap();
+    utils::copy_dir(Path::new("../fixtures/normal"), dir.path());
+
+    append(
+        &dir.path().join("Cargo.toml"),
+        "[workspace.metadata.workspaces.init]\nexclude = \"examples\"\n",
+    );
+
+    let err = utils::run_err(
+        dir.path().to_str().unwrap(),
+        &["ws", "config", "--validate"],
+    );
+    assert!(err.contains("invalid workspaces config"));
+    assert!(err.contains(
+        "invalid `workspace.metadata.workspaces.init.exclude`: invalid type: string \"examples\", expected a sequence"
+    ));
+
+    // Every command loading the workspace fails the same way
+    let err = utils::run_err(dir.path().to_str().unwrap(), &["ws", "list"]);
+    assert!(err.contains("invalid `workspace.metadata.workspaces.init.exclude`"));
+}
+
+#[test]
+fn test_validate_unknown_key() {
+    let dir = tempdir().unwrap();
+    utils::copy_dir(Path::new("../fixtures/normal"), dir.path());
+
+    append(
+        &dir.path().join("dep1/Cargo.toml"),
+        "[package.metadata.workspaces]\nskip_publsh = true\n",
+    );
+
+    let warning = format!(
+        "warn config {}: unknown key `package.metadata.workspaces.skip_publsh`, did you mean `skip_publish`?",
+        dir.path().join("dep1").join("Cargo.toml").display()
+    );
+
+    let err = utils::run_err(
+        dir.path().to_str().unwrap(),
+        &["ws", "config", "--validate"],
+    );
+    assert!(err.contains(&warning));
+    assert!(err.contains("found 1 unknown key(s) in the workspaces config"));
+
+    // The other commands only warn about it
+    let (out, err) = utils::run(dir.path().to_str().unwrap(), &["ws", "list"]);
+    assert!(err.contains(&warning));
+    assert_eq!(out, "dep1\ndep2\ntop\n");
+}
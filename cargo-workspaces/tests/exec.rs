@@ -28,3 +28,608 @@ fn test_normal_ignore() {
     assert_snapshot!(err);
     assert_snapshot!(out);
 }
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_ignore_errors_for() {
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "exec", "--ignore-errors-for=*", "false"],
+    );
+    assert!(err.contains("warn ignoring failure"));
+    assert!(err.contains("info success ok"));
+
+    // Only the failures of the matching crates are ignored
+    let (_, err) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--ignore-errors-for=dep*",
+            "sh",
+            "-c",
+            "test {name} = dep2",
+        ],
+    );
+    assert!(err.contains("warn ignoring failure dep1"));
+    assert!(!err.contains("ignoring failure top"));
+    assert!(!err.contains("info success ok"));
+
+    // An invalid pattern fails before running anything
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "exec", "--ignore-errors-for=[", "false"],
+    );
+    assert!(err.contains("error parsing glob '['"));
+    assert!(!err.contains("ignoring failure"));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_json_summary() {
+    let (out, err) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--no-bail",
+            "--json-summary",
+            "sh",
+            "-c",
+            "printf abc; printf %% >&2; test {name} != dep2",
+        ],
+    );
+    // The output of the crates is still shown before the summary, which starts on its own line
+    assert!(out.starts_with("abcabcabc\n{"));
+    assert_eq!(err.matches("%").count(), 3);
+
+    let json: serde_json::Value = serde_json::from_str(&out["abcabcabc\n".len()..]).unwrap();
+    assert_eq!(json["total"], 3);
+    assert_eq!(json["succeeded"], 2);
+    assert_eq!(json["failed"], 1);
+
+    let results = json["results"].as_array().unwrap();
+    let dep2 = results.iter().find(|x| x["name"] == "dep2").unwrap();
+    assert_eq!(dep2["path"], "dep2");
+    assert_eq!(dep2["exit_code"], 1);
+    assert_eq!(dep2["stdout_bytes"], 3);
+    assert_eq!(dep2["stderr_bytes"], 1);
+    assert!(dep2["duration_ms"].is_u64());
+
+    // The crates which ran are summarized when the run stops early as well
+    let (out, _) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--json-summary",
+            "sh",
+            "-c",
+            "echo abc; false",
+        ],
+    );
+    let json: serde_json::Value = serde_json::from_str(&out["abc\n".len()..]).unwrap();
+    assert_eq!(json["total"], 1);
+    assert_eq!(json["failed"], 1);
+
+    let (out, err) = utils::run(
+        "../fixtures/normal",
+        &["ws", "exec", "--json-summary", "does-not-exist"],
+    );
+    assert!(err.contains("error:"));
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json["total"], 0);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_group_output() {
+    let (out, _) = utils::run(
+        "../fixtures/normal",
+        &["ws", "exec", "--group-output", "echo", "hello"],
+    );
+    assert!(out.contains("=== dep1 ===\nhello\n"));
+    assert!(out.contains("=== top ===\nhello\n"));
+}
+
+#[test]
+fn test_normal_global_dry_run() {
+    let err = utils::run_err("../fixtures/normal", &["ws", "--dry-run", "exec", "false"]);
+    assert!(err.contains("info would run false in"));
+    assert!(err.contains("info success ok"));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_interrupted() {
+    use assert_cmd::cargo::CommandCargoExt;
+    use std::{
+        fs::read_to_string,
+        process::{Command, Stdio},
+        thread::sleep,
+        time::Duration,
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = dir.path().join("pid");
+    let script = format!("echo $$ > {}; exec sleep 30", pid_file.display());
+
+    let child = Command::cargo_bin("cargo-ws")
+        .unwrap()
+        .current_dir("../fixtures/normal")
+        .args(["ws", "exec", "sh", "-c", &script])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Wait for the command of the first crate to start
+    let pid = loop {
+        let pid = read_to_string(&pid_file).unwrap_or_default();
+
+        if pid.ends_with('\n') {
+            break pid.trim().to_string();
+        }
+
+        sleep(Duration::from_millis(50));
+    };
+
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let err = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(130));
+    assert!(err.contains("interrupted, cleaning up"));
+    assert!(err.contains("completed before interruption nothing"));
+
+    // The command of the crate was terminated too
+    let alive = Command::new("kill")
+        .args(["-0", &pid])
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+
+    assert!(!alive.success());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_substitute() {
+    let (out, _) = utils::run(
+        "../fixtures/normal",
+        &["ws", "exec", "echo", "{name}@{version}", "{{name}}"],
+    );
+    assert!(out.contains("dep1@0.1.0 {name}\n"));
+    assert!(out.contains("top@0.1.0 {name}\n"));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_exit_code() {
+    use assert_cmd::Command;
+
+    let exit_code = |args: &[&str]| {
+        Command::cargo_bin("cargo-ws")
+            .unwrap()
+            .current_dir("../fixtures/normal")
+            .args(["ws", "exec", "--no-bail"])
+            .args(args)
+            .args(["sh", "-c", "exit 101"])
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+
+    assert_eq!(exit_code(&[]), Some(101));
+    assert_eq!(exit_code(&["--exit-code=summary"]), Some(3));
+    assert_eq!(exit_code(&["--exit-code", "max"]), Some(101));
+    assert_eq!(exit_code(&["--exit-code", "custom", "7"]), Some(7));
+    assert_eq!(exit_code(&["--exit-code=custom", "7"]), Some(7));
+
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "exec", "--exit-code", "custom", "256", "false"],
+    );
+    assert!(err.contains("expected an exit code from 0 to 255 after custom, got 256"));
+
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "exec", "--exit-code", "custom", "7"],
+    );
+    assert!(err.contains("missing the command to run"));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_timeout() {
+    use assert_cmd::Command;
+    use std::{fs::read_to_string, process::Stdio, time::Instant};
+
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = dir.path().join("pids");
+    // The background sleep is only killed along with the whole process group
+    let script = format!("sleep 30 & echo $! >> {}; wait", pid_file.display());
+
+    let start = Instant::now();
+    let output = Command::cargo_bin("cargo-ws")
+        .unwrap()
+        .current_dir("../fixtures/normal")
+        .args(["ws", "exec", "--no-bail", "--timeout=1s"])
+        .args(["sh", "-c", &script])
+        .output()
+        .unwrap();
+    let err = String::from_utf8(output.stderr).unwrap();
+
+    assert!(start.elapsed().as_secs() < 30);
+    assert_eq!(output.status.code(), Some(124));
+    assert!(err.contains("warn killed dep1 timed out after 1s"));
+    assert!(err.contains("3 crate(s) failed: "));
+    assert!(err.contains("top (timed out after 1s)"));
+
+    for pid in read_to_string(&pid_file).unwrap().lines() {
+        // Killed orphans stay zombies until init reaps them, which some containers never do
+        let state = std::process::Command::new("ps")
+            .args(["-o", "stat=", "-p", pid])
+            .stderr(Stdio::null())
+            .output()
+            .unwrap();
+        let state = String::from_utf8(state.stdout).unwrap();
+
+        assert!(state.trim().is_empty() || state.starts_with('Z'));
+    }
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_continue_file() {
+    use std::fs::{read_to_string, write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("progress");
+    let continue_file = format!("--continue-file={}", path.display());
+
+    // The last crate fails, the others are recorded
+    let (_, err) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--no-bail",
+            &continue_file,
+            "sh",
+            "-c",
+            "test {name} != top",
+        ],
+    );
+    assert!(err.contains("1 crate(s) failed: top (exit code 1)"));
+    assert_eq!(read_to_string(&path).unwrap(), "dep1\ndep2\n");
+
+    write(&path, "dep1\n").unwrap();
+
+    let (out, err) = utils::run(
+        "../fixtures/normal",
+        &["ws", "exec", &continue_file, "echo", "{name}"],
+    );
+    assert!(err.contains("info skipping completed dep1"));
+    assert_eq!(out, "dep2\ntop\n");
+
+    // Cleared once everything succeeded
+    assert!(!path.exists());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_junit() {
+    use std::fs::read_to_string;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("junit.xml");
+    let junit = format!("--junit={}", path.display());
+
+    let (_, err) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--no-bail",
+            &junit,
+            "--junit-suite=tests",
+            "sh",
+            "-c",
+            "echo '<oops>' >&2; test {name} != dep2",
+        ],
+    );
+    // The human summary is unchanged
+    assert!(err.contains("1 crate(s) failed: dep2 (exit code 1)"));
+
+    let xml = read_to_string(&path).unwrap();
+    assert!(xml.contains(r#"<testsuite name="tests" tests="3" failures="1" errors="0""#));
+    assert!(xml.contains(r#"<testcase name="dep1" classname="tests""#));
+    assert!(xml.contains(r#"<failure message="exit code 1">&lt;oops&gt;</failure>"#));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_junit_error() {
+    use std::fs::read_to_string;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("junit.xml");
+    let junit = format!("--junit={}", path.display());
+
+    // The command can't even be spawned
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "exec", &junit, "cargo-ws-missing-command"],
+    );
+    assert!(err.contains("error: "));
+
+    let xml = read_to_string(&path).unwrap();
+    assert!(xml.contains(r#"<testsuite name="cargo-workspaces" tests="1" failures="0" errors="1""#));
+    assert!(xml.contains(r#"<testcase name="dep1" classname="cargo-workspaces""#));
+    assert!(xml.contains(r#"<error message=""#));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_profile() {
+    let (out, _) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--profile",
+            "ci",
+            "sh",
+            "-c",
+            "echo $CARGO_PROFILE",
+        ],
+    );
+    assert_eq!(out, "ci\nci\nci\n");
+
+    // Cargo commands get the flag, before the arguments of the harness
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "--dry-run",
+            "exec",
+            "--profile",
+            "ci",
+            "cargo",
+            "test",
+            "--",
+            "--nocapture",
+        ],
+    );
+    assert!(err.contains("info would run cargo test --profile ci -- --nocapture in "));
+
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "--dry-run",
+            "exec",
+            "--profile",
+            "ci",
+            "cargo",
+            "build",
+            "--profile=release",
+        ],
+    );
+    assert!(err.contains("info would run cargo build --profile=release in "));
+
+    // Subcommands which don't take the flag only get the variable
+    let (out, _) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--profile",
+            "ci",
+            "cargo",
+            "metadata",
+            "--format-version",
+            "1",
+            "--no-deps",
+        ],
+    );
+    assert_eq!(out.lines().count(), 3);
+    assert!(out.lines().all(|x| x.starts_with("{\"packages\":")));
+}
+
+#[test]
+fn test_normal_cargo_flags() {
+    // The global flags go before the subcommand, after the toolchain
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "--locked",
+            "--dry-run",
+            "exec",
+            "cargo",
+            "+stable",
+            "build",
+        ],
+    );
+    assert!(err.contains("info would run cargo +stable --locked build in "));
+
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "--frozen",
+            "--dry-run",
+            "exec",
+            "cargo",
+            "--frozen",
+            "check",
+        ],
+    );
+    assert!(err.contains("info would run cargo --frozen check in "));
+
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "--locked", "--dry-run", "exec", "echo", "--locked"],
+    );
+    assert!(err.contains("info would run echo --locked in "));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_post_args() {
+    let (out, _) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--",
+            "echo",
+            "{name}",
+            "--",
+            "--nocapture",
+            "{name}",
+        ],
+    );
+    assert!(out.contains("dep1 -- --nocapture dep1\n"));
+    assert!(out.contains("top -- --nocapture top\n"));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_root_package() {
+    let dir = std::fs::canonicalize("../fixtures/root_package").unwrap();
+    let dir = dir.to_str().unwrap();
+
+    let (out, _) = utils::run(dir, &["ws", "exec", "pwd"]);
+    assert_eq!(out, format!("{}/dep1\n{}\n", dir, dir));
+
+    let (out, _) = utils::run(dir, &["ws", "exec", "--root-only", "pwd"]);
+    assert_eq!(out, format!("{}\n", dir));
+
+    let (out, _) = utils::run(dir, &["ws", "exec", "--exclude-root", "pwd"]);
+    assert_eq!(out, format!("{}/dep1\n", dir));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_root_only_virtual() {
+    let (out, err) = utils::run("../fixtures/normal", &["ws", "exec", "--root-only", "pwd"]);
+    assert!(out.is_empty());
+    assert!(err.contains("warn no root package"));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_no_deps() {
+    let (out, _) = utils::run(
+        "../fixtures/normal",
+        &["ws", "exec", "--no-deps", "basename", "{path}"],
+    );
+    assert_eq!(out, "top\n");
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_at_root() {
+    let (out, _) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--at-root",
+            "sh",
+            "-c",
+            "echo $CARGO_WS_PKG_NAME $(basename $PWD)",
+        ],
+    );
+    assert_eq!(out, "dep1 normal\ndep2 normal\ntop normal\n");
+}
+
+#[test]
+fn test_normal_cargo() {
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "--dry-run",
+            "exec",
+            "--cargo",
+            "cargo",
+            "test",
+            "--",
+            "-q",
+        ],
+    );
+    assert!(err.contains("would run cargo test -p dep1 -- -q in "));
+    assert!(!err.contains("/dep1\n"));
+}
+
+#[test]
+fn test_cargo_package() {
+    let dir = utils::workspace(&[("dep1", ""), ("dep2", "")], "resolver = \"2\"\n");
+    let root = dir.path();
+
+    // Run at the root, each command only sees its own crate
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "exec", "--cargo", "cargo", "tree", "--depth", "0"],
+    );
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("dep1 v0.1.0 ("));
+    assert!(lines[1].starts_with("dep2 v0.1.0 ("));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_normal_max_crates() {
+    let (out, err) = utils::run(
+        "../fixtures/normal",
+        &["ws", "exec", "--max-crates", "2", "basename", "{path}"],
+    );
+    assert_eq!(out, "dep1\ndep2\n");
+    assert!(err.contains("info limiting to the first 2 of 3 crates"));
+
+    // Ignored crates do not count towards the limit
+    let (out, _) = utils::run(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "exec",
+            "--ignore",
+            "dep1",
+            "--max-crates",
+            "1",
+            "basename",
+            "{path}",
+        ],
+    );
+    assert_eq!(out, "dep2\n");
+}
+
+#[test]
+fn test_dependency_cycle() {
+    let dep = |name: &str| format!("\n[dependencies]\n{0} = {{ path = \"../{0}\" }}\n", name);
+    let dir = utils::workspace(&[("a", &dep("b")), ("b", &dep("c")), ("c", &dep("a"))], "");
+    let root = dir.path();
+
+    let err = utils::run_err(root.to_str().unwrap(), &["ws", "exec", "pwd"]);
+
+    assert!(err.contains("dependency cycle a -> b -> c -> a\n"));
+
+    for (name, dep) in [("a", "b"), ("b", "c"), ("c", "a")] {
+        assert!(err.contains(&format!(
+            " - {} depends on {} in [dependencies] of {}",
+            name,
+            dep,
+            root.join(name).join("Cargo.toml").display()
+        )));
+    }
+}
@@ -0,0 +1,21 @@
+mod utils;
+use serial_test::serial;
+use std::fs::{read_to_string, remove_file};
+
+#[test]
+#[serial]
+fn test_gitlab() {
+    let path = "../fixtures/normal/.gitlab-ci.yml";
+
+    let err = utils::run_err("../fixtures/normal", &["ws", "generate-ci", "gitlab"]);
+    assert!(err.contains("created"));
+
+    let data = read_to_string(path).unwrap();
+    assert!(data.contains("- dep1\n"));
+    assert!(data.contains("- top\n"));
+
+    let err = utils::run_err("../fixtures/normal", &["ws", "generate-ci", "gitlab"]);
+    assert!(err.contains("error"));
+
+    remove_file(path).unwrap();
+}
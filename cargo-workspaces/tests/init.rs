@@ -1,7 +1,7 @@
 mod utils;
 use insta::assert_snapshot;
 use serial_test::serial;
-use std::fs::{read_to_string, rename, write};
+use std::fs::{read_to_string, remove_dir_all, rename, write};
 
 #[test]
 fn test_no_path() {
@@ -53,11 +53,10 @@ fn test_root_with_no_manifest() {
     // Rename Cargo.toml
     rename(manifest, backup).unwrap();
 
+    // The sources of the root crate are not a crate without its manifest
     let err = utils::run_err("../fixtures/root", &["ws", "init"]);
-    assert_snapshot!(err);
-
-    let data = read_to_string(manifest).unwrap();
-    assert_snapshot!(data);
+    assert!(err.contains("did not find any crates in ."));
+    assert!(read_to_string(manifest).is_err());
 
     // Rename Cargo.toml
     rename(backup, manifest).unwrap();
@@ -72,7 +71,8 @@ fn test_root_with_no_manifest_initialized() {
     // Rename Cargo.toml
     rename(manifest, backup).unwrap();
 
-    utils::run_err("../fixtures/root", &["ws", "init"]);
+    // As left behind by earlier versions, which initialized it without any member
+    write(manifest, "[workspace]\nmembers = []\n").unwrap();
 
     let err = utils::run_err("../fixtures/root", &["ws", "init"]);
 
@@ -167,3 +167,219 @@ fn test_root_with_manifest_no_workspace_initialized() {
     // Rename Cargo.toml
     rename(backup, manifest).unwrap();
 }
+
+#[test]
+#[serial]
+fn test_normal_generate_cargo_config() {
+    let manifest = "../fixtures/normal/Cargo.toml";
+    let config = "../fixtures/normal/.cargo/config.toml";
+    let content = read_to_string(manifest).unwrap();
+
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "init", "--generate-cargo-config"],
+    );
+    assert!(err.contains("info created"));
+
+    let data = read_to_string(config).unwrap();
+    assert!(data.contains("target-dir = \"target\""));
+    assert!(data.contains("retry = 3"));
+
+    write(config, "# custom\n").unwrap();
+
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "init", "--generate-cargo-config"],
+    );
+    assert!(err.contains("warn not overwriting existing cargo config"));
+    assert_eq!(read_to_string(config).unwrap(), "# custom\n");
+
+    utils::run_err(
+        "../fixtures/normal",
+        &[
+            "ws",
+            "init",
+            "--generate-cargo-config",
+            "--force-cargo-config",
+        ],
+    );
+    assert!(read_to_string(config).unwrap().contains("retry = 3"));
+
+    // Restore Cargo.toml and remove the config
+    write(manifest, content).unwrap();
+    remove_dir_all("../fixtures/normal/.cargo").unwrap();
+}
+
+#[test]
+fn test_no_crates() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let err = utils::run_err(dir.path().to_str().unwrap(), &["ws", "init"]);
+    assert!(err.contains("did not find any crates in ."));
+    assert!(!dir.path().join("Cargo.toml").exists());
+}
+
+#[test]
+fn test_skipped_dirs() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+
+    utils::member(root, "a", "");
+    // Like the copies `cargo package` leaves in the target directory
+    utils::member(&root.join("target/package"), "b", "");
+    utils::member(&root.join("vendor"), "c", "");
+    write(root.join(".gitignore"), "/vendor\n").unwrap();
+    utils::git(root, &["init", "-q"]);
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &["ws", "init"]);
+    assert!(err.contains("info crates a\n"));
+
+    let data = read_to_string(root.join("Cargo.toml")).unwrap();
+    assert_eq!(
+        data,
+        "[workspace]\nmembers = [\n    \"a\",\n]\nresolver = \"3\"\n"
+    );
+}
+
+#[test]
+fn test_interactive_without_terminal() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+
+    utils::member(root, "a", "");
+
+    // The output of the tests is captured, so there is nothing to prompt in
+    let err = utils::run_err(root.to_str().unwrap(), &["ws", "init", "--interactive"]);
+    assert!(err.contains("error: --interactive needs a terminal to prompt in"));
+    assert!(!root.join("Cargo.toml").exists());
+}
+
+#[test]
+fn test_root_package_resolver() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    write(root.join("src/lib.rs"), "").unwrap();
+    write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"root\"\nversion = \"0.1.0\"\nedition = \"2021\"\nresolver = \"2\"\n",
+    )
+    .unwrap();
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &["ws", "init"]);
+    assert!(err.contains("info moved resolver \"2\" from [package] to [workspace]"));
+
+    let data = read_to_string(root.join("Cargo.toml")).unwrap();
+    assert!(data.contains("[workspace]\nmembers = [\n    \"\",\n]\nresolver = \"2\"\n"));
+    assert_eq!(data.matches("resolver").count(), 1);
+}
+
+#[test]
+fn test_editorconfig() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let editorconfig = root.join(".editorconfig");
+
+    std::fs::create_dir_all(root.join("dep1/src")).unwrap();
+    write(root.join("dep1/src/lib.rs"), "").unwrap();
+    write(
+        root.join("dep1/Cargo.toml"),
+        "[package]\nname = \"dep1\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &["ws", "init", "--editorconfig"]);
+    assert!(err.contains("info created"));
+
+    let data = read_to_string(&editorconfig).unwrap();
+    assert!(data.starts_with("root = true\n\n[*.rs]\n"));
+    assert!(data.contains("indent_size = 4\n"));
+    assert!(data.contains("end_of_line = lf\n"));
+
+    write(&editorconfig, "# custom\n").unwrap();
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &["ws", "init", "--editorconfig"]);
+    assert!(err.contains("warn not overwriting existing .editorconfig"));
+    assert_eq!(read_to_string(&editorconfig).unwrap(), "# custom\n");
+
+    let err = utils::run_err(
+        root.to_str().unwrap(),
+        &["ws", "init", "--editorconfig", "--no-editorconfig"],
+    );
+    assert!(err.contains("cannot be used with"));
+}
+
+#[test]
+fn test_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+
+    std::fs::create_dir_all(root.join("crates/dep1/src")).unwrap();
+    write(root.join("crates/dep1/src/lib.rs"), "").unwrap();
+    write(
+        root.join("crates/dep1/Cargo.toml"),
+        "[package]\nname = \"dep1\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+
+    let (out, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "--dry-run", "init", "--json"],
+    );
+    assert!(err.contains("info would initialize"));
+    assert!(!root.join("Cargo.toml").exists());
+
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json["dry_run"], true);
+    assert_eq!(json["resolver"], "3");
+    assert_eq!(json["root_package"], false);
+    assert_eq!(
+        json["members"],
+        serde_json::json!([{ "name": "dep1", "path": "crates/dep1" }])
+    );
+    assert!(json["root"]
+        .as_str()
+        .unwrap()
+        .ends_with(root.file_name().unwrap().to_str().unwrap()));
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "init", "--json"]);
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert!(json.get("dry_run").is_none());
+    assert_eq!(json["members"][0]["name"], "dep1");
+    assert!(root.join("Cargo.toml").exists());
+}
+
+#[test]
+fn test_virtual() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let package = "[package]\nname = \"root\"\nversion = \"0.1.0\"\nedition = \"2021\"\n";
+
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    write(root.join("src/lib.rs"), "").unwrap();
+    write(root.join("Cargo.toml"), package).unwrap();
+
+    let err = utils::run_err(root.to_str().unwrap(), &["ws", "init", "--virtual"]);
+    assert!(err.contains("has a [package], which a virtual manifest cannot have"));
+    assert_eq!(read_to_string(root.join("Cargo.toml")).unwrap(), package);
+
+    // Without a root package the manifest only has the workspace
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+
+    std::fs::create_dir_all(root.join("dep1/src")).unwrap();
+    write(root.join("dep1/src/lib.rs"), "").unwrap();
+    write(
+        root.join("dep1/Cargo.toml"),
+        package.replace("root", "dep1"),
+    )
+    .unwrap();
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &["ws", "init", "--virtual"]);
+    assert!(err.contains("info initialized"));
+
+    let data = read_to_string(root.join("Cargo.toml")).unwrap();
+    assert!(data.starts_with("[workspace]\nmembers = [\n    \"dep1\",\n]\n"));
+    assert!(!data.contains("[package]"));
+}
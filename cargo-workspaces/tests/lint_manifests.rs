@@ -0,0 +1,81 @@
+mod utils;
+use std::{fs, path::Path};
+
+fn packaged_files() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    utils::copy_dir(Path::new("../fixtures/packaged_files"), dir.path());
+    dir
+}
+
+#[test]
+fn test_lint() {
+    let dir = packaged_files();
+
+    let err = utils::run_err(dir.path().to_str().unwrap(), &["ws", "lint-manifests"]);
+    assert!(!err.contains("alpha:"));
+    assert!(err.contains("warn not packaged beta: readme README.md does not exist"));
+    assert!(err.contains(
+        "warn not packaged gamma: LICENSE-MIT only at the workspace root, without a license-file entry"
+    ));
+    assert!(err.contains("found 3 problem(s) in the manifests"));
+}
+
+#[test]
+fn test_fix_readme_inherited() {
+    let dir = packaged_files();
+    let ws = dir.path();
+    let root_manifest = fs::read_to_string(ws.join("Cargo.toml")).unwrap();
+
+    fs::write(
+        ws.join("Cargo.toml"),
+        format!(
+            "{}\n[workspace.package]\nreadme = \"README.md\"\n",
+            root_manifest
+        ),
+    )
+    .unwrap();
+
+    let err = utils::run_err(ws.to_str().unwrap(), &["ws", "lint-manifests"]);
+    assert!(err.contains(
+        "warn not packaged gamma: no readme, while the workspace one could be inherited with readme.workspace = true"
+    ));
+
+    let err = utils::run_err(ws.to_str().unwrap(), &["ws", "lint-manifests", "--fix"]);
+    assert!(err.contains("info fixed beta: readme README.md does not exist"));
+    assert!(err.contains("info fixed gamma: no readme"));
+    assert!(err.contains("info success ok"));
+
+    for member in ["beta", "gamma"] {
+        let manifest = fs::read_to_string(ws.join(member).join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("\nreadme.workspace = true\n"));
+    }
+
+    let err = utils::run_err(ws.to_str().unwrap(), &["ws", "lint-manifests"]);
+    assert!(!err.contains("not packaged"));
+    assert!(err.contains("info success ok"));
+}
+
+#[test]
+fn test_fix_missing_license_file() {
+    let dir = packaged_files();
+    let ws = dir.path();
+    let gamma = ws.join("gamma/Cargo.toml");
+    let manifest = fs::read_to_string(&gamma).unwrap();
+
+    fs::write(
+        &gamma,
+        manifest.replace("license = \"MIT\"", "license-file = \"LICENSE-MIT\""),
+    )
+    .unwrap();
+
+    let err = utils::run_err(ws.to_str().unwrap(), &["ws", "lint-manifests", "--fix"]);
+    assert!(err.contains("info fixed gamma: license-file LICENSE-MIT does not exist"));
+    assert_eq!(
+        fs::read_to_string(ws.join("gamma/LICENSE-MIT")).unwrap(),
+        fs::read_to_string(ws.join("LICENSE-MIT")).unwrap()
+    );
+
+    // Beta has no readme to fall back on
+    assert!(err.contains("warn not packaged beta: readme README.md does not exist"));
+    assert!(err.contains("found 1 problem(s) in the manifests"));
+}
@@ -1,5 +1,24 @@
 mod utils;
 use insta::assert_snapshot;
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::Path,
+    process::Command,
+};
+use tempfile::tempdir;
+
+fn manifest(dir: &Path, body: &str) {
+    create_dir_all(dir.join("src")).unwrap();
+    write(dir.join("Cargo.toml"), body).unwrap();
+    write(dir.join("src/lib.rs"), "").unwrap();
+}
+
+fn package(dir: &Path, name: &str) {
+    manifest(
+        dir,
+        &format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name),
+    );
+}
 
 #[test]
 fn test_single() {
@@ -19,6 +38,20 @@ fn test_long_root() {
     assert_snapshot!(out);
 }
 
+#[test]
+fn test_long_root_package() {
+    let out = utils::run_out("../fixtures/root_package", &["ws", "ll"]);
+    assert_eq!(out, "dep1         v0.1.0 dep1\nroot_package v0.1.0 .\n");
+}
+
+#[test]
+fn test_json_root_package() {
+    let out = utils::run_out("../fixtures/root_package", &["ws", "list", "--json"]);
+    let root = std::fs::canonicalize("../fixtures/root_package").unwrap();
+
+    assert!(out.contains(&format!(r#""location": "{}""#, root.display())));
+}
+
 #[test]
 fn test_all() {
     let out = utils::run_out("../fixtures/private", &["ws", "la"]);
@@ -62,3 +95,291 @@ fn test_json_conflicts_with_long() {
     let err = utils::run_err("../fixtures/private", &["ws", "list", "--long", "--json"]);
     assert_snapshot!(err);
 }
+
+#[test]
+fn test_csv() {
+    let out = utils::run_out("../fixtures/normal", &["ws", "list", "--csv"]);
+
+    assert!(out.starts_with("name,version,path,edition,publish,description,license,authors\r\n"));
+    assert!(out.contains("dep1,0.1.0,dep1,2018,true,,,Pavan Kumar Sunkara"));
+    assert!(out.ends_with("\r\n"));
+}
+
+#[test]
+fn test_csv_columns() {
+    let out = utils::run_out(
+        "../fixtures/private",
+        &["ws", "list", "-a", "--csv", "--csv-columns=name,publish"],
+    );
+
+    assert!(out.starts_with("name,publish\r\n"));
+    assert!(out.contains("simple,true\r\n"));
+    assert!(out.contains("private,false\r\n"));
+}
+
+#[test]
+fn test_dependents_of() {
+    let out = utils::run_out(
+        "../fixtures/normal",
+        &["ws", "list", "--dependents-of", "dep1"],
+    );
+    assert_eq!(
+        out,
+        "top  normal (depth 1, via dep1)\ndep2 normal (depth 1, via dep1)\n"
+    );
+}
+
+#[test]
+fn test_dependents_of_diamond() {
+    let dep = |deps: &[&str]| {
+        let deps = deps
+            .iter()
+            .map(|x| format!("{} = {{ path = \"../{}\" }}\n", x, x))
+            .collect::<String>();
+
+        format!("\n[dependencies]\n{}", deps)
+    };
+    let dir = utils::workspace(
+        &[
+            ("a", ""),
+            ("b", &dep(&["a"])),
+            ("c", &dep(&["a"])),
+            ("d", &dep(&["b", "c"])),
+        ],
+        "",
+    );
+
+    let out = utils::run_out(
+        dir.path().to_str().unwrap(),
+        &["ws", "list", "--dependents-of", "a", "--json"],
+    );
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+
+    // d is reached through both b and c
+    assert_eq!(
+        json["adjacency"],
+        serde_json::json!({ "a": ["b", "c"], "b": ["d"], "c": ["d"], "d": [] })
+    );
+}
+
+#[test]
+fn test_dependents_of_unknown() {
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "list", "--dependents-of", "dpe1"],
+    );
+    assert!(err.contains("(did you mean dep1?)"));
+}
+
+#[test]
+fn test_features() {
+    let out = utils::run_out("../fixtures/publish", &["ws", "list", "--features"]);
+    assert_eq!(out, "alpha default, std\nbeta\n");
+}
+
+#[test]
+fn test_features_json() {
+    let out = utils::run_out(
+        "../fixtures/publish",
+        &["ws", "list", "--features", "--json"],
+    );
+
+    assert!(out.contains(r#""name": "alpha""#));
+    assert!(out.contains(r#""std": []"#));
+    assert!(out.contains(r#""features": {}"#));
+}
+
+#[test]
+fn test_orphans() {
+    let out = utils::run_out("../fixtures/normal", &["ws", "list", "--orphans"]);
+    assert!(out.starts_with("top binary, publishable, last touched "));
+    assert_eq!(out.lines().count(), 1);
+
+    let out = utils::run_out("../fixtures/normal", &["ws", "list", "--orphans", "--json"]);
+    assert!(out.contains(r#""binary": true"#));
+    assert!(out.contains(r#""last_commit_date": "#));
+}
+
+#[test]
+fn test_orphans_kept() {
+    let dir = utils::workspace(
+        &[
+            (
+                "alpha",
+                "\n[dependencies]\ndelta = { path = \"../delta\" }\n",
+            ),
+            (
+                "beta",
+                "\n[package.metadata.workspaces]\nentry_point = true\n",
+            ),
+            ("delta", ""),
+            ("gamma", ""),
+        ],
+        "default-members = [\"gamma\"]\n",
+    );
+
+    // The entry point and the default member are not orphans, nor is a dependency
+    let out = utils::run_out(
+        dir.path().to_str().unwrap(),
+        &["ws", "list", "--orphans", "--json"],
+    );
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    let names = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| x["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["alpha"]);
+}
+
+#[test]
+fn test_count() {
+    let out = utils::run_out("../fixtures/private", &["ws", "list", "--count"]);
+    assert_eq!(out, "1\n");
+
+    let out = utils::run_out("../fixtures/private", &["ws", "list", "-a", "--count"]);
+    assert_eq!(out, "2\n");
+
+    let out = utils::run_out(
+        "../fixtures/normal",
+        &["ws", "list", "--dependents-of", "dep1", "--count"],
+    );
+    assert_eq!(out, "2\n");
+}
+
+#[test]
+fn test_unreferenced() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"a\"]\nexclude = [\"examples\"]\n",
+    )
+    .unwrap();
+    package(&root.join("a"), "a");
+    package(&root.join("stray"), "stray-crate");
+    package(&root.join("examples/ex"), "ex");
+    package(&root.join("target/package/a"), "a");
+    package(&root.join("ignored"), "ignored");
+    manifest(&root.join("nested"), "[workspace]\nmembers = [\"b\"]\n");
+    package(&root.join("nested/b"), "b");
+    manifest(
+        &root.join("other"),
+        "[package]\nname = \"other\"\nversion = \"0.1.0\"\nworkspace = \"../nested\"\n",
+    );
+
+    write(root.join(".gitignore"), "/ignored\n").unwrap();
+    let status = Command::new("git")
+        .current_dir(root)
+        .args(["init", "-q"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let root_str = root.to_str().unwrap();
+
+    let out = utils::run_out(root_str, &["ws", "list", "--unreferenced"]);
+    assert_eq!(out, "stray-crate stray\n");
+
+    let out = utils::run_out(root_str, &["ws", "list", "--unreferenced", "--count"]);
+    assert_eq!(out, "1\n");
+
+    let (out, err) = utils::run(root_str, &["ws", "list", "--unreferenced", "--fix"]);
+    assert_eq!(out, "stray-crate stray\n");
+    assert!(err.contains("added stray to workspace.members"));
+    assert_eq!(
+        read_to_string(root.join("Cargo.toml")).unwrap(),
+        "[workspace]\nmembers = [\n    \"a\",\n    \"stray\",\n]\nexclude = [\"examples\"]\n"
+    );
+
+    let out = utils::run_out(root_str, &["ws", "list", "--unreferenced"]);
+    assert_eq!(out, "");
+}
+
+#[test]
+fn test_unreferenced_fix_exclude() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"a\"]\n").unwrap();
+    package(&root.join("a"), "a");
+    package(&root.join("fixtures/b"), "b");
+    package(&root.join("fixtures/c"), "c");
+
+    let (out, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "list", "--unreferenced", "--fix=exclude"],
+    );
+    assert_eq!(out, "b fixtures/b\nc fixtures/c\n");
+    assert!(err.contains("added fixtures/b, fixtures/c to workspace.exclude"));
+    assert_eq!(
+        read_to_string(root.join("Cargo.toml")).unwrap(),
+        "[workspace]\nmembers = [\"a\"]\nexclude = [\n    \"fixtures/b\",\n    \"fixtures/c\",\n]\n"
+    );
+}
+
+#[test]
+fn test_check_names_requires_owner() {
+    let err = utils::run_err("../fixtures/single", &["ws", "list", "--check-names"]);
+    assert!(err.contains("--owner <LOGIN>"));
+}
+
+#[test]
+fn test_check_names_offline() {
+    let err = utils::run_err(
+        "../fixtures/single",
+        &[
+            "ws",
+            "--offline",
+            "list",
+            "--check-names",
+            "--owner",
+            "alice",
+        ],
+    );
+    assert!(err.contains("unable to check the names on crates.io in offline mode"));
+}
+
+#[test]
+fn test_last_commit() {
+    let dir = utils::workspace(&[("alpha", ""), ("beta", "")], "");
+    let root = dir.path();
+
+    write(root.join("beta/src/lib.rs"), "pub fn b() {}\n").unwrap();
+    utils::git(root, &["add", "."]);
+    utils::git(
+        root,
+        &[
+            "commit",
+            "-q",
+            "-m",
+            "edit",
+            "--author",
+            "Jane <jane@example.com>",
+        ],
+    );
+
+    let init = utils::git(root, &["rev-parse", "HEAD~1"]);
+    let edit = utils::git(root, &["rev-parse", "HEAD"]);
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "list", "--last-commit"]);
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("alpha"));
+    assert!(lines[0].contains(&init[..7]));
+    assert!(lines[0].ends_with(" test"));
+    assert!(lines[1].starts_with("beta"));
+    assert!(lines[1].contains(&edit[..7]));
+    assert!(lines[1].ends_with(" Jane"));
+
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "list", "--last-commit", "--json"],
+    );
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json[0]["last_commit_sha"], init.as_str());
+    assert_eq!(json[1]["last_commit_sha"], edit.as_str());
+    assert_eq!(json[1]["last_commit_author"], "Jane");
+}
@@ -0,0 +1,49 @@
+mod utils;
+
+#[test]
+fn test_path() {
+    let out = utils::run_out("../fixtures/normal", &["ws", "path", "dep1"]);
+    let path = std::path::Path::new(out.trim_end());
+
+    assert!(path.is_absolute());
+    assert!(path.ends_with("normal/dep1"));
+}
+
+#[test]
+fn test_path_relative() {
+    let out = utils::run_out(
+        "../fixtures/normal/dep1",
+        &["ws", "path", "--relative", "dep2"],
+    );
+    assert_eq!(out, "dep2\n");
+}
+
+#[test]
+fn test_path_unknown() {
+    let err = utils::run_err("../fixtures/normal", &["ws", "path", "dpe1"]);
+    assert!(err.contains("dpe1 is not a workspace member (did you mean dep1?)"));
+}
+
+#[test]
+fn test_path_relative_root_package() {
+    let out = utils::run_out(
+        "../fixtures/root_package/dep1",
+        &["ws", "path", "--relative", "root_package"],
+    );
+    assert_eq!(out, ".\n");
+}
+
+#[test]
+fn test_path_root_package() {
+    let out = utils::run_out(
+        "../fixtures/root_package/dep1",
+        &["ws", "path", "root_package"],
+    );
+    let path = std::path::Path::new(out.trim_end());
+
+    assert!(!out.trim_end().ends_with(std::path::MAIN_SEPARATOR));
+    assert_eq!(
+        path,
+        std::fs::canonicalize("../fixtures/root_package").unwrap()
+    );
+}
@@ -0,0 +1,640 @@
+mod utils;
+
+use insta::assert_snapshot;
+use std::{fs, path::Path};
+
+#[test]
+fn test_verify_documentation() {
+    let dir = tempfile::tempdir().unwrap();
+    let ws = dir.path().join("ws");
+
+    utils::copy_dir(Path::new("../fixtures/publish"), &ws);
+
+    // A broken intra doc link is only a warning, which is denied
+    fs::write(
+        ws.join("beta/src/lib.rs"),
+        "/// See [`Missing`]\npub fn b() {}\n",
+    )
+    .unwrap();
+
+    let (_, err) = utils::run(
+        ws.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "--publish-as-is",
+            "--dry-run",
+            "--verify-documentation",
+        ],
+    );
+
+    assert!(err.contains("info documenting alpha"));
+    assert!(err.contains("documentation failed to build"));
+    assert!(err.contains(" - beta: exit code 101, see "));
+    assert!(!err.contains(" - alpha:"));
+    assert!(!err.contains("info checking"));
+}
+
+#[test]
+fn test_verify_documentation_before_versioning() {
+    let dir = utils::workspace(&[("alpha", "")], "");
+    let root = dir.path();
+
+    fs::write(
+        root.join("alpha/src/lib.rs"),
+        "/// See [`Missing`]\npub fn a() {}\n",
+    )
+    .unwrap();
+    utils::commit(root, "docs");
+
+    let err = utils::run_err(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "patch",
+            "-y",
+            "--no-git-push",
+            "--allow-incomplete-metadata",
+            "--verify-documentation",
+        ],
+    );
+
+    assert!(err.contains(" - alpha: exit code 101, see "));
+
+    // Nothing was versioned
+    let manifest = fs::read_to_string(root.join("alpha/Cargo.toml")).unwrap();
+    assert!(manifest.contains("version = \"0.1.0\""));
+    assert_eq!(utils::git(root, &["tag", "--list"]), "v0.1.0");
+}
+
+#[test]
+fn test_packaged_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let ws = dir.path().join("ws");
+
+    utils::copy_dir(Path::new("../fixtures/packaged_files"), &ws);
+
+    let (_, err) = utils::run(
+        ws.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "--publish-as-is",
+            "--dry-run",
+            "--no-verify",
+        ],
+    );
+
+    // The readme and license outside of alpha are packaged by cargo
+    assert!(!err.contains("alpha:"));
+    assert!(err.contains("warn not packaged beta: readme README.md does not exist"));
+    assert!(err.contains(
+        "warn not packaged gamma: LICENSE-MIT only at the workspace root, without a license-file entry"
+    ));
+}
+
+#[test]
+fn test_fix_packaged_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let ws = dir.path().join("ws");
+
+    utils::copy_dir(Path::new("../fixtures/packaged_files"), &ws);
+
+    let manifests = ["Cargo.toml", "alpha/Cargo.toml", "beta/Cargo.toml"]
+        .map(|x| fs::read_to_string(ws.join(x)).unwrap());
+
+    let err = utils::run_err(
+        ws.to_str().unwrap(),
+        &["ws", "publish", "--fix-packaged-files"],
+    );
+
+    assert!(err.contains("warn not packaged beta: readme README.md does not exist"));
+    assert!(err.contains(
+        "info fixed beta: LICENSE-MIT only at the workspace root, without a license-file entry"
+    ));
+    assert!(err.contains(
+        "info fixed gamma: LICENSE-MIT only at the workspace root, without a license-file entry"
+    ));
+    assert!(err.contains("warn fixed files are not committed"));
+
+    assert!(ws.join("beta/LICENSE-MIT").exists());
+    assert!(ws.join("gamma/LICENSE-MIT").exists());
+    assert!(!ws.join("alpha/LICENSE-MIT").exists());
+
+    // No manifest is rewritten
+    for (path, manifest) in ["Cargo.toml", "alpha/Cargo.toml", "beta/Cargo.toml"]
+        .iter()
+        .zip(manifests)
+    {
+        assert_eq!(fs::read_to_string(ws.join(path)).unwrap(), manifest);
+    }
+}
+
+#[test]
+fn test_root_package_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let ws = dir.path().join("ws");
+    let registry = utils::Registry::start();
+
+    utils::copy_dir(Path::new("../fixtures/root_package"), &ws);
+
+    registry.configure(&ws);
+
+    let (_, err) = utils::run(
+        ws.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "--publish-as-is",
+            "--dry-run",
+            "--no-verify",
+            "--registry",
+            "local",
+        ],
+    );
+
+    // The root package depends on the member, so it goes last
+    let dep1 = err.find("info checking dep1").unwrap();
+    let root = err.find("info checking root_package").unwrap();
+    assert!(dep1 < root);
+}
+
+#[test]
+fn test_dependency_not_published() {
+    let dir = tempfile::tempdir().unwrap();
+    let ws = dir.path().join("ws");
+    let registry = utils::Registry::start();
+
+    utils::copy_dir(Path::new("../fixtures/publish"), &ws);
+
+    // Only an older version of the crate beta depends on is on the registry, the matching
+    // one was yanked
+    registry.add("alpha", "0.0.9", false);
+    registry.add("alpha", "0.1.0", true);
+
+    let alpha = ws.join("alpha/Cargo.toml");
+    let manifest = fs::read_to_string(&alpha).unwrap();
+    fs::write(
+        &alpha,
+        format!(
+            "{}\n[package.metadata.workspaces]\nskip_publish = true\n",
+            manifest
+        ),
+    )
+    .unwrap();
+
+    registry.configure(&ws);
+
+    let err = utils::run_err(
+        ws.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "--publish-as-is",
+            "--no-verify",
+            "--registry",
+            "local",
+        ],
+    );
+
+    assert!(err.contains(
+        "beta depends on alpha >=0.1.0, <0.2.0, which has no matching version on the registry. Publish alpha first"
+    ));
+    assert!(!err.contains("Packaging"));
+}
+
+const META: &str = "license = \"MIT\"\ndescription = \"a\"\nrepository = \"https://example.com\"\n";
+
+fn registry_workspace(members: &[(&str, &str)]) -> (tempfile::TempDir, utils::Registry) {
+    let registry = utils::Registry::start();
+    let dir = utils::workspace(members, "");
+
+    registry.configure(dir.path());
+    utils::commit(dir.path(), "registry");
+
+    (dir, registry)
+}
+
+#[test]
+fn test_local_registry() {
+    let beta = format!(
+        "{}\n[dependencies]\nalpha = {{ version = \"0.1.0\", path = \"../alpha\", registry = \"local\" }}\n",
+        META
+    );
+    let (dir, registry) = registry_workspace(&[("alpha", META), ("beta", &beta)]);
+    let root = dir.path().to_str().unwrap();
+    let args = [
+        "ws",
+        "publish",
+        "--publish-as-is",
+        "--no-verify",
+        "--registry",
+        "local",
+        "--token",
+        "x",
+    ];
+
+    // Like cargo, which clones the index, only the committed entries count
+    fs::create_dir_all(registry.index().join("al/ph")).unwrap();
+    fs::write(
+        registry.index().join("al/ph/alpha"),
+        "{\"name\":\"alpha\",\"vers\":\"0.1.0\",\"deps\":[],\"cksum\":\"\",\"features\":{},\"yanked\":false}\n",
+    )
+    .unwrap();
+
+    let (_, err) = utils::run(root, &[&args[..], &["--dry-run"]].concat());
+    assert!(err.contains("info checking alpha"));
+    assert!(!err.contains("already published alpha"));
+
+    fs::remove_dir_all(registry.index().join("al")).unwrap();
+
+    let (_, err) = utils::run(root, &args);
+    assert!(err.contains("info published alpha v0.1.0"));
+    assert!(err.contains("info published beta v0.1.0"));
+    assert_eq!(registry.versions("alpha"), ["0.1.0"]);
+    assert_eq!(registry.versions("beta"), ["0.1.0"]);
+
+    let (_, err) = utils::run(root, &args);
+    assert!(err.contains("info already published alpha v0.1.0"));
+    assert!(err.contains("info already published beta v0.1.0"));
+}
+
+/// Two crates depending on each other, at a version which is not published yet. Cargo
+/// resolves even the optional dependencies when packaging, so older versions are.
+fn cycle_workspace(optional: bool) -> (tempfile::TempDir, utils::Registry) {
+    let (dir, registry) = registry_workspace(&[]);
+    let root = dir.path();
+    let member = |dep: &str, optional: bool| {
+        format!(
+            "{}\n[dependencies]\n{} = {{ version = \"0.1.0\", path = \"../{}\", registry = \"local\", optional = {} }}\n",
+            META, dep, dep, optional
+        )
+    };
+
+    fs::write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"a\", \"b\"]\n",
+    )
+    .unwrap();
+    let mut a = member("b", optional);
+
+    // Verifying a would download b, which the registry only has the index entry of
+    if optional {
+        a.push_str("\n[features]\ndefault = [\"b\"]\n");
+    }
+
+    utils::member_version(root, "a", "0.1.1", &a);
+    utils::member_version(root, "b", "0.1.1", &member("a", false));
+    utils::commit(root, "cycle");
+
+    registry.add("a", "0.1.0", false);
+    registry.add("b", "0.1.0", false);
+
+    (dir, registry)
+}
+
+const CYCLE_ARGS: [&str; 7] = [
+    "ws",
+    "publish",
+    "--publish-as-is",
+    "--registry",
+    "local",
+    "--token",
+    "x",
+];
+
+#[test]
+fn test_optional_cycle() {
+    let (dir, registry) = cycle_workspace(true);
+
+    // a goes first, without waiting for b nor being verified
+    let (_, err) = utils::run(dir.path().to_str().unwrap(), &CYCLE_ARGS);
+    assert!(err.contains("warn ignoring dependency a -> b to break a cycle (optional)"));
+    assert!(err.contains("info published a v0.1.1"));
+    assert!(err.contains("info published b v0.1.1"));
+    assert_eq!(registry.versions("a"), ["0.1.0", "0.1.1"]);
+    assert_eq!(registry.versions("b"), ["0.1.0", "0.1.1"]);
+}
+
+#[test]
+fn test_dependency_cycle() {
+    let (dir, registry) = cycle_workspace(false);
+    let root = dir.path();
+
+    let err = utils::run_err(root.to_str().unwrap(), &CYCLE_ARGS);
+    assert!(err.contains("dependency cycle a -> b -> a\n"));
+    assert!(err.contains(&format!(
+        " - b depends on a in [dependencies] of {}",
+        root.join("b/Cargo.toml").display()
+    )));
+    assert!(err.contains("`--allow-cycle a,b`"));
+    assert_eq!(registry.versions("a"), ["0.1.0"]);
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &[&CYCLE_ARGS[..], &["--allow-cycle", "a,b"]].concat(),
+    );
+    assert!(err.contains("warn ignoring dependency b -> a to break a cycle (allowed cycle)"));
+    assert!(err.contains("info published a v0.1.1"));
+    assert!(err.contains("info published b v0.1.1"));
+    assert_eq!(registry.versions("b"), ["0.1.0", "0.1.1"]);
+}
+
+#[test]
+fn test_release_manifest() {
+    let (dir, registry) = registry_workspace(&[("alpha", META)]);
+    let root = dir.path();
+    let manifest = root.join("release-manifest.json");
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "--publish-as-is",
+            "--no-verify",
+            "--registry",
+            "local",
+            "--token",
+            "x",
+        ],
+    );
+    assert!(err.contains("info wrote release manifest release-manifest.json"));
+
+    let mut json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest).unwrap()).unwrap();
+    assert_eq!(json["sha"], utils::git(root, &["rev-parse", "HEAD"]));
+    assert_eq!(json["crates"][0]["name"], "alpha");
+    assert_eq!(json["crates"][0]["version"], "0.1.0");
+    assert_eq!(json["crates"][0]["registry"], "local");
+    assert_eq!(json["crates"][0]["checksum"].as_str().unwrap().len(), 64);
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &["ws", "verify-release"]);
+    assert!(err.contains("info verified alpha v0.1.0"));
+    assert!(err.contains("info success ok"));
+
+    // Without a checksum on one side, the crate is downloaded from the registry
+    registry.add("gamma", "0.1.0", false);
+    fs::write(registry.crates().join("gamma-0.1.0.crate"), "gamma").unwrap();
+    let gamma = "be9d587defa1f0c09ef49eb17e206983a5f8f8289e4281860bd0ee5a19592c67";
+
+    json["crates"][0]["checksum"] = serde_json::Value::Null;
+    json["crates"].as_array_mut().unwrap().extend([
+        serde_json::json!({ "name": "beta", "version": "0.1.0", "registry": "local", "checksum": null }),
+        serde_json::json!({ "name": "gamma", "version": "0.1.0", "registry": "local", "checksum": gamma }),
+    ]);
+    fs::write(&manifest, json.to_string()).unwrap();
+
+    let err = utils::run_err(root.to_str().unwrap(), &["ws", "verify-release"]);
+    assert!(err.contains("info verified alpha v0.1.0"));
+    assert!(err.contains(" - beta v0.1.0 is not published to local"));
+    assert!(err.contains("info verified gamma v0.1.0"));
+
+    json["crates"][0]["checksum"] = "0".repeat(64).into();
+    json["crates"][2]["checksum"] = "abc".into();
+    fs::write(&manifest, json.to_string()).unwrap();
+
+    let err = utils::run_err(root.to_str().unwrap(), &["ws", "verify-release"]);
+    assert!(err.contains(&format!(
+        " - alpha v0.1.0: checksum {} does not match ",
+        "0".repeat(64)
+    )));
+    assert!(err.contains(&format!(
+        " - gamma v0.1.0: checksum abc does not match {} downloaded from local",
+        gamma
+    )));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_strict_hook_release_manifest() {
+    let (dir, registry) = registry_workspace(&[("alpha", META)]);
+    let root = dir.path();
+    let cargo_toml = fs::read_to_string(root.join("Cargo.toml")).unwrap();
+
+    fs::write(
+        root.join("Cargo.toml"),
+        format!(
+            "{}\n[workspace.metadata.workspaces]\nrelease_manifest = \"released.json\"\n",
+            cargo_toml
+        ),
+    )
+    .unwrap();
+    utils::commit(root, "config");
+
+    let err = utils::run_err(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "--publish-as-is",
+            "--no-verify",
+            "--registry",
+            "local",
+            "--token",
+            "x",
+            "--post-publish-hook",
+            "exit 1",
+            "--strict-hooks",
+        ],
+    );
+    assert!(err.contains("post-publish hook failed for alpha v0.1.0"));
+    assert_eq!(registry.versions("alpha"), ["0.1.0"]);
+
+    // The crate is out, so it is in the manifest even though the release failed
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(root.join("released.json")).unwrap()).unwrap();
+    assert_eq!(json["crates"][0]["name"], "alpha");
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &["ws", "verify-release"]);
+    assert!(err.contains("info verified alpha v0.1.0"));
+}
+
+#[test]
+fn test_bump_patch_on_conflict() {
+    let (dir, registry) = registry_workspace(&[("alpha", META)]);
+    let root = dir.path();
+
+    // Somebody else already released the next version
+    registry.add("alpha", "0.1.1", false);
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "patch",
+            "--force",
+            "*",
+            "-y",
+            "--no-git-push",
+            "--no-verify",
+            "--bump-patch-on-conflict",
+            "--registry",
+            "local",
+            "--token",
+            "x",
+        ],
+    );
+
+    assert!(err.contains("info bumped conflicting version alpha v0.1.1 => 0.1.2"));
+    assert_eq!(registry.versions("alpha"), ["0.1.1", "0.1.2"]);
+}
+
+#[test]
+fn test_bump_patch_on_conflict_as_is() {
+    let (dir, registry) = registry_workspace(&[("alpha", META), ("beta", META)]);
+    let root = dir.path();
+
+    // An interrupted run which published the first crate
+    registry.add("alpha", "0.1.0", false);
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "--publish-as-is",
+            "--no-verify",
+            "--bump-patch-on-conflict",
+            "--registry",
+            "local",
+            "--token",
+            "x",
+        ],
+    );
+
+    assert!(err.contains("info already published alpha v0.1.0"));
+    assert!(err.contains("info published beta v0.1.0"));
+    assert_eq!(registry.versions("alpha"), ["0.1.0"]);
+    assert_eq!(registry.versions("beta"), ["0.1.0"]);
+}
+
+#[test]
+fn test_incomplete_metadata() {
+    let (dir, _registry) = registry_workspace(&[
+        ("alpha", "description = \"a\"\n"),
+        ("beta", "publish = [\"local\"]\n"),
+    ]);
+    let root = dir.path().to_str().unwrap();
+    let args = [
+        "ws",
+        "publish",
+        "--publish-as-is",
+        "--dry-run",
+        "--no-verify",
+    ];
+
+    // crates.io requires the metadata, unlike other registries
+    let (_, err) = utils::run(root, &args);
+    assert!(err.contains(
+        "error: crates are missing metadata required for publishing\n - alpha: license, repository or homepage\n"
+    ));
+    assert!(!err.contains(" - beta:"));
+
+    let (_, err) = utils::run(root, &[&args[..], &["--registry", "local"]].concat());
+    assert!(!err.contains("missing metadata"));
+    assert!(err.contains("info checking alpha"));
+}
+
+#[test]
+fn test_no_private() {
+    let dir = tempfile::tempdir().unwrap();
+    let ws = dir.path().join("ws");
+    let registry = utils::Registry::start();
+
+    utils::copy_dir(Path::new("../fixtures/private"), &ws);
+    registry.configure(&ws);
+
+    let (_, err) = utils::run(
+        ws.to_str().unwrap(),
+        &[
+            "ws",
+            "publish",
+            "--publish-as-is",
+            "--dry-run",
+            "--no-verify",
+            "--no-private",
+            "--registry",
+            "local",
+        ],
+    );
+
+    // Only the lines logged by cargo-workspaces, not by cargo
+    let err = err
+        .lines()
+        .filter(|x| x.starts_with("info ") || x.starts_with("warn "))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    assert_snapshot!(err);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_metadata_loaded_once() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let beta = format!(
+        "{}\n[dependencies]\nalpha = {{ version = \"0.1.0\", path = \"../alpha\", registry = \"local\" }}\n",
+        META
+    );
+    let (dir, _registry) = registry_workspace(&[("alpha", META), ("beta", &beta)]);
+    let root = dir.path();
+    fs::write(root.join("alpha/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    utils::commit(root, "change alpha");
+
+    // A cargo on the path recording the subcommands it runs before handing over
+    let bin = tempfile::tempdir().unwrap();
+    let log = bin.path().join("log");
+    let wrapper = bin.path().join("cargo");
+    fs::write(
+        &wrapper,
+        format!(
+            "#!/bin/sh\necho \"$1\" >> '{}'\nexec '{}' \"$@\"\n",
+            log.display(),
+            env!("CARGO")
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = assert_cmd::Command::cargo_bin("cargo-ws")
+        .unwrap()
+        .current_dir(root)
+        .args([
+            "ws",
+            "publish",
+            "patch",
+            "-y",
+            "--no-git-push",
+            "--no-verify",
+            "--registry",
+            "local",
+            "--token",
+            "x",
+        ])
+        .env("CARGO", &wrapper)
+        .env(
+            "PATH",
+            format!(
+                "{}:{}",
+                bin.path().display(),
+                std::env::var("PATH").unwrap()
+            ),
+        )
+        .output()
+        .unwrap();
+    let err = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", err);
+    assert!(err.contains("info published alpha v0.1.1"), "{}", err);
+
+    let ran = fs::read_to_string(&log).unwrap();
+    assert_eq!(
+        ran.lines().filter(|x| *x == "metadata").count(),
+        1,
+        "{}",
+        ran
+    );
+}
@@ -0,0 +1,49 @@
+mod utils;
+use assert_cmd::Command;
+use std::fs;
+use utils::{member_version, workspace};
+
+/// Runs `readme-index --check` and returns whether it succeeded along with its stderr
+fn check(root: &str) -> (bool, String) {
+    let output = Command::cargo_bin("cargo-ws")
+        .unwrap()
+        .current_dir(root)
+        .args(["ws", "readme-index", "--check"])
+        .output()
+        .unwrap();
+
+    (
+        output.status.success(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_check() {
+    let dir = workspace(&[("dep1", "description = \"First\"\n")], "");
+    let root = dir.path();
+    let ws = root.to_str().unwrap();
+    fs::write(root.join("README.md"), "# Workspace\n").unwrap();
+
+    let (ok, err) = check(ws);
+    assert!(!ok);
+    assert!(err.contains("README index is out of date in "));
+    assert_eq!(
+        fs::read_to_string(root.join("README.md")).unwrap(),
+        "# Workspace\n"
+    );
+
+    let err = utils::run_err(ws, &["ws", "readme-index"]);
+    assert!(err.contains("info updated "));
+
+    let (ok, err) = check(ws);
+    assert!(ok);
+    assert!(err.contains("info up to date "));
+
+    // A new version makes the index stale again
+    member_version(root, "dep1", "0.2.0", "description = \"First\"\n");
+
+    let (ok, err) = check(ws);
+    assert!(!ok);
+    assert!(err.contains("README index is out of date in "));
+}
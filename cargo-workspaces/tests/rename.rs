@@ -0,0 +1,70 @@
+mod utils;
+use std::fs::{read_to_string, write};
+use utils::workspace;
+
+#[test]
+fn test_mapping_file() {
+    let dir = workspace(
+        &[
+            ("acme-core", "publish = false\n"),
+            (
+                "acme-cli",
+                "\n[dependencies]\nacme-core = { path = \"../acme-core\" }\n",
+            ),
+            ("other", ""),
+        ],
+        "",
+    );
+    let root = dir.path();
+    let mapping = root.join("mapping.toml");
+
+    write(&mapping, "acme-core = \"corp core\"\n").unwrap();
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "rename", "--mapping-file", "mapping.toml"],
+    );
+    assert!(err.contains("invalid crate name corp core"));
+
+    write(&mapping, "acme-core = \"corp\"\nacme-cli = \"corp\"\n").unwrap();
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "rename", "--mapping-file", "mapping.toml"],
+    );
+    assert!(err.contains("crates acme-cli, acme-core would all be named corp"));
+
+    // A member which is not renamed keeps its name
+    write(&mapping, "acme-core = \"other\"\n").unwrap();
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "rename", "--mapping-file", "mapping.toml"],
+    );
+    assert!(err.contains("crates acme-core, other would all be named other"));
+
+    // Nothing was written by the failed renames
+    assert!(read_to_string(root.join("acme-core/Cargo.toml"))
+        .unwrap()
+        .contains("name = \"acme-core\""));
+
+    // Private crates are renamed as well
+    write(
+        &mapping,
+        "acme-core = \"corp-core\"\nacme-cli = \"corp-cli\"\n",
+    )
+    .unwrap();
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "rename", "--all", "--mapping-file", "mapping.toml"],
+    );
+    assert_eq!(err, "");
+
+    assert!(read_to_string(root.join("acme-core/Cargo.toml"))
+        .unwrap()
+        .contains("name = \"corp-core\""));
+
+    let cli = read_to_string(root.join("acme-cli/Cargo.toml")).unwrap();
+    assert!(cli.contains("name = \"corp-cli\""));
+    assert!(cli.contains("acme-core = { path = \"../acme-core\", package = \"corp-core\" }"));
+    assert!(read_to_string(root.join("other/Cargo.toml"))
+        .unwrap()
+        .contains("name = \"other\""));
+}
@@ -0,0 +1,13 @@
+mod utils;
+
+#[test]
+fn test_root() {
+    let out = utils::run_out("../fixtures/normal/dep1", &["ws", "root"]);
+    assert!(out.trim_end().ends_with("normal"));
+}
+
+#[test]
+fn test_root_workspace_root() {
+    let out = utils::run_out("../fixtures", &["ws", "--workspace-root", "normal", "root"]);
+    assert!(out.trim_end().ends_with("normal"));
+}
@@ -0,0 +1,117 @@
+mod utils;
+use std::fs::write;
+use utils::{commit, git, member_version, workspace};
+
+#[test]
+fn test_backfill() {
+    let dir = workspace(&[("dep1", ""), ("dep2", "")], "");
+    let root = dir.path();
+    let ws = root.to_str().unwrap();
+
+    member_version(root, "dep1", "0.2.0", "");
+    let bump = commit(root, "bump dep1");
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit dep1");
+
+    // A reverted release makes the current version of dep2 appear twice
+    member_version(root, "dep2", "0.2.0", "");
+    commit(root, "bump dep2");
+    member_version(root, "dep2", "0.1.0", "");
+    commit(root, "revert dep2");
+
+    let err = utils::run_err(ws, &["ws", "--dry-run", "tag", "--backfill"]);
+    assert!(err.contains(&format!("info would tag dep1@0.2.0 at {}", &bump[..7])));
+    assert!(err.contains("warn ambiguous"));
+    assert!(err.contains(" - dep2@0.1.0: "));
+    assert_eq!(git(root, &["tag", "--list"]), "v0.1.0");
+
+    utils::run_err(ws, &["ws", "tag", "--backfill"]);
+    assert_eq!(git(root, &["tag", "--list"]), "dep1@0.2.0\nv0.1.0");
+    assert_eq!(git(root, &["rev-list", "-n", "1", "dep1@0.2.0"]), bump);
+    assert_eq!(git(root, &["cat-file", "-t", "dep1@0.2.0"]), "tag");
+
+    let err = utils::run_err(ws, &["ws", "tag", "--backfill"]);
+    assert!(err.contains("info already tagged dep1@0.2.0"));
+
+    // The crates share the workspace tag with --no-individual-tags
+    let err = utils::run_err(
+        ws,
+        &[
+            "ws",
+            "--dry-run",
+            "tag",
+            "--backfill",
+            "--no-individual-tags",
+            "--tag-prefix",
+            "release-",
+        ],
+    );
+    assert!(err.contains(&format!("info would tag release-0.2.0 at {}", &bump[..7])));
+}
+
+#[test]
+fn test_from_registry() {
+    let dir = workspace(&[("dep1", "")], "");
+    let root = dir.path();
+    let ws = root.to_str().unwrap();
+    let registry = utils::Registry::start();
+    let first = git(root, &["rev-parse", "HEAD"]);
+
+    member_version(root, "dep1", "0.2.0", "");
+    let second = commit(root, "bump dep1");
+    member_version(root, "dep1", "0.3.0", "");
+    commit(root, "bump dep1 again");
+
+    // Only the published versions are tagged, not the current one
+    registry.add("dep1", "0.1.0", false);
+    registry.add("dep1", "0.2.0", false);
+    registry.configure(root);
+
+    let err = utils::run_err(
+        ws,
+        &[
+            "ws",
+            "tag",
+            "--backfill",
+            "--from-registry",
+            "--registry",
+            "local",
+        ],
+    );
+    assert!(err.contains(&format!("info tagged dep1@0.1.0 at {}", &first[..7])));
+    assert!(err.contains(&format!("info tagged dep1@0.2.0 at {}", &second[..7])));
+    assert_eq!(
+        git(root, &["tag", "--list"]),
+        "dep1@0.1.0\ndep1@0.2.0\nv0.1.0"
+    );
+}
+
+#[test]
+fn test_release_options_rejected() {
+    let dir = workspace(&[("dep1", "")], "");
+    let ws = dir.path().to_str().unwrap();
+
+    // Only the tag naming options apply, the release ones are refused instead of ignored
+    for arg in ["--push", "--no-git-tag", "--amend", "--no-git-commit"] {
+        let err = utils::run_err(ws, &["ws", "tag", "--backfill", arg]);
+        assert!(
+            err.contains(&format!("Found argument '{}'", arg)),
+            "{}",
+            err
+        );
+    }
+
+    let err = utils::run_err(
+        ws,
+        &[
+            "ws",
+            "tag",
+            "--backfill",
+            "--no-individual-tags",
+            "--individual-tag-prefix",
+            "x@",
+        ],
+    );
+    assert!(err.contains("cannot be used with"), "{}", err);
+    assert_eq!(git(dir.path(), &["tag", "--list"]), "v0.1.0");
+}
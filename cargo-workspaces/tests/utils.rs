@@ -1,6 +1,18 @@
 #![allow(dead_code)]
 use assert_cmd::Command;
-use std::str::from_utf8;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process,
+    str::from_utf8,
+    thread,
+};
+use tempfile::{tempdir, TempDir};
+use url::Url;
 
 pub fn run(dir: &str, args: &[&str]) -> (String, String) {
     let output = Command::cargo_bin("cargo-ws")
@@ -29,3 +41,302 @@ pub fn run_err(dir: &str, args: &[&str]) -> String {
     assert!(out.is_empty());
     err
 }
+
+pub fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let to = to.join(entry.file_name());
+
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &to);
+        } else {
+            fs::copy(entry.path(), to).unwrap();
+        }
+    }
+}
+
+/// Runs git in the directory, expecting it to succeed, and returns its trimmed output
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = process::Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    from_utf8(&output.stdout).unwrap().trim().to_string()
+}
+
+/// Commits everything in the directory and returns the hash of the commit
+pub fn commit(dir: &Path, msg: &str) -> String {
+    git(dir, &["add", "-A"]);
+    git(
+        dir,
+        &[
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-q",
+            "-m",
+            msg,
+        ],
+    );
+    git(dir, &["rev-parse", "HEAD"])
+}
+
+/// Writes a library crate with the version, followed by the rest of the manifest
+pub fn member_version(dir: &Path, name: &str, version: &str, rest: &str) {
+    fs::create_dir_all(dir.join(name).join("src")).unwrap();
+    fs::write(
+        dir.join(name).join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2021\"\n{}",
+            name, version, rest
+        ),
+    )
+    .unwrap();
+    fs::write(dir.join(name).join("src/lib.rs"), "").unwrap();
+}
+
+pub fn member(dir: &Path, name: &str, rest: &str) {
+    member_version(dir, name, "0.1.0", rest);
+}
+
+/// A git repository on `master` with the members at `0.1.0`, committed and tagged `v0.1.0`.
+/// The rest is appended to the workspace manifest.
+pub fn workspace(members: &[(&str, &str)], rest: &str) -> TempDir {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    let names = members
+        .iter()
+        .map(|(name, _)| format!("\"{}\"", name))
+        .collect::<Vec<_>>();
+
+    fs::write(
+        root.join("Cargo.toml"),
+        format!("[workspace]\nmembers = [{}]\n{}", names.join(", "), rest),
+    )
+    .unwrap();
+
+    for (name, rest) in members {
+        member(root, name, rest);
+    }
+
+    git(root, &["init", "-q", "-b", "master"]);
+    git(root, &["config", "user.name", "test"]);
+    git(root, &["config", "user.email", "test@example.com"]);
+    commit(root, "init");
+    git(root, &["tag", "v0.1.0"]);
+
+    dir
+}
+
+/// An alternative registry with a git index on disk, and the publish API of crates.io
+/// served on localhost
+pub struct Registry {
+    dir: TempDir,
+}
+
+impl Registry {
+    pub fn start() -> Self {
+        let dir = tempdir().unwrap();
+        let index = dir.path().join("index");
+        let crates = dir.path().join("crates");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        fs::create_dir_all(&index).unwrap();
+        fs::create_dir_all(&crates).unwrap();
+        fs::write(
+            index.join("config.json"),
+            json!({
+                "dl": format!("{}{{crate}}-{{version}}.crate", Url::from_directory_path(&crates).unwrap()),
+                "api": format!("http://{}", listener.local_addr().unwrap()),
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        git(&index, &["init", "-q"]);
+        commit(&index, "init");
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                serve(stream.unwrap(), &index, &crates);
+            }
+        });
+
+        Self { dir }
+    }
+
+    pub fn index(&self) -> PathBuf {
+        self.dir.path().join("index")
+    }
+
+    /// Where the `.crate` files are downloaded from
+    pub fn crates(&self) -> PathBuf {
+        self.dir.path().join("crates")
+    }
+
+    /// Makes the registry available as `local` to the workspace
+    pub fn configure(&self, ws: &Path) {
+        fs::create_dir_all(ws.join(".cargo")).unwrap();
+        fs::write(
+            ws.join(".cargo/config.toml"),
+            format!(
+                "[registries.local]\nindex = \"{}\"\n",
+                Url::from_directory_path(self.index()).unwrap()
+            ),
+        )
+        .unwrap();
+    }
+
+    /// Adds a version to the index without uploading it
+    pub fn add(&self, name: &str, version: &str, yanked: bool) {
+        let entry = json!({
+            "name": name,
+            "vers": version,
+            "deps": [],
+            "cksum": "",
+            "features": {},
+            "yanked": yanked,
+        });
+
+        append_entry(&self.index(), name, &entry);
+    }
+
+    /// The versions in the committed index
+    pub fn versions(&self, name: &str) -> Vec<String> {
+        let path = format!("HEAD:{}", index_path(name));
+        let output = process::Command::new("git")
+            .current_dir(self.index())
+            .args(["show", &path])
+            .output()
+            .unwrap();
+
+        from_utf8(&output.stdout)
+            .unwrap()
+            .lines()
+            .map(|x| {
+                serde_json::from_str::<Value>(x).unwrap()["vers"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect()
+    }
+}
+
+fn index_path(name: &str) -> String {
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+fn append_entry(index: &Path, name: &str, entry: &Value) {
+    let path = index.join(index_path(name));
+    let mut contents = fs::read_to_string(&path).unwrap_or_default();
+
+    contents.push_str(&format!("{}\n", entry));
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, contents).unwrap();
+
+    commit(index, &format!("{} {}", name, entry["vers"]));
+}
+
+/// Handles `cargo publish`, which sends the metadata and the `.crate` file in a single body
+fn serve(mut stream: TcpStream, index: &Path, crates: &Path) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request = String::new();
+    reader.read_line(&mut request).unwrap();
+
+    let mut length = 0;
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+
+        let line = line.trim().to_lowercase();
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(x) = line.strip_prefix("content-length:") {
+            length = x.trim().parse().unwrap();
+        } else if line == "expect: 100-continue" {
+            stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").unwrap();
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+
+    if !request.starts_with("PUT /api/v1/crates/new") {
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        return;
+    }
+
+    let read_u32 = |at: usize| u32::from_le_bytes(body[at..at + 4].try_into().unwrap()) as usize;
+    let json_len = read_u32(0);
+    let meta: Value = serde_json::from_slice(&body[4..4 + json_len]).unwrap();
+    let crate_len = read_u32(4 + json_len);
+    let krate = &body[8 + json_len..8 + json_len + crate_len];
+
+    let name = meta["name"].as_str().unwrap();
+    let version = meta["vers"].as_str().unwrap();
+
+    fs::write(crates.join(format!("{}-{}.crate", name, version)), krate).unwrap();
+
+    let deps = meta["deps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| {
+            let mut dep = json!({
+                "name": x["explicit_name_in_toml"].as_str().or(x["name"].as_str()),
+                "req": x["version_req"],
+                "features": x["features"],
+                "optional": x["optional"],
+                "default_features": x["default_features"],
+                "target": x["target"],
+                "kind": x["kind"],
+                "registry": x["registry"],
+            });
+
+            if x["explicit_name_in_toml"].is_string() {
+                dep["package"] = x["name"].clone();
+            }
+
+            dep
+        })
+        .collect::<Vec<_>>();
+
+    let cksum = Sha256::digest(krate)
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect::<String>();
+
+    let entry = json!({
+        "name": name,
+        "vers": version,
+        "deps": deps,
+        "cksum": cksum,
+        "features": meta["features"],
+        "yanked": false,
+    });
+
+    append_entry(index, name, &entry);
+
+    stream
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}")
+        .unwrap();
+}
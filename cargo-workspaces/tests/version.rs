@@ -0,0 +1,825 @@
+mod utils;
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    process::Command,
+};
+use tempfile::tempdir;
+use utils::{commit, git, member, workspace};
+
+#[test]
+fn test_root_package() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"dep1\"]\n\n[package]\nname = \"root\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    create_dir_all(root.join("src")).unwrap();
+    write(root.join("src/lib.rs"), "").unwrap();
+    member(root, "dep1", "");
+
+    git(root, &["init", "-q"]);
+    commit(root, "init");
+    git(root, &["tag", "v0.1.0"]);
+
+    // The files of the members do not belong to the root package
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (out, _) = utils::run(root.to_str().unwrap(), &["ws", "changed"]);
+    assert_eq!(out, "dep1\n");
+
+    utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "version",
+            "patch",
+            "-y",
+            "--no-git-commit",
+            "--include-root",
+        ],
+    );
+
+    let manifest = read_to_string(root.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("version = \"0.1.1\""));
+}
+
+#[test]
+fn test_snapshot() {
+    let dir = workspace(&[("dep1", ""), ("dep2", "")], "");
+    let root = dir.path();
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    // Snapshots do not need to be on an allowed branch
+    git(root, &["checkout", "-q", "--detach"]);
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "version", "minor", "-y", "--snapshot"],
+    );
+    assert!(err.contains("info snapshot not committing, tagging or pushing the versions"));
+
+    let manifest = read_to_string(root.join("dep1/Cargo.toml")).unwrap();
+    let version = manifest
+        .lines()
+        .find_map(|x| x.strip_prefix("version = \"0.2.0-dev."))
+        .unwrap()
+        .trim_end_matches('"');
+
+    assert_eq!(version.len(), 14);
+    assert!(version.starts_with("20"));
+    assert!(version.chars().all(|c| c.is_ascii_digit()));
+    assert!(read_to_string(root.join("dep2/Cargo.toml"))
+        .unwrap()
+        .contains("version = \"0.1.0\""));
+    assert_eq!(git(root, &["tag", "--list"]), "v0.1.0");
+}
+
+#[test]
+fn test_inherited_version() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"dep1\", \"dep2\"]\n\n[workspace.package]\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+
+    for name in ["dep1", "dep2"] {
+        create_dir_all(root.join(name).join("src")).unwrap();
+        write(
+            root.join(name).join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion.workspace = true\nedition = \"2021\"\n",
+                name
+            ),
+        )
+        .unwrap();
+        write(root.join(name).join("src/lib.rs"), "").unwrap();
+    }
+
+    git(root, &["init", "-q"]);
+    commit(root, "init");
+    git(root, &["tag", "v0.1.0"]);
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "version", "minor", "-y", "--no-git-commit"],
+    );
+    assert!(err.contains("info inherited version only writing it to `[workspace.package]`"));
+    assert!(!err.contains("mixed versions"));
+
+    let manifest = read_to_string(root.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("[workspace.package]\nversion = \"0.2.0\"\n"));
+    assert!(read_to_string(root.join("dep1/Cargo.toml"))
+        .unwrap()
+        .contains("version.workspace = true"));
+
+    // A crate with its own version
+    write(
+        root.join("Cargo.toml"),
+        manifest.replace("\"dep2\"]", "\"dep2\", \"dep3\"]"),
+    )
+    .unwrap();
+    member(root, "dep3", "");
+    commit(root, "own version");
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "version",
+            "patch",
+            "-y",
+            "--no-git-commit",
+            "--force",
+            "*",
+        ],
+    );
+    assert!(err.contains(
+        "warn mixed versions dep1, dep2 inherit `[workspace.package] version` but not dep3"
+    ));
+}
+
+#[test]
+fn test_tracked_lock() {
+    let dir = workspace(&[("dep1", ""), ("dep2", "")], "");
+    let root = dir.path();
+
+    // Untracked lockfiles are left alone
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    utils::run(
+        root.to_str().unwrap(),
+        &["ws", "version", "patch", "-y", "--no-git-commit"],
+    );
+    assert!(!root.join("Cargo.lock").exists());
+
+    git(root, &["checkout", "-q", "--", "."]);
+    let status = Command::new("cargo")
+        .current_dir(root)
+        .args(["generate-lockfile", "--offline"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    commit(root, "lock");
+
+    utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "version",
+            "patch",
+            "-y",
+            "--no-git-push",
+            "--no-individual-tags",
+        ],
+    );
+
+    let lock = read_to_string(root.join("Cargo.lock")).unwrap();
+    assert!(lock.contains("name = \"dep1\"\nversion = \"0.1.1\""));
+
+    // The lockfile is part of the release commit
+    assert_eq!(git(root, &["status", "--porcelain"]), "");
+}
+
+#[test]
+fn test_ignored_lock() {
+    let dir = workspace(&[("dep1", "")], "");
+    let root = dir.path();
+
+    write(root.join(".gitignore"), "Cargo.lock\n").unwrap();
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "version",
+            "patch",
+            "-y",
+            "--no-git-push",
+            "--update-lock",
+        ],
+    );
+    assert!(err.contains("success ok"));
+
+    // The lockfile is updated but stays out of the release commit
+    let lock = read_to_string(root.join("Cargo.lock")).unwrap();
+    assert!(lock.contains("name = \"dep1\"\nversion = \"0.1.1\""));
+    assert_eq!(git(root, &["ls-files", "Cargo.lock"]), "");
+    assert_eq!(git(root, &["status", "--porcelain"]), "");
+}
+
+#[test]
+fn test_reset_undo_git() {
+    let dir = workspace(&[("dep1", ""), ("dep2", "")], "");
+    let root = dir.path();
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "version", "patch", "-y", "--no-git-push"],
+    );
+    assert!(err.contains("info success ok"));
+
+    let reset = ["ws", "version", "--reset-to", "0.1.0", "--undo-git", "-y"];
+
+    // Uncommitted changes would be lost
+    write(root.join("dep2/src/lib.rs"), "pub fn b() {}\n").unwrap();
+
+    let err = utils::run_err(root.to_str().unwrap(), &reset);
+    assert!(err.contains("the work tree has uncommitted changes"));
+    assert!(git(root, &["tag", "--list"]).contains("v0.1.1"));
+
+    git(root, &["checkout", "-q", "--", "."]);
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &reset);
+    assert!(err.contains("info undone release commit and tags "));
+    assert!(err.contains("warn reset versions are not committed"));
+
+    assert_eq!(git(root, &["tag", "--list"]), "v0.1.0");
+    assert_eq!(git(root, &["log", "--format=%s"]), "edit\ninit");
+    assert!(read_to_string(root.join("dep1/Cargo.toml"))
+        .unwrap()
+        .contains("version = \"0.1.0\""));
+
+    // The remaining commit was not created by the version command
+    let err = utils::run_err(root.to_str().unwrap(), &reset);
+    assert!(err.contains("HEAD is not a commit created by the version command"));
+}
+
+#[test]
+fn test_push() {
+    let dir = workspace(&[("dep1", "")], "");
+    let root = dir.path();
+    let remote = tempdir().unwrap();
+
+    git(
+        remote.path(),
+        &["clone", "-q", "--bare", root.to_str().unwrap(), "."],
+    );
+    git(
+        root,
+        &["remote", "add", "upstream", remote.path().to_str().unwrap()],
+    );
+    git(root, &["fetch", "-q", "upstream"]);
+
+    // Only the tags created by the run are pushed
+    git(root, &["tag", "unrelated"]);
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "version", "patch", "-y", "--push=upstream"],
+    );
+    assert!(err.contains("info git pushing"));
+    assert!(err.contains("info success ok"));
+
+    assert_eq!(
+        git(remote.path(), &["rev-parse", "master"]),
+        git(root, &["rev-parse", "HEAD"])
+    );
+    assert_eq!(
+        git(remote.path(), &["tag", "--list"]),
+        "dep1@0.1.1\nv0.1.0\nv0.1.1"
+    );
+}
+
+// The hook is a shell script
+#[cfg(not(windows))]
+#[test]
+fn test_push_failed() {
+    use std::{
+        fs::{set_permissions, Permissions},
+        os::unix::fs::PermissionsExt,
+    };
+
+    let dir = workspace(&[("dep1", "")], "");
+    let root = dir.path();
+    let remote = tempdir().unwrap();
+
+    // The remote rejects every push
+    git(
+        remote.path(),
+        &["clone", "-q", "--bare", root.to_str().unwrap(), "."],
+    );
+    write(
+        remote.path().join("hooks/pre-receive"),
+        "#!/bin/sh\necho rejected >&2\nexit 1\n",
+    )
+    .unwrap();
+    set_permissions(
+        remote.path().join("hooks/pre-receive"),
+        Permissions::from_mode(0o755),
+    )
+    .unwrap();
+
+    git(
+        root,
+        &["remote", "add", "broken", remote.path().to_str().unwrap()],
+    );
+    git(root, &["fetch", "-q", "broken"]);
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    let err = utils::run_err(
+        root.to_str().unwrap(),
+        &["ws", "version", "patch", "-y", "--push=broken"],
+    );
+    assert!(err.contains("error: unable to push to broken: "));
+    assert!(err.contains("rejected"));
+    assert!(err.contains(
+        "the release was committed and tagged locally, once the problem is fixed, push it with:\n    git push --atomic broken master refs/tags/v0.1.1 refs/tags/dep1@0.1.1\n"
+    ));
+
+    // The release stays local
+    assert_eq!(git(root, &["tag", "--list"]), "dep1@0.1.1\nv0.1.0\nv0.1.1");
+    assert_eq!(git(remote.path(), &["tag", "--list"]), "v0.1.0");
+}
+
+#[test]
+fn test_exact_unpublished() {
+    let dir = workspace(
+        &[
+            ("dep1", ""),
+            ("internal", "publish = false\n"),
+            (
+                "top",
+                "\n[dependencies]\ndep1 = { path = \"../dep1\", version = \"0.1.0\" }\ninternal = { path = \"../internal\", version = \"0.1.0\" }\n",
+            ),
+        ],
+        "",
+    );
+    let root = dir.path();
+
+    utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "version",
+            "patch",
+            "-y",
+            "-a",
+            "--exact",
+            "--force",
+            "*",
+            "--no-git-commit",
+        ],
+    );
+
+    // Requirements on crates which are never published are not pinned
+    let manifest = read_to_string(root.join("top/Cargo.toml")).unwrap();
+    assert!(manifest.contains("dep1 = { path = \"../dep1\", version = \"=0.1.1\" }"));
+    assert!(manifest.contains("internal = { path = \"../internal\", version = \"0.1.0\" }"));
+}
+
+#[test]
+fn test_lockstep_skips_independent() {
+    let dir = workspace(
+        &[
+            ("dep1", ""),
+            (
+                "ind",
+                "\n[package.metadata.cargo-workspaces]\nindependent = true\n",
+            ),
+        ],
+        "",
+    );
+    let root = dir.path();
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    write(root.join("ind/src/lib.rs"), "pub fn b() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "version", "minor", "-y", "--no-git-commit"],
+    );
+    assert!(err.contains("info skipping independent ind"));
+    assert!(read_to_string(root.join("dep1/Cargo.toml"))
+        .unwrap()
+        .contains("version = \"0.2.0\""));
+    assert!(read_to_string(root.join("ind/Cargo.toml"))
+        .unwrap()
+        .contains("version = \"0.1.0\""));
+
+    git(root, &["checkout", "-q", "--", "."]);
+
+    // Targeting the crate by name bumps it as well
+    utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "version",
+            "minor",
+            "-y",
+            "--no-git-commit",
+            "--force",
+            "ind",
+        ],
+    );
+    assert!(read_to_string(root.join("ind/Cargo.toml"))
+        .unwrap()
+        .contains("version = \"0.2.0\""));
+}
+
+#[test]
+fn test_not_git_repo() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"dep1\", \"dep2\"]\n",
+    )
+    .unwrap();
+    member(root, "dep1", "");
+    member(root, "dep2", "");
+
+    let (out, err) = utils::run(root.to_str().unwrap(), &["ws", "changed"]);
+    assert_eq!(out, "dep1\ndep2\n");
+    assert!(err.contains("warn not a git repository considering every crate as changed"));
+
+    let err = utils::run_err(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--since", "v0.1.0"],
+    );
+    assert!(err.contains("error: not a git repository"));
+
+    // The versions are written without committing or tagging them
+    let (_, err) = utils::run(root.to_str().unwrap(), &["ws", "version", "patch", "-y"]);
+    assert!(err.contains("skipping the commit and the tags of the versions"));
+    assert!(err.contains("info success ok"));
+    assert!(read_to_string(root.join("dep2/Cargo.toml"))
+        .unwrap()
+        .contains("version = \"0.1.1\""));
+}
+
+#[test]
+fn test_forge_release_dry_run() {
+    let dir = workspace(
+        &[("dep1", "")],
+        "\n[workspace.metadata.workspaces]\nforge = \"gitlab\"\napi_url = \"https://git.example.com/api/v4\"\n",
+    );
+    let root = dir.path();
+
+    git(
+        root,
+        &[
+            "remote",
+            "add",
+            "origin",
+            "git@git.example.com:group/sub/repo.git",
+        ],
+    );
+    write(
+        root.join("dep1/CHANGELOG.md"),
+        "# Changelog\n\n## 0.2.0-rc.1\n\n- Things\n",
+    )
+    .unwrap();
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "--dry-run",
+            "version",
+            "custom",
+            "0.2.0-rc.1",
+            "-y",
+            "--no-git-push",
+            "--forge-release",
+        ],
+    );
+    assert!(err.contains(
+        "info would create release POST https://git.example.com/api/v4/projects/group%2Fsub%2Frepo/releases\n"
+    ));
+    assert!(err.contains("\"description\":\"- Things\""));
+    assert!(err.contains("\"tag_name\":\"dep1@0.2.0-rc.1\""));
+}
+
+#[test]
+fn test_gha_matrix() {
+    let dir = workspace(&[("dep1", ""), ("dep2", "")], "");
+    let root = dir.path();
+
+    // Nothing changed is still a valid matrix
+    let out = utils::run_out(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--format", "gha-matrix"],
+    );
+    assert_eq!(out, "{\"crate\":[]}\n");
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    write(root.join("dep2/src/lib.rs"), "pub fn b() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (out, _) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "changed", "--format", "gha-matrix"],
+    );
+    assert_eq!(out, "{\"crate\":[\"dep1\",\"dep2\"]}\n");
+}
+
+#[test]
+fn test_version_step_summary() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+
+    write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"dep1\"]\n",
+    )
+    .unwrap();
+    member(root, "dep1", "");
+
+    git(root, &["init", "-q"]);
+    commit(root, "init");
+
+    let summary = root.join("summary.md");
+    write(&summary, "Previous step\n").unwrap();
+
+    let output = assert_cmd::Command::cargo_bin("cargo-ws")
+        .unwrap()
+        .current_dir(root)
+        .args(["ws", "version", "minor", "-y", "--no-git-push"])
+        .env("GITHUB_STEP_SUMMARY", &summary)
+        .env("GITHUB_SERVER_URL", "https://github.com")
+        .env("GITHUB_REPOSITORY", "owner/repo")
+        .env("GIT_AUTHOR_NAME", "test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let sha = git(root, &["rev-parse", "HEAD"]);
+
+    assert_eq!(
+        read_to_string(&summary).unwrap(),
+        format!(
+            "Previous step\n### Versions\n\n| Crate | Old | New |\n| --- | --- | --- |\n| dep1 | 0.1.0 | 0.2.0 |\n\nCommit: [`{}`](https://github.com/owner/repo/commit/{})\n\nTags: [`dep1@0.2.0`](https://github.com/owner/repo/releases/tag/dep1@0.2.0)\n\n",
+            &sha[..7],
+            sha
+        )
+    );
+}
+
+#[test]
+fn test_bump_patch_dependents() {
+    let independent = "\n[package.metadata.workspaces]\nindependent = true\n";
+    let dir = workspace(
+        &[
+            ("dep1", independent),
+            (
+                "dep2",
+                &format!(
+                    "{}\n[dependencies]\ndep1 = {{ path = \"../dep1\", version = \"0.1.0\" }}\n",
+                    independent
+                ),
+            ),
+            (
+                "dep3",
+                &format!(
+                    "{}\n[dependencies]\ndep2 = {{ path = \"../dep2\", version = \"0.1.0\" }}\n",
+                    independent
+                ),
+            ),
+        ],
+        "",
+    );
+    let root = dir.path();
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "version",
+            "minor",
+            "-y",
+            "--no-git-commit",
+            "--bump-patch-dependents",
+        ],
+    );
+
+    let version = |name: &str| {
+        read_to_string(root.join(name).join("Cargo.toml"))
+            .unwrap()
+            .lines()
+            .find_map(|x| x.strip_prefix("version = "))
+            .unwrap()
+            .to_string()
+    };
+
+    // dep3 still accepts the new dep2, but dep2 is re-released with the new dep1
+    assert_eq!(version("dep1"), "\"0.2.0\"");
+    assert_eq!(version("dep2"), "\"0.1.1\"");
+    assert_eq!(version("dep3"), "\"0.1.1\"");
+}
+
+#[test]
+fn test_metadata_loaded_once() {
+    let dir = workspace(
+        &[
+            ("dep1", ""),
+            ("dep2", "[dependencies]\ndep1 = { path = \"../dep1\" }\n"),
+        ],
+        "",
+    );
+    let root = dir.path();
+
+    write(root.join("dep1/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    commit(root, "edit");
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &["ws", "-v", "version", "patch", "-y", "--no-git-commit"],
+    );
+
+    assert!(err.contains("info success ok"));
+    assert_eq!(err.matches("debug timing cargo metadata took").count(), 1);
+}
+
+fn tags_after_release(rest: &str, args: &[&str]) -> Vec<String> {
+    let dir = workspace(
+        &[
+            ("dep1", ""),
+            ("ind", "\n[package.metadata.workspaces]\nindependent = true\n"),
+            (
+                "fixed",
+                "\n[package.metadata.workspaces]\nskip_version = true\n\n[dependencies]\ndep1 = { path = \"../dep1\", version = \"0.1.0\" }\n",
+            ),
+            ("internal", "\n[package.metadata.workspaces]\nskip_publish = true\n"),
+        ],
+        rest,
+    );
+    let root = dir.path();
+
+    let mut cmd = vec![
+        "ws",
+        "version",
+        "minor",
+        "-y",
+        "--no-git-push",
+        "--force",
+        "*",
+    ];
+    cmd.extend(args);
+
+    let (_, err) = utils::run(root.to_str().unwrap(), &cmd);
+    assert!(err.contains("info success ok"), "{}", err);
+
+    // The crates which are not versioned still get their requirements updated
+    let fixed = read_to_string(root.join("fixed/Cargo.toml")).unwrap();
+    assert!(fixed.contains("version = \"0.1.0\"\n"));
+    assert!(fixed.contains("version = \"0.2.0\" }"));
+
+    git(root, &["tag", "--list"])
+        .lines()
+        .map(|x| x.to_string())
+        .collect()
+}
+
+#[test]
+fn test_individual_tags() {
+    assert_eq!(
+        tags_after_release("", &[]),
+        [
+            "dep1@0.2.0",
+            "ind@0.2.0",
+            "internal@0.2.0",
+            "v0.1.0",
+            "v0.2.0"
+        ]
+    );
+}
+
+#[test]
+fn test_no_individual_tags() {
+    // Independent crates keep their tags whether grouping comes from the flag or the config
+    let grouped = ["ind@0.2.0", "v0.1.0", "v0.2.0"];
+
+    assert_eq!(tags_after_release("", &["--no-individual-tags"]), grouped);
+    assert_eq!(
+        tags_after_release(
+            "\n[workspace.metadata.workspaces]\nno_individual_tags = true\n",
+            &[]
+        ),
+        grouped
+    );
+}
+
+#[test]
+fn test_skip_tag_for() {
+    assert_eq!(
+        tags_after_release("", &["--skip-tag-for", "*n*"]),
+        ["dep1@0.2.0", "v0.1.0", "v0.2.0"]
+    );
+
+    // It also applies to the independent crates of a grouped workspace
+    assert_eq!(
+        tags_after_release(
+            "\n[workspace.metadata.workspaces]\nno_individual_tags = true\n",
+            &["--skip-tag-for", "ind"]
+        ),
+        ["v0.1.0", "v0.2.0"]
+    );
+}
+
+#[test]
+fn test_commit_per_crate() {
+    let independent = "\n[package.metadata.workspaces]\nindependent = true\n";
+    let dependent = format!(
+        "{}\n[dependencies]\na = {{ path = \"../a\", version = \"0.1.0\" }}\n",
+        independent
+    );
+
+    // Grouping the tags from the config does not apply to the independent crates either
+    for rest in [
+        "",
+        "\n[workspace.metadata.workspaces]\nno_individual_tags = true\n",
+    ] {
+        let dir = workspace(&[("a", independent), ("b", &dependent)], rest);
+        let root = dir.path();
+
+        let (_, err) = utils::run(
+            root.to_str().unwrap(),
+            &[
+                "ws",
+                "version",
+                "patch",
+                "-y",
+                "--no-git-push",
+                "--commit-per-crate",
+                "--force",
+                "*",
+            ],
+        );
+        assert!(err.contains("info success ok"), "{}", err);
+
+        // One commit per crate, in the order of their dependencies
+        assert_eq!(
+            git(root, &["log", "--format=%s"]),
+            "Release b@0.1.1\nRelease a@0.1.1\ninit"
+        );
+        assert_eq!(git(root, &["tag", "--list"]), "a@0.1.1\nb@0.1.1\nv0.1.0");
+        assert_eq!(git(root, &["tag", "--points-at", "HEAD~1"]), "a@0.1.1");
+    }
+}
+
+#[test]
+fn test_dev_dependency_cycle() {
+    let dir = workspace(
+        &[
+            (
+                "foo",
+                "\n[dev-dependencies]\nfoo-test-util = { path = \"../foo-test-util\", version = \"0.1.0\" }\n",
+            ),
+            (
+                "foo-test-util",
+                "\n[dependencies]\nfoo = { path = \"../foo\", version = \"0.1.0\" }\n",
+            ),
+        ],
+        "",
+    );
+    let root = dir.path();
+
+    let (_, err) = utils::run(
+        root.to_str().unwrap(),
+        &[
+            "ws",
+            "version",
+            "minor",
+            "-y",
+            "--no-git-commit",
+            "--force",
+            "*",
+        ],
+    );
+    assert!(err.contains("info success ok"));
+
+    // The requirements are still updated through the dev-dependency
+    let foo = read_to_string(root.join("foo/Cargo.toml")).unwrap();
+    assert!(foo.contains("foo-test-util = { path = \"../foo-test-util\", version = \"0.2.0\" }"));
+}